@@ -103,6 +103,9 @@ public enum TaskType {
     GenericGenerator,
     GenericCrashReport,
     GenericRegression,
+    AflppFuzz,
+    LibfuzzerMinimize,
+    GenericMinimize,
 }
 
 public enum Os {
@@ -124,6 +127,7 @@ public enum ContainerType {
     UniqueInputs,
     UniqueReports,
     RegressionReports,
+    MinimizedCrashes,
     Logs,
     ExtraSetup,
     ExtraOutput,
@@ -132,7 +136,8 @@ public enum ContainerType {
 
 [SkipRename]
 public enum StatsFormat {
-    AFL
+    AFL,
+    Honggfuzz
 }
 
 public enum TaskDebugFlag {
@@ -239,7 +239,8 @@ public record TaskDetails(
     string? SourceAllowlist = null,
     string? TargetAssembly = null,
     string? TargetClass = null,
-    string? TargetMethod = null
+    string? TargetMethod = null,
+    uint? MaxDequeueCount = null
 );
 
 public record TaskVm(
@@ -1099,6 +1100,8 @@ public record TaskUnitConfig(
     Dictionary<string, string> Tags
     ) {
     public Uri? inputQueue { get; set; }
+    public Uri? DeadLetterQueue { get; set; }
+    public uint? MaxDequeueCount { get; set; }
     public String? SupervisorExe { get; set; }
     public Dictionary<string, string>? SupervisorEnv { get; set; }
     public List<string>? SupervisorOptions { get; set; }
@@ -1152,6 +1155,7 @@ public record TaskUnitConfig(
     public IContainerDef? UniqueInputs { get; set; }
     public IContainerDef? UniqueReports { get; set; }
     public IContainerDef? RegressionReports { get; set; }
+    public IContainerDef? MinimizedCrashes { get; set; }
     public IContainerDef? ExtraSetup { get; set; }
     public IContainerDef? ExtraOutput { get; set; }
     public ulong? MinAvailableMemoryMb { get; set; }
@@ -80,7 +80,7 @@ public record NodeStateUpdate(
     NodeStateData? Data = null
 ) : NodeEventBase;
 
-// NodeSettingUpEventData, NodeDoneEventData, or ProcessOutput
+// NodeSettingUpEventData, NodeBusyEventData, NodeDoneEventData, or ProcessOutput
 [JsonConverter(typeof(SubclassConverter<NodeStateData>))]
 public abstract record NodeStateData;
 
@@ -95,6 +95,15 @@ public record NodeSettingUpEventData(
     List<NodeSettingUpData>? TaskData
 ) : NodeStateData;
 
+public record NodeWorkUnitData(
+    [property: Required] Guid JobId,
+    [property: Required] Guid TaskId,
+    [property: Required] string State);
+
+public record NodeBusyEventData(
+    List<NodeWorkUnitData>? WorkUnits
+) : NodeStateData;
+
 public record NodeDoneEventData(
     [property: JsonIgnore(Condition = JsonIgnoreCondition.WhenWritingNull)]
     string? Error,
@@ -76,10 +76,13 @@ public class Config : IConfig {
         ) {
             // It's okay if this is null because the agent will use a default value if so.
             MinAvailableMemoryMb = task.Config.Task.MinAvailableMemoryMb,
+            // As above, the agent has a default for this.
+            MaxDequeueCount = task.Config.Task.MaxDequeueCount,
         };
 
         if (definition.MonitorQueue != null) {
             config.inputQueue = await _queue.GetQueueSas(task.TaskId.ToString(), StorageType.Corpus, QueueSasPermissions.All);
+            config.DeadLetterQueue = await _queue.GetQueueSas(_context.TaskOperations.GetPoisonQueue(task.TaskId), StorageType.Corpus, QueueSasPermissions.Add);
         }
 
         if (task.Config.Containers is not null) {
@@ -149,6 +152,9 @@ public class Config : IConfig {
                     case ContainerType.RegressionReports:
                         config.RegressionReports = def;
                         break;
+                    case ContainerType.MinimizedCrashes:
+                        config.MinimizedCrashes = def;
+                        break;
                     case ContainerType.ExtraSetup:
                         config.ExtraSetup = def;
                         break;
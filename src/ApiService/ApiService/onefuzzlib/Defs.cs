@@ -357,6 +357,47 @@ public static class Defs {
                     MonitorQueue: ContainerType.Crashes
                 )
             },
+            {
+                TaskType.LibfuzzerMinimize,
+                new TaskDefinition(
+                    Features: new[]
+                    {
+                        TaskFeature.TargetExe,
+                        TaskFeature.TargetEnv,
+                        TaskFeature.TargetOptions,
+                        TaskFeature.TargetTimeout,
+                        TaskFeature.CheckRetryCount,
+                        TaskFeature.CheckFuzzerHelp,
+                        TaskFeature.MinimizedStackDepth,
+                    },
+                    Vm: new VmDefinition(Compare: Compare.AtLeast, Value: 1),
+                    Containers: new[]
+                    {
+                        _setupContainer,
+                        new ContainerDefinition(
+                            Type: ContainerType.Crashes,
+                            Compare: Compare.Equal,
+                            Value: 1,
+                            Permissions: ContainerPermission.Read | ContainerPermission.List
+                        ),
+                        new ContainerDefinition(
+                            Type: ContainerType.MinimizedCrashes,
+                            Compare: Compare.Equal,
+                            Value: 1,
+                            Permissions: ContainerPermission.Write
+                        ),
+                        new ContainerDefinition(
+                            Type: ContainerType.Reports,
+                            Compare: Compare.AtMost,
+                            Value: 1,
+                            Permissions: ContainerPermission.Write
+                        ),
+                        _extraSetupContainer,
+                        _extraOutputContainer,
+                    },
+                    MonitorQueue: ContainerType.Crashes
+                )
+            },
             {
                 TaskType.LibfuzzerCoverage,
                 new TaskDefinition(
@@ -501,6 +542,55 @@ public static class Defs {
                     }
                 )
             },
+            {
+                TaskType.AflppFuzz,
+                new TaskDefinition(
+                    Features: new[]
+                    {
+                        TaskFeature.TargetExe,
+                        TaskFeature.TargetEnv,
+                        TaskFeature.TargetOptions,
+                        TaskFeature.TargetWorkers,
+                        TaskFeature.SupervisorExe,
+                        TaskFeature.SupervisorEnv,
+                        TaskFeature.SupervisorOptions,
+                        TaskFeature.EnsembleSyncDelay,
+                    },
+                    Vm: new VmDefinition(Compare: Compare.AtLeast, Value: 1),
+                    Containers: new[]
+                    {
+                        _setupContainer,
+                        new ContainerDefinition(
+                            Type: ContainerType.Tools,
+                            Compare: Compare.AtMost,
+                            Value: 1,
+                            Permissions: ContainerPermission.Read | ContainerPermission.List
+                        ),
+                        new ContainerDefinition(
+                            Type: ContainerType.Crashes,
+                            Compare: Compare.Equal,
+                            Value: 1,
+                            Permissions: ContainerPermission.Write
+                        ),
+                        new ContainerDefinition(
+                            Type: ContainerType.Inputs,
+                            Compare: Compare.Equal,
+                            Value: 1,
+                            Permissions: ContainerPermission.Write
+                                | ContainerPermission.Read
+                                | ContainerPermission.List
+                        ),
+                        new ContainerDefinition(
+                            Type: ContainerType.ReadonlyInputs,
+                            Compare: Compare.AtLeast,
+                            Value: 0,
+                            Permissions: ContainerPermission.Read | ContainerPermission.List
+                        ),
+                        _extraSetupContainer,
+                        _extraOutputContainer,
+                    }
+                )
+            },
             {
                 TaskType.GenericMerge,
                 new TaskDefinition(
@@ -636,6 +726,48 @@ public static class Defs {
                     MonitorQueue: ContainerType.Crashes
                 )
             },
+            {
+                TaskType.GenericMinimize,
+                new TaskDefinition(
+                    Features: new[]
+                    {
+                        TaskFeature.TargetExe,
+                        TaskFeature.TargetEnv,
+                        TaskFeature.TargetOptions,
+                        TaskFeature.TargetTimeout,
+                        TaskFeature.CheckAsanLog,
+                        TaskFeature.CheckDebugger,
+                        TaskFeature.CheckRetryCount,
+                        TaskFeature.MinimizedStackDepth,
+                    },
+                    Vm: new VmDefinition(Compare: Compare.AtLeast, Value: 1),
+                    Containers: new[]
+                    {
+                        _setupContainer,
+                        new ContainerDefinition(
+                            Type: ContainerType.Crashes,
+                            Compare: Compare.Equal,
+                            Value: 1,
+                            Permissions: ContainerPermission.Read | ContainerPermission.List
+                        ),
+                        new ContainerDefinition(
+                            Type: ContainerType.MinimizedCrashes,
+                            Compare: Compare.Equal,
+                            Value: 1,
+                            Permissions: ContainerPermission.Write
+                        ),
+                        new ContainerDefinition(
+                            Type: ContainerType.Reports,
+                            Compare: Compare.AtMost,
+                            Value: 1,
+                            Permissions: ContainerPermission.Write
+                        ),
+                        _extraSetupContainer,
+                        _extraOutputContainer,
+                    },
+                    MonitorQueue: ContainerType.Crashes
+                )
+            },
             {
                 TaskType.GenericRegression,
                 new TaskDefinition(
@@ -26,6 +26,8 @@ public interface ITaskOperations : IStatefulOrm<Task, TaskState> {
     Async.Task<Task> SetState(Task task, TaskState state);
     Async.Task<OneFuzzResult<Task>> Create(TaskConfig config, Guid jobId, UserInfo userInfo);
 
+    string GetPoisonQueue(Guid taskId);
+
     // state transitions:
     Async.Task<Task> Init(Task task);
     Async.Task<Task> Waiting(Task task);
@@ -330,8 +332,12 @@ public class TaskOperations : StatefulOrm<Task, TaskState, TaskOperations>, ITas
         return null;
     }
 
+    public string GetPoisonQueue(Guid taskId)
+        => $"{taskId}-poison";
+
     public async Async.Task<Task> Init(Task task) {
         await _context.Queue.CreateQueue($"{task.TaskId}", StorageType.Corpus);
+        await _context.Queue.CreateQueue(GetPoisonQueue(task.TaskId), StorageType.Corpus);
         return await SetState(task, TaskState.Waiting);
     }
 
@@ -349,6 +355,7 @@ public class TaskOperations : StatefulOrm<Task, TaskState, TaskOperations>, ITas
     public async Async.Task<Task> Stopped(Task task) {
         task = await SetState(task, TaskState.Stopped);
         await _context.Queue.DeleteQueue($"{task.TaskId}", StorageType.Corpus);
+        await _context.Queue.DeleteQueue(GetPoisonQueue(task.TaskId), StorageType.Corpus);
 
         //     # TODO: we need to 'unschedule' this task from the existing pools
         var job = await _context.JobOperations.Get(task.JobId);
@@ -0,0 +1,180 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT License.
+
+use std::collections::{BTreeMap, BTreeSet};
+
+use debuggable_module::path::FilePath;
+use debuggable_module::Offset;
+
+use crate::binary::{BinaryCoverage, ModuleBinaryCoverage};
+
+/// Binary coverage recorded separately for each input of a corpus.
+///
+/// Only reached blocks are retained for each input, since the recorded coverage
+/// of a single input includes every known block of each module.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct CorpusCoverage<K> {
+    pub inputs: BTreeMap<K, BinaryCoverage>,
+}
+
+impl<K> Default for CorpusCoverage<K> {
+    fn default() -> Self {
+        Self {
+            inputs: BTreeMap::new(),
+        }
+    }
+}
+
+impl<K: Clone + Ord> CorpusCoverage<K> {
+    /// Record the coverage of `input`, replacing any previous coverage for it.
+    pub fn insert(&mut self, input: K, coverage: &BinaryCoverage) {
+        let mut reached = BinaryCoverage::default();
+
+        for (path, module) in &coverage.modules {
+            let offsets: BTreeMap<_, _> = module
+                .offsets
+                .iter()
+                .filter(|(_, count)| count.reached())
+                .map(|(&offset, &count)| (offset, count))
+                .collect();
+
+            if !offsets.is_empty() {
+                let module = ModuleBinaryCoverage { offsets };
+                reached.modules.insert(path.clone(), module);
+            }
+        }
+
+        self.inputs.insert(input, reached);
+    }
+
+    /// Inputs whose coverage reached the block at `offset` in `module`.
+    pub fn inputs_reaching<'a>(
+        &'a self,
+        module: &'a FilePath,
+        offset: Offset,
+    ) -> impl Iterator<Item = &'a K> + 'a {
+        self.inputs
+            .iter()
+            .filter(move |(_, coverage)| {
+                coverage
+                    .modules
+                    .get(module)
+                    .map(|m| m.offsets.contains_key(&offset))
+                    .unwrap_or(false)
+            })
+            .map(|(input, _)| input)
+    }
+
+    /// Select a small subset of inputs which together reach every block
+    /// reached by the whole corpus.
+    ///
+    /// Minimal set cover is NP-hard, so this uses the greedy approximation:
+    /// repeatedly select the input which reaches the most blocks not yet
+    /// reached by the selected inputs. Ties are broken by input order.
+    pub fn minimize(&self) -> Vec<K> {
+        let mut candidates: Vec<(&K, BTreeSet<(&FilePath, Offset)>)> = self
+            .inputs
+            .iter()
+            .map(|(input, coverage)| (input, blocks(coverage)))
+            .filter(|(_, blocks)| !blocks.is_empty())
+            .collect();
+
+        let mut selected = vec![];
+
+        loop {
+            let best = candidates
+                .iter()
+                .enumerate()
+                .map(|(index, (_, blocks))| (index, blocks.len()))
+                .filter(|&(_, new)| new > 0)
+                // `max_by_key()` returns the last maximum, so reverse to prefer
+                // earlier inputs.
+                .rev()
+                .max_by_key(|&(_, new)| new);
+
+            let Some((index, _)) = best else {
+                break;
+            };
+
+            let (input, reached) = candidates.remove(index);
+            selected.push(input.clone());
+
+            for (_, blocks) in &mut candidates {
+                blocks.retain(|block| !reached.contains(block));
+            }
+        }
+
+        selected
+    }
+}
+
+fn blocks(coverage: &BinaryCoverage) -> BTreeSet<(&FilePath, Offset)> {
+    coverage
+        .modules
+        .iter()
+        .flat_map(|(path, module)| module.offsets.keys().map(move |&offset| (path, offset)))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use anyhow::Result;
+    use pretty_assertions::assert_eq;
+
+    use super::*;
+    use crate::binary::Count;
+
+    fn coverage(module: &FilePath, counts: &[(u64, u32)]) -> BinaryCoverage {
+        let mut module_coverage = ModuleBinaryCoverage::default();
+        for &(offset, count) in counts {
+            module_coverage.offsets.insert(Offset(offset), Count(count));
+        }
+
+        let mut coverage = BinaryCoverage::default();
+        coverage.modules.insert(module.clone(), module_coverage);
+        coverage
+    }
+
+    #[test]
+    fn test_insert_keeps_reached_blocks() -> Result<()> {
+        let main = FilePath::new("/setup/main.exe")?;
+
+        let mut corpus = CorpusCoverage::default();
+        corpus.insert("a", &coverage(&main, &[(1, 1), (2, 0), (3, 2)]));
+        corpus.insert("b", &coverage(&main, &[(1, 0), (2, 0), (3, 0)]));
+
+        assert_eq!(corpus.inputs["a"], coverage(&main, &[(1, 1), (3, 2)]));
+        assert!(corpus.inputs["b"].modules.is_empty());
+
+        let reaching: Vec<_> = corpus.inputs_reaching(&main, Offset(3)).collect();
+        assert_eq!(reaching, [&"a"]);
+        assert_eq!(corpus.inputs_reaching(&main, Offset(2)).count(), 0);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_minimize() -> Result<()> {
+        let main = FilePath::new("/setup/main.exe")?;
+        let lib = FilePath::new("/setup/lib.so")?;
+
+        let mut corpus = CorpusCoverage::default();
+        corpus.insert("a", &coverage(&main, &[(1, 1), (2, 1)]));
+        corpus.insert("b", &coverage(&main, &[(1, 1), (2, 1), (3, 1), (4, 1)]));
+        corpus.insert("c", &coverage(&main, &[(4, 1), (5, 1)]));
+        corpus.insert("d", &coverage(&main, &[(3, 1)]));
+        corpus.insert("e", &coverage(&lib, &[(1, 1)]));
+        corpus.insert("f", &coverage(&lib, &[(1, 1)]));
+        corpus.insert("g", &coverage(&main, &[(6, 0)]));
+
+        assert_eq!(corpus.minimize(), ["b", "c", "e"]);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_minimize_empty() {
+        let corpus = CorpusCoverage::<String>::default();
+        assert!(corpus.minimize().is_empty());
+    }
+}
@@ -0,0 +1,271 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT License.
+
+use std::collections::{BTreeMap, BTreeSet};
+
+use debuggable_module::path::FilePath;
+use debuggable_module::Offset;
+
+use crate::binary::{BinaryCoverage, Count};
+use crate::source::{FileCoverage, Line, SourceCoverage};
+
+/// Blocks reached by one binary coverage snapshot but not another.
+///
+/// Only modules with at least one added or lost block are present.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct BinaryCoverageDiff {
+    pub modules: BTreeMap<FilePath, ModuleBinaryCoverageDiff>,
+}
+
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct ModuleBinaryCoverageDiff {
+    /// Blocks reached in the newer snapshot, but not the older one.
+    pub added: BTreeSet<Offset>,
+
+    /// Blocks reached in the older snapshot, but not the newer one.
+    pub lost: BTreeSet<Offset>,
+}
+
+/// Lines reached by one source coverage snapshot but not another.
+///
+/// Only files with at least one added or lost line are present.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct SourceCoverageDiff {
+    pub files: BTreeMap<FilePath, FileCoverageDiff>,
+}
+
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct FileCoverageDiff {
+    /// Lines reached in the newer snapshot, but not the older one.
+    pub added: BTreeSet<Line>,
+
+    /// Lines reached in the older snapshot, but not the newer one.
+    pub lost: BTreeSet<Line>,
+}
+
+impl BinaryCoverage {
+    /// Compare `self` against a `newer` snapshot.
+    ///
+    /// A block is only considered reached if its hit count is nonzero, so
+    /// blocks that are known but unreached in one snapshot and missing from
+    /// the other are not reported.
+    pub fn diff(&self, newer: &Self) -> BinaryCoverageDiff {
+        let mut diff = BinaryCoverageDiff::default();
+
+        let paths: BTreeSet<_> = self.modules.keys().chain(newer.modules.keys()).collect();
+
+        for path in paths {
+            let old = self.modules.get(path).map(|m| &m.offsets);
+            let new = newer.modules.get(path).map(|m| &m.offsets);
+            let (added, lost) = diff_reached(old, new);
+
+            if !added.is_empty() || !lost.is_empty() {
+                let module = ModuleBinaryCoverageDiff { added, lost };
+                diff.modules.insert(path.clone(), module);
+            }
+        }
+
+        diff
+    }
+}
+
+impl BinaryCoverageDiff {
+    pub fn is_empty(&self) -> bool {
+        self.modules.is_empty()
+    }
+
+    pub fn added(&self) -> usize {
+        self.modules.values().map(|m| m.added.len()).sum()
+    }
+
+    pub fn lost(&self) -> usize {
+        self.modules.values().map(|m| m.lost.len()).sum()
+    }
+}
+
+impl SourceCoverage {
+    /// Compare `self` against a `newer` snapshot.
+    ///
+    /// A line is only considered reached if its hit count is nonzero.
+    pub fn diff(&self, newer: &Self) -> SourceCoverageDiff {
+        let mut diff = SourceCoverageDiff::default();
+
+        let paths: BTreeSet<_> = self.files.keys().chain(newer.files.keys()).collect();
+
+        for path in paths {
+            let old = self.files.get(path).map(|f| &f.lines);
+            let new = newer.files.get(path).map(|f| &f.lines);
+            let (added, lost) = diff_reached(old, new);
+
+            if !added.is_empty() || !lost.is_empty() {
+                let file = FileCoverageDiff { added, lost };
+                diff.files.insert(path.clone(), file);
+            }
+        }
+
+        diff
+    }
+}
+
+impl SourceCoverageDiff {
+    pub fn is_empty(&self) -> bool {
+        self.files.is_empty()
+    }
+
+    pub fn added(&self) -> usize {
+        self.files.values().map(|f| f.added.len()).sum()
+    }
+
+    pub fn lost(&self) -> usize {
+        self.files.values().map(|f| f.lost.len()).sum()
+    }
+
+    /// Represent the diff as source coverage of the changed lines only, where
+    /// added lines are hit once and lost lines are not hit.
+    ///
+    /// This lets the diff be rendered by any source coverage output format,
+    /// such as Cobertura.
+    pub fn to_source_coverage(&self) -> SourceCoverage {
+        let mut source = SourceCoverage::default();
+
+        for (path, diff) in &self.files {
+            let mut file = FileCoverage::default();
+            file.lines
+                .extend(diff.added.iter().map(|&line| (line, Count(1))));
+            file.lines
+                .extend(diff.lost.iter().map(|&line| (line, Count(0))));
+            source.files.insert(path.clone(), file);
+        }
+
+        source
+    }
+}
+
+fn diff_reached<K: Copy + Ord>(
+    old: Option<&BTreeMap<K, Count>>,
+    new: Option<&BTreeMap<K, Count>>,
+) -> (BTreeSet<K>, BTreeSet<K>) {
+    let reached = |counts: Option<&BTreeMap<K, Count>>| -> BTreeSet<K> {
+        counts
+            .into_iter()
+            .flatten()
+            .filter(|(_, count)| count.reached())
+            .map(|(&k, _)| k)
+            .collect()
+    };
+
+    let old = reached(old);
+    let new = reached(new);
+
+    let added = new.difference(&old).copied().collect();
+    let lost = old.difference(&new).copied().collect();
+
+    (added, lost)
+}
+
+#[cfg(test)]
+mod tests {
+    use anyhow::Result;
+    use pretty_assertions::assert_eq;
+
+    use super::*;
+    use crate::binary::ModuleBinaryCoverage;
+
+    fn module(counts: &[(u64, u32)]) -> ModuleBinaryCoverage {
+        let mut module = ModuleBinaryCoverage::default();
+        for &(offset, count) in counts {
+            module.offsets.insert(Offset(offset), Count(count));
+        }
+        module
+    }
+
+    fn file(counts: &[(u32, u32)]) -> Result<FileCoverage> {
+        let mut file = FileCoverage::default();
+        for &(line, count) in counts {
+            file.lines.insert(Line::new(line)?, Count(count));
+        }
+        Ok(file)
+    }
+
+    #[test]
+    fn test_binary_diff() -> Result<()> {
+        let main = FilePath::new("/setup/main.exe")?;
+        let lib = FilePath::new("/setup/lib.so")?;
+        let unchanged = FilePath::new("/setup/unchanged.so")?;
+
+        let mut old = BinaryCoverage::default();
+        old.modules
+            .insert(main.clone(), module(&[(1, 1), (2, 0), (3, 4)]));
+        old.modules.insert(unchanged.clone(), module(&[(1, 1)]));
+
+        let mut new = BinaryCoverage::default();
+        new.modules
+            .insert(main.clone(), module(&[(1, 2), (2, 1), (3, 0)]));
+        new.modules.insert(lib.clone(), module(&[(7, 1), (8, 0)]));
+        new.modules.insert(unchanged, module(&[(1, 5)]));
+
+        let diff = old.diff(&new);
+
+        let mut expected = BinaryCoverageDiff::default();
+        expected.modules.insert(
+            main,
+            ModuleBinaryCoverageDiff {
+                added: [Offset(2)].into(),
+                lost: [Offset(3)].into(),
+            },
+        );
+        expected.modules.insert(
+            lib,
+            ModuleBinaryCoverageDiff {
+                added: [Offset(7)].into(),
+                lost: BTreeSet::new(),
+            },
+        );
+        assert_eq!(diff, expected);
+        assert_eq!(diff.added(), 2);
+        assert_eq!(diff.lost(), 1);
+
+        assert!(new.diff(&new).is_empty());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_source_diff() -> Result<()> {
+        let fuzz = FilePath::new("/src/fuzz.c")?;
+        let lib = FilePath::new("/src/lib.c")?;
+
+        let mut old = SourceCoverage::default();
+        old.files.insert(fuzz.clone(), file(&[(1, 1), (2, 0)])?);
+        old.files.insert(lib.clone(), file(&[(5, 3)])?);
+
+        let mut new = SourceCoverage::default();
+        new.files.insert(fuzz.clone(), file(&[(1, 1), (2, 1)])?);
+
+        let diff = old.diff(&new);
+
+        let mut expected = SourceCoverageDiff::default();
+        expected.files.insert(
+            fuzz.clone(),
+            FileCoverageDiff {
+                added: [Line::new(2)?].into(),
+                lost: BTreeSet::new(),
+            },
+        );
+        expected.files.insert(
+            lib.clone(),
+            FileCoverageDiff {
+                added: BTreeSet::new(),
+                lost: [Line::new(5)?].into(),
+            },
+        );
+        assert_eq!(diff, expected);
+
+        let mut expected = SourceCoverage::default();
+        expected.files.insert(fuzz, file(&[(2, 1)])?);
+        expected.files.insert(lib, file(&[(5, 0)])?);
+        assert_eq!(diff.to_source_coverage(), expected);
+
+        Ok(())
+    }
+}
@@ -0,0 +1,221 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT License.
+
+use std::io::{self, Write};
+
+use debuggable_module::path::FilePath;
+
+use crate::source::{FileCoverage, Line, SourceCoverage};
+
+const STYLE: &str = "\
+body { font-family: sans-serif; margin: 2em; }
+table { border-collapse: collapse; }
+th, td { padding: 0 0.5em; text-align: left; }
+table.summary td.number { text-align: right; }
+table.source { font-family: monospace; white-space: pre; }
+table.source td.line, table.source td.count { color: #666; text-align: right; }
+tr.hit { background-color: #dfd; }
+tr.miss { background-color: #fdd; }
+";
+
+/// Write source coverage as a single, self-contained HTML page.
+///
+/// The page contains a per-file summary, followed by the source of each file
+/// annotated with line hit counts.  `load_source` is used to read the text of
+/// each file; files it cannot load are shown as a list of instrumented lines.
+pub fn write_html(
+    source: &SourceCoverage,
+    mut load_source: impl FnMut(&FilePath) -> Option<String>,
+    mut writer: impl Write,
+) -> io::Result<()> {
+    writeln!(writer, "<!DOCTYPE html>")?;
+    writeln!(writer, "<html>")?;
+    writeln!(writer, "<head>")?;
+    writeln!(writer, "<meta charset=\"utf-8\">")?;
+    writeln!(writer, "<title>Source coverage</title>")?;
+    writeln!(writer, "<style>\n{STYLE}</style>")?;
+    writeln!(writer, "</head>")?;
+    writeln!(writer, "<body>")?;
+    writeln!(writer, "<h1>Source coverage</h1>")?;
+
+    writeln!(writer, "<table class=\"summary\">")?;
+    writeln!(
+        writer,
+        "<tr><th>File</th><th>Lines hit</th><th>Lines</th><th>Coverage</th></tr>"
+    )?;
+    let mut total = HitCounts::default();
+    for (index, (file_path, file)) in source.files.iter().enumerate() {
+        let counts = HitCounts::from(file);
+        total.hit += counts.hit;
+        total.lines += counts.lines;
+        writeln!(
+            writer,
+            "<tr><td><a href=\"#file-{index}\">{}</a></td>{}</tr>",
+            escape(file_path.as_str()),
+            counts.cells(),
+        )?;
+    }
+    writeln!(writer, "<tr><th>Total</th>{}</tr>", total.cells())?;
+    writeln!(writer, "</table>")?;
+
+    for (index, (file_path, file)) in source.files.iter().enumerate() {
+        writeln!(
+            writer,
+            "<h2 id=\"file-{index}\">{}</h2>",
+            escape(file_path.as_str())
+        )?;
+
+        writeln!(writer, "<table class=\"source\">")?;
+        match load_source(file_path) {
+            Some(text) => {
+                for (number, text) in (1..).zip(text.lines()) {
+                    write_line(&mut writer, file, number, text)?;
+                }
+            }
+            None => {
+                writeln!(writer, "<tr><td colspan=\"3\">source unavailable</td></tr>")?;
+                for line in file.lines.keys() {
+                    write_line(&mut writer, file, line.number(), "")?;
+                }
+            }
+        }
+        writeln!(writer, "</table>")?;
+    }
+
+    writeln!(writer, "</body>")?;
+    writeln!(writer, "</html>")?;
+
+    Ok(())
+}
+
+fn write_line(
+    writer: &mut impl Write,
+    file: &FileCoverage,
+    number: u32,
+    text: &str,
+) -> io::Result<()> {
+    let count = Line::new(number)
+        .ok()
+        .and_then(|line| file.lines.get(&line));
+
+    let (class, count) = match count {
+        Some(count) if count.reached() => (" class=\"hit\"", count.0.to_string()),
+        Some(count) => (" class=\"miss\"", count.0.to_string()),
+        None => ("", String::new()),
+    };
+
+    writeln!(
+        writer,
+        "<tr{class}><td class=\"line\">{number}</td><td class=\"count\">{count}</td><td>{}</td></tr>",
+        escape(text)
+    )
+}
+
+#[derive(Default)]
+struct HitCounts {
+    hit: usize,
+    lines: usize,
+}
+
+impl From<&FileCoverage> for HitCounts {
+    fn from(file: &FileCoverage) -> Self {
+        Self {
+            hit: file.lines.values().filter(|count| count.reached()).count(),
+            lines: file.lines.len(),
+        }
+    }
+}
+
+impl HitCounts {
+    fn cells(&self) -> String {
+        let rate = if self.lines == 0 {
+            0.0
+        } else {
+            100.0 * self.hit as f64 / self.lines as f64
+        };
+
+        format!(
+            "<td class=\"number\">{}</td><td class=\"number\">{}</td><td class=\"number\">{rate:.2}%</td>",
+            self.hit, self.lines
+        )
+    }
+}
+
+fn escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&#39;"),
+            _ => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+#[cfg(test)]
+mod tests {
+    use anyhow::Result;
+
+    use super::*;
+    use crate::source::Count;
+
+    fn coverage() -> Result<SourceCoverage> {
+        let mut source = SourceCoverage::default();
+
+        let mut file = FileCoverage::default();
+        file.lines.insert(Line::new(2)?, Count(5));
+        file.lines.insert(Line::new(3)?, Count(0));
+        source.files.insert(FilePath::new("/src/fuzz.c")?, file);
+
+        let mut file = FileCoverage::default();
+        file.lines.insert(Line::new(1)?, Count(1));
+        source.files.insert(FilePath::new("/src/missing.h")?, file);
+
+        Ok(source)
+    }
+
+    #[test]
+    fn test_write_html() -> Result<()> {
+        let source = coverage()?;
+
+        let mut html = vec![];
+        write_html(
+            &source,
+            |path| {
+                (path.as_str() == "/src/fuzz.c")
+                    .then(|| "int x;\nif (a < b)\n  abort();\n".to_string())
+            },
+            &mut html,
+        )?;
+        let html = String::from_utf8(html)?;
+
+        assert!(html.contains("<a href=\"#file-0\">/src/fuzz.c</a>"));
+        assert!(html.contains("<h2 id=\"file-1\">/src/missing.h</h2>"));
+        assert!(html.contains("<th>Total</th><td class=\"number\">2</td><td class=\"number\">3</td><td class=\"number\">66.67%</td>"));
+
+        assert!(html.contains(
+            "<tr><td class=\"line\">1</td><td class=\"count\"></td><td>int x;</td></tr>"
+        ));
+        assert!(html.contains("<tr class=\"hit\"><td class=\"line\">2</td><td class=\"count\">5</td><td>if (a &lt; b)</td></tr>"));
+        assert!(html.contains("<tr class=\"miss\"><td class=\"line\">3</td><td class=\"count\">0</td><td>  abort();</td></tr>"));
+
+        assert!(html.contains("source unavailable"));
+        assert!(html.contains(
+            "<tr class=\"hit\"><td class=\"line\">1</td><td class=\"count\">1</td><td></td></tr>"
+        ));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_escape() {
+        assert_eq!(
+            escape("<a href='x'>&\"</a>"),
+            "&lt;a href=&#39;x&#39;&gt;&amp;&quot;&lt;/a&gt;"
+        );
+    }
+}
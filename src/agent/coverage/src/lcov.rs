@@ -0,0 +1,77 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT License.
+
+use std::io::{self, Write};
+
+use crate::source::SourceCoverage;
+
+/// Write source coverage as an LCOV tracefile, as consumed by `genhtml` and
+/// most editor coverage extensions.
+///
+/// Only line coverage is recorded, so each file's record contains `DA` entries
+/// and line totals, but no function or branch entries.
+pub fn write_lcov(source: &SourceCoverage, mut writer: impl Write) -> io::Result<()> {
+    for (file_path, file) in &source.files {
+        writeln!(writer, "TN:")?;
+        writeln!(writer, "SF:{file_path}")?;
+
+        for (line, count) in &file.lines {
+            writeln!(writer, "DA:{},{}", line.number(), count.0)?;
+        }
+
+        let hit = file.lines.values().filter(|count| count.reached()).count();
+        writeln!(writer, "LH:{hit}")?;
+        writeln!(writer, "LF:{}", file.lines.len())?;
+        writeln!(writer, "end_of_record")?;
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use anyhow::Result;
+    use debuggable_module::path::FilePath;
+    use pretty_assertions::assert_eq;
+
+    use super::*;
+    use crate::source::{Count, FileCoverage, Line};
+
+    #[test]
+    fn test_write_lcov() -> Result<()> {
+        let mut source = SourceCoverage::default();
+
+        let mut file = FileCoverage::default();
+        file.lines.insert(Line::new(3)?, Count(1));
+        file.lines.insert(Line::new(4)?, Count(0));
+        file.lines.insert(Line::new(7)?, Count(12));
+        source.files.insert(FilePath::new("/src/fuzz.c")?, file);
+
+        let mut file = FileCoverage::default();
+        file.lines.insert(Line::new(1)?, Count(0));
+        source.files.insert(FilePath::new("/src/lib.h")?, file);
+
+        let mut lcov = vec![];
+        write_lcov(&source, &mut lcov)?;
+
+        let expected = "\
+TN:
+SF:/src/fuzz.c
+DA:3,1
+DA:4,0
+DA:7,12
+LH:2
+LF:3
+end_of_record
+TN:
+SF:/src/lib.h
+DA:1,0
+LH:0
+LF:1
+end_of_record
+";
+        assert_eq!(expected, String::from_utf8(lcov)?);
+
+        Ok(())
+    }
+}
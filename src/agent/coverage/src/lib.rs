@@ -7,6 +7,10 @@ extern crate log;
 pub mod allowlist;
 pub mod binary;
 pub mod cobertura;
+pub mod corpus;
+pub mod diff;
+pub mod html;
+pub mod lcov;
 pub mod record;
 pub mod source;
 mod timer;
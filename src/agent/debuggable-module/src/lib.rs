@@ -15,6 +15,7 @@ pub mod linux;
 pub mod load_module;
 pub mod loader;
 pub mod path;
+pub mod symbolize;
 pub mod windows;
 
 use crate::debuginfo::DebugInfo;
@@ -0,0 +1,76 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT License.
+
+use anyhow::Result;
+use symbolic::debuginfo::Object;
+use symbolic::demangle::{Demangle, DemangleOptions};
+use symbolic::symcache::{SymCache, SymCacheConverter};
+
+use crate::{Module, Offset};
+
+/// Source location of an instruction, as described by the debuginfo of its
+/// module.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct SymbolizedFrame {
+    /// Demangled name of the function containing the instruction.
+    pub function: String,
+
+    /// Full path of the source file, if known.
+    pub file: Option<String>,
+
+    /// Source line number, if known.
+    pub line: Option<u32>,
+}
+
+/// Symbolizes module-relative offsets after the fact, using the debuginfo of
+/// the module instead of an external symbolizer.
+pub struct Symbolizer {
+    symcache: Vec<u8>,
+}
+
+impl Symbolizer {
+    pub fn new(module: &dyn Module) -> Result<Self> {
+        let mut symcache = vec![];
+        let mut converter = SymCacheConverter::new();
+
+        let exe = Object::parse(module.executable_data())?;
+        converter.process_object(&exe)?;
+
+        let di = Object::parse(module.debuginfo_data())?;
+        converter.process_object(&di)?;
+
+        converter.serialize(&mut std::io::Cursor::new(&mut symcache))?;
+
+        Ok(Self { symcache })
+    }
+
+    /// Symbolize the instruction at the module-relative `offset`.
+    ///
+    /// Returns one frame for each function inlined at the offset, innermost
+    /// first, followed by the function they were inlined into. The result is
+    /// empty if the offset is not covered by the debuginfo.
+    pub fn symbolize(&self, offset: Offset) -> Result<Vec<SymbolizedFrame>> {
+        let symcache = SymCache::parse(&self.symcache)?;
+        let opts = DemangleOptions::complete();
+
+        let frames = symcache
+            .lookup(offset.0)
+            .map(|location| {
+                let function = location
+                    .function()
+                    .name_for_demangling()
+                    .try_demangle(opts)
+                    .into_owned();
+
+                SymbolizedFrame {
+                    function,
+                    file: location.file().map(|file| file.full_path()),
+                    // line numbers are 0 when unknown
+                    line: Some(location.line()).filter(|line| *line != 0),
+                }
+            })
+            .collect();
+
+        Ok(frames)
+    }
+}
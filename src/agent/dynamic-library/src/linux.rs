@@ -14,7 +14,19 @@ use regex::Regex;
 const LD_LIBRARY_PATH: &str = "LD_LIBRARY_PATH";
 
 pub fn find_missing(
+    cmd: Command,
+) -> Result<(HashSet<MissingDynamicLibrary>, Vec<String>), io::Error> {
+    find_missing_with_launcher(cmd, Ok)
+}
+
+/// Find the missing libraries of `cmd`, when run by a launcher such as a container
+/// runtime, which may not have the same libraries as the host.
+///
+/// `launch` wraps a command into one which runs it as `cmd` will be run, both for
+/// `cmd` itself and for the `ldd` command which checks its linked libraries.
+pub fn find_missing_with_launcher(
     mut cmd: Command,
+    launch: impl Fn(Command) -> Result<Command, io::Error>,
 ) -> Result<(HashSet<MissingDynamicLibrary>, Vec<String>), io::Error> {
     // Check for missing _linked_ dynamic libraries.
     //
@@ -22,7 +34,9 @@ pub fn find_missing(
     // output. The debug output gets truncated when a linked shared library is not found,
     // since any in-progress searches are aborted.
     let library_path = explicit_library_path(&cmd);
-    let linked = LinkedDynamicLibraries::search(cmd.get_program(), library_path)?;
+    let ldd = LinkedDynamicLibraries::command(cmd.get_program(), library_path);
+    let output = launch(ldd)?.output()?;
+    let linked = LinkedDynamicLibraries::parse(&*output.stdout);
     let missing_linked = linked.not_found();
 
     if !missing_linked.is_empty() {
@@ -33,7 +47,7 @@ pub fn find_missing(
     //
     // Invoke the command with `LD_DEBUG` set, and parse the debug output.
     cmd.env("LD_DEBUG", "libs");
-    let output = cmd.output()?;
+    let output = launch(cmd)?.output()?;
     let logs = LdDebugLogs::parse(&*output.stderr);
 
     Ok((logs.missing(), Vec::new()))
@@ -277,6 +291,12 @@ impl LinkedDynamicLibraries {
         module: impl AsRef<OsStr>,
         library_path: Option<&OsStr>,
     ) -> Result<std::process::Output, io::Error> {
+        let output = Self::command(module, library_path).output()?;
+        Ok(output)
+    }
+
+    /// The `ldd` command listing the linked libraries of `module`.
+    pub fn command(module: impl AsRef<OsStr>, library_path: Option<&OsStr>) -> Command {
         let mut cmd = Command::new("ldd");
         cmd.arg(module);
         if let Some(library_path) = library_path {
@@ -284,8 +304,7 @@ impl LinkedDynamicLibraries {
         } else {
             cmd.env_remove(LD_LIBRARY_PATH);
         }
-        let output = cmd.output()?;
-        Ok(output)
+        cmd
     }
 
     pub fn parse<R: io::Read>(readable: R) -> Self {
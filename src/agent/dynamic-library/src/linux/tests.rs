@@ -81,3 +81,17 @@ fn test_ld_debug_logs_parse_none_missing() {
 
     assert!(missing.is_empty())
 }
+
+#[test]
+fn test_find_missing_with_launcher() {
+    let launched = std::cell::RefCell::new(vec![]);
+
+    let (missing, _) = find_missing_with_launcher(Command::new("true"), |cmd| {
+        launched.borrow_mut().push(cmd.get_program().to_owned());
+        Ok(cmd)
+    })
+    .unwrap();
+
+    assert!(missing.is_empty());
+    assert_eq!(&*launched.borrow(), &["ldd", "true"]);
+}
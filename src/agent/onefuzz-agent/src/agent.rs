@@ -5,16 +5,19 @@
 use std::time::Duration;
 
 use anyhow::{Error, Result};
+use onefuzz::process::ExitStatus;
 use tokio::time;
 
 use crate::coordinator::*;
 use crate::done::set_done_lock;
 use crate::heartbeat::{AgentHeartbeatClient, HeartbeatSender};
 use crate::reboot::*;
+use crate::resources::ResourcePool;
 use crate::scheduler::*;
 use crate::setup::*;
-use crate::work::IWorkQueue;
-use crate::worker::{IWorkerRunner, WorkerEvent};
+use crate::work::journal::{WorkJournal, WorkUnitState};
+use crate::work::{IWorkQueue, TaskId, WorkSet};
+use crate::worker::{IWorkerRunner, WorkerEvent, WorkerState};
 
 const PENDING_COMMANDS_DELAY: time::Duration = time::Duration::from_secs(10);
 const BUSY_DELAY: time::Duration = time::Duration::from_secs(1);
@@ -27,7 +30,14 @@ pub struct Agent {
     work_queue: Box<dyn IWorkQueue>,
     worker_runner: Box<dyn IWorkerRunner>,
     heartbeat: Option<AgentHeartbeatClient>,
+    journal: Option<WorkJournal>,
+    resources: ResourcePool,
+    // Whether other work sets are admitted while busy. The service only
+    // schedules work on free nodes, so this is only done in standalone mode.
+    concurrent: bool,
     previous_state: NodeState,
+    reported_work_units: Vec<WorkUnitStatus>,
+    last_admission: Option<time::Instant>,
     last_poll_command: Result<Option<NodeCommand>, PollCommandError>,
     managed: bool,
     machine_id: uuid::Uuid,
@@ -43,6 +53,9 @@ impl Agent {
         work_queue: Box<dyn IWorkQueue>,
         worker_runner: Box<dyn IWorkerRunner>,
         heartbeat: Option<AgentHeartbeatClient>,
+        journal: Option<WorkJournal>,
+        resources: ResourcePool,
+        concurrent: bool,
         managed: bool,
         machine_id: uuid::Uuid,
     ) -> Self {
@@ -58,7 +71,12 @@ impl Agent {
             work_queue,
             worker_runner,
             heartbeat,
+            journal,
+            resources,
+            concurrent,
             previous_state,
+            reported_work_units: vec![],
+            last_admission: None,
             last_poll_command,
             managed,
             machine_id,
@@ -117,7 +135,7 @@ impl Agent {
     async fn emit_state_update_if_changed(&self, event: StateUpdateEvent) -> Result<()> {
         match (&event, self.previous_state) {
             (StateUpdateEvent::Free, NodeState::Free)
-            | (StateUpdateEvent::Busy, NodeState::Busy)
+            | (StateUpdateEvent::Busy { .. }, NodeState::Busy)
             | (StateUpdateEvent::SettingUp { .. }, NodeState::SettingUp)
             | (StateUpdateEvent::Rebooting, NodeState::Rebooting)
             | (StateUpdateEvent::Ready, NodeState::Ready)
@@ -144,9 +162,17 @@ impl Agent {
             if can_schedule.allowed {
                 info!("claiming work set: {:?}", msg.work_set);
 
+                // Journal the work set before claiming it, so that it is not lost if the agent
+                // is interrupted once it is claimed. If it is instead interrupted before, the
+                // work set is resumed, and may then also run on another node.
+                let task_ids = task_ids(&msg.work_set);
+                self.journal_claimed(&msg.work_set).await;
+
                 match self.work_queue.claim(msg).await {
                     Err(err) => {
                         error!("unable to claim work set: {}", err);
+                        self.journal_transition(&task_ids, WorkUnitState::Done)
+                            .await;
 
                         // We were unable to claim the work set, so it will reappear in the pool's
                         // work queue when the visibility timeout expires. Don't execute the work,
@@ -226,13 +252,28 @@ impl Agent {
             })
             .collect();
 
+        let task_ids = task_ids(state.work_set());
         self.emit_state_update_if_changed(StateUpdateEvent::SettingUp { task_data: tasks })
             .await?;
+        self.journal_transition(&task_ids, WorkUnitState::SettingUp)
+            .await;
 
         let scheduler = match state.finish(self.setup_runner.as_mut()).await? {
-            SetupDone::Ready(s) => s.into(),
-            SetupDone::PendingReboot(s) => s.into(),
-            SetupDone::Done(s) => s.into(),
+            SetupDone::Ready(s) => {
+                self.journal_transition(&task_ids, WorkUnitState::Ready)
+                    .await;
+                s.into()
+            }
+            SetupDone::PendingReboot(s) => {
+                self.journal_transition(&task_ids, WorkUnitState::Ready)
+                    .await;
+                s.into()
+            }
+            SetupDone::Done(s) => {
+                self.journal_transition(&task_ids, WorkUnitState::Done)
+                    .await;
+                s.into()
+            }
         };
 
         Ok(Self {
@@ -258,21 +299,34 @@ impl Agent {
         unreachable!()
     }
 
-    async fn ready(self, state: State<Ready>, previous: NodeState) -> Result<Self> {
+    async fn ready(mut self, state: State<Ready>, previous: NodeState) -> Result<Self> {
         info!("agent ready");
         self.emit_state_update_if_changed(StateUpdateEvent::Ready)
             .await?;
+        let task_ids = task_ids(state.work_set());
+        self.journal_transition(&task_ids, WorkUnitState::Running)
+            .await;
+
+        // The node is idle until the work set runs, so it is always admitted.
+        let allocations = self.resources.admit(state.work_set()).unwrap_or_default();
+
         Ok(Self {
             previous_state: previous,
 
-            scheduler: Some(state.run(self.machine_id).await?.into()),
+            scheduler: Some(state.run(self.machine_id, allocations).await?.into()),
             ..self
         })
     }
 
     async fn busy(mut self, state: State<Busy>, previous: NodeState) -> Result<Self> {
-        self.emit_state_update_if_changed(StateUpdateEvent::Busy)
-            .await?;
+        let work_units = state.work_units();
+        if self.previous_state != NodeState::Busy || work_units != self.reported_work_units {
+            let event = StateUpdateEvent::Busy {
+                work_units: work_units.clone(),
+            };
+            self.coordinator.emit_event(event.into()).await?;
+            self.reported_work_units = work_units;
+        }
 
         // Without this sleep, the `Agent.run` loop turns into an extremely tight loop calling
         // `wait4` of the running agents.  This sleep adds a small window to allow the rest of the
@@ -288,9 +342,26 @@ impl Agent {
             .await?;
 
         for event in events {
+            if let WorkerEvent::Done { task_id, .. } = &event {
+                self.journal_transition(&[*task_id], WorkUnitState::Done)
+                    .await;
+                self.resources.release(*task_id);
+            }
             self.coordinator.emit_event(event.into()).await?;
         }
 
+        // Stopped workers are done without a `WorkerEvent`.
+        for unit in &self.reported_work_units {
+            if unit.state == WorkerState::Done {
+                self.resources.release(unit.task_id);
+            }
+        }
+
+        let updated = match updated {
+            Updated::Busy(state) if self.should_admit() => Updated::Busy(self.admit(state).await?),
+            updated => updated,
+        };
+
         Ok(Self {
             previous_state: previous,
             scheduler: Some(updated.into()),
@@ -298,6 +369,125 @@ impl Agent {
         })
     }
 
+    fn should_admit(&self) -> bool {
+        let polled_recently = self
+            .last_admission
+            .map(|last| last.elapsed() < self.sleep_duration)
+            .unwrap_or(false);
+
+        self.concurrent && self.resources.has_capacity() && !polled_recently
+    }
+
+    // Runs another work set alongside the busy one, if the node has the
+    // resources it needs. Work sets which are not admitted are given back to
+    // the work queue, for other nodes to take.
+    async fn admit(&mut self, state: State<Busy>) -> Result<State<Busy>> {
+        self.last_admission = Some(time::Instant::now());
+
+        let Some(msg) = self.work_queue.poll().await? else {
+            return Ok(state);
+        };
+
+        if msg.work_set.reboot {
+            // Leave it for a free node, as rebooting would stop the busy work set.
+            debug!("not admitting work set which requires a reboot");
+            self.work_queue.release(msg).await?;
+            return Ok(state);
+        }
+
+        let Some(allocations) = self.resources.admit(&msg.work_set) else {
+            debug!("not enough resources to admit work set");
+            self.work_queue.release(msg).await?;
+            return Ok(state);
+        };
+        let task_ids = task_ids(&msg.work_set);
+
+        let can_schedule = self.coordinator.can_schedule(&msg.work_set).await?;
+        if !can_schedule.allowed {
+            self.release(&task_ids);
+            warn!(
+                "unable to admit work set: {:?}, Reason {}",
+                msg.work_set,
+                can_schedule.reason.unwrap_or_default()
+            );
+            if can_schedule.work_stopped {
+                match self.work_queue.claim(msg).await {
+                    Err(err) => error!("unable to drop stopped work: {}", err),
+                    Ok(work_set) => info!("dropped stopped work set: {:?}", work_set),
+                }
+            } else {
+                self.work_queue.release(msg).await?;
+            }
+            return Ok(state);
+        }
+
+        // Journaled before it is claimed, as in `free()`.
+        if let Some(journal) = &self.journal {
+            if let Err(err) = journal.admitted(&msg.work_set).await {
+                warn!("unable to journal admitted work set: {:?}", err);
+            }
+        }
+
+        let work_set = match self.work_queue.claim(msg).await {
+            Ok(work_set) => work_set,
+            Err(err) => {
+                error!("unable to claim work set: {}", err);
+                self.release(&task_ids);
+                self.journal_transition(&task_ids, WorkUnitState::Done)
+                    .await;
+                return Ok(state);
+            }
+        };
+        info!("admitted work set: {:?}", work_set);
+
+        self.journal_transition(&task_ids, WorkUnitState::SettingUp)
+            .await;
+        let failure = match self.setup_runner.run(&work_set).await {
+            Ok(Some(output)) if !output.exit_status.success => Some((
+                output.exit_status,
+                format!("error running target setup script: {}", output.stderr),
+            )),
+            Ok(_) => None,
+            Err(err) => {
+                let exit_status = ExitStatus {
+                    code: Some(1),
+                    signal: None,
+                    success: false,
+                };
+                Some((exit_status, format!("{err:?}")))
+            }
+        };
+
+        // Only the units of the admitted work set fail, not the busy ones.
+        if let Some((exit_status, error)) = failure {
+            warn!("unable to set up admitted work set: {}", error);
+            for unit in &work_set.work_units {
+                let event = WorkerEvent::Done {
+                    job_id: unit.job_id,
+                    task_id: unit.task_id,
+                    exit_status,
+                    stderr: error.clone(),
+                    stdout: String::default(),
+                };
+                self.coordinator.emit_event(event.into()).await?;
+            }
+            self.release(&task_ids);
+            self.journal_transition(&task_ids, WorkUnitState::Done)
+                .await;
+            return Ok(state);
+        }
+
+        self.journal_transition(&task_ids, WorkUnitState::Running)
+            .await;
+        state.admit(work_set, self.machine_id, allocations)
+    }
+
+    fn release(&mut self, task_ids: &[TaskId]) {
+        for task_id in task_ids {
+            self.resources.release(*task_id);
+        }
+    }
+
     async fn done(self, state: State<Done>, previous: NodeState) -> Result<Self> {
         info!("agent done");
         set_done_lock(self.machine_id).await?;
@@ -381,6 +571,34 @@ impl Agent {
     async fn sleep(&self) {
         time::sleep(self.sleep_duration).await;
     }
+
+    // Failing to journal work only affects its recovery after a restart, so it
+    // does not stop the agent.
+    async fn journal_claimed(&self, work_set: &WorkSet) {
+        if let Some(journal) = &self.journal {
+            if let Err(err) = journal.claimed(work_set).await {
+                warn!("unable to journal claimed work set: {:?}", err);
+            }
+        }
+    }
+
+    async fn journal_transition(&self, task_ids: &[TaskId], state: WorkUnitState) {
+        if let Some(journal) = &self.journal {
+            for task_id in task_ids {
+                if let Err(err) = journal.record(*task_id, state).await {
+                    warn!("unable to journal work unit {}: {:?}", task_id, err);
+                }
+            }
+        }
+    }
+}
+
+fn task_ids(work_set: &WorkSet) -> Vec<TaskId> {
+    work_set
+        .work_units
+        .iter()
+        .map(|unit| unit.task_id)
+        .collect()
 }
 
 // The agent owns a `Scheduler`, which it must consume when driving its state
@@ -10,7 +10,7 @@ use crate::setup::double::*;
 use crate::work::double::*;
 use crate::work::*;
 use crate::worker::double::*;
-use crate::worker::WorkerEvent;
+use crate::worker::{WorkerEvent, WorkerState};
 use onefuzz::process::ExitStatus;
 
 use super::*;
@@ -34,6 +34,9 @@ impl Fixture {
             work_queue,
             worker_runner,
             None,
+            None,
+            ResourcePool::new(1, 0),
+            false,
             true,
             Uuid::new_v4(),
         )
@@ -79,6 +82,7 @@ impl Fixture {
             task_id: self.task_id(),
             config,
             env: std::collections::HashMap::new(),
+            resources: Default::default(),
         }
     }
 }
@@ -125,6 +129,56 @@ async fn test_update_free_has_work() {
     assert_eq!(claimed_worksets, &[Fixture.work_set()]);
 }
 
+#[tokio::test]
+async fn test_update_free_journals_claimed_work() -> Result<()> {
+    let dir = tempfile::tempdir()?;
+    let journal = WorkJournal::new(dir.path().join("journal.jsonl"));
+
+    let mut agent = Fixture.agent();
+    agent.journal = Some(journal.clone());
+    agent
+        .work_queue
+        .downcast_mut::<WorkQueueDouble>()
+        .unwrap()
+        .available
+        .push(Fixture.message());
+
+    let (_, done) = agent.update().await?;
+    assert!(!done);
+
+    let journaled = journal
+        .load()
+        .await?
+        .ok_or_else(|| anyhow!("claimed work set not journaled"))?;
+    assert_eq!(journaled.work_set, Fixture.work_set());
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_update_free_claim_failure() -> Result<()> {
+    let dir = tempfile::tempdir()?;
+    let journal = WorkJournal::new(dir.path().join("journal.jsonl"));
+
+    let mut agent = Fixture.agent();
+    agent.journal = Some(journal.clone());
+    let double = agent.work_queue.downcast_mut::<WorkQueueDouble>().unwrap();
+    double.fail_claim = true;
+    double.available.push(Fixture.message());
+
+    let (agent, done) = agent.update().await?;
+    assert!(!done);
+    assert!(matches!(agent.scheduler.unwrap(), Scheduler::Free(..)));
+
+    // left for another node to claim, so not resumed by this one
+    let double: &WorkQueueDouble = agent.work_queue.downcast_ref().unwrap();
+    assert!(double.claimed.is_empty());
+    assert_eq!(double.available.len(), 1);
+    assert!(journal.load().await?.is_none());
+
+    Ok(())
+}
+
 #[tokio::test]
 async fn test_emitted_state() {
     let mut agent = Agent {
@@ -165,11 +219,24 @@ async fn test_emitted_state() {
             }],
         }),
         NodeEvent::StateUpdate(StateUpdateEvent::Ready),
-        NodeEvent::StateUpdate(StateUpdateEvent::Busy),
+        NodeEvent::StateUpdate(StateUpdateEvent::Busy {
+            work_units: vec![WorkUnitStatus {
+                job_id: Fixture.job_id(),
+                task_id: Fixture.task_id(),
+                state: WorkerState::Ready,
+            }],
+        }),
         NodeEvent::WorkerEvent(WorkerEvent::Running {
             job_id: Fixture.job_id(),
             task_id: Fixture.task_id(),
         }),
+        NodeEvent::StateUpdate(StateUpdateEvent::Busy {
+            work_units: vec![WorkUnitStatus {
+                job_id: Fixture.job_id(),
+                task_id: Fixture.task_id(),
+                state: WorkerState::Running,
+            }],
+        }),
         NodeEvent::WorkerEvent(WorkerEvent::Done {
             job_id: Fixture.job_id(),
             task_id: Fixture.task_id(),
@@ -243,3 +310,132 @@ async fn test_emitted_state_failed_setup() {
         .await
         .unwrap();
 }
+
+#[tokio::test]
+async fn test_busy_admits_partitioned_work_set() {
+    let mut agent = Agent {
+        resources: ResourcePool::new(2, 0),
+        concurrent: true,
+        ..Fixture.agent()
+    };
+
+    let mut first = Fixture.work_set();
+    first.work_units[0].resources.cores = Some(1);
+
+    let mut second = Fixture.work_set();
+    second.work_units[0].task_id = Uuid::new_v4();
+    second.work_units[0].resources.cores = Some(1);
+
+    let double = agent.work_queue.downcast_mut::<WorkQueueDouble>().unwrap();
+    for work_set in [&second, &first] {
+        double.available.push(Message {
+            work_set: work_set.clone(),
+            queue_message: None,
+        });
+    }
+
+    // Free, SettingUp, Ready, then Busy with the first work set, during which
+    // the second work set is admitted.
+    for _i in 0..5 {
+        let done;
+        (agent, done) = agent.update().await.unwrap();
+        assert!(!done);
+    }
+
+    assert!(matches!(agent.scheduler, Some(Scheduler::Busy(..))));
+    assert!(!agent.resources.has_capacity());
+
+    let double: &WorkQueueDouble = agent.work_queue.downcast_ref().unwrap();
+    let claimed_worksets = double
+        .claimed
+        .iter()
+        .map(|cl| cl.work_set.clone())
+        .collect::<Vec<WorkSet>>();
+    assert_eq!(claimed_worksets, &[first, second.clone()]);
+
+    let coordinator: &CoordinatorDouble = agent.coordinator.downcast_ref().unwrap();
+    let events = coordinator.events.read().await;
+    let expected_event = NodeEvent::StateUpdate(StateUpdateEvent::Busy {
+        work_units: vec![
+            WorkUnitStatus {
+                job_id: Fixture.job_id(),
+                task_id: Fixture.task_id(),
+                state: WorkerState::Running,
+            },
+            WorkUnitStatus {
+                job_id: Fixture.job_id(),
+                task_id: second.work_units[0].task_id,
+                state: WorkerState::Ready,
+            },
+        ],
+    });
+    assert!(events.contains(&expected_event));
+}
+
+// Runs `agent` until it is busy with `first`, with `second` queued after it.
+async fn run_until_busy(mut agent: Agent, first: &WorkSet, second: &WorkSet) -> Agent {
+    let double = agent.work_queue.downcast_mut::<WorkQueueDouble>().unwrap();
+    for work_set in [second, first] {
+        double.available.push(Message {
+            work_set: work_set.clone(),
+            queue_message: None,
+        });
+    }
+
+    for _i in 0..5 {
+        let done;
+        (agent, done) = agent.update().await.unwrap();
+        assert!(!done);
+    }
+    assert!(matches!(agent.scheduler, Some(Scheduler::Busy(..))));
+
+    agent
+}
+
+#[tokio::test]
+async fn test_busy_releases_work_set_it_cannot_admit() {
+    let agent = Agent {
+        resources: ResourcePool::new(2, 0),
+        concurrent: true,
+        ..Fixture.agent()
+    };
+
+    let mut first = Fixture.work_set();
+    first.work_units[0].resources.cores = Some(1);
+
+    let mut second = Fixture.work_set();
+    second.work_units[0].task_id = Uuid::new_v4();
+    second.work_units[0].resources.cores = Some(2);
+
+    let agent = run_until_busy(agent, &first, &second).await;
+
+    // Given back to the queue, rather than dropped.
+    let double: &WorkQueueDouble = agent.work_queue.downcast_ref().unwrap();
+    let claimed: Vec<_> = double.claimed.iter().map(|m| &m.work_set).collect();
+    let available: Vec<_> = double.available.iter().map(|m| &m.work_set).collect();
+    assert_eq!(claimed, [&first]);
+    assert_eq!(available, [&second]);
+}
+
+#[tokio::test]
+async fn test_busy_does_not_admit_without_concurrency() {
+    let agent = Agent {
+        resources: ResourcePool::new(2, 0),
+        ..Fixture.agent()
+    };
+
+    let mut first = Fixture.work_set();
+    first.work_units[0].resources.cores = Some(1);
+
+    let mut second = Fixture.work_set();
+    second.work_units[0].task_id = Uuid::new_v4();
+    second.work_units[0].resources.cores = Some(1);
+
+    let agent = run_until_busy(agent, &first, &second).await;
+
+    let double: &WorkQueueDouble = agent.work_queue.downcast_ref().unwrap();
+    let claimed: Vec<_> = double.claimed.iter().map(|m| &m.work_set).collect();
+    let available: Vec<_> = double.available.iter().map(|m| &m.work_set).collect();
+    assert_eq!(claimed, [&first]);
+    assert_eq!(available, [&second]);
+}
@@ -11,6 +11,7 @@ use onefuzz::{
 use onefuzz_telemetry::{InstanceTelemetryKey, MicrosoftTelemetryKey};
 use reqwest_retry::SendRetry;
 use std::{
+    net::SocketAddr,
     path::{Path, PathBuf},
     time::{Duration, Instant},
 };
@@ -20,11 +21,13 @@ use uuid::Uuid;
 
 #[derive(Clone, Debug, Deserialize, Eq, PartialEq)]
 pub struct StaticConfig {
-    pub credentials: Credentials,
+    /// Unset without `onefuzz_url`, as in standalone mode.
+    pub credentials: Option<Credentials>,
 
     pub pool_name: String,
 
-    pub onefuzz_url: Url,
+    /// Only optional in standalone mode, where the service is not contacted.
+    pub onefuzz_url: Option<Url>,
 
     pub multi_tenant_domain: Option<String>,
 
@@ -36,12 +39,16 @@ pub struct StaticConfig {
 
     pub job_result_queue: Option<Url>,
 
-    pub instance_id: Uuid,
+    pub instance_id: Option<Uuid>,
 
     #[serde(default = "default_as_true")]
     pub managed: bool,
 
     pub machine_identity: MachineIdentity,
+
+    /// If set, serve Prometheus metrics for the agent and its tasks here.
+    #[serde(default)]
+    pub metrics_address: Option<SocketAddr>,
 }
 
 fn default_as_true() -> bool {
@@ -63,7 +70,8 @@ struct RawStaticConfig {
 
     pub pool_name: String,
 
-    pub onefuzz_url: Url,
+    #[serde(default)]
+    pub onefuzz_url: Option<Url>,
 
     pub multi_tenant_domain: Option<String>,
 
@@ -75,37 +83,40 @@ struct RawStaticConfig {
 
     pub job_result_queue: Option<Url>,
 
-    pub instance_id: Uuid,
+    #[serde(default)]
+    pub instance_id: Option<Uuid>,
 
     #[serde(default = "default_as_true")]
     pub managed: bool,
 
     pub machine_identity: Option<MachineIdentity>,
+
+    #[serde(default)]
+    pub metrics_address: Option<SocketAddr>,
 }
 
 impl StaticConfig {
     pub async fn new(data: &[u8], machine_identity: Option<MachineIdentity>) -> Result<Self> {
         let config: RawStaticConfig = serde_json::from_slice(data)?;
 
-        let credentials = match config.client_credentials {
-            Some(client) => ClientCredentials::new(
-                client.client_id,
-                client.client_secret,
-                config.onefuzz_url.to_string(),
-                client.tenant,
-                client.multi_tenant_domain,
-            )
-            .into(),
-            None => {
+        let credentials = match (&config.onefuzz_url, config.client_credentials) {
+            (None, _) => None,
+            (Some(onefuzz_url), Some(client)) => Some(
+                ClientCredentials::new(
+                    client.client_id,
+                    client.client_secret,
+                    onefuzz_url.to_string(),
+                    client.tenant,
+                    client.multi_tenant_domain,
+                )
+                .into(),
+            ),
+            (Some(onefuzz_url), None) => {
                 // Remove trailing `/`, which is treated as a distinct resource.
-                let resource = config
-                    .onefuzz_url
-                    .to_string()
-                    .trim_end_matches('/')
-                    .to_owned();
+                let resource = onefuzz_url.to_string().trim_end_matches('/').to_owned();
                 let managed =
                     ManagedIdentityCredentials::new(resource, config.multi_tenant_domain.clone())?;
-                managed.into()
+                Some(managed.into())
             }
         };
         let machine_identity = match machine_identity.or(config.machine_identity) {
@@ -125,6 +136,7 @@ impl StaticConfig {
             instance_id: config.instance_id,
             managed: config.managed,
             machine_identity,
+            metrics_address: config.metrics_address,
         };
 
         Ok(config)
@@ -163,6 +175,12 @@ impl StaticConfig {
             None
         };
 
+        let metrics_address = if let Ok(addr) = std::env::var("ONEFUZZ_AGENT_METRICS_ADDR") {
+            Some(addr.parse()?)
+        } else {
+            None
+        };
+
         let instance_telemetry_key =
             if let Ok(key) = std::env::var("ONEFUZZ_INSTANCE_TELEMETRY_KEY") {
                 Some(InstanceTelemetryKey::new(Uuid::parse_str(&key)?))
@@ -187,24 +205,38 @@ impl StaticConfig {
         .into();
 
         Ok(Self {
-            credentials,
+            credentials: Some(credentials),
             pool_name,
-            onefuzz_url,
+            onefuzz_url: Some(onefuzz_url),
             multi_tenant_domain,
             instance_telemetry_key,
             microsoft_telemetry_key,
             heartbeat_queue,
             job_result_queue,
-            instance_id,
+            instance_id: Some(instance_id),
             managed: !is_unmanaged,
             machine_identity,
+            metrics_address,
         })
     }
 
-    fn register_url(&self) -> Url {
-        let mut url = self.onefuzz_url.clone();
+    /// URL of the service, which is required unless in standalone mode.
+    pub fn onefuzz_url(&self) -> Result<&Url> {
+        self.onefuzz_url
+            .as_ref()
+            .ok_or_else(|| anyhow!("onefuzz_url is required unless in standalone mode"))
+    }
+
+    pub fn credentials(&self) -> Result<&Credentials> {
+        self.credentials
+            .as_ref()
+            .ok_or_else(|| anyhow!("onefuzz_url is required unless in standalone mode"))
+    }
+
+    fn register_url(&self) -> Result<Url> {
+        let mut url = self.onefuzz_url()?.clone();
         url.set_path("/api/agents/registration");
-        url
+        Ok(url)
     }
 }
 
@@ -264,11 +296,11 @@ const REGISTRATION_RETRY_PERIOD: Duration = Duration::from_secs(60);
 
 impl Registration {
     pub async fn create(config: StaticConfig, managed: bool, timeout: Duration) -> Result<Self> {
-        let token = config.credentials.access_token().await?;
+        let token = config.credentials()?.access_token().await?;
         let machine_name = &config.machine_identity.machine_name;
         let machine_id = config.machine_identity.machine_id;
 
-        let mut url = config.register_url();
+        let mut url = config.register_url()?;
         url.query_pairs_mut()
             .append_pair("machine_id", &machine_id.to_string())
             .append_pair("machine_name", machine_name)
@@ -349,11 +381,11 @@ impl Registration {
 
     pub async fn renew(&self) -> Result<Self> {
         info!("renewing registration");
-        let token = self.config.credentials.access_token().await?;
+        let token = self.config.credentials()?.access_token().await?;
 
         let machine_id = self.machine_id.to_string();
 
-        let mut url = self.config.register_url();
+        let mut url = self.config.register_url()?;
         url.query_pairs_mut().append_pair("machine_id", &machine_id);
 
         let response = reqwest::Client::new()
@@ -16,8 +16,8 @@ use uuid::Uuid;
 
 use crate::commands::SshKeyInfo;
 use crate::config::Registration;
-use crate::work::{TaskId, WorkSet};
-use crate::worker::WorkerEvent;
+use crate::work::{JobId, TaskId, WorkSet};
+use crate::worker::{WorkerEvent, WorkerState};
 
 #[derive(Debug, Deserialize, Eq, PartialEq, Serialize, Clone)]
 pub struct StopTask {
@@ -93,6 +93,15 @@ pub struct SettingUpData {
     pub job_id: Uuid,
 }
 
+/// State of a work unit run by a busy node.
+#[derive(Clone, Debug, Deserialize, Eq, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub struct WorkUnitStatus {
+    pub job_id: JobId,
+    pub task_id: TaskId,
+    pub state: WorkerState,
+}
+
 #[derive(Clone, Debug, Deserialize, Eq, PartialEq, Serialize)]
 #[serde(rename_all = "snake_case", tag = "state", content = "data")]
 pub enum StateUpdateEvent {
@@ -103,7 +112,9 @@ pub enum StateUpdateEvent {
     },
     Rebooting,
     Ready,
-    Busy,
+    Busy {
+        work_units: Vec<WorkUnitStatus>,
+    },
     Done {
         error: Option<String>,
         script_output: Option<Output>,
@@ -201,7 +212,7 @@ pub struct Coordinator {
 impl Coordinator {
     pub async fn new(registration: Registration) -> Result<Self> {
         let client = Client::new();
-        let token = registration.config.credentials.access_token().await?;
+        let token = registration.config.credentials()?.access_token().await?;
 
         Ok(Self {
             client,
@@ -282,7 +293,7 @@ impl Coordinator {
 
         debug!("checking if able to schedule task ID = {}", task_id);
 
-        let mut url = self.registration.config.onefuzz_url.clone();
+        let mut url = self.registration.config.onefuzz_url()?.clone();
         url.set_path("/api/agents/can_schedule");
         let request = self.client.post(url).json(&envelope);
 
@@ -303,7 +314,12 @@ impl Coordinator {
 
     async fn refresh_token(&self) -> Result<AccessToken> {
         let mut token = self.token.write().await;
-        *token = self.registration.config.credentials.access_token().await?;
+        *token = self
+            .registration
+            .config
+            .credentials()?
+            .access_token()
+            .await?;
         Ok(token.clone())
     }
 
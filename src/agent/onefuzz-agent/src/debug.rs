@@ -73,7 +73,13 @@ fn debug_node_event_state_update(state: NodeState) -> Result<()> {
         }
         NodeState::Rebooting => StateUpdateEvent::Rebooting,
         NodeState::Ready => StateUpdateEvent::Ready,
-        NodeState::Busy => StateUpdateEvent::Busy,
+        NodeState::Busy => StateUpdateEvent::Busy {
+            work_units: vec![WorkUnitStatus {
+                job_id: Uuid::new_v4(),
+                task_id: Uuid::new_v4(),
+                state: WorkerState::Running,
+            }],
+        },
         NodeState::Done => StateUpdateEvent::Done {
             error: None,
             script_output: None,
@@ -175,6 +181,7 @@ fn debug_run_worker(opt: RunWorkerOpt) -> Result<()> {
         job_id: Uuid::new_v4(),
         task_id,
         env: std::collections::HashMap::new(),
+        resources: Default::default(),
     };
     let work_set = WorkSet {
         reboot: false,
@@ -207,7 +214,13 @@ async fn run_worker(mut work_set: WorkSet) -> Result<Vec<WorkerEvent>> {
     let extra_setup_dir = work_set.extra_setup_dir()?;
     let work_dir = work_unit.working_dir(setup_runner.machine_id)?;
 
-    let mut worker = Worker::new(work_dir, setup_dir, extra_setup_dir, work_unit);
+    let mut worker = Worker::new(
+        work_dir,
+        setup_dir,
+        extra_setup_dir,
+        work_unit,
+        Default::default(),
+    );
     while !worker.is_done() {
         worker = worker
             .update(
@@ -10,9 +10,15 @@ extern crate anyhow;
 #[macro_use]
 extern crate onefuzz_telemetry;
 
+use crate::standalone::{StandaloneConfig, StandaloneCoordinator, StandaloneWorkQueue};
+use crate::work::journal::{WorkJournal, WorkUnitState};
 use crate::{
-    config::StaticConfig, coordinator::StateUpdateEvent, heartbeat::init_agent_heartbeat,
-    panic::set_panic_handler, work::WorkSet, worker::WorkerEvent,
+    config::StaticConfig,
+    coordinator::{ICoordinator, StateUpdateEvent},
+    heartbeat::init_agent_heartbeat,
+    panic::set_panic_handler,
+    work::{IWorkQueue, WorkSet, WorkUnit},
+    worker::WorkerEvent,
 };
 use std::fs::OpenOptions;
 use std::path::PathBuf;
@@ -24,6 +30,7 @@ use onefuzz::machine_id::MachineIdentity;
 use onefuzz::process::ExitStatus;
 use onefuzz_telemetry::{self as telemetry, EventData, Role};
 use std::io::{self, Write};
+use url::Url;
 use uuid::Uuid;
 
 pub mod agent;
@@ -36,10 +43,13 @@ pub mod done;
 pub mod failure;
 pub mod heartbeat;
 pub mod log_uploader;
+pub mod metrics;
 pub mod panic;
 pub mod reboot;
+pub mod resources;
 pub mod scheduler;
 pub mod setup;
+pub mod standalone;
 pub mod validations;
 pub mod work;
 pub mod worker;
@@ -73,6 +83,21 @@ struct RunOpt {
 
     #[arg(long = "reset_lock", action = ArgAction::SetTrue )]
     reset_node_lock: bool,
+
+    /// runs without the OneFuzz service, taking work sets from the `work`
+    /// directory and node commands from the `commands` directory of this
+    /// directory, and appending node events to its `events.jsonl`
+    #[arg(long = "standalone")]
+    standalone: Option<PathBuf>,
+
+    /// in standalone mode, takes work sets from this `file://` queue instead
+    #[arg(long = "work_queue", requires = "standalone")]
+    work_queue: Option<Url>,
+
+    /// in standalone mode, takes node commands from this `file://` queue
+    /// instead
+    #[arg(long = "command_queue", requires = "standalone")]
+    command_queue: Option<Url>,
 }
 
 fn main() -> Result<()> {
@@ -165,6 +190,18 @@ fn redirect(opt: RunOpt) -> Result<()> {
         cmd.arg("--reset_lock");
     }
 
+    if let Some(standalone) = opt.standalone {
+        cmd.arg("--standalone").arg(standalone);
+    }
+
+    if let Some(work_queue) = opt.work_queue {
+        cmd.arg("--work_queue").arg(work_queue.as_str());
+    }
+
+    if let Some(command_queue) = opt.command_queue {
+        cmd.arg("--command_queue").arg(command_queue.as_str());
+    }
+
     let exit_status: ExitStatus = cmd
         .spawn()
         .context("unable to start child onefuzz-agent")?
@@ -191,6 +228,10 @@ fn run(opt: RunOpt) -> Result<()> {
     }
     let rt = tokio::runtime::Runtime::new()?;
     let reset_lock = opt.reset_node_lock;
+    let standalone = opt
+        .standalone
+        .clone()
+        .map(|dir| (dir, opt.work_queue.clone(), opt.command_queue.clone()));
     let config = rt.block_on(load_config(opt));
 
     // We can't send telemetry, because we couldn't get a telemetry key from the config.
@@ -212,7 +253,17 @@ fn run(opt: RunOpt) -> Result<()> {
         return Ok(());
     }
 
-    let result = rt.block_on(run_agent(config, reset_lock));
+    let standalone = match standalone {
+        Some((dir, work_queue, command_queue)) => Some(StandaloneConfig::new(
+            &dir,
+            work_queue,
+            command_queue,
+            machine_id,
+        )?),
+        None => None,
+    };
+
+    let result = rt.block_on(run_agent(config, reset_lock, standalone));
 
     if let Err(err) = &result {
         error!("error running supervisor agent: {:?}", err);
@@ -247,60 +298,125 @@ async fn load_config(opt: RunOpt) -> Result<StaticConfig> {
     Ok(config)
 }
 
-async fn check_existing_worksets(coordinator: &mut coordinator::Coordinator) -> Result<()> {
+async fn check_existing_worksets(coordinator: &dyn ICoordinator, machine_id: Uuid) -> Result<()> {
     // Having existing worksets at this point means the supervisor crashed. If
     // that is the case, mark each of the work units within the workset as
     // failed, then exit as a failure.
 
-    if let Some(work) = WorkSet::load_from_fs_context(coordinator.get_machine_id()).await? {
+    if let Some(work) = WorkSet::load_from_fs_context(machine_id).await? {
         warn!("onefuzz-agent unexpectedly identified an existing workset on start");
-        let failure = match failure::read_failure(coordinator.get_machine_id()) {
-            Ok(value) => format!("onefuzz-agent failed: {value}"),
-            Err(failure_err) => {
-                warn!("unable to read failure: {:?}", failure_err);
-                let logs = failure::read_logs().unwrap_or_else(|logs_err| {
-                    format!("unable to read failure message or logs: {failure_err:?} {logs_err:?}")
-                });
-                format!("onefuzz-agent failed: {logs}")
-            }
-        };
+        fail_work_units(coordinator, machine_id, &work.work_units).await?;
 
-        for unit in &work.work_units {
-            let event = WorkerEvent::Done {
-                job_id: unit.job_id,
-                task_id: unit.task_id,
-                stdout: "".to_string(),
-                stderr: failure.clone(),
-                exit_status: ExitStatus {
-                    code: Some(1),
-                    signal: None,
-                    success: false,
-                },
-            };
-            coordinator.emit_event(event.into()).await?;
+        anyhow::bail!(
+            "failed to start due to pre-existing workset config: {}",
+            WorkSet::context_path(machine_id)?.display()
+        );
+    }
+
+    Ok(())
+}
+
+async fn fail_work_units(
+    coordinator: &dyn ICoordinator,
+    machine_id: Uuid,
+    work_units: &[WorkUnit],
+) -> Result<()> {
+    let failure = emit_failed_work_units(coordinator, machine_id, work_units).await?;
+
+    let event = StateUpdateEvent::Done {
+        error: Some(failure),
+        script_output: None,
+    };
+    coordinator.emit_event(event.into()).await?;
+
+    // force set done semaphore, as to not prevent the supervisor continuing
+    // to report the workset as failed.
+    done::set_done_lock(machine_id).await?;
+
+    Ok(())
+}
+
+// Reports the work units as failed, returning the failure they were reported
+// with.
+async fn emit_failed_work_units(
+    coordinator: &dyn ICoordinator,
+    machine_id: Uuid,
+    work_units: &[WorkUnit],
+) -> Result<String> {
+    let failure = match failure::read_failure(machine_id) {
+        Ok(value) => format!("onefuzz-agent failed: {value}"),
+        Err(failure_err) => {
+            warn!("unable to read failure: {:?}", failure_err);
+            let logs = failure::read_logs().unwrap_or_else(|logs_err| {
+                format!("unable to read failure message or logs: {failure_err:?} {logs_err:?}")
+            });
+            format!("onefuzz-agent failed: {logs}")
         }
+    };
 
-        let event = StateUpdateEvent::Done {
-            error: Some(failure),
-            script_output: None,
+    for unit in work_units {
+        let event = WorkerEvent::Done {
+            job_id: unit.job_id,
+            task_id: unit.task_id,
+            stdout: "".to_string(),
+            stderr: failure.clone(),
+            exit_status: ExitStatus {
+                code: Some(1),
+                signal: None,
+                success: false,
+            },
         };
         coordinator.emit_event(event.into()).await?;
+    }
 
-        // force set done semaphore, as to not prevent the supervisor continuing
-        // to report the workset as failed.
-        let machine_id = coordinator.get_machine_id();
-        done::set_done_lock(machine_id).await?;
-        anyhow::bail!(
-            "failed to start due to pre-existing workset config: {}",
-            WorkSet::context_path(machine_id)?.display()
+    Ok(failure)
+}
+
+// Recovers the work claimed before the agent restarted, as recorded by the
+// work journal. Work units which cannot be resumed are failed, without failing
+// the others, or the agent.
+async fn recover_work(
+    coordinator: &dyn ICoordinator,
+    machine_id: Uuid,
+    journal: &WorkJournal,
+) -> Result<scheduler::Scheduler> {
+    let recovery = journal.recover().await?;
+
+    if recovery.is_empty() {
+        check_existing_worksets(coordinator, machine_id).await?;
+        return Ok(scheduler::Scheduler::new(None));
+    }
+
+    if !recovery.fail.is_empty() {
+        warn!(
+            "onefuzz-agent identified interrupted work units on start: {}",
+            recovery.fail.len()
         );
+        emit_failed_work_units(coordinator, machine_id, &recovery.fail).await?;
+        for unit in &recovery.fail {
+            journal.record(unit.task_id, WorkUnitState::Done).await?;
+        }
     }
 
-    Ok(())
+    WorkSet::remove_context(machine_id).await?;
+
+    match recovery.resume {
+        Some(work_set) => {
+            info!("resuming setup of interrupted work set: {:?}", work_set);
+            Ok(scheduler::Scheduler::resume(work_set))
+        }
+        None => Ok(scheduler::Scheduler::new(None)),
+    }
 }
 
-async fn run_agent(config: StaticConfig, reset_node: bool) -> Result<()> {
-    telemetry::set_property(EventData::InstanceId(config.instance_id));
+async fn run_agent(
+    config: StaticConfig,
+    reset_node: bool,
+    standalone: Option<StandaloneConfig>,
+) -> Result<()> {
+    if let Some(instance_id) = config.instance_id {
+        telemetry::set_property(EventData::InstanceId(instance_id));
+    }
     telemetry::set_property(EventData::MachineId(config.machine_identity.machine_id));
     telemetry::set_property(EventData::Version(env!("ONEFUZZ_VERSION").to_string()));
     telemetry::set_property(EventData::Role(Role::Supervisor));
@@ -309,35 +425,63 @@ async fn run_agent(config: StaticConfig, reset_node: bool) -> Result<()> {
         telemetry::set_property(EventData::ScalesetId(scaleset_name.to_string()));
     }
 
-    let registration = match config::Registration::load_existing(config.clone()).await {
-        Ok(registration) => registration,
-        Err(_) => {
-            if config.managed {
-                config::Registration::create_managed(config.clone()).await?
-            } else {
-                config::Registration::create_unmanaged(config.clone()).await?
+    if let Some(addr) = config.metrics_address {
+        telemetry::metrics::enable();
+
+        let machine_id = config.machine_identity.machine_id;
+        tokio::spawn(async move {
+            if let Err(err) = metrics::serve(addr, machine_id).await {
+                error!("unable to serve metrics: {:?}", err);
             }
+        });
+    }
+
+    let machine_id = config.machine_identity.machine_id;
+    let concurrent = standalone.is_some();
+    let (coordinator, work_queue): (Box<dyn ICoordinator>, Box<dyn IWorkQueue>) = match standalone {
+        Some(standalone) => {
+            info!("running in standalone mode");
+            let coordinator =
+                StandaloneCoordinator::new(standalone.commands, standalone.events, machine_id);
+            let work_queue = StandaloneWorkQueue::new(standalone.work);
+            (Box::new(coordinator), Box::new(work_queue))
         }
-    };
-    debug!("current registration: {:?}", registration);
+        None => {
+            let registration = match config::Registration::load_existing(config.clone()).await {
+                Ok(registration) => registration,
+                Err(_) => {
+                    if config.managed {
+                        config::Registration::create_managed(config.clone()).await?
+                    } else {
+                        config::Registration::create_unmanaged(config.clone()).await?
+                    }
+                }
+            };
+            debug!("current registration: {:?}", registration);
+
+            let coordinator = coordinator::Coordinator::new(registration.clone()).await?;
+            debug!("initialized coordinator");
 
-    let mut coordinator = coordinator::Coordinator::new(registration.clone()).await?;
-    debug!("initialized coordinator");
+            let work_queue = work::WorkQueue::new(registration)?;
+            (Box::new(coordinator), Box::new(work_queue))
+        }
+    };
 
     let reboot = reboot::Reboot::new(config.machine_identity.machine_id);
     let reboot_context = reboot.load_context().await?;
+    let journal = WorkJournal::for_machine(config.machine_identity.machine_id)?;
     if reset_node {
         WorkSet::remove_context(config.machine_identity.machine_id).await?;
+        journal.clear().await?;
     }
 
-    if reboot_context.is_none() {
-        check_existing_worksets(&mut coordinator).await?;
-    }
-    let scheduler = scheduler::Scheduler::new(reboot_context);
+    let scheduler = if reboot_context.is_some() {
+        scheduler::Scheduler::new(reboot_context)
+    } else {
+        recover_work(coordinator.as_ref(), machine_id, &journal).await?
+    };
     debug!("loaded scheduler: {}", scheduler);
 
-    let work_queue = work::WorkQueue::new(registration.clone())?;
-
     let agent_heartbeat = match config.heartbeat_queue {
         Some(url) => Some(
             init_agent_heartbeat(
@@ -350,15 +494,18 @@ async fn run_agent(config: StaticConfig, reset_node: bool) -> Result<()> {
         None => None,
     };
     let agent = agent::Agent::new(
-        Box::new(coordinator),
+        coordinator,
         Box::new(reboot),
         scheduler,
         Box::new(setup::SetupRunner {
             machine_id: config.machine_identity.machine_id,
         }),
-        Box::new(work_queue),
+        work_queue,
         Box::new(worker::WorkerRunner::new(config.machine_identity.clone())),
         agent_heartbeat,
+        Some(journal),
+        resources::ResourcePool::for_node()?,
+        concurrent,
         config.managed,
         config.machine_identity.machine_id,
     );
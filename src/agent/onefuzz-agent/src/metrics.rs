@@ -0,0 +1,60 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT License.
+
+use std::net::SocketAddr;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime};
+
+use anyhow::Result;
+use onefuzz_telemetry::metrics::{self, Sample};
+use uuid::Uuid;
+
+/// Name of the metrics snapshot saved by each task in its working directory.
+pub const TASK_METRICS_FILE: &str = "metrics.json";
+
+/// Snapshots older than this are assumed to belong to exited tasks.
+const STALE_SNAPSHOT_AGE: Duration = Duration::from_secs(5 * 60);
+
+/// Serve the metrics of the agent, and of the tasks it is running.
+pub async fn serve(addr: SocketAddr, machine_id: Uuid) -> Result<()> {
+    let tasks_dir = onefuzz::fs::onefuzz_root()?.join(machine_id.to_string());
+
+    info!("serving metrics at http://{}/metrics", addr);
+    metrics::serve(addr, move || collect(&tasks_dir)).await
+}
+
+fn collect(tasks_dir: &Path) -> Vec<Sample> {
+    let mut samples = metrics::snapshot();
+
+    for path in task_snapshots(tasks_dir) {
+        match metrics::read_snapshot(&path) {
+            Ok(task_samples) => samples.extend(task_samples),
+            Err(err) => debug!("unable to read metrics {}: {:?}", path.display(), err),
+        }
+    }
+
+    samples
+}
+
+fn task_snapshots(tasks_dir: &Path) -> Vec<PathBuf> {
+    let Ok(entries) = std::fs::read_dir(tasks_dir) else {
+        return vec![];
+    };
+
+    entries
+        .filter_map(|entry| entry.ok())
+        .map(|entry| entry.path().join(TASK_METRICS_FILE))
+        .filter(|path| is_fresh(path))
+        .collect()
+}
+
+fn is_fresh(path: &Path) -> bool {
+    let modified = std::fs::metadata(path).and_then(|m| m.modified());
+
+    match modified.map(|t| SystemTime::now().duration_since(t)) {
+        Ok(Ok(age)) => age < STALE_SNAPSHOT_AGE,
+        // Modified in the future, relative to our clock.
+        Ok(Err(_)) => true,
+        Err(_) => false,
+    }
+}
@@ -0,0 +1,367 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT License.
+
+//! Partitioning of the CPU cores and memory of the node between the work units
+//! it runs concurrently.
+
+use std::collections::{BTreeSet, HashMap};
+use std::process::Command;
+
+use anyhow::Result;
+
+use crate::work::{TaskId, WorkSet, WorkUnit};
+
+/// Resources of the node reserved for a work unit.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Allocation {
+    /// CPU cores the task is pinned to, if any.
+    pub cores: Vec<usize>,
+
+    /// Memory limit of the task, if any.
+    pub memory_bytes: Option<u64>,
+}
+
+impl Allocation {
+    /// Pin the process spawned by `cmd` to the allocated cores.
+    #[cfg(target_os = "linux")]
+    pub fn pin(&self, cmd: &mut Command) -> Result<()> {
+        use nix::sched::{sched_setaffinity, CpuSet};
+        use nix::unistd::Pid;
+        use std::os::unix::process::CommandExt;
+
+        if self.cores.is_empty() {
+            return Ok(());
+        }
+
+        let mut cpu_set = CpuSet::new();
+        for core in &self.cores {
+            cpu_set.set(*core)?;
+        }
+
+        // Safety: only calls `sched_setaffinity`, which is async-signal-safe.
+        unsafe {
+            cmd.pre_exec(move || {
+                sched_setaffinity(Pid::from_raw(0), &cpu_set).map_err(std::io::Error::from)
+            });
+        }
+
+        Ok(())
+    }
+
+    #[cfg(not(target_os = "linux"))]
+    pub fn pin(&self, _cmd: &mut Command) -> Result<()> {
+        if !self.cores.is_empty() {
+            warn!("pinning work units to cores is only supported on Linux");
+        }
+
+        Ok(())
+    }
+
+    /// Limit the memory of the spawned process `pid` of `task_id`, in a cgroup
+    /// of its own, which is returned.
+    #[cfg(target_os = "linux")]
+    pub fn confine(&self, task_id: TaskId, pid: u32) -> Result<Option<onefuzz::cgroup::Cgroup>> {
+        use onefuzz::cgroup::Cgroup;
+
+        let Some(memory_bytes) = self.memory_bytes else {
+            return Ok(None);
+        };
+
+        if !Cgroup::is_supported() {
+            warn!(
+                "unable to limit memory of task {}: cgroup v2 not mounted",
+                task_id
+            );
+            return Ok(None);
+        }
+
+        let cgroup = Cgroup::create(&format!("task-{task_id}"), &["memory"])?;
+        cgroup.set_memory_max(memory_bytes)?;
+        cgroup.add_process(pid)?;
+
+        Ok(Some(cgroup))
+    }
+}
+
+/// Tracks the resources of the node which are not reserved by work units.
+#[derive(Debug)]
+pub struct ResourcePool {
+    cores: usize,
+    memory_bytes: u64,
+    free_cores: BTreeSet<usize>,
+    free_memory_bytes: u64,
+    allocations: HashMap<TaskId, Allocation>,
+
+    // Whether the allocated work units were given the whole node.
+    exclusive: bool,
+}
+
+impl ResourcePool {
+    pub fn new(cores: usize, memory_bytes: u64) -> Self {
+        Self::with_cores(0..cores, memory_bytes)
+    }
+
+    /// Pool of the cores with the ids `cores`, which need not be contiguous.
+    pub fn with_cores(cores: impl IntoIterator<Item = usize>, memory_bytes: u64) -> Self {
+        let free_cores: BTreeSet<usize> = cores.into_iter().collect();
+        Self {
+            cores: free_cores.len(),
+            memory_bytes,
+            free_cores,
+            free_memory_bytes: memory_bytes,
+            allocations: HashMap::new(),
+            exclusive: false,
+        }
+    }
+
+    /// The cores the agent may run on, and the memory available when it
+    /// starts.
+    pub fn for_node() -> Result<Self> {
+        let memory_bytes = onefuzz::memory::available_bytes()?;
+        Ok(Self::with_cores(allowed_cores()?, memory_bytes))
+    }
+
+    pub fn is_idle(&self) -> bool {
+        self.allocations.is_empty()
+    }
+
+    /// Whether another work set may be admitted.
+    pub fn has_capacity(&self) -> bool {
+        !self.exclusive && !self.free_cores.is_empty()
+    }
+
+    /// Reserve resources for all of the units of `work_set`, if available.
+    ///
+    /// Work sets whose units do not all set `resources.cores` are only admitted
+    /// when the node is idle, and then reserve the whole node. So are work
+    /// sets which exceed the resources of the node.
+    pub fn admit(&mut self, work_set: &WorkSet) -> Option<HashMap<TaskId, Allocation>> {
+        if self.exclusive {
+            return None;
+        }
+
+        let partitioned = work_set
+            .work_units
+            .iter()
+            .all(|unit| unit.resources.cores.is_some());
+
+        let cores: usize = work_set
+            .work_units
+            .iter()
+            .filter_map(|unit| unit.resources.cores)
+            .sum();
+        let memory_bytes: u64 = work_set
+            .work_units
+            .iter()
+            .filter_map(|unit| unit.resources.memory_bytes)
+            .sum();
+
+        if partitioned && cores <= self.free_cores.len() && memory_bytes <= self.free_memory_bytes {
+            let allocations: HashMap<TaskId, Allocation> = work_set
+                .work_units
+                .iter()
+                .map(|unit| (unit.task_id, self.allocate(unit)))
+                .collect();
+            self.allocations.extend(allocations.clone());
+            return Some(allocations);
+        }
+
+        if !self.is_idle() {
+            return None;
+        }
+
+        if partitioned {
+            warn!(
+                "work set needs {} cores and {} bytes, more than the node has ({} cores, {} bytes), running it alone",
+                cores, memory_bytes, self.cores, self.memory_bytes
+            );
+        }
+
+        // Memory limits are still enforced, as they do not depend on the other
+        // units of the node.
+        let allocations: HashMap<TaskId, Allocation> = work_set
+            .work_units
+            .iter()
+            .map(|unit| {
+                let allocation = Allocation {
+                    cores: vec![],
+                    memory_bytes: unit.resources.memory_bytes,
+                };
+                (unit.task_id, allocation)
+            })
+            .collect();
+        self.allocations.extend(allocations.clone());
+        self.exclusive = true;
+
+        Some(allocations)
+    }
+
+    fn allocate(&mut self, unit: &WorkUnit) -> Allocation {
+        let count = unit.resources.cores.unwrap_or_default();
+        let cores: Vec<usize> = self.free_cores.iter().take(count).copied().collect();
+        for core in &cores {
+            self.free_cores.remove(core);
+        }
+
+        let memory_bytes = unit.resources.memory_bytes;
+        self.free_memory_bytes -= memory_bytes.unwrap_or_default();
+
+        Allocation {
+            cores,
+            memory_bytes,
+        }
+    }
+
+    /// Release the resources of the unit of `task_id`, once done.
+    pub fn release(&mut self, task_id: TaskId) {
+        let Some(allocation) = self.allocations.remove(&task_id) else {
+            return;
+        };
+
+        if self.exclusive {
+            if self.allocations.is_empty() {
+                self.exclusive = false;
+            }
+            return;
+        }
+
+        self.free_cores.extend(allocation.cores);
+        self.free_memory_bytes += allocation.memory_bytes.unwrap_or_default();
+    }
+}
+
+// Ids of the cores the agent is allowed to run on, which may be fewer than
+// those of the node, and not contiguous, such as when run under `taskset`.
+#[cfg(target_os = "linux")]
+fn allowed_cores() -> Result<Vec<usize>> {
+    use nix::sched::{sched_getaffinity, CpuSet};
+    use nix::unistd::Pid;
+
+    let cpu_set = sched_getaffinity(Pid::from_raw(0))?;
+    let cores = (0..CpuSet::count())
+        .filter(|core| cpu_set.is_set(*core).unwrap_or_default())
+        .collect();
+
+    Ok(cores)
+}
+
+#[cfg(not(target_os = "linux"))]
+fn allowed_cores() -> Result<Vec<usize>> {
+    let cores = std::thread::available_parallelism()?.get();
+    Ok((0..cores).collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use uuid::Uuid;
+
+    use super::*;
+    use crate::work::{test_work_set, test_work_unit, Resources};
+
+    fn work_set(resources: &[(Option<usize>, Option<u64>)]) -> WorkSet {
+        let work_units = resources
+            .iter()
+            .map(|(cores, memory_bytes)| WorkUnit {
+                resources: Resources {
+                    cores: *cores,
+                    memory_bytes: *memory_bytes,
+                },
+                ..test_work_unit(Uuid::new_v4())
+            })
+            .collect();
+
+        test_work_set(work_units)
+    }
+
+    #[test]
+    fn test_admit_partitioned() {
+        let mut pool = ResourcePool::new(4, 1000);
+
+        let first = work_set(&[(Some(1), Some(100)), (Some(2), None)]);
+        let allocations = pool.admit(&first).expect("first work set not admitted");
+        assert_eq!(
+            allocations[&first.work_units[0].task_id],
+            Allocation {
+                cores: vec![0],
+                memory_bytes: Some(100),
+            }
+        );
+        assert_eq!(allocations[&first.work_units[1].task_id].cores, [1, 2]);
+
+        // not enough cores left
+        assert!(pool.admit(&work_set(&[(Some(2), None)])).is_none());
+        // not enough memory left
+        assert!(pool.admit(&work_set(&[(Some(1), Some(901))])).is_none());
+        // not partitioned, and the node is not idle
+        assert!(pool.admit(&work_set(&[(None, None)])).is_none());
+
+        let second = work_set(&[(Some(1), Some(900))]);
+        let allocations = pool.admit(&second).expect("second work set not admitted");
+        assert_eq!(allocations[&second.work_units[0].task_id].cores, [3]);
+        assert!(!pool.has_capacity());
+
+        pool.release(first.work_units[1].task_id);
+        let third = work_set(&[(Some(2), None)]);
+        let allocations = pool.admit(&third).expect("third work set not admitted");
+        assert_eq!(allocations[&third.work_units[0].task_id].cores, [1, 2]);
+    }
+
+    #[test]
+    fn test_admit_exclusive() {
+        let mut pool = ResourcePool::new(2, 1000);
+
+        // too large for the node, so run alone, unpinned
+        let large = work_set(&[(Some(4), Some(100))]);
+        let allocations = pool.admit(&large).expect("large work set not admitted");
+        assert_eq!(
+            allocations[&large.work_units[0].task_id],
+            Allocation {
+                cores: vec![],
+                memory_bytes: Some(100),
+            }
+        );
+        assert!(!pool.has_capacity());
+        assert!(pool.admit(&work_set(&[(Some(1), None)])).is_none());
+
+        pool.release(large.work_units[0].task_id);
+        assert!(pool.is_idle());
+        assert!(pool.has_capacity());
+
+        let unpartitioned = work_set(&[(None, None), (Some(1), None)]);
+        assert!(pool.admit(&unpartitioned).is_some());
+        assert!(!pool.has_capacity());
+    }
+
+    #[test]
+    fn test_admit_allowed_cores() {
+        let mut pool = ResourcePool::with_cores([5, 2, 7], 1000);
+
+        let first = work_set(&[(Some(2), None)]);
+        let allocations = pool.admit(&first).expect("first work set not admitted");
+        assert_eq!(allocations[&first.work_units[0].task_id].cores, [2, 5]);
+
+        assert!(pool.admit(&work_set(&[(Some(2), None)])).is_none());
+
+        pool.release(first.work_units[0].task_id);
+        let second = work_set(&[(Some(3), None)]);
+        let allocations = pool.admit(&second).expect("second work set not admitted");
+        assert_eq!(allocations[&second.work_units[0].task_id].cores, [2, 5, 7]);
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn test_allowed_cores() -> Result<()> {
+        use nix::sched::sched_getaffinity;
+        use nix::unistd::Pid;
+
+        let cores = allowed_cores()?;
+        assert!(!cores.is_empty());
+
+        let cpu_set = sched_getaffinity(Pid::from_raw(0))?;
+        for core in cores {
+            assert!(cpu_set.is_set(core)?);
+        }
+
+        Ok(())
+    }
+}
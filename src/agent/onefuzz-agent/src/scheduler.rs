@@ -1,14 +1,16 @@
 // Copyright (c) Microsoft Corporation.
 // Licensed under the MIT License.
 
+use std::collections::HashMap;
 use std::fmt;
 
 use anyhow::Result;
 use onefuzz::process::Output;
 
 use crate::commands::add_ssh_key;
-use crate::coordinator::{NodeCommand, NodeState};
+use crate::coordinator::{NodeCommand, NodeState, WorkUnitStatus};
 use crate::reboot::RebootContext;
+use crate::resources::Allocation;
 use crate::setup::ISetupRunner;
 use crate::work::*;
 use crate::worker::*;
@@ -63,6 +65,15 @@ impl Scheduler {
         }
     }
 
+    /// Set up a work set again, after its setup was interrupted by an agent
+    /// restart.
+    pub fn resume(work_set: WorkSet) -> Self {
+        let state = State {
+            ctx: SettingUp { work_set },
+        };
+        state.into()
+    }
+
     pub async fn execute_command(self, cmd: NodeCommand, managed: bool) -> Result<Self> {
         match cmd {
             NodeCommand::AddSshKey(ssh_key_info) => {
@@ -247,28 +258,50 @@ impl State<PendingReboot> {
     }
 }
 
+// Creates the workers of the units of `work_set`, given the resources
+// allocated to each unit, if any.
+fn workers(
+    work_set: WorkSet,
+    machine_id: uuid::Uuid,
+    mut allocations: HashMap<TaskId, Allocation>,
+) -> Result<Vec<Option<Worker>>> {
+    let mut workers = vec![];
+    let setup_dir = work_set.setup_dir()?;
+    let extra_setup_dir = work_set.extra_setup_dir()?;
+
+    for work in work_set.work_units {
+        let work_dir = work.working_dir(machine_id)?;
+        let allocation = allocations.remove(&work.task_id).unwrap_or_default();
+        let worker = Some(Worker::new(
+            work_dir,
+            setup_dir.clone(),
+            extra_setup_dir.clone(),
+            work,
+            allocation,
+        ));
+        workers.push(worker);
+    }
+
+    Ok(workers)
+}
+
 impl State<Ready> {
-    pub async fn run(self, machine_id: uuid::Uuid) -> Result<State<Busy>> {
-        let mut workers = vec![];
-        let setup_dir = self.ctx.work_set.setup_dir()?;
-        let extra_setup_dir = self.ctx.work_set.extra_setup_dir()?;
-
-        for work in self.ctx.work_set.work_units {
-            let work_dir = work.working_dir(machine_id)?;
-            let worker = Some(Worker::new(
-                work_dir,
-                setup_dir.clone(),
-                extra_setup_dir.clone(),
-                work,
-            ));
-            workers.push(worker);
-        }
+    pub async fn run(
+        self,
+        machine_id: uuid::Uuid,
+        allocations: HashMap<TaskId, Allocation>,
+    ) -> Result<State<Busy>> {
+        let workers = workers(self.ctx.work_set, machine_id, allocations)?;
 
         let ctx = Busy { workers };
         let state = ctx.into();
 
         Ok(state)
     }
+
+    pub fn work_set(&self) -> &WorkSet {
+        &self.ctx.work_set
+    }
 }
 
 impl State<Busy> {
@@ -295,6 +328,33 @@ impl State<Busy> {
         Ok(updated)
     }
 
+    /// Run the units of another work set, which is set up, alongside those
+    /// already running.
+    pub fn admit(
+        mut self,
+        work_set: WorkSet,
+        machine_id: uuid::Uuid,
+        allocations: HashMap<TaskId, Allocation>,
+    ) -> Result<Self> {
+        let workers = workers(work_set, machine_id, allocations)?;
+        self.ctx.workers.extend(workers);
+
+        Ok(self)
+    }
+
+    pub fn work_units(&self) -> Vec<WorkUnitStatus> {
+        self.ctx
+            .workers
+            .iter()
+            .flatten()
+            .map(|worker| WorkUnitStatus {
+                job_id: worker.work().job_id,
+                task_id: worker.work().task_id,
+                state: worker.state(),
+            })
+            .collect()
+    }
+
     fn all_workers_done(&self) -> bool {
         self.ctx
             .workers
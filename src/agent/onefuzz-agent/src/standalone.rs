@@ -0,0 +1,496 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT License.
+
+//! Stand-ins for the OneFuzz service, to run the agent in self-hosted pools.
+//!
+//! In standalone mode, work sets and node commands are taken from local
+//! sources, and node events are appended to a local JSONL file, instead of
+//! going through the service's coordinator and pool work queue.
+
+use std::ffi::OsString;
+use std::io::ErrorKind;
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context, Result};
+use serde::de::DeserializeOwned;
+use storage_queue::{Message as QueueMessage, QueueClient};
+use tokio::fs::{self, OpenOptions};
+use tokio::io::AsyncWriteExt;
+use url::Url;
+use uuid::Uuid;
+
+use crate::coordinator::{
+    CanSchedule, ICoordinator, NodeCommand, NodeEvent, NodeEventEnvelope, PollCommandError,
+};
+use crate::work::{IWorkQueue, Message, WorkSet};
+
+/// Where a standalone agent takes its work and commands from, and writes its
+/// events to.
+pub struct StandaloneConfig {
+    pub work: LocalSource,
+    pub commands: LocalSource,
+    pub events: PathBuf,
+}
+
+impl StandaloneConfig {
+    /// Lay out the standalone mode in `dir`, with work sets in its `work`
+    /// directory, node commands in its `commands` directory, and node events
+    /// appended to its `events.jsonl`. Either source of messages can be a
+    /// `file://` queue instead.
+    ///
+    /// Messages are claimed by `machine_id`, so several agents can share `dir`.
+    pub fn new(
+        dir: &Path,
+        work_queue: Option<Url>,
+        command_queue: Option<Url>,
+        machine_id: Uuid,
+    ) -> Result<Self> {
+        let work = match work_queue {
+            Some(url) => LocalSource::queue(url)?,
+            None => LocalSource::dir(dir.join("work"), machine_id),
+        };
+        let commands = match command_queue {
+            Some(url) => LocalSource::queue(url)?,
+            None => LocalSource::dir(dir.join("commands"), machine_id),
+        };
+        let events = dir.join("events.jsonl");
+
+        Ok(Self {
+            work,
+            commands,
+            events,
+        })
+    }
+}
+
+/// Local source of messages, each the JSON of a work set or node command.
+pub enum LocalSource {
+    /// Directory where each `*.json` file is a message, taken in file name
+    /// order.
+    ///
+    /// A message is claimed by renaming it into the `claimed` directory of
+    /// the agent, so it is only ever popped by a single agent.
+    Dir { path: PathBuf, claimed: PathBuf },
+    /// `file://` queue, as used by local tasks, where each message is the
+    /// JSON of a work set or node command, as enqueued by `enqueue_raw()`.
+    ///
+    /// Messages enqueued by `enqueue()` are not JSON, and so are dropped.
+    Queue(QueueClient),
+}
+
+/// A message popped from a `LocalSource`, until it is deleted.
+pub enum LocalMessage {
+    File(PathBuf),
+    Queue(QueueMessage),
+}
+
+impl LocalSource {
+    pub fn dir(path: impl Into<PathBuf>, machine_id: Uuid) -> Self {
+        let path = path.into();
+        let claimed = path.join(CLAIMED_DIR).join(machine_id.to_string());
+
+        Self::Dir { path, claimed }
+    }
+
+    pub fn queue(url: Url) -> Result<Self> {
+        if url.scheme() != "file" {
+            bail!("standalone queues must be file:// URLs: {}", url);
+        }
+
+        Ok(Self::Queue(QueueClient::new(url)?))
+    }
+
+    pub async fn pop<T: DeserializeOwned>(&self) -> Result<Option<(T, LocalMessage)>> {
+        match self {
+            Self::Dir { path, claimed } => pop_file(path, claimed).await,
+            Self::Queue(queue) => {
+                while let Some(message) = queue.pop().await? {
+                    let QueueMessage::LocalQueueMessage(local) = &message else {
+                        bail!("standalone queue returned a remote message");
+                    };
+
+                    match serde_json::from_slice(&local.data) {
+                        Ok(value) => return Ok(Some((value, LocalMessage::Queue(message)))),
+                        Err(err) => {
+                            warn!("dropping invalid standalone queue message: {}", err);
+                            message.delete().await?;
+                        }
+                    }
+                }
+
+                Ok(None)
+            }
+        }
+    }
+
+    /// Give up `message` without deleting it, so it can be popped again, by
+    /// this agent or another.
+    pub async fn release(&self, message: LocalMessage) -> Result<()> {
+        match (self, message) {
+            (Self::Dir { path: dir, .. }, LocalMessage::File(path)) => {
+                let name = path.file_name().unwrap_or_default();
+                fs::rename(&path, dir.join(name))
+                    .await
+                    .with_context(|| format!("unable to release message: {}", path.display()))
+            }
+            (Self::Queue(queue), LocalMessage::Queue(message)) => {
+                let QueueMessage::LocalQueueMessage(local) = &message else {
+                    bail!("standalone queue returned a remote message");
+                };
+                queue.enqueue_raw(&local.data).await?;
+                message.delete().await
+            }
+            _ => bail!("message was not popped from this source"),
+        }
+    }
+}
+
+const CLAIMED_DIR: &str = "claimed";
+
+// Pops the first valid message file of `dir`, after any left in `claimed` by
+// a previous run of the agent, which were popped but never deleted.
+//
+// Messages are claimed by renaming them into `claimed`, which only succeeds
+// for one of the agents sharing `dir`. Invalid files are renamed in `dir`, so
+// they are skipped from then on.
+async fn pop_file<T: DeserializeOwned>(
+    dir: &Path,
+    claimed: &Path,
+) -> Result<Option<(T, LocalMessage)>> {
+    for name in list_messages(claimed).await? {
+        if let Some(popped) = read_message(dir, claimed.join(name)).await? {
+            return Ok(Some(popped));
+        }
+    }
+
+    for name in list_messages(dir).await? {
+        fs::create_dir_all(claimed)
+            .await
+            .with_context(|| format!("unable to create directory: {}", claimed.display()))?;
+
+        let path = claimed.join(&name);
+        match fs::rename(dir.join(&name), &path).await {
+            Ok(()) => {}
+            // claimed by another agent since it was listed
+            Err(err) if err.kind() == ErrorKind::NotFound => continue,
+            Err(err) => {
+                return Err(err).with_context(|| {
+                    format!("unable to claim message: {}", dir.join(&name).display())
+                })
+            }
+        }
+
+        if let Some(popped) = read_message(dir, path).await? {
+            return Ok(Some(popped));
+        }
+    }
+
+    Ok(None)
+}
+
+// File names of the messages of `dir`, in order.
+async fn list_messages(dir: &Path) -> Result<Vec<OsString>> {
+    let mut entries = match fs::read_dir(dir).await {
+        Ok(entries) => entries,
+        Err(err) if err.kind() == ErrorKind::NotFound => return Ok(vec![]),
+        Err(err) => {
+            return Err(err).with_context(|| format!("unable to list messages: {}", dir.display()))
+        }
+    };
+
+    let mut names = vec![];
+    while let Some(entry) = entries.next_entry().await? {
+        let name = entry.file_name();
+        if Path::new(&name)
+            .extension()
+            .map(|ext| ext == "json")
+            .unwrap_or(false)
+        {
+            names.push(name);
+        }
+    }
+    names.sort();
+
+    Ok(names)
+}
+
+// Reads the claimed message at `path`, moving it back to `dir` as an invalid
+// file if it cannot be parsed.
+async fn read_message<T: DeserializeOwned>(
+    dir: &Path,
+    path: PathBuf,
+) -> Result<Option<(T, LocalMessage)>> {
+    let data = fs::read(&path)
+        .await
+        .with_context(|| format!("unable to read message: {}", path.display()))?;
+
+    match serde_json::from_slice(&data) {
+        Ok(value) => Ok(Some((value, LocalMessage::File(path)))),
+        Err(err) => {
+            warn!("skipping invalid message {}: {}", path.display(), err);
+            let name = path.file_name().unwrap_or_default();
+            let invalid = dir.join(name).with_extension("invalid");
+            fs::rename(&path, invalid).await?;
+            Ok(None)
+        }
+    }
+}
+
+impl LocalMessage {
+    pub async fn delete(self) -> Result<()> {
+        match self {
+            Self::File(path) => fs::remove_file(&path)
+                .await
+                .with_context(|| format!("unable to delete message: {}", path.display())),
+            Self::Queue(message) => message.delete().await,
+        }
+    }
+}
+
+/// Stands in for the service's coordinator.
+///
+/// Node commands are taken from `commands`, and node events are appended to
+/// the JSONL file at `events`. Work is always allowed to be scheduled.
+pub struct StandaloneCoordinator {
+    commands: LocalSource,
+    events: PathBuf,
+    machine_id: Uuid,
+}
+
+impl StandaloneCoordinator {
+    pub fn new(commands: LocalSource, events: impl Into<PathBuf>, machine_id: Uuid) -> Self {
+        let events = events.into();
+
+        Self {
+            commands,
+            events,
+            machine_id,
+        }
+    }
+}
+
+#[async_trait]
+impl ICoordinator for StandaloneCoordinator {
+    async fn poll_commands(&mut self) -> Result<Option<NodeCommand>, PollCommandError> {
+        let popped = self
+            .commands
+            .pop()
+            .await
+            .map_err(PollCommandError::RequestFailed)?;
+
+        match popped {
+            Some((command, message)) => {
+                message
+                    .delete()
+                    .await
+                    .map_err(PollCommandError::ClaimFailed)?;
+                Ok(Some(command))
+            }
+            None => Ok(None),
+        }
+    }
+
+    async fn emit_event(&self, event: NodeEvent) -> Result<()> {
+        let envelope = NodeEventEnvelope {
+            event,
+            machine_id: self.machine_id,
+        };
+        let mut data = serde_json::to_vec(&envelope)?;
+        data.push(b'\n');
+
+        if let Some(dir) = self.events.parent() {
+            fs::create_dir_all(dir).await?;
+        }
+        let mut file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.events)
+            .await
+            .with_context(|| format!("unable to open event log: {}", self.events.display()))?;
+        file.write_all(&data).await?;
+
+        Ok(())
+    }
+
+    async fn can_schedule(&self, _work: &WorkSet) -> Result<CanSchedule> {
+        Ok(CanSchedule {
+            allowed: true,
+            work_stopped: false,
+            reason: None,
+        })
+    }
+}
+
+/// Stands in for the pool work queue, taking work sets from a `LocalSource`.
+pub struct StandaloneWorkQueue {
+    source: LocalSource,
+
+    // Message of the last polled work set, deleted when it is claimed.
+    pending: Option<LocalMessage>,
+}
+
+impl StandaloneWorkQueue {
+    pub fn new(source: LocalSource) -> Self {
+        Self {
+            source,
+            pending: None,
+        }
+    }
+}
+
+#[async_trait]
+impl IWorkQueue for StandaloneWorkQueue {
+    async fn poll(&mut self) -> Result<Option<Message>> {
+        let popped = self.source.pop().await?;
+
+        Ok(popped.map(|(work_set, message)| {
+            self.pending = Some(message);
+            Message {
+                queue_message: None,
+                work_set,
+            }
+        }))
+    }
+
+    async fn claim(&mut self, message: Message) -> Result<WorkSet> {
+        let pending = self
+            .pending
+            .take()
+            .ok_or_else(|| anyhow!("claimed work set was not polled"))?;
+        pending.delete().await?;
+
+        Ok(message.work_set)
+    }
+
+    async fn release(&mut self, _message: Message) -> Result<()> {
+        let pending = self
+            .pending
+            .take()
+            .ok_or_else(|| anyhow!("released work set was not polled"))?;
+
+        self.source.release(pending).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::coordinator::StateUpdateEvent;
+    use crate::work::{test_work_set, test_work_unit};
+
+    fn work_set(task_id: &str) -> WorkSet {
+        test_work_set(vec![test_work_unit(task_id.parse().unwrap())])
+    }
+
+    #[tokio::test]
+    async fn test_work_queue_dir() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let first = work_set("eb8ee6b8-6f2d-43b1-aec2-022e9813e86b");
+        let second = work_set("ed1eeec9-2f39-442d-9e70-563454b866c0");
+        fs::write(dir.path().join("0-invalid.json"), "{").await?;
+        fs::write(dir.path().join("1.json"), serde_json::to_vec(&first)?).await?;
+        fs::write(dir.path().join("2.json"), serde_json::to_vec(&second)?).await?;
+
+        let machine_id = Uuid::new_v4();
+        let mut queue = StandaloneWorkQueue::new(LocalSource::dir(dir.path(), machine_id));
+
+        let message = queue.poll().await?.expect("missing work set");
+        assert_eq!(message.work_set, first);
+        assert!(dir.path().join("0-invalid.invalid").exists());
+        assert!(!dir.path().join("1.json").exists());
+        assert_eq!(queue.claim(message).await?, first);
+
+        // once polled, it is not seen by other agents
+        let message = queue.poll().await?.expect("missing work set");
+        assert_eq!(message.work_set, second);
+        let mut other = StandaloneWorkQueue::new(LocalSource::dir(dir.path(), Uuid::new_v4()));
+        assert!(other.poll().await?.is_none());
+
+        // but it is polled again by the same agent if it was never claimed
+        drop(message);
+        let mut queue = StandaloneWorkQueue::new(LocalSource::dir(dir.path(), machine_id));
+        let message = queue.poll().await?.expect("missing work set");
+        assert_eq!(queue.claim(message).await?, second);
+
+        assert!(queue.poll().await?.is_none());
+        assert!(other.poll().await?.is_none());
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_work_queue_file_queue() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let url = Url::from_file_path(dir.path().join("work-queue"))
+            .map_err(|_| anyhow!("invalid queue path"))?;
+        let first = work_set("eb8ee6b8-6f2d-43b1-aec2-022e9813e86b");
+        let second = work_set("ed1eeec9-2f39-442d-9e70-563454b866c0");
+        let client = QueueClient::new(url.clone())?;
+        client.enqueue_raw(&serde_json::to_vec(&first)?).await?;
+        // not raw JSON, so dropped, without blocking the messages after it
+        client.enqueue("https://contoso.com/inputs/crash").await?;
+        client.enqueue_raw(&serde_json::to_vec(&second)?).await?;
+        drop(client);
+
+        let mut queue = StandaloneWorkQueue::new(LocalSource::queue(url)?);
+        let message = queue.poll().await?.expect("missing work set");
+        assert_eq!(queue.claim(message).await?, first);
+        let message = queue.poll().await?.expect("missing work set");
+        assert_eq!(queue.claim(message).await?, second);
+        assert!(queue.poll().await?.is_none());
+
+        assert!(LocalSource::queue(Url::parse("https://contoso.com/queue")?).is_err());
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_coordinator() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let commands = dir.path().join("commands");
+        fs::create_dir(&commands).await?;
+        fs::write(commands.join("stop.json"), r#"{ "stop": {} }"#).await?;
+
+        let machine_id = Uuid::new_v4();
+        let events = dir.path().join("events.jsonl");
+        let mut coordinator = StandaloneCoordinator::new(
+            LocalSource::dir(&commands, machine_id),
+            &events,
+            machine_id,
+        );
+
+        assert!(matches!(
+            coordinator.poll_commands().await,
+            Ok(Some(NodeCommand::Stop {}))
+        ));
+        assert!(matches!(coordinator.poll_commands().await, Ok(None)));
+
+        coordinator
+            .emit_event(StateUpdateEvent::Free.into())
+            .await?;
+        coordinator
+            .emit_event(StateUpdateEvent::Ready.into())
+            .await?;
+
+        let data = fs::read_to_string(&events).await?;
+        let envelopes = data
+            .lines()
+            .map(serde_json::from_str)
+            .collect::<Result<Vec<NodeEventEnvelope>, _>>()?;
+        assert_eq!(
+            envelopes,
+            [
+                NodeEventEnvelope {
+                    event: StateUpdateEvent::Free.into(),
+                    machine_id,
+                },
+                NodeEventEnvelope {
+                    event: StateUpdateEvent::Ready.into(),
+                    machine_id,
+                },
+            ]
+        );
+
+        Ok(())
+    }
+}
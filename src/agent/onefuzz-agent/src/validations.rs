@@ -3,7 +3,7 @@ use std::path::{Path, PathBuf};
 use crate::setup::SetupRunner;
 use anyhow::Result;
 use clap::Parser;
-use onefuzz::{libfuzzer::LibFuzzer, machine_id::MachineIdentity};
+use onefuzz::{libfuzzer::LibFuzzer, machine_id::MachineIdentity, sandbox::Sandbox};
 use uuid::Uuid;
 
 #[derive(Parser, Debug)]
@@ -121,7 +121,8 @@ async fn get_logs(config: ValidationConfig) -> Result<()> {
         },
     );
 
-    let cmd = libfuzzer.build_std_command(None, None, None, None, None)?;
+    let cmd =
+        libfuzzer.build_std_command(None, None, None, None, None, &Sandbox::default(), &[])?;
     print_logs(cmd)?;
     Ok(())
 }
@@ -3,6 +3,7 @@
 
 use std::collections::HashMap;
 use std::path::PathBuf;
+use std::time::Duration;
 use std::{io::ErrorKind, sync::Arc};
 
 use anyhow::{Context, Result};
@@ -115,6 +116,24 @@ pub struct WorkUnit {
 
     /// Environment variables to set for the task.
     pub env: HashMap<String, String>,
+
+    /// Resources to reserve for the task, out of those of the node.
+    #[serde(default)]
+    pub resources: Resources,
+}
+
+/// Budget of node resources for a work unit.
+///
+/// Units which set `cores` can run alongside the units of other work sets,
+/// within the cores and memory of the node. Units which do not are given the
+/// whole node.
+#[derive(Clone, Debug, Default, Deserialize, Eq, PartialEq, Serialize)]
+pub struct Resources {
+    /// Number of CPU cores to pin the task to.
+    pub cores: Option<usize>,
+
+    /// Maximum memory of the task, in bytes.
+    pub memory_bytes: Option<u64>,
 }
 
 impl WorkUnit {
@@ -134,6 +153,10 @@ pub trait IWorkQueue: Downcast {
     async fn poll(&mut self) -> Result<Option<Message>>;
 
     async fn claim(&mut self, message: Message) -> Result<WorkSet>;
+
+    /// Give up a polled message without claiming it, so that it can be polled
+    /// again, by this node or another.
+    async fn release(&mut self, message: Message) -> Result<()>;
 }
 
 #[async_trait]
@@ -145,6 +168,10 @@ impl IWorkQueue for WorkQueue {
     async fn claim(&mut self, message: Message) -> Result<WorkSet> {
         self.claim(message).await
     }
+
+    async fn release(&mut self, message: Message) -> Result<()> {
+        self.release(message).await
+    }
 }
 
 impl_downcast!(IWorkQueue);
@@ -243,7 +270,45 @@ impl WorkQueue {
             Ok(message.work_set)
         }
     }
+
+    pub async fn release(&mut self, message: Message) -> Result<()> {
+        if let Some(mut queue_message) = message.queue_message {
+            // Visible again right away, rather than after its visibility timeout.
+            queue_message
+                .extend_visibility(Duration::ZERO)
+                .await
+                .context("unable to release work")?;
+        }
+
+        Ok(())
+    }
 }
 
+pub mod journal;
+
 #[cfg(test)]
 pub mod double;
+
+/// Work set of `work_units`, as used by tests.
+#[cfg(test)]
+pub fn test_work_set(work_units: Vec<WorkUnit>) -> WorkSet {
+    WorkSet {
+        reboot: false,
+        setup_url: BlobContainerUrl::parse("https://contoso.com/my-setup-container").unwrap(),
+        extra_setup_url: None,
+        script: false,
+        work_units,
+    }
+}
+
+/// Work unit of the task `task_id`, of a new job, as used by tests.
+#[cfg(test)]
+pub fn test_work_unit(task_id: TaskId) -> WorkUnit {
+    WorkUnit {
+        job_id: Uuid::new_v4(),
+        task_id,
+        config: r#"{ "hello": "world" }"#.to_owned().into(),
+        env: HashMap::new(),
+        resources: Default::default(),
+    }
+}
@@ -7,6 +7,7 @@ use super::*;
 pub struct WorkQueueDouble {
     pub available: Vec<Message>,
     pub claimed: Vec<Message>,
+    pub fail_claim: bool,
 }
 
 #[async_trait]
@@ -16,8 +17,18 @@ impl IWorkQueue for WorkQueueDouble {
     }
 
     async fn claim(&mut self, message: Message) -> Result<WorkSet> {
+        if self.fail_claim {
+            self.available.push(message);
+            bail!("simulated claim failure");
+        }
+
         let work_set = message.work_set.clone();
         self.claimed.push(message);
         Ok(work_set)
     }
+
+    async fn release(&mut self, message: Message) -> Result<()> {
+        self.available.push(message);
+        Ok(())
+    }
 }
@@ -0,0 +1,584 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT License.
+
+//! Journal of the transitions of the work units claimed by the agent.
+//!
+//! Each transition is appended to the journal, and flushed to disk, before the
+//! agent acts on it. After a restart, the journal is replayed to resume the
+//! units which were interrupted before they started running, and to fail the
+//! others, instead of losing track of them.
+//!
+//! The journal is compacted as units finish, so it only keeps the entries of
+//! the units which are still pending.
+
+use std::collections::HashMap;
+use std::io::ErrorKind;
+use std::path::PathBuf;
+
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use tokio::fs::{self, OpenOptions};
+use tokio::io::AsyncWriteExt;
+use uuid::Uuid;
+
+use super::{TaskId, WorkSet, WorkUnit};
+
+// Number of times setting up a work set can be interrupted before its units are
+// failed, as the setup itself may be what keeps killing the agent.
+const MAX_RESUMES: usize = 2;
+
+#[derive(Clone, Copy, Debug, Deserialize, Eq, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum WorkUnitState {
+    /// Claimed from the work queue.
+    Claimed,
+    /// Its work set is being set up.
+    SettingUp,
+    /// Its work set is set up, and its task is about to run.
+    Ready,
+    /// Its task was started.
+    Running,
+    /// Its task exited, or it failed to be set up.
+    Done,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+#[serde(tag = "entry", rename_all = "snake_case")]
+enum Entry {
+    Claimed {
+        work_set: WorkSet,
+    },
+    Admitted {
+        work_set: WorkSet,
+    },
+    Transition {
+        task_id: TaskId,
+        state: WorkUnitState,
+    },
+    Resumed,
+}
+
+/// How to recover the work of the journal after an agent restart, which is
+/// empty if no work was interrupted.
+#[derive(Debug, Default, Eq, PartialEq)]
+pub struct Recovery {
+    /// Work set to set up again, of its units which were interrupted before
+    /// their tasks started running.
+    pub resume: Option<WorkSet>,
+    /// Units to fail, as their tasks were interrupted while running, setting
+    /// them up was interrupted too many times, or they were admitted alongside
+    /// other work sets.
+    pub fail: Vec<WorkUnit>,
+}
+
+impl Recovery {
+    pub fn is_empty(&self) -> bool {
+        self.resume.is_none() && self.fail.is_empty()
+    }
+}
+
+/// A work set, as replayed from the journal.
+#[derive(Clone, Debug)]
+pub struct JournaledWorkSet {
+    pub work_set: WorkSet,
+    /// Units of the work sets admitted while the work set was running.
+    pub admitted: Vec<WorkUnit>,
+    states: HashMap<TaskId, WorkUnitState>,
+    resumed: usize,
+}
+
+impl JournaledWorkSet {
+    fn new(work_set: WorkSet) -> Self {
+        Self {
+            work_set,
+            admitted: vec![],
+            states: HashMap::new(),
+            resumed: 0,
+        }
+    }
+
+    pub fn state(&self, task_id: TaskId) -> WorkUnitState {
+        self.states
+            .get(&task_id)
+            .copied()
+            .unwrap_or(WorkUnitState::Claimed)
+    }
+
+    fn is_pending(&self, unit: &WorkUnit) -> bool {
+        self.state(unit.task_id) != WorkUnitState::Done
+    }
+
+    pub fn recovery(&self) -> Recovery {
+        let mut resume = vec![];
+        let mut fail = vec![];
+
+        for unit in self
+            .work_set
+            .work_units
+            .iter()
+            .filter(|u| self.is_pending(u))
+        {
+            let running = self.state(unit.task_id) == WorkUnitState::Running;
+            if running || self.resumed >= MAX_RESUMES {
+                fail.push(unit.clone());
+            } else {
+                resume.push(unit.clone());
+            }
+        }
+
+        // Admitted work sets are not resumed, as their setup may differ from
+        // that of the journaled work set.
+        fail.extend(self.admitted.iter().filter(|u| self.is_pending(u)).cloned());
+
+        let resume = (!resume.is_empty()).then(|| WorkSet {
+            work_units: resume,
+            ..self.work_set.clone()
+        });
+
+        Recovery { resume, fail }
+    }
+
+    // Entries which replay to the pending units of the work set.
+    fn compacted(&self) -> Vec<Entry> {
+        let pending = |units: &[WorkUnit]| -> Vec<WorkUnit> {
+            units
+                .iter()
+                .filter(|u| self.is_pending(u))
+                .cloned()
+                .collect()
+        };
+
+        let mut entries = vec![Entry::Claimed {
+            work_set: WorkSet {
+                work_units: pending(&self.work_set.work_units),
+                ..self.work_set.clone()
+            },
+        }];
+
+        let admitted = pending(&self.admitted);
+        if !admitted.is_empty() {
+            entries.push(Entry::Admitted {
+                work_set: WorkSet {
+                    work_units: admitted,
+                    ..self.work_set.clone()
+                },
+            });
+        }
+
+        for unit in self.work_set.work_units.iter().chain(&self.admitted) {
+            let state = self.state(unit.task_id);
+            if state != WorkUnitState::Claimed && state != WorkUnitState::Done {
+                entries.push(Entry::Transition {
+                    task_id: unit.task_id,
+                    state,
+                });
+            }
+        }
+
+        entries.extend((0..self.resumed).map(|_| Entry::Resumed));
+        entries
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct WorkJournal {
+    path: PathBuf,
+}
+
+impl WorkJournal {
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        let path = path.into();
+        Self { path }
+    }
+
+    pub fn for_machine(machine_id: Uuid) -> Result<Self> {
+        let path = onefuzz::fs::onefuzz_root()?.join(format!("work_journal-{machine_id}.jsonl"));
+        Ok(Self::new(path))
+    }
+
+    /// Start the journal of a newly claimed work set, replacing that of any
+    /// previous one.
+    pub async fn claimed(&self, work_set: &WorkSet) -> Result<()> {
+        let entry = Entry::Claimed {
+            work_set: work_set.clone(),
+        };
+        self.replace(&[entry]).await
+    }
+
+    async fn replace(&self, entries: &[Entry]) -> Result<()> {
+        // Replace the previous journal atomically, so that a crash leaves
+        // either one of them.
+        let tmp = self.path.with_extension("tmp");
+        let mut file = fs::File::create(&tmp)
+            .await
+            .with_context(|| format!("unable to create work journal: {}", tmp.display()))?;
+        for entry in entries {
+            file.write_all(&line(entry)?).await?;
+        }
+        file.sync_all().await?;
+        fs::rename(&tmp, &self.path)
+            .await
+            .with_context(|| format!("unable to replace work journal: {}", self.path.display()))?;
+
+        Ok(())
+    }
+
+    /// Record a work set admitted alongside the journaled one.
+    pub async fn admitted(&self, work_set: &WorkSet) -> Result<()> {
+        let entry = Entry::Admitted {
+            work_set: work_set.clone(),
+        };
+        self.append(&entry).await
+    }
+
+    pub async fn record(&self, task_id: TaskId, state: WorkUnitState) -> Result<()> {
+        self.append(&Entry::Transition { task_id, state }).await?;
+
+        if state == WorkUnitState::Done {
+            self.compact().await?;
+        }
+
+        Ok(())
+    }
+
+    /// Drop the entries of the finished units, removing the journal once all
+    /// of them are.
+    pub async fn compact(&self) -> Result<()> {
+        let Some(journaled) = self.load().await? else {
+            return Ok(());
+        };
+
+        let finished = journaled
+            .work_set
+            .work_units
+            .iter()
+            .chain(&journaled.admitted)
+            .all(|unit| !journaled.is_pending(unit));
+
+        if finished {
+            self.clear().await
+        } else {
+            self.replace(&journaled.compacted()).await
+        }
+    }
+
+    async fn append(&self, entry: &Entry) -> Result<()> {
+        // Not created if missing, as entries are only valid after the work set
+        // they refer to.
+        let mut file = OpenOptions::new()
+            .append(true)
+            .open(&self.path)
+            .await
+            .with_context(|| format!("unable to open work journal: {}", self.path.display()))?;
+        file.write_all(&line(entry)?).await?;
+        file.sync_data().await?;
+
+        Ok(())
+    }
+
+    /// Replay the journal, if any.
+    pub async fn load(&self) -> Result<Option<JournaledWorkSet>> {
+        let data = match fs::read_to_string(&self.path).await {
+            Ok(data) => data,
+            Err(err) if err.kind() == ErrorKind::NotFound => return Ok(None),
+            Err(err) => {
+                return Err(err).with_context(|| {
+                    format!("unable to read work journal: {}", self.path.display())
+                })
+            }
+        };
+
+        let mut journaled: Option<JournaledWorkSet> = None;
+
+        for line in data.lines().filter(|line| !line.is_empty()) {
+            // A crash while appending can leave a partial entry.
+            let entry: Entry = match serde_json::from_str(line) {
+                Ok(entry) => entry,
+                Err(err) => {
+                    warn!("ignoring invalid work journal entry: {}", err);
+                    continue;
+                }
+            };
+
+            if let Entry::Claimed { work_set } = entry {
+                journaled = Some(JournaledWorkSet::new(work_set));
+                continue;
+            }
+
+            let current = journaled
+                .as_mut()
+                .ok_or_else(|| anyhow!("work journal entry precedes its work set"))?;
+
+            match entry {
+                Entry::Admitted { work_set } => {
+                    current.admitted.extend(work_set.work_units);
+                }
+                Entry::Transition { task_id, state } => {
+                    current.states.insert(task_id, state);
+                }
+                Entry::Resumed => {
+                    current.resumed += 1;
+                }
+                Entry::Claimed { .. } => unreachable!(),
+            }
+        }
+
+        Ok(journaled)
+    }
+
+    /// Decide how to recover the work of the journal after an agent restart.
+    pub async fn recover(&self) -> Result<Recovery> {
+        let recovery = match self.load().await? {
+            Some(journaled) => journaled.recovery(),
+            None => Recovery::default(),
+        };
+
+        if recovery.resume.is_some() {
+            self.append(&Entry::Resumed).await?;
+        }
+
+        Ok(recovery)
+    }
+
+    pub async fn clear(&self) -> Result<()> {
+        match fs::remove_file(&self.path).await {
+            Ok(()) => Ok(()),
+            Err(err) if err.kind() == ErrorKind::NotFound => Ok(()),
+            Err(err) => Err(err)
+                .with_context(|| format!("unable to remove work journal: {}", self.path.display())),
+        }
+    }
+}
+
+fn line(entry: &Entry) -> Result<Vec<u8>> {
+    let mut data = serde_json::to_vec(entry)?;
+    data.push(b'\n');
+    Ok(data)
+}
+
+#[cfg(test)]
+mod tests {
+    use reqwest::Url;
+    use storage_queue::QueueClient;
+
+    use super::*;
+    use crate::work::{test_work_set, test_work_unit};
+
+    fn work_set() -> WorkSet {
+        test_work_set(vec![
+            test_work_unit("eb8ee6b8-6f2d-43b1-aec2-022e9813e86b".parse().unwrap()),
+            test_work_unit("ed1eeec9-2f39-442d-9e70-563454b866c0".parse().unwrap()),
+        ])
+    }
+
+    // Claims the work set of the queue, as the agent would.
+    async fn claim(queue: &QueueClient, journal: &WorkJournal) -> Result<WorkSet> {
+        let message = queue
+            .pop()
+            .await?
+            .ok_or_else(|| anyhow!("missing work set"))?;
+        let work_set: WorkSet = message.get()?;
+        journal.claimed(&work_set).await?;
+        message.delete().await?;
+        Ok(work_set)
+    }
+
+    #[tokio::test]
+    async fn test_recover_from_file_queue() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let url = Url::from_file_path(dir.path().join("work-queue"))
+            .map_err(|_| anyhow!("invalid queue path"))?;
+        let queue = QueueClient::new(url)?;
+        queue.enqueue_raw(&serde_json::to_vec(&work_set())?).await?;
+
+        let journal = WorkJournal::new(dir.path().join("journal.jsonl"));
+        assert!(journal.recover().await?.is_empty());
+
+        let work_set = claim(&queue, &journal).await?;
+        assert!(queue.pop().await?.is_none());
+
+        // interrupted while setting up
+        for unit in &work_set.work_units {
+            journal
+                .record(unit.task_id, WorkUnitState::SettingUp)
+                .await?;
+        }
+        assert_eq!(
+            journal.recover().await?,
+            Recovery {
+                resume: Some(work_set.clone()),
+                fail: vec![],
+            }
+        );
+
+        // interrupted while running, after one of the tasks exited
+        let [done, running] = &work_set.work_units[..] else {
+            bail!("unexpected work units");
+        };
+        journal.record(done.task_id, WorkUnitState::Running).await?;
+        journal
+            .record(running.task_id, WorkUnitState::Running)
+            .await?;
+        journal.record(done.task_id, WorkUnitState::Done).await?;
+        assert_eq!(
+            journal.recover().await?,
+            Recovery {
+                resume: None,
+                fail: vec![running.clone()],
+            }
+        );
+
+        // removed once all of the units finished
+        journal.record(running.task_id, WorkUnitState::Done).await?;
+        assert!(journal.recover().await?.is_empty());
+        assert!(journal.load().await?.is_none());
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_recover_fails_repeated_setup() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let journal = WorkJournal::new(dir.path().join("journal.jsonl"));
+        let work_set = work_set();
+        journal.claimed(&work_set).await?;
+
+        for _ in 0..MAX_RESUMES {
+            let recovery = journal.recover().await?;
+            assert_eq!(recovery.resume, Some(work_set.clone()));
+        }
+        assert_eq!(
+            journal.recover().await?,
+            Recovery {
+                resume: None,
+                fail: work_set.work_units.clone(),
+            }
+        );
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_recover_fails_admitted() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let journal = WorkJournal::new(dir.path().join("journal.jsonl"));
+        let mut work_set = work_set();
+        let admitted = WorkSet {
+            work_units: work_set.work_units.split_off(1),
+            ..work_set.clone()
+        };
+
+        journal.claimed(&work_set).await?;
+        let task_id = work_set.work_units[0].task_id;
+        journal.record(task_id, WorkUnitState::Running).await?;
+        journal.admitted(&admitted).await?;
+        journal.record(task_id, WorkUnitState::Done).await?;
+
+        // interrupted while setting up the admitted work set
+        let admitted_task_id = admitted.work_units[0].task_id;
+        journal
+            .record(admitted_task_id, WorkUnitState::SettingUp)
+            .await?;
+        assert_eq!(
+            journal.recover().await?,
+            Recovery {
+                resume: None,
+                fail: admitted.work_units.clone(),
+            }
+        );
+
+        journal
+            .record(admitted_task_id, WorkUnitState::Done)
+            .await?;
+        assert!(journal.recover().await?.is_empty());
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_recover_fails_only_running_units() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let journal = WorkJournal::new(dir.path().join("journal.jsonl"));
+        let work_set = work_set();
+        journal.claimed(&work_set).await?;
+
+        let [running, ready] = &work_set.work_units[..] else {
+            bail!("unexpected work units");
+        };
+        journal
+            .record(running.task_id, WorkUnitState::Running)
+            .await?;
+        journal.record(ready.task_id, WorkUnitState::Ready).await?;
+
+        assert_eq!(
+            journal.recover().await?,
+            Recovery {
+                resume: Some(WorkSet {
+                    work_units: vec![ready.clone()],
+                    ..work_set.clone()
+                }),
+                fail: vec![running.clone()],
+            }
+        );
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_compact() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let journal = WorkJournal::new(dir.path().join("journal.jsonl"));
+        let mut work_set = work_set();
+        let admitted = WorkSet {
+            work_units: work_set.work_units.split_off(1),
+            ..work_set.clone()
+        };
+
+        journal.claimed(&work_set).await?;
+        let task_id = work_set.work_units[0].task_id;
+        let admitted_task_id = admitted.work_units[0].task_id;
+        journal.record(task_id, WorkUnitState::Running).await?;
+        journal.admitted(&admitted).await?;
+        journal
+            .record(admitted_task_id, WorkUnitState::Running)
+            .await?;
+        journal.record(task_id, WorkUnitState::Done).await?;
+
+        // only the entries of the admitted unit are left
+        let data = fs::read_to_string(&journal.path).await?;
+        assert_eq!(data.lines().count(), 3, "{data}");
+        let journaled = journal
+            .load()
+            .await?
+            .ok_or_else(|| anyhow!("missing work set"))?;
+        assert!(journaled.work_set.work_units.is_empty());
+        assert_eq!(journaled.admitted, admitted.work_units);
+        assert_eq!(journaled.state(admitted_task_id), WorkUnitState::Running);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_load_ignores_partial_entry() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let journal = WorkJournal::new(dir.path().join("journal.jsonl"));
+        let work_set = work_set();
+        journal.claimed(&work_set).await?;
+
+        let task_id = work_set.work_units[0].task_id;
+        journal.record(task_id, WorkUnitState::Running).await?;
+
+        let mut data = fs::read(&journal.path).await?;
+        data.extend_from_slice(br#"{"entry":"transition","task_id":"#);
+        fs::write(&journal.path, data).await?;
+
+        let journaled = journal
+            .load()
+            .await?
+            .ok_or_else(|| anyhow!("missing work set"))?;
+        assert_eq!(journaled.work_set, work_set);
+        assert_eq!(journaled.state(task_id), WorkUnitState::Running);
+
+        Ok(())
+    }
+}
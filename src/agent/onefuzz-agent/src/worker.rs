@@ -23,6 +23,7 @@ use tokio::{
 use url::Url;
 use uuid::Uuid;
 
+use crate::resources::Allocation;
 use crate::work::*;
 use crate::{buffer::TailBuffer, log_uploader::Uploader};
 
@@ -47,6 +48,15 @@ pub enum WorkerEvent {
     },
 }
 
+#[derive(Clone, Copy, Debug, Deserialize, Eq, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum WorkerState {
+    Ready,
+    Running,
+    Stopping,
+    Done,
+}
+
 #[derive(Debug)]
 pub enum Worker {
     Ready(State<Ready>),
@@ -61,11 +71,13 @@ impl Worker {
         setup_dir: PathBuf,
         extra_setup_dir: Option<PathBuf>,
         work: WorkUnit,
+        allocation: Allocation,
     ) -> Self {
         let ctx = Ready {
             work_dir,
             setup_dir,
             extra_setup_dir,
+            allocation,
         };
 
         let state = State { ctx, work };
@@ -76,6 +88,24 @@ impl Worker {
         matches!(self, Worker::Done(..))
     }
 
+    pub fn state(&self) -> WorkerState {
+        match self {
+            Worker::Ready(..) => WorkerState::Ready,
+            Worker::Running(..) => WorkerState::Running,
+            Worker::Stopping(..) => WorkerState::Stopping,
+            Worker::Done(..) => WorkerState::Done,
+        }
+    }
+
+    pub fn work(&self) -> &WorkUnit {
+        match self {
+            Worker::Ready(state) => state.work(),
+            Worker::Running(state) => state.work(),
+            Worker::Stopping(state) => state.work(),
+            Worker::Done(state) => state.work(),
+        }
+    }
+
     pub async fn update(
         self,
         events: &mut Vec<WorkerEvent>,
@@ -125,6 +155,7 @@ pub struct Ready {
     work_dir: PathBuf,
     setup_dir: PathBuf,
     extra_setup_dir: Option<PathBuf>,
+    allocation: Allocation,
 }
 
 #[derive(Debug)]
@@ -181,6 +212,7 @@ impl State<Ready> {
                 &self.ctx.setup_dir,
                 self.ctx.extra_setup_dir,
                 &self.work,
+                &self.ctx.allocation,
                 from_agent_to_task_endpoint,
                 from_task_to_agent_endpoint,
             )
@@ -413,6 +445,7 @@ pub trait IWorkerRunner: Downcast {
         setup_dir: &Path,
         extra_setup_dir: Option<PathBuf>,
         work: &WorkUnit,
+        allocation: &Allocation,
         from_agent_to_task_endpoint: String,
         from_task_to_agent_endpoint: String,
     ) -> Result<Box<dyn IWorkerChild>>;
@@ -445,6 +478,7 @@ impl IWorkerRunner for WorkerRunner {
         setup_dir: &Path,
         extra_setup_dir: Option<PathBuf>,
         work: &WorkUnit,
+        allocation: &Allocation,
         from_agent_to_task_endpoint: String,
         from_task_to_agent_endpoint: String,
     ) -> Result<Box<dyn IWorkerChild>> {
@@ -505,6 +539,11 @@ impl IWorkerRunner for WorkerRunner {
             cmd.env(k, v);
         }
 
+        if onefuzz_telemetry::metrics::is_enabled() {
+            let metrics_path = working_dir.join(crate::metrics::TASK_METRICS_FILE);
+            cmd.env("ONEFUZZ_METRICS_FILE", metrics_path);
+        }
+
         cmd.arg("managed");
         cmd.arg(config_path);
         cmd.arg(setup_dir);
@@ -516,7 +555,18 @@ impl IWorkerRunner for WorkerRunner {
         cmd.stderr(Stdio::piped());
         cmd.stdout(Stdio::piped());
 
-        Ok(Box::new(RedirectedChild::spawn(cmd)?))
+        allocation.pin(&mut cmd)?;
+
+        #[allow(unused_mut)]
+        let mut child = RedirectedChild::spawn(cmd)?;
+
+        #[cfg(target_os = "linux")]
+        match allocation.confine(work.task_id, child.child.id()) {
+            Ok(cgroup) => child.cgroup = cgroup,
+            Err(err) => warn!("unable to limit memory of task {}: {:?}", work.task_id, err),
+        }
+
+        Ok(Box::new(child))
     }
 }
 
@@ -557,6 +607,10 @@ struct RedirectedChild {
 
     /// Worker threads which continuously read from the redirected streams.
     streams: Option<StreamReaderThreads>,
+
+    /// Cgroup limiting the memory of the child process, if any.
+    #[cfg(target_os = "linux")]
+    cgroup: Option<onefuzz::cgroup::Cgroup>,
 }
 
 impl RedirectedChild {
@@ -572,7 +626,12 @@ impl RedirectedChild {
         let stdout = child.stdout.take().unwrap();
         let streams = Some(StreamReaderThreads::new(stderr, stdout));
 
-        Ok(Self { child, streams })
+        Ok(Self {
+            child,
+            streams,
+            #[cfg(target_os = "linux")]
+            cgroup: None,
+        })
     }
 }
 
@@ -668,6 +727,13 @@ impl IWorkerChild for RedirectedChild {
                 .ok_or_else(|| format_err!("onefuzz-task streams not captured"))?
                 .join()?;
 
+            #[cfg(target_os = "linux")]
+            if let Some(cgroup) = self.cgroup.take() {
+                if let Err(err) = cgroup.remove() {
+                    warn!("unable to remove task cgroup: {:?}", err);
+                }
+            }
+
             Some(Output {
                 exit_status,
                 stderr: streams.stderr,
@@ -17,6 +17,7 @@ impl IWorkerRunner for WorkerRunnerDouble {
         _setup_dir: &Path,
         _extra_setup_dir: Option<PathBuf>,
         _work: &WorkUnit,
+        _allocation: &Allocation,
         from_agent_to_task_endpoint: String,
         from_task_to_agent_endpoint: String,
     ) -> Result<Box<dyn IWorkerChild>> {
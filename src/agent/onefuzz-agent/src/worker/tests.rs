@@ -21,6 +21,7 @@ impl Fixture {
             task_id,
             config,
             env: std::collections::HashMap::new(),
+            resources: Default::default(),
         }
     }
 
@@ -63,6 +64,7 @@ impl IWorkerRunner for RunnerDouble {
         _setup_dir: &Path,
         _extra_setup_dir: Option<PathBuf>,
         _work: &WorkUnit,
+        _allocation: &Allocation,
         from_agent_to_task_endpoint: String,
         from_task_to_agent_endpoint: String,
     ) -> Result<Box<dyn IWorkerChild>> {
@@ -97,6 +99,7 @@ async fn test_ready_run() {
             work_dir: PathBuf::default(),
             setup_dir: PathBuf::default(),
             extra_setup_dir: None,
+            allocation: Allocation::default(),
         },
         work: Fixture.work(),
     };
@@ -200,6 +203,7 @@ async fn test_worker_ready_update() {
             work_dir: PathBuf::default(),
             setup_dir: PathBuf::default(),
             extra_setup_dir: None,
+            allocation: Allocation::default(),
         },
         work: Fixture.work(),
     };
@@ -0,0 +1,172 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT License.
+
+//! Compare two `coverage.json` or `source-coverage.json` snapshots, and report
+//! the blocks or lines that were reached by one but not the other.
+
+use std::path::{Path, PathBuf};
+
+use anyhow::{bail, Context, Result};
+use clap::Parser;
+use cobertura::CoberturaCoverage;
+use coverage::allowlist::AllowList;
+use coverage::binary::BinaryCoverage;
+use coverage::source::{binary_to_source_coverage, SourceCoverage};
+use onefuzz_file_format::coverage::{
+    binary::BinaryCoverageJson,
+    diff::{BinaryCoverageDiffJson, SourceCoverageDiffJson},
+    source::SourceCoverageJson,
+};
+
+#[derive(Parser, Debug)]
+struct Args {
+    /// Older coverage snapshot.
+    old: PathBuf,
+
+    /// Newer coverage snapshot.
+    new: PathBuf,
+
+    #[arg(short, long, value_enum, default_value_t = OutputFormat::Text)]
+    output: OutputFormat,
+
+    /// Convert binary coverage snapshots to source coverage before comparing.
+    ///
+    /// Requires the covered modules and their debug info to be present at the
+    /// paths recorded in the snapshots. Implied by `--output cobertura`.
+    #[arg(long)]
+    source: bool,
+
+    /// Source allowlist used when converting binary coverage.
+    #[arg(long)]
+    source_allowlist: Option<String>,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, clap::ValueEnum)]
+enum OutputFormat {
+    Text,
+    Json,
+    Cobertura,
+}
+
+enum Snapshot {
+    Binary(BinaryCoverage),
+    Source(SourceCoverage),
+}
+
+impl Snapshot {
+    fn load(path: &Path) -> Result<Self> {
+        let text = std::fs::read_to_string(path)
+            .with_context(|| format!("reading coverage file {}", path.display()))?;
+
+        if let Ok(json) = BinaryCoverageJson::deserialize(&text) {
+            return Ok(Self::Binary(json.try_into()?));
+        }
+
+        if let Ok(json) = SourceCoverageJson::deserialize(&text) {
+            return Ok(Self::Source(json.try_into()?));
+        }
+
+        bail!("{} is not a binary or source coverage file", path.display())
+    }
+
+    fn into_source(self, allowlist: &AllowList) -> Result<SourceCoverage> {
+        match self {
+            Self::Binary(binary) => binary_to_source_coverage(&binary, allowlist)
+                .context("converting binary coverage to source coverage"),
+            Self::Source(source) => Ok(source),
+        }
+    }
+}
+
+fn main() -> Result<()> {
+    let args = Args::parse();
+
+    let old = Snapshot::load(&args.old)?;
+    let new = Snapshot::load(&args.new)?;
+
+    let to_source = args.source || args.output == OutputFormat::Cobertura;
+
+    if !to_source {
+        match (&old, &new) {
+            (Snapshot::Binary(old), Snapshot::Binary(new)) => {
+                return print_binary_diff(old, new, args.output);
+            }
+            (Snapshot::Source(_), Snapshot::Source(_)) => {}
+            _ => bail!("cannot compare binary and source coverage without `--source`"),
+        }
+    }
+
+    let allowlist = args
+        .source_allowlist
+        .map(AllowList::load)
+        .unwrap_or_else(|| Ok(AllowList::default()))
+        .context("loading source allowlist")?;
+
+    let old = old.into_source(&allowlist)?;
+    let new = new.into_source(&allowlist)?;
+    print_source_diff(&old, &new, args.output)
+}
+
+fn print_binary_diff(
+    old: &BinaryCoverage,
+    new: &BinaryCoverage,
+    output: OutputFormat,
+) -> Result<()> {
+    let diff = old.diff(new);
+
+    match output {
+        OutputFormat::Text => {
+            for (module, blocks) in &diff.modules {
+                for offset in &blocks.added {
+                    println!("+ {module}+{:x}", offset.0);
+                }
+                for offset in &blocks.lost {
+                    println!("- {module}+{:x}", offset.0);
+                }
+            }
+            println!("{} blocks added, {} blocks lost", diff.added(), diff.lost());
+        }
+        OutputFormat::Json => {
+            let json = BinaryCoverageDiffJson::from(&diff);
+            println!("{}", serde_json::to_string_pretty(&json)?);
+        }
+        OutputFormat::Cobertura => {
+            // Unreachable, since Cobertura output implies `--source`.
+            bail!("Cobertura output requires source coverage");
+        }
+    }
+
+    Ok(())
+}
+
+fn print_source_diff(
+    old: &SourceCoverage,
+    new: &SourceCoverage,
+    output: OutputFormat,
+) -> Result<()> {
+    let diff = old.diff(new);
+
+    match output {
+        OutputFormat::Text => {
+            for (file, lines) in &diff.files {
+                for line in &lines.added {
+                    println!("+ {file}:{}", line.number());
+                }
+                for line in &lines.lost {
+                    println!("- {file}:{}", line.number());
+                }
+            }
+            println!("{} lines added, {} lines lost", diff.added(), diff.lost());
+        }
+        OutputFormat::Json => {
+            let json = SourceCoverageDiffJson::from(&diff);
+            println!("{}", serde_json::to_string_pretty(&json)?);
+        }
+        OutputFormat::Cobertura => {
+            let cobertura = CoberturaCoverage::from(&diff.to_source_coverage());
+            println!("{}", cobertura.to_string()?);
+        }
+    }
+
+    Ok(())
+}
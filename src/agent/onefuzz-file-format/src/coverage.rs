@@ -2,4 +2,5 @@
 // Licensed under the MIT License.
 
 pub mod binary;
+pub mod diff;
 pub mod source;
@@ -0,0 +1,66 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT License.
+
+use std::collections::BTreeMap;
+
+use coverage::diff::{BinaryCoverageDiff, SourceCoverageDiff};
+
+use crate::hex::Hex;
+
+#[derive(Default, Deserialize, Serialize)]
+pub struct BinaryCoverageDiffJson {
+    #[serde(flatten)]
+    pub modules: BTreeMap<String, ModuleCoverageDiffJson>,
+}
+
+#[derive(Default, Deserialize, Serialize)]
+pub struct ModuleCoverageDiffJson {
+    pub added: Vec<Hex>,
+    pub lost: Vec<Hex>,
+}
+
+impl From<&BinaryCoverageDiff> for BinaryCoverageDiffJson {
+    fn from(diff: &BinaryCoverageDiff) -> Self {
+        let mut json = BinaryCoverageDiffJson::default();
+
+        for (path, module) in &diff.modules {
+            let module_json = ModuleCoverageDiffJson {
+                added: module.added.iter().map(|offset| Hex(offset.0)).collect(),
+                lost: module.lost.iter().map(|offset| Hex(offset.0)).collect(),
+            };
+
+            json.modules.insert(path.to_string(), module_json);
+        }
+
+        json
+    }
+}
+
+#[derive(Default, Deserialize, Serialize)]
+pub struct SourceCoverageDiffJson {
+    #[serde(flatten)]
+    pub files: BTreeMap<String, FileCoverageDiffJson>,
+}
+
+#[derive(Default, Deserialize, Serialize)]
+pub struct FileCoverageDiffJson {
+    pub added: Vec<u32>,
+    pub lost: Vec<u32>,
+}
+
+impl From<&SourceCoverageDiff> for SourceCoverageDiffJson {
+    fn from(diff: &SourceCoverageDiff) -> Self {
+        let mut json = SourceCoverageDiffJson::default();
+
+        for (path, file) in &diff.files {
+            let file_json = FileCoverageDiffJson {
+                added: file.added.iter().map(|line| line.number()).collect(),
+                lost: file.lost.iter().map(|line| line.number()).collect(),
+            };
+
+            json.files.insert(path.to_string(), file_json);
+        }
+
+        json
+    }
+}
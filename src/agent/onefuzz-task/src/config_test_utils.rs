@@ -36,7 +36,7 @@ pub mod arbitraries {
     use reqwest::Url;
     use uuid::Uuid;
 
-    use crate::tasks::{analysis, config::CommonConfig, coverage, fuzz, merge, report};
+    use crate::tasks::{analysis, config::CommonConfig, coverage, fuzz, merge, minimize, report};
 
     prop_compose! {
         fn arb_uuid()(
@@ -113,6 +113,8 @@ pub mod arbitraries {
             SyncedDir {
                 local_path,
                 remote_path,
+                sync_mode: Default::default(),
+                retention: Default::default(),
             }
         }
     }
@@ -159,6 +161,9 @@ pub mod arbitraries {
                 extra_setup_dir,
                 extra_output,
                 min_available_memory_mb,
+                max_dequeue_count: crate::tasks::config::default_max_dequeue_count(),
+                dead_letter_queue: None,
+                sandbox: Default::default(),
                 machine_identity,
                 tags,
                 from_agent_to_task_endpoint,
@@ -272,6 +277,7 @@ pub mod arbitraries {
             input_queue in Just(None),
             readonly_inputs in prop::collection::vec(arb_synced_dir(), 10),
             coverage in arb_synced_dir(),
+            minimized_corpus in option::of(arb_synced_dir()),
             common in arb_common_config(),
         ) -> coverage::generic::Config {
             coverage::generic::Config {
@@ -285,6 +291,7 @@ pub mod arbitraries {
                 input_queue,
                 readonly_inputs,
                 coverage,
+                minimized_corpus,
                 common,
             }
         }
@@ -389,6 +396,7 @@ pub mod arbitraries {
             readonly_inputs in prop::collection::vec(arb_synced_dir(), 10),
             crashes in arb_synced_dir(),
             tools in option::of(arb_synced_dir()),
+            coverage_corpus in option::of(arb_synced_dir()),
             target_exe in arb_pathbuf(),
             target_env in prop::collection::hash_map(".*", ".*", 10),
             target_options in arb_string_vec_no_vars(),
@@ -407,6 +415,7 @@ pub mod arbitraries {
                 readonly_inputs,
                 crashes,
                 tools,
+                coverage_corpus,
                 target_exe,
                 target_env,
                 target_options,
@@ -484,4 +493,145 @@ pub mod arbitraries {
             arb_supervisor_config().boxed()
         }
     }
+
+    prop_compose! {
+        fn arb_aflpp_fuzz_config()(
+            inputs in arb_synced_dir(),
+            readonly_inputs in option::of(prop::collection::vec(arb_synced_dir(), 3)),
+            crashes in arb_synced_dir(),
+            hangs in option::of(arb_synced_dir()),
+            tools in option::of(arb_synced_dir()),
+            afl_fuzz_exe in Just("src/lib.rs".to_string()),
+            afl_env in prop::collection::hash_map(".*", ".*", 10),
+            afl_options in arb_string_vec_no_vars(),
+            target_exe in arb_pathbuf(),
+            target_env in prop::collection::hash_map(".*", ".*", 10),
+            target_options in arb_string_vec_no_vars(),
+            target_workers in any::<usize>(),
+            ensemble_sync_delay in option::of(any::<u64>()),
+            common in arb_common_config(),
+        ) -> fuzz::aflpp::Config {
+            fuzz::aflpp::Config {
+                inputs,
+                readonly_inputs,
+                crashes,
+                hangs,
+                tools,
+                afl_fuzz_exe,
+                afl_env,
+                afl_options,
+                target_exe,
+                target_env,
+                target_options,
+                target_workers,
+                ensemble_sync_delay,
+                common,
+            }
+        }
+    }
+
+    impl Arbitrary for fuzz::aflpp::Config {
+        type Parameters = ();
+        type Strategy = BoxedStrategy<Self>;
+
+        fn arbitrary_with(_args: Self::Parameters) -> Self::Strategy {
+            arb_aflpp_fuzz_config().boxed()
+        }
+    }
+
+    prop_compose! {
+        fn arb_libfuzzer_minimize_config()(
+            target_exe in arb_pathbuf(),
+            target_env in prop::collection::hash_map(".*", ".*", 10),
+            target_options in arb_string_vec_no_vars(),
+            target_timeout in option::of(any::<u64>()),
+            input_queue in Just(None),
+            crashes in option::of(arb_synced_dir()),
+            minimized_crashes in arb_synced_dir(),
+            reports in option::of(arb_synced_dir()),
+            check_fuzzer_help in any::<bool>(),
+            check_retry_count in any::<u64>(),
+            check_queue in any::<bool>(),
+            minimized_stack_depth in option::of(any::<usize>()),
+            minimize_runs in any::<u64>(),
+            minimize_timeout in any::<u64>(),
+            minimize_max_tests in any::<u64>(),
+            common in arb_common_config(),
+        ) -> minimize::libfuzzer::Config {
+            minimize::libfuzzer::Config {
+                target_exe,
+                target_env,
+                target_options,
+                target_timeout,
+                input_queue,
+                crashes,
+                minimized_crashes,
+                reports,
+                check_fuzzer_help,
+                check_retry_count,
+                check_queue,
+                minimized_stack_depth,
+                minimize_runs,
+                minimize_timeout,
+                minimize_max_tests,
+                common,
+            }
+        }
+    }
+
+    impl Arbitrary for minimize::libfuzzer::Config {
+        type Parameters = ();
+        type Strategy = BoxedStrategy<Self>;
+
+        fn arbitrary_with(_args: Self::Parameters) -> Self::Strategy {
+            arb_libfuzzer_minimize_config().boxed()
+        }
+    }
+
+    prop_compose! {
+        fn arb_generic_minimize_config()(
+            target_exe in arb_pathbuf(),
+            target_env in prop::collection::hash_map(".*", ".*", 10),
+            target_options in arb_string_vec_no_vars(),
+            target_timeout in option::of(any::<u64>()),
+            input_queue in Just(None),
+            crashes in option::of(arb_synced_dir()),
+            minimized_crashes in arb_synced_dir(),
+            reports in option::of(arb_synced_dir()),
+            check_asan_log in any::<bool>(),
+            check_debugger in any::<bool>(),
+            check_retry_count in any::<u64>(),
+            check_queue in any::<bool>(),
+            minimized_stack_depth in option::of(any::<usize>()),
+            minimize_max_tests in any::<u64>(),
+            common in arb_common_config(),
+        ) -> minimize::generic::Config {
+            minimize::generic::Config {
+                target_exe,
+                target_env,
+                target_options,
+                target_timeout,
+                input_queue,
+                crashes,
+                minimized_crashes,
+                reports,
+                check_asan_log,
+                check_debugger,
+                check_retry_count,
+                check_queue,
+                minimized_stack_depth,
+                minimize_max_tests,
+                common,
+            }
+        }
+    }
+
+    impl Arbitrary for minimize::generic::Config {
+        type Parameters = ();
+        type Strategy = BoxedStrategy<Self>;
+
+        fn arbitrary_with(_args: Self::Parameters) -> Self::Strategy {
+            arb_generic_minimize_config().boxed()
+        }
+    }
 }
@@ -155,11 +155,15 @@ pub fn get_synced_dirs(
                 Ok(SyncedDir {
                     remote_path: Some(remote_blob_url),
                     local_path: path,
+                    sync_mode: Default::default(),
+                    retention: Default::default(),
                 })
             } else {
                 Ok(SyncedDir {
                     remote_path: None,
                     local_path: path.clone(),
+                    sync_mode: Default::default(),
+                    retention: Default::default(),
                 })
             }
         })
@@ -185,11 +189,15 @@ pub fn get_synced_dir(
         Ok(SyncedDir {
             remote_path: Some(remote_blob_url),
             local_path: path,
+            sync_mode: Default::default(),
+            retention: Default::default(),
         })
     } else {
         Ok(SyncedDir {
             remote_path: None,
             local_path: remote_path,
+            sync_mode: Default::default(),
+            retention: Default::default(),
         })
     }
 }
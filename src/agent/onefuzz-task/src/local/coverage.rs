@@ -69,6 +69,7 @@ pub fn build_coverage_config(
         input_queue,
         readonly_inputs,
         coverage,
+        minimized_corpus: None,
         common,
     };
 
@@ -189,6 +190,7 @@ impl Template<Coverage> for Coverage {
             },
             coverage_filter: None,
             coverage: context.to_monitored_sync_dir("coverage", self.coverage.clone())?,
+            minimized_corpus: None,
             module_allowlist: self.module_allowlist.clone(),
             source_allowlist: self.source_allowlist.clone(),
         };
@@ -100,6 +100,9 @@ impl Template<CrashReport> for CrashReport {
             check_retry_count: self.check_retry_count,
             check_queue: self.check_queue,
             minimized_stack_depth: self.minimized_stack_depth,
+            stack_filter: Default::default(),
+            crash_bucketing: None,
+            sarif_output: false,
             common: CommonConfig {
                 task_id: uuid::Uuid::new_v4(),
                 ..context.common.clone()
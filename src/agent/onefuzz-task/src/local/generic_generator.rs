@@ -74,6 +74,7 @@ impl Template<Generator> for Generator {
                 .tools
                 .as_ref()
                 .and_then(|path_buf| context.to_monitored_sync_dir("tools", path_buf).ok()),
+            coverage_corpus: None,
 
             target_exe: self.target_exe.clone(),
             target_env: self.target_env.clone(),
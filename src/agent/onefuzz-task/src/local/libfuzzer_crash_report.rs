@@ -93,6 +93,9 @@ impl Template<LibfuzzerCrashReport> for LibfuzzerCrashReport {
             check_fuzzer_help: self.check_fuzzer_help,
             check_retry_count: self.check_retry_count,
             minimized_stack_depth: self.minimized_stack_depth,
+            stack_filter: Default::default(),
+            crash_bucketing: None,
+            sarif_output: false,
             check_queue: self.check_queue,
             common: CommonConfig {
                 task_id: uuid::Uuid::new_v4(),
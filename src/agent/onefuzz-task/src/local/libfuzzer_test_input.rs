@@ -56,6 +56,9 @@ impl Template<LibfuzzerTestInput> for LibfuzzerTestInput {
                 target_timeout: c.target_timeout,
                 check_retry_count: c.check_retry_count,
                 minimized_stack_depth: c.minimized_stack_depth,
+                stack_filter: &Default::default(),
+                symbolizer: &Default::default(),
+                sandbox: &Default::default(),
                 machine_identity: MachineIdentity {
                     machine_id: uuid::Uuid::new_v4(),
                     machine_name: "local".to_string(),
@@ -161,11 +161,15 @@ impl RunContext {
             Ok(SyncedDir {
                 remote_path: Some(remote_blob_url),
                 local_path: path,
+                sync_mode: Default::default(),
+                retention: Default::default(),
             })
         } else {
             Ok(SyncedDir {
                 remote_path: None,
                 local_path: PathBuf::from(path),
+                sync_mode: Default::default(),
+                retention: Default::default(),
             })
         }
     }
@@ -64,6 +64,9 @@ impl Template<TestInput> for TestInput {
                 check_asan_log: c.check_asan_log,
                 check_debugger: c.check_debugger,
                 minimized_stack_depth: c.minimized_stack_depth,
+                stack_filter: &Default::default(),
+                symbolizer: &Default::default(),
+                sandbox: &Default::default(),
                 machine_identity: MachineIdentity {
                     machine_id: uuid::Uuid::new_v4(),
                     machine_name: "local".to_string(),
@@ -222,6 +222,12 @@ impl TerminalUi {
                 | EventData::CoveragePathsFound(_)
                 | EventData::CoveragePathsImported(_)
                 | EventData::CoverageMaxDepth(_)
+                | EventData::CoverageEdgesFound(_)
+                | EventData::CoverageEdgesTotal(_)
+                | EventData::CyclesDone(_)
+                | EventData::Stability(_)
+                | EventData::SavedCrashes(_)
+                | EventData::SavedHangs(_)
         )
     }
 
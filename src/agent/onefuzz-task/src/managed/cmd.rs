@@ -1,5 +1,6 @@
 // Copyright (c) Microsoft Corporation.
 // Licensed under the MIT License.
+use std::net::SocketAddr;
 use std::path::PathBuf;
 
 use anyhow::Result;
@@ -15,6 +16,13 @@ use tokio::task;
 use onefuzz_task_lib::tasks::config::{CommonConfig, Config};
 
 const OOM_CHECK_INTERVAL: Duration = Duration::from_secs(5);
+const METRICS_SNAPSHOT_INTERVAL: Duration = Duration::from_secs(15);
+
+/// Address at which to serve Prometheus metrics for this task.
+const METRICS_ADDR_ENV: &str = "ONEFUZZ_METRICS_ADDR";
+
+/// Path to which to periodically save metrics, to be served by the agent.
+const METRICS_FILE_ENV: &str = "ONEFUZZ_METRICS_FILE";
 
 pub async fn run(args: &clap::ArgMatches) -> Result<()> {
     let _logger = Logger::try_with_env_or_str("info")?
@@ -93,6 +101,7 @@ pub async fn run(args: &clap::ArgMatches) -> Result<()> {
     });
 
     init_telemetry(config.common()).await;
+    init_metrics()?;
 
     let min_available_memory_bytes = 1_000_000 * config.common().min_available_memory_mb;
 
@@ -184,6 +193,45 @@ async fn init_telemetry(config: &CommonConfig) {
     .await;
 }
 
+fn init_metrics() -> Result<()> {
+    let addr = std::env::var(METRICS_ADDR_ENV).ok();
+    let path = std::env::var_os(METRICS_FILE_ENV).map(PathBuf::from);
+
+    if addr.is_none() && path.is_none() {
+        return Ok(());
+    }
+
+    onefuzz_telemetry::metrics::enable();
+
+    if let Some(addr) = addr {
+        let addr: SocketAddr = addr
+            .parse()
+            .map_err(|err| anyhow::format_err!("invalid {METRICS_ADDR_ENV}: {addr}: {err}"))?;
+        info!("serving metrics at http://{}/metrics", addr);
+
+        task::spawn(async move {
+            let collect = onefuzz_telemetry::metrics::snapshot;
+            if let Err(err) = onefuzz_telemetry::metrics::serve(addr, collect).await {
+                error!("unable to serve metrics: {:?}", err);
+            }
+        });
+    }
+
+    if let Some(path) = path {
+        task::spawn(async move {
+            loop {
+                tokio::time::sleep(METRICS_SNAPSHOT_INTERVAL).await;
+
+                if let Err(err) = onefuzz_telemetry::metrics::write_snapshot(&path).await {
+                    warn!("unable to save metrics to {}: {:?}", path.display(), err);
+                }
+            }
+        });
+    }
+
+    Ok(())
+}
+
 const CONFIG_ARG: &str = "config";
 const SETUP_DIR_ARG: &str = "setup_dir";
 const EXTRA_SETUP_DIR_ARG: &str = "extra_setup_dir";
@@ -6,7 +6,10 @@ use crate::tasks::{
     utils::try_resolve_setup_relative_path,
 };
 use anyhow::{Context, Result};
-use onefuzz::{az_copy, blob::url::BlobUrl};
+use onefuzz::{
+    az_copy,
+    blob::{url::BlobUrl, BlobClient},
+};
 use onefuzz::{
     expand::Expand,
     fs::{set_executable, OwnedDir},
@@ -221,6 +224,11 @@ async fn _copy(input_url: BlobUrl, destination_folder: &OwnedDir) -> Result<Path
         BlobUrl::LocalFile(path) => {
             tokio::fs::copy(path, &destination_path).await?;
         }
+        BlobUrl::S3(input_url) => {
+            BlobClient::new()
+                .get_file(&input_url, &destination_path)
+                .await?;
+        }
     }
     Ok(destination_path)
 }
@@ -7,12 +7,13 @@ use crate::tasks::coverage;
 use crate::tasks::{
     analysis, fuzz,
     heartbeat::{init_task_heartbeat, TaskHeartbeatClient},
-    merge, regression, report,
+    merge, minimize, regression, report,
 };
 use anyhow::{Context, Result};
 use onefuzz::{
     expand::Expand,
     machine_id::MachineIdentity,
+    sandbox::SandboxConfig,
     syncdir::{SyncOperation, SyncedDir},
 };
 use onefuzz_result::job_result::{init_job_result, TaskJobResultClient};
@@ -27,15 +28,21 @@ use std::{
     path::{Path, PathBuf},
     time::Duration,
 };
+use storage_queue::QueueClient;
 use tokio_util::sync::CancellationToken;
 use uuid::Uuid;
 
 const DEFAULT_MIN_AVAILABLE_MEMORY_MB: u64 = 100;
+const DEFAULT_MAX_DEQUEUE_COUNT: u32 = 5;
 
 pub fn default_min_available_memory_mb() -> u64 {
     DEFAULT_MIN_AVAILABLE_MEMORY_MB
 }
 
+pub fn default_max_dequeue_count() -> u32 {
+    DEFAULT_MAX_DEQUEUE_COUNT
+}
+
 #[derive(Debug, Deserialize, PartialEq, Eq, Clone)]
 pub enum ContainerType {
     #[serde(alias = "inputs")]
@@ -77,6 +84,22 @@ pub struct CommonConfig {
     #[serde(default = "default_min_available_memory_mb")]
     pub min_available_memory_mb: u64,
 
+    /// Number of times a message of the input queue can be popped before it
+    /// is considered poison, as processing its input keeps failing or killing
+    /// the task, and moved to `dead_letter_queue`.
+    #[serde(default = "default_max_dequeue_count")]
+    pub max_dequeue_count: u32,
+
+    /// Queue to move poison messages of the input queue to. If unset, they
+    /// are deleted, and reported as `poison_message` events.
+    #[serde(default)]
+    pub dead_letter_queue: Option<QueueClient>,
+
+    /// Sandbox to run the targets of the task in. Targets run unconfined
+    /// when unset.
+    #[serde(default)]
+    pub sandbox: SandboxConfig,
+
     pub machine_identity: MachineIdentity,
 
     #[serde(default)]
@@ -162,6 +185,9 @@ impl Default for CommonConfig {
             extra_setup_dir: Default::default(),
             extra_output: Default::default(),
             min_available_memory_mb: Default::default(),
+            max_dequeue_count: default_max_dequeue_count(),
+            dead_letter_queue: Default::default(),
+            sandbox: Default::default(),
             machine_identity: MachineIdentity {
                 machine_id: uuid::Uuid::new_v4(),
                 machine_name: "test".to_string(),
@@ -177,6 +203,9 @@ impl Default for CommonConfig {
 #[derive(Debug, Deserialize)]
 #[serde(tag = "task_type")]
 pub enum Config {
+    #[serde(alias = "aflpp_fuzz")]
+    AflppFuzz(fuzz::aflpp::Config),
+
     #[serde(alias = "coverage")]
     Coverage(coverage::generic::Config),
 
@@ -198,6 +227,9 @@ pub enum Config {
     #[serde(alias = "libfuzzer_merge")]
     LibFuzzerMerge(merge::libfuzzer_merge::Config),
 
+    #[serde(alias = "libfuzzer_minimize")]
+    LibFuzzerMinimize(minimize::libfuzzer::Config),
+
     #[serde(alias = "libfuzzer_regression")]
     LibFuzzerRegression(regression::libfuzzer::Config),
 
@@ -213,6 +245,9 @@ pub enum Config {
     #[serde(alias = "generic_merge")]
     GenericMerge(merge::generic::Config),
 
+    #[serde(alias = "generic_minimize")]
+    GenericMinimize(minimize::generic::Config),
+
     #[serde(alias = "generic_crash_report")]
     GenericReport(report::generic::Config),
 
@@ -240,16 +275,19 @@ impl Config {
 
     fn common_mut(&mut self) -> &mut CommonConfig {
         match self {
+            Config::AflppFuzz(c) => &mut c.common,
             Config::Coverage(c) => &mut c.common,
             Config::DotnetCoverage(c) => &mut c.common,
             Config::DotnetCrashReport(c) => &mut c.common,
             Config::LibFuzzerDotnetFuzz(c) => &mut c.common,
             Config::LibFuzzerFuzz(c) => &mut c.common,
             Config::LibFuzzerMerge(c) => &mut c.common,
+            Config::LibFuzzerMinimize(c) => &mut c.common,
             Config::LibFuzzerReport(c) => &mut c.common,
             Config::LibFuzzerRegression(c) => &mut c.common,
             Config::GenericAnalysis(c) => &mut c.common,
             Config::GenericMerge(c) => &mut c.common,
+            Config::GenericMinimize(c) => &mut c.common,
             Config::GenericReport(c) => &mut c.common,
             Config::GenericSupervisor(c) => &mut c.common,
             Config::GenericGenerator(c) => &mut c.common,
@@ -259,16 +297,19 @@ impl Config {
 
     pub fn common(&self) -> &CommonConfig {
         match self {
+            Config::AflppFuzz(c) => &c.common,
             Config::Coverage(c) => &c.common,
             Config::DotnetCoverage(c) => &c.common,
             Config::DotnetCrashReport(c) => &c.common,
             Config::LibFuzzerDotnetFuzz(c) => &c.common,
             Config::LibFuzzerFuzz(c) => &c.common,
             Config::LibFuzzerMerge(c) => &c.common,
+            Config::LibFuzzerMinimize(c) => &c.common,
             Config::LibFuzzerReport(c) => &c.common,
             Config::LibFuzzerRegression(c) => &c.common,
             Config::GenericAnalysis(c) => &c.common,
             Config::GenericMerge(c) => &c.common,
+            Config::GenericMinimize(c) => &c.common,
             Config::GenericReport(c) => &c.common,
             Config::GenericSupervisor(c) => &c.common,
             Config::GenericGenerator(c) => &c.common,
@@ -278,16 +319,19 @@ impl Config {
 
     pub fn report_event(&self) {
         let event_type = match self {
+            Config::AflppFuzz(_) => "aflpp_fuzz",
             Config::Coverage(_) => "coverage",
             Config::DotnetCoverage(_) => "dotnet_coverage",
             Config::DotnetCrashReport(_) => "dotnet_crash_report",
             Config::LibFuzzerDotnetFuzz(_) => "libfuzzer_fuzz",
             Config::LibFuzzerFuzz(_) => "libfuzzer_fuzz",
             Config::LibFuzzerMerge(_) => "libfuzzer_merge",
+            Config::LibFuzzerMinimize(_) => "libfuzzer_minimize",
             Config::LibFuzzerReport(_) => "libfuzzer_crash_report",
             Config::LibFuzzerRegression(_) => "libfuzzer_regression",
             Config::GenericAnalysis(_) => "generic_analysis",
             Config::GenericMerge(_) => "generic_merge",
+            Config::GenericMinimize(_) => "generic_minimize",
             Config::GenericReport(_) => "generic_crash_report",
             Config::GenericSupervisor(_) => "generic_supervisor",
             Config::GenericGenerator(_) => "generic_generator",
@@ -333,6 +377,13 @@ impl Config {
             dir.init().await.context("initing extra_output_dir")?;
         }
 
+        if let Some(container) = &self.common().sandbox.container {
+            container
+                .prepare(&self.common().get_expand())
+                .await
+                .context("preparing target container image")?;
+        }
+
         let sync_cancellation = CancellationToken::new();
         let background_sync_task = async {
             if let Some(dir) = extra_output_dir {
@@ -352,6 +403,7 @@ impl Config {
 
         let run_task = async {
             let result = match self {
+                Config::AflppFuzz(config) => fuzz::aflpp::AflppFuzzTask::new(config).run().await,
                 Config::Coverage(config) => {
                     coverage::generic::CoverageTask::new(config).run().await
                 }
@@ -381,12 +433,22 @@ impl Config {
                         .await
                 }
                 Config::LibFuzzerMerge(config) => merge::libfuzzer_merge::spawn(config).await,
+                Config::LibFuzzerMinimize(config) => {
+                    minimize::libfuzzer::LibFuzzerMinimizeTask::new(config)
+                        .run()
+                        .await
+                }
                 Config::GenericAnalysis(config) => analysis::generic::run(config).await,
                 Config::GenericGenerator(config) => {
                     fuzz::generator::GeneratorTask::new(config).run().await
                 }
                 Config::GenericSupervisor(config) => fuzz::supervisor::spawn(config).await,
                 Config::GenericMerge(config) => merge::generic::spawn(&config).await,
+                Config::GenericMinimize(config) => {
+                    minimize::generic::GenericMinimizeTask::new(config)
+                        .run()
+                        .await
+                }
                 Config::GenericReport(config) => {
                     report::generic::ReportTask::new(config).managed_run().await
                 }
@@ -73,7 +73,8 @@ pub struct DotnetCoverageTask {
 
 impl DotnetCoverageTask {
     pub fn new(config: Config) -> Self {
-        let poller = InputPoller::new("dotnet_coverage");
+        let poller = InputPoller::new("dotnet_coverage")
+            .with_max_dequeue_count(config.common.max_dequeue_count);
         Self { config, poller }
     }
 
@@ -141,7 +142,8 @@ impl DotnetCoverageTask {
         if let Some(queue) = &self.config.input_queue {
             info!("polling queue for new coverage inputs");
 
-            let callback = CallbackImpl::new(queue.clone(), context)?;
+            let callback = CallbackImpl::new(queue.clone(), context)?
+                .with_dead_letter_queue(self.config.common.dead_letter_queue.clone());
             self.poller.run(callback).await?;
         }
 
@@ -3,6 +3,7 @@
 
 use std::collections::HashMap;
 use std::convert::TryFrom;
+use std::ffi::{OsStr, OsString};
 use std::path::{Path, PathBuf};
 use std::process::{Command, Stdio};
 use std::sync::Arc;
@@ -13,6 +14,9 @@ use async_trait::async_trait;
 use cobertura::{CoberturaCoverage, WriteXml};
 use coverage::allowlist::AllowList;
 use coverage::binary::{BinaryCoverage, DebugInfoCache};
+use coverage::corpus::CorpusCoverage;
+use coverage::html::write_html;
+use coverage::lcov::write_lcov;
 use coverage::record::CoverageRecorder;
 use coverage::source::{binary_to_source_coverage, SourceCoverage};
 use debuggable_module::load_module::LoadModule;
@@ -21,7 +25,7 @@ use debuggable_module::path::FilePath;
 use debuggable_module::Module;
 use onefuzz::env::LD_LIBRARY_PATH;
 use onefuzz::expand::{Expand, PlaceHolder};
-use onefuzz::syncdir::SyncedDir;
+use onefuzz::syncdir::{SyncOperation, SyncedDir};
 use onefuzz_file_format::coverage::{
     binary::{v1::BinaryCoverageJson as BinaryCoverageJsonV1, BinaryCoverageJson},
     source::{v1::SourceCoverageJson as SourceCoverageJsonV1, SourceCoverageJson},
@@ -48,6 +52,8 @@ use super::COBERTURA_COVERAGE_FILE;
 const MAX_COVERAGE_RECORDING_ATTEMPTS: usize = 2;
 const COVERAGE_FILE: &str = "coverage.json";
 const SOURCE_COVERAGE_FILE: &str = "source-coverage.json";
+const LCOV_COVERAGE_FILE: &str = "lcov.info";
+const HTML_COVERAGE_FILE: &str = "coverage.html";
 
 const DEFAULT_TARGET_TIMEOUT: Duration = Duration::from_secs(120);
 
@@ -72,6 +78,11 @@ pub struct Config {
     pub readonly_inputs: Vec<SyncedDir>,
     pub coverage: SyncedDir,
 
+    /// If set, record coverage per input, and save a minimal set of inputs
+    /// that reaches the same blocks as all recorded inputs.
+    #[serde(default)]
+    pub minimized_corpus: Option<SyncedDir>,
+
     #[serde(flatten)]
     pub common: CommonConfig,
 }
@@ -98,7 +109,8 @@ pub struct CoverageTask {
 
 impl CoverageTask {
     pub fn new(config: Config) -> Self {
-        let poller = InputPoller::new("coverage");
+        let poller =
+            InputPoller::new("coverage").with_max_dequeue_count(config.common.max_dequeue_count);
         Self { config, poller }
     }
 
@@ -110,6 +122,9 @@ impl CoverageTask {
         }
 
         self.config.coverage.init_pull().await?;
+        if let Some(minimized_corpus) = &self.config.minimized_corpus {
+            minimized_corpus.init().await?;
+        }
 
         let coverage_file = self.config.coverage.local_path.join(COVERAGE_FILE);
 
@@ -166,6 +181,7 @@ impl CoverageTask {
             context.heartbeat.alive();
         }
 
+        context.save_minimized_corpus().await?;
         context.save_and_sync_coverage().await?;
         context.report_coverage_stats().await;
         context.heartbeat.alive();
@@ -173,7 +189,8 @@ impl CoverageTask {
         if let Some(queue) = &self.config.input_queue {
             info!("polling queue for new coverage inputs");
 
-            let callback = CallbackImpl::new(queue.clone(), context)?;
+            let callback = CallbackImpl::new(queue.clone(), context)?
+                .with_dead_letter_queue(self.config.common.dead_letter_queue.clone());
             self.poller.run(callback).await?;
         }
 
@@ -229,6 +246,7 @@ struct TaskContext<'a> {
     heartbeat: Option<TaskHeartbeatClient>,
     job_result: Option<TaskJobResultClient>,
     cache: Arc<DebugInfoCache>,
+    corpus: Option<CorpusCoverage<PathBuf>>,
 }
 
 impl<'a> TaskContext<'a> {
@@ -259,6 +277,10 @@ impl<'a> TaskContext<'a> {
             heartbeat,
             job_result,
             cache: Arc::new(cache),
+            corpus: config
+                .minimized_corpus
+                .as_ref()
+                .map(|_| CorpusCoverage::default()),
         })
     }
 
@@ -304,6 +326,9 @@ impl<'a> TaskContext<'a> {
             event!(coverage_empty; EventData::Path = input.display().to_string());
             metric!(coverage_empty; 1.0; EventData::Path = input.display().to_string());
         }
+        if let Some(corpus) = &mut self.corpus {
+            corpus.insert(input.to_owned(), &coverage);
+        }
         let mut self_coverage = RwLock::write(&self.coverage).await;
         self_coverage.merge(&coverage);
         Ok(())
@@ -360,58 +385,13 @@ impl<'a> TaskContext<'a> {
             .target_exe(&target_exe)
             .input_path(input);
 
-        let mut cmd = Command::new(&target_exe);
-
-        let target_options = expand.evaluate(&self.config.target_options)?;
-        cmd.args(target_options);
-
-        for (k, v) in &self.config.target_env {
-            cmd.env(k, expand.evaluate_value(v)?);
-        }
-
-        // Make shared library resolution on Linux match behavior in other tasks.
-        if cfg!(target_os = "linux") {
-            let cmd_ld_library_path = cmd
-                .get_envs()
-                .find(|(k, _)| *k == LD_LIBRARY_PATH)
-                .map(|(_, v)| v);
-
-            // Depending on user-provided values, obtain a base value for `LD_LIBRARY_PATH`, which
-            // we will update to include the local root of the setup directory.
-            let ld_library_path = match cmd_ld_library_path {
-                None => {
-                    // The user did not provide an `LD_LIBRARY_PATH`, so the child process will
-                    // inherit the current actual value (if any). It would be best to never inherit
-                    // the current environment in any user subprocess invocation, but since we do,
-                    // preserve the existing behavior.
-                    std::env::var_os(LD_LIBRARY_PATH).unwrap_or_default()
-                }
-                Some(None) => {
-                    // This is actually unreachable, since it can only occur as the result of a call
-                    // to `env_clear(LD_LIBRARY_PATH)`. Even if this could happen, we'd reset it to
-                    // the setup dir, so use the empty path as our base.
-                    "".into()
-                }
-                Some(Some(path)) => {
-                    // `LD_LIBRARY_PATH` was set by the user-provided `target_env`, and we may have
-                    // expanded some placeholder variables. Extend that.
-                    path.to_owned()
-                }
-            };
-
-            // Add the setup directory to the library path and ensure it will occur in the child
-            // environment.
-            let ld_library_path =
-                onefuzz::env::update_path(ld_library_path, &self.config.common.setup_dir)?;
-            cmd.env(LD_LIBRARY_PATH, ld_library_path);
-        }
-
-        cmd.env_remove("RUST_LOG");
-        cmd.stdin(Stdio::null());
-        cmd.stdout(Stdio::piped());
-        cmd.stderr(Stdio::piped());
-
-        Ok(cmd)
+        target_command(
+            &expand,
+            &target_exe,
+            &self.config.target_options,
+            &self.config.target_env,
+            &self.config.common.setup_dir,
+        )
     }
 
     pub async fn record_corpus(&mut self, dir: &Path) -> Result<usize> {
@@ -459,6 +439,66 @@ impl<'a> TaskContext<'a> {
         Ok(count)
     }
 
+    /// Replace the minimized corpus with a minimal set of the recorded inputs
+    /// that reaches every block they reach together.
+    ///
+    /// Later inputs are added to the minimized corpus only if they reach new
+    /// blocks, so per-input coverage is no longer needed, and is discarded.
+    pub async fn save_minimized_corpus(&mut self) -> Result<()> {
+        let (Some(corpus), Some(minimized_corpus)) =
+            (self.corpus.take(), &self.config.minimized_corpus)
+        else {
+            return Ok(());
+        };
+
+        let selected = corpus.minimize();
+        info!(
+            "minimized corpus of {} inputs to {} inputs",
+            corpus.inputs.len(),
+            selected.len()
+        );
+
+        let dir = &minimized_corpus.local_path;
+        let mut entries = fs::read_dir(dir).await?;
+        while let Some(entry) = entries.next_entry().await? {
+            if entry.file_type().await?.is_file() {
+                fs::remove_file(entry.path()).await?;
+            }
+        }
+
+        for input in &selected {
+            self.copy_to_minimized_corpus(input, dir).await?;
+        }
+
+        // Inputs dropped by minimization must also be removed remotely.
+        minimized_corpus.sync(SyncOperation::Push, true).await?;
+        Ok(())
+    }
+
+    /// Add an input to the minimized corpus if it reached new blocks.
+    async fn save_if_new_coverage(&self, input: &Path, covered_before: u64) -> Result<()> {
+        let Some(minimized_corpus) = &self.config.minimized_corpus else {
+            return Ok(());
+        };
+
+        let covered = CoverageStats::new(&*RwLock::read(&self.coverage).await).covered;
+        if covered > covered_before {
+            self.copy_to_minimized_corpus(input, &minimized_corpus.local_path)
+                .await?;
+            minimized_corpus.sync_push().await?;
+        }
+
+        Ok(())
+    }
+
+    async fn copy_to_minimized_corpus(&self, input: &Path, dir: &Path) -> Result<()> {
+        let file_name = minimized_file_name(&self.config.readonly_inputs, input)?;
+        fs::copy(input, dir.join(file_name))
+            .await
+            .with_context(|| format!("copying {} to minimized corpus", input.display()))?;
+        Ok(())
+    }
+
     pub async fn report_coverage_stats(&self) {
         use EventData::*;
 
@@ -484,6 +524,8 @@ impl<'a> TaskContext<'a> {
         binary_coverage_path: &Path,
         source_coverage_path: &Path,
         copbertura_file_path: &Path,
+        lcov_file_path: &Path,
+        html_file_path: &Path,
     ) -> Result<()> {
         let source = Self::source_coverage(coverage, source_allowlist.clone()).await?;
         let coverage = coverage.read().await;
@@ -491,6 +533,8 @@ impl<'a> TaskContext<'a> {
         Self::save_binary_coverage(&coverage, binary_coverage_path)?;
         Self::save_source_coverage(&source, source_coverage_path).await?;
         Self::save_cobertura_xml(&source, copbertura_file_path).await?;
+        Self::save_lcov(&source, lcov_file_path).await?;
+        Self::save_html(&source, html_file_path).await?;
         Ok(())
     }
 
@@ -514,6 +558,8 @@ impl<'a> TaskContext<'a> {
 
         let source_coverage_path = self.config.coverage.local_path.join(SOURCE_COVERAGE_FILE);
         let binary_coverage_path = self.config.coverage.local_path.join(COVERAGE_FILE);
+        let lcov_file_path = self.config.coverage.local_path.join(LCOV_COVERAGE_FILE);
+        let html_file_path = self.config.coverage.local_path.join(HTML_COVERAGE_FILE);
 
         Self::save_coverage(
             &self.coverage,
@@ -521,6 +567,8 @@ impl<'a> TaskContext<'a> {
             &binary_coverage_path,
             &source_coverage_path,
             &copbertura_file_path,
+            &lcov_file_path,
+            &html_file_path,
         )
         .await?;
         self.config.coverage.sync_push().await?;
@@ -538,6 +586,27 @@ impl<'a> TaskContext<'a> {
         Ok(())
     }
 
+    async fn save_lcov(source: &SourceCoverage, path: &Path) -> Result<()> {
+        let lcov_file = std::fs::File::create(path)
+            .with_context(|| format!("creating lcov coverage file {}", path.display()))?;
+        let lcov_file_writer = std::io::BufWriter::new(lcov_file);
+        write_lcov(source, lcov_file_writer)
+            .with_context(|| format!("serializing lcov coverage to {}", path.display()))?;
+        Ok(())
+    }
+
+    async fn save_html(source: &SourceCoverage, path: &Path) -> Result<()> {
+        let html_file = std::fs::File::create(path)
+            .with_context(|| format!("creating html coverage file {}", path.display()))?;
+        let html_file_writer = std::io::BufWriter::new(html_file);
+        // Source paths come from debug info, so the annotated source is only
+        // available when the task runs where the target was built.
+        let load_source = |path: &FilePath| std::fs::read_to_string(path).ok();
+        write_html(source, load_source, html_file_writer)
+            .with_context(|| format!("writing html coverage report to {}", path.display()))?;
+        Ok(())
+    }
+
     async fn save_source_coverage(source: &SourceCoverage, path: &Path) -> Result<()> {
         let json = SourceCoverageJson::V1(SourceCoverageJsonV1::from(source));
         let source_coverage_file = std::fs::File::create(path)
@@ -568,7 +637,9 @@ impl<'a> Processor for TaskContext<'a> {
     async fn process(&mut self, _url: Option<Url>, input: &Path) -> Result<()> {
         self.heartbeat.alive();
 
+        let covered_before = CoverageStats::new(&*RwLock::read(&self.coverage).await).covered;
         self.record_input(input).await?;
+        self.save_if_new_coverage(input, covered_before).await?;
         self.save_and_sync_coverage().await?;
         self.report_coverage_stats().await;
 
@@ -605,6 +676,100 @@ impl CoverageStats {
     }
 }
 
+/// Command running `target_exe` to record its coverage, with the options and
+/// environment of the target evaluated by `expand`.
+pub(crate) fn target_command(
+    expand: &Expand<'_>,
+    target_exe: &Path,
+    target_options: &[String],
+    target_env: &HashMap<String, String>,
+    setup_dir: &Path,
+) -> Result<Command> {
+    let mut cmd = Command::new(target_exe);
+
+    let target_options = expand.evaluate(target_options)?;
+    cmd.args(target_options);
+
+    for (k, v) in target_env {
+        cmd.env(k, expand.evaluate_value(v)?);
+    }
+
+    // Make shared library resolution on Linux match behavior in other tasks.
+    if cfg!(target_os = "linux") {
+        let cmd_ld_library_path = cmd
+            .get_envs()
+            .find(|(k, _)| *k == LD_LIBRARY_PATH)
+            .map(|(_, v)| v);
+
+        // Depending on user-provided values, obtain a base value for `LD_LIBRARY_PATH`, which
+        // we will update to include the local root of the setup directory.
+        let ld_library_path = match cmd_ld_library_path {
+            None => {
+                // The user did not provide an `LD_LIBRARY_PATH`, so the child process will
+                // inherit the current actual value (if any). It would be best to never inherit
+                // the current environment in any user subprocess invocation, but since we do,
+                // preserve the existing behavior.
+                std::env::var_os(LD_LIBRARY_PATH).unwrap_or_default()
+            }
+            Some(None) => {
+                // This is actually unreachable, since it can only occur as the result of a call
+                // to `env_clear(LD_LIBRARY_PATH)`. Even if this could happen, we'd reset it to
+                // the setup dir, so use the empty path as our base.
+                "".into()
+            }
+            Some(Some(path)) => {
+                // `LD_LIBRARY_PATH` was set by the user-provided `target_env`, and we may have
+                // expanded some placeholder variables. Extend that.
+                path.to_owned()
+            }
+        };
+
+        // Add the setup directory to the library path and ensure it will occur in the child
+        // environment.
+        let ld_library_path = onefuzz::env::update_path(ld_library_path, &setup_dir.to_path_buf())?;
+        cmd.env(LD_LIBRARY_PATH, ld_library_path);
+    }
+
+    cmd.env_remove("RUST_LOG");
+    cmd.stdin(Stdio::null());
+    cmd.stdout(Stdio::piped());
+    cmd.stderr(Stdio::piped());
+
+    Ok(cmd)
+}
+
+/// Name of `input` in the minimized corpus.
+///
+/// Inputs from a readonly inputs directory are prefixed with the name of its
+/// container, or of the directory itself, so that inputs with the same name in
+/// different directories do not replace each other.
+fn minimized_file_name(readonly_inputs: &[SyncedDir], input: &Path) -> Result<OsString> {
+    let file_name = input
+        .file_name()
+        .ok_or_else(|| format_err!("invalid input path: {}", input.display()))?;
+
+    let source = readonly_inputs
+        .iter()
+        .find(|dir| input.parent() == Some(dir.local_path.as_path()));
+    let Some(source) = source else {
+        return Ok(file_name.to_owned());
+    };
+
+    let namespace = match source.remote_path.as_ref().and_then(|url| url.container()) {
+        Some(container) => OsString::from(container),
+        None => source
+            .local_path
+            .file_name()
+            .map(OsStr::to_owned)
+            .unwrap_or_default(),
+    };
+
+    let mut name = namespace;
+    name.push("-");
+    name.push(file_name);
+    Ok(name)
+}
+
 #[cfg(test)]
 mod tests {
     use onefuzz::expand::PlaceHolder;
@@ -612,7 +777,66 @@ mod tests {
 
     use crate::config_test_utils::GetExpandFields;
 
-    use super::Config;
+    use super::*;
+
+    #[test]
+    fn test_target_command() -> Result<()> {
+        use onefuzz::machine_id::MachineIdentity;
+
+        let machine_identity = MachineIdentity {
+            machine_id: uuid::Uuid::new_v4(),
+            machine_name: "test".to_string(),
+            scaleset_name: None,
+        };
+        let expand = Expand::new(&machine_identity).input_path("/task/input");
+
+        let target_options = vec!["-x".to_string(), "{input}".to_string()];
+        let target_env = HashMap::from([("INPUT".to_string(), "{input}".to_string())]);
+        let cmd = target_command(
+            &expand,
+            Path::new("/setup/fuzz.exe"),
+            &target_options,
+            &target_env,
+            Path::new("/setup"),
+        )?;
+
+        assert_eq!(cmd.get_program(), "/setup/fuzz.exe");
+        let args: Vec<_> = cmd.get_args().collect();
+        assert_eq!(args, ["-x", "/task/input"]);
+
+        let env: HashMap<_, _> = cmd.get_envs().collect();
+        assert_eq!(env[OsStr::new("INPUT")], Some(OsStr::new("/task/input")));
+        if cfg!(target_os = "linux") {
+            let ld_library_path = env[OsStr::new(LD_LIBRARY_PATH)].unwrap();
+            assert!(std::env::split_paths(ld_library_path).any(|p| p == Path::new("/setup")));
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_minimized_file_name() -> Result<()> {
+        let readonly_inputs: Vec<SyncedDir> = ["corpus-a", "corpus-b"]
+            .iter()
+            .map(|name| SyncedDir {
+                local_path: PathBuf::from("/task").join(name),
+                remote_path: None,
+                sync_mode: Default::default(),
+                retention: Default::default(),
+            })
+            .collect();
+
+        let a = minimized_file_name(&readonly_inputs, Path::new("/task/corpus-a/input"))?;
+        let b = minimized_file_name(&readonly_inputs, Path::new("/task/corpus-b/input"))?;
+        assert_eq!(a, "corpus-a-input");
+        assert_eq!(b, "corpus-b-input");
+
+        // Inputs from the queue are not in a readonly inputs directory.
+        let queued = minimized_file_name(&readonly_inputs, Path::new("/tmp/download/input"))?;
+        assert_eq!(queued, "input");
+
+        Ok(())
+    }
 
     impl GetExpandFields for Config {
         fn get_expand_fields(&self) -> Vec<(PlaceHolder, String)> {
@@ -0,0 +1,598 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT License.
+
+//! Native AFL++ fuzzing task.
+//!
+//! Runs one AFL++ instance per worker in a shared sync directory: worker 0 is
+//! the main (`-M`) instance, all others are secondaries (`-S`).  The per-instance
+//! `crashes/` and `hangs/` directories are periodically harvested into the
+//! task's `SyncedDir`s, along with the `queue/` of the main instance, which
+//! imports the entries of the secondaries.  Each instance's `fuzzer_stats` is
+//! reported as `runtime_stats`.
+
+use crate::tasks::{
+    config::CommonConfig,
+    fuzz::libfuzzer::common::default_workers,
+    heartbeat::{HeartbeatSender, TaskHeartbeatClient},
+    stats::afl::read_stats,
+    utils::try_resolve_setup_relative_path,
+};
+use anyhow::{Context, Result};
+use futures::future::try_join_all;
+use onefuzz::{
+    expand::Expand,
+    fs::{has_files, list_files, set_executable, OwnedDir},
+    process::monitor_process,
+    syncdir::{continuous_sync, SyncOperation::Pull, SyncedDir},
+};
+use onefuzz_result::job_result::{JobResultData, JobResultSender, TaskJobResultClient};
+use onefuzz_telemetry::{
+    Event::{new_coverage, new_result, runtime_stats},
+    EventData,
+};
+use serde::Deserialize;
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+    process::Stdio,
+};
+use tokio::{
+    process::Command,
+    time::{sleep, Duration, Instant},
+};
+
+// Period of harvesting crashes, hangs and new corpus entries from the AFL++
+// output directories.
+const HARVEST_PERIOD: Duration = Duration::from_secs(10);
+
+// Period of reporting `fuzzer_stats` of all instances.
+const RUNTIME_STATS_PERIOD: Duration = Duration::from_secs(60);
+
+// Minimum duration between launches of the same AFL++ instance.
+const COOLOFF_PERIOD: Duration = Duration::from_secs(10);
+
+const AFL_INPUT_MARKER: &str = "@@";
+const MAIN_INSTANCE: &str = "main";
+const SECONDARY_INSTANCE_PREFIX: &str = "secondary_";
+
+#[derive(Debug, Deserialize, Clone)]
+pub struct Config {
+    pub inputs: SyncedDir,
+    pub readonly_inputs: Option<Vec<SyncedDir>>,
+    pub crashes: SyncedDir,
+    pub hangs: Option<SyncedDir>,
+    pub tools: Option<SyncedDir>,
+
+    // The service sets these from the task's `supervisor_*` options.
+    #[serde(alias = "supervisor_exe")]
+    pub afl_fuzz_exe: String,
+    #[serde(default, alias = "supervisor_env")]
+    pub afl_env: HashMap<String, String>,
+    #[serde(default, alias = "supervisor_options")]
+    pub afl_options: Vec<String>,
+
+    pub target_exe: PathBuf,
+    #[serde(default)]
+    pub target_env: HashMap<String, String>,
+    #[serde(default)]
+    pub target_options: Vec<String>,
+
+    #[serde(default = "default_workers")]
+    pub target_workers: usize,
+    pub ensemble_sync_delay: Option<u64>,
+
+    #[serde(flatten)]
+    pub common: CommonConfig,
+}
+
+impl Config {
+    pub fn get_expand(&self) -> Expand<'_> {
+        self.common
+            .get_expand()
+            .input_corpus(&self.inputs.local_path)
+            .crashes(&self.crashes.local_path)
+            .target_exe(&self.target_exe)
+            .target_options(&self.target_options)
+            .input_marker(AFL_INPUT_MARKER)
+            .set_optional_ref(&self.tools, |expand, tools| {
+                expand.tools_dir(&tools.local_path)
+            })
+    }
+}
+
+pub struct AflppFuzzTask {
+    config: Config,
+}
+
+impl AflppFuzzTask {
+    pub fn new(config: Config) -> Self {
+        Self { config }
+    }
+
+    fn workers(&self) -> usize {
+        match self.config.target_workers {
+            0 => default_workers(),
+            x => x,
+        }
+    }
+
+    fn instance_names(&self) -> Vec<String> {
+        (0..self.workers()).map(instance_name).collect()
+    }
+
+    pub async fn run(&self) -> Result<()> {
+        self.init_directories().await?;
+
+        if !has_files(&self.config.inputs.local_path).await? {
+            bail!(
+                "AFL++ requires at least one seed input: {}",
+                self.config.inputs.local_path.display()
+            );
+        }
+
+        let runtime_dir = OwnedDir::new(self.config.common.task_id.to_string());
+        runtime_dir.create_if_missing().await?;
+        let sync_dir = runtime_dir.path().join("afl_sync");
+        tokio::fs::create_dir_all(&sync_dir).await?;
+
+        let hb_client = self.config.common.init_heartbeat(None).await?;
+        let jr_client = self.config.common.init_job_result().await?;
+
+        let resync = self.continuous_sync_inputs();
+        let new_inputs = self
+            .config
+            .inputs
+            .monitor_results(new_coverage, true, &jr_client);
+        let new_crashes = self
+            .config
+            .crashes
+            .monitor_results(new_result, true, &jr_client);
+        let new_hangs = async {
+            if let Some(hangs) = &self.config.hangs {
+                hangs.monitor_results(new_result, true, &jr_client).await
+            } else {
+                Ok(())
+            }
+        };
+
+        let fuzzers = self.run_fuzzers(&sync_dir);
+        let harvest = self.harvest_results(&sync_dir);
+        let report_stats = self.report_stats(&sync_dir, &hb_client, &jr_client);
+
+        futures::try_join!(
+            resync,
+            new_inputs,
+            new_crashes,
+            new_hangs,
+            fuzzers,
+            harvest,
+            report_stats
+        )?;
+
+        Ok(())
+    }
+
+    async fn init_directories(&self) -> Result<()> {
+        self.config.inputs.init_pull().await?;
+        if let Some(readonly_inputs) = &self.config.readonly_inputs {
+            for dir in readonly_inputs {
+                dir.init_pull().await?;
+            }
+        }
+
+        if let Some(tools) = &self.config.tools {
+            tools.init_pull().await?;
+            set_executable(&tools.local_path).await?;
+        }
+
+        self.config.crashes.init().await?;
+        if let Some(hangs) = &self.config.hangs {
+            hangs.init().await?;
+        }
+
+        Ok(())
+    }
+
+    async fn continuous_sync_inputs(&self) -> Result<()> {
+        let mut dirs = vec![self.config.inputs.clone()];
+        if let Some(inputs) = &self.config.readonly_inputs {
+            dirs.extend(inputs.clone());
+        }
+        continuous_sync(&dirs, Pull, self.config.ensemble_sync_delay).await
+    }
+
+    async fn run_fuzzers(&self, sync_dir: &Path) -> Result<()> {
+        let fuzzers: Vec<_> = (0..self.workers())
+            .map(|worker_id| self.start_fuzzer_monitor(sync_dir, worker_id))
+            .collect();
+
+        try_join_all(fuzzers).await?;
+
+        Ok(())
+    }
+
+    // Restart an AFL++ instance whenever it exits.  `AFL_AUTORESUME` makes the
+    // restarted instance pick up its existing queue from the sync directory.
+    async fn start_fuzzer_monitor(&self, sync_dir: &Path, worker_id: usize) -> Result<()> {
+        loop {
+            let instant = Instant::now();
+            let mut cmd = self.build_command(sync_dir, worker_id).await?;
+
+            info!("starting AFL++ instance {}: {:?}", worker_id, cmd);
+            let child = cmd
+                .spawn()
+                .with_context(|| format!("AFL++ failed to start: {cmd:?}"))?;
+            let context = format!("afl-fuzz {}", instance_name(worker_id));
+            if let Err(err) = monitor_process(child, context, true, None).await {
+                warn!("AFL++ instance {} exited: {:?}", worker_id, err);
+            }
+
+            let runtime = instant.elapsed();
+            if runtime < COOLOFF_PERIOD {
+                sleep(COOLOFF_PERIOD - runtime).await;
+            }
+        }
+    }
+
+    async fn build_command(&self, sync_dir: &Path, worker_id: usize) -> Result<Command> {
+        let target_exe =
+            try_resolve_setup_relative_path(&self.config.common.setup_dir, &self.config.target_exe)
+                .await?;
+        let expand = self.config.get_expand().target_exe(&target_exe);
+
+        let afl_fuzz_exe = expand.evaluate_value(&self.config.afl_fuzz_exe)?;
+        let mut cmd = Command::new(afl_fuzz_exe);
+        cmd.kill_on_drop(true)
+            .env_remove("RUST_LOG")
+            .env("AFL_NO_UI", "1")
+            .env("AFL_AUTORESUME", "1")
+            .env("AFL_SKIP_CPUFREQ", "1")
+            .stdin(Stdio::null())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped());
+
+        for (k, v) in &self.config.target_env {
+            cmd.env(k, expand.evaluate_value(v)?);
+        }
+        for (k, v) in &self.config.afl_env {
+            cmd.env(k, expand.evaluate_value(v)?);
+        }
+
+        let name = instance_name(worker_id);
+        if worker_id == 0 {
+            cmd.arg("-M").arg(&name);
+            // The main instance imports corpus entries pulled from other
+            // nodes and from the read-only input containers.
+            cmd.arg("-F").arg(&self.config.inputs.local_path);
+            for dir in self.config.readonly_inputs.iter().flatten() {
+                cmd.arg("-F").arg(&dir.local_path);
+            }
+        } else {
+            cmd.arg("-S").arg(&name);
+        }
+
+        cmd.arg("-i")
+            .arg(&self.config.inputs.local_path)
+            .arg("-o")
+            .arg(sync_dir);
+        cmd.args(expand.evaluate(&self.config.afl_options)?);
+
+        cmd.arg("--").arg(&target_exe);
+        cmd.args(expand.evaluate(&self.config.target_options)?);
+
+        Ok(cmd)
+    }
+
+    async fn harvest_results(&self, sync_dir: &Path) -> Result<()> {
+        loop {
+            for name in self.instance_names() {
+                let instance_dir = sync_dir.join(&name);
+
+                harvest_dir(
+                    &instance_dir.join("crashes"),
+                    &self.config.crashes.local_path,
+                    &name,
+                    false,
+                )
+                .await?;
+
+                if let Some(hangs) = &self.config.hangs {
+                    harvest_dir(&instance_dir.join("hangs"), &hangs.local_path, &name, false)
+                        .await?;
+                }
+
+                // The main instance imports the queues of the secondaries, so
+                // only its queue is harvested, to not upload the same inputs
+                // once per instance.
+                if name == MAIN_INSTANCE {
+                    harvest_dir(
+                        &instance_dir.join("queue"),
+                        &self.config.inputs.local_path,
+                        &name,
+                        true,
+                    )
+                    .await?;
+                }
+            }
+
+            sleep(HARVEST_PERIOD).await;
+        }
+    }
+
+    async fn report_stats(
+        &self,
+        sync_dir: &Path,
+        heartbeat_client: &Option<TaskHeartbeatClient>,
+        jr_client: &Option<TaskJobResultClient>,
+    ) -> Result<()> {
+        loop {
+            sleep(RUNTIME_STATS_PERIOD).await;
+            heartbeat_client.alive();
+
+            let mut total = TotalStats::default();
+            for (worker_id, name) in self.instance_names().iter().enumerate() {
+                let stats_path = sync_dir.join(name).join("fuzzer_stats");
+                let mut stats = match read_stats(&stats_path).await {
+                    Ok(stats) => stats,
+                    Err(err) => {
+                        debug!("no AFL++ stats for instance {}: {:?}", name, err);
+                        continue;
+                    }
+                };
+
+                total.update(&stats);
+
+                stats.push(EventData::WorkerId(worker_id));
+                log_events!(runtime_stats; stats);
+            }
+
+            total.report(jr_client).await;
+        }
+    }
+}
+
+/// Stats summed across all AFL++ instances of the task.
+#[derive(Debug, Default)]
+struct TotalStats {
+    count: u64,
+    execs_sec: f64,
+    saved_crashes: u64,
+    saved_hangs: u64,
+}
+
+impl TotalStats {
+    fn update(&mut self, stats: &[EventData]) {
+        for stat in stats {
+            match stat {
+                EventData::Count(x) => self.count += x,
+                EventData::ExecsSecond(x) => self.execs_sec += x,
+                EventData::SavedCrashes(x) => self.saved_crashes += x,
+                EventData::SavedHangs(x) => self.saved_hangs += x,
+                _ => {}
+            }
+        }
+    }
+
+    async fn report(&self, jr_client: &Option<TaskJobResultClient>) {
+        metric!(
+            runtime_stats;
+            1.0;
+            EventData::Count = self.count,
+            EventData::ExecsSecond = self.execs_sec,
+            EventData::SavedCrashes = self.saved_crashes,
+            EventData::SavedHangs = self.saved_hangs
+        );
+        if let Some(jr_client) = jr_client {
+            let _ = jr_client
+                .send_direct(
+                    JobResultData::RuntimeStats,
+                    HashMap::from([
+                        ("total_count".to_string(), self.count as f64),
+                        ("execs_sec".to_string(), self.execs_sec),
+                        ("saved_crashes".to_string(), self.saved_crashes as f64),
+                        ("saved_hangs".to_string(), self.saved_hangs as f64),
+                    ]),
+                )
+                .await;
+        }
+    }
+}
+
+fn instance_name(worker_id: usize) -> String {
+    if worker_id == 0 {
+        MAIN_INSTANCE.to_string()
+    } else {
+        format!("{SECONDARY_INSTANCE_PREFIX}{worker_id}")
+    }
+}
+
+// AFL++ entry names look like `id:000003,sig:06,src:000001,time:1123,op:havoc,rep:4`.
+// Prefix them with the instance name, since ids are only unique per instance,
+// and replace the characters that are awkward in blob names and on Windows.
+fn harvested_name(instance: &str, file_name: &str) -> String {
+    let file_name = file_name.replace([':', ','], "_");
+    format!("{instance}-{file_name}")
+}
+
+// Entries that are not new findings of this task:
+// - `README.txt`, written by AFL++ into `crashes/`
+// - `orig:` entries, copies of the seeds the instance was started with
+// - `sync:` entries, imported from other instances or foreign directories,
+//   unless imported from a secondary instance and `include_secondaries` is set
+fn should_harvest(file_name: &str, include_secondaries: bool) -> bool {
+    if !file_name.starts_with("id:") || file_name.contains(",orig:") {
+        return false;
+    }
+
+    match file_name
+        .split(',')
+        .find_map(|field| field.strip_prefix("sync:"))
+    {
+        Some(instance) => include_secondaries && instance.starts_with(SECONDARY_INSTANCE_PREFIX),
+        None => true,
+    }
+}
+
+/// Copy new AFL++ entries from `src` into `dst`, including the entries
+/// imported from secondary instances if `include_secondaries` is set.
+///
+/// Files are first copied under a dotfile name and then renamed, so that
+/// directory monitors ignoring dotfiles only observe complete files.
+async fn harvest_dir(
+    src: &Path,
+    dst: &Path,
+    instance: &str,
+    include_secondaries: bool,
+) -> Result<()> {
+    if !src.is_dir() {
+        return Ok(());
+    }
+
+    for file in list_files(src).await? {
+        let file_name = match file.file_name() {
+            Some(name) => name.to_string_lossy().to_string(),
+            None => continue,
+        };
+        if !should_harvest(&file_name, include_secondaries) {
+            continue;
+        }
+
+        let name = harvested_name(instance, &file_name);
+        let destination = dst.join(&name);
+        if destination.exists() {
+            continue;
+        }
+
+        let temp = dst.join(format!(".{name}"));
+        tokio::fs::copy(&file, &temp)
+            .await
+            .with_context(|| format!("unable to copy AFL++ result: {}", file.display()))?;
+        tokio::fs::rename(&temp, &destination)
+            .await
+            .with_context(|| {
+                format!("unable to move AFL++ result into place: {}", temp.display())
+            })?;
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use onefuzz::expand::PlaceHolder;
+    use proptest::prelude::*;
+
+    use crate::config_test_utils::GetExpandFields;
+
+    use super::*;
+
+    impl GetExpandFields for Config {
+        fn get_expand_fields(&self) -> Vec<(PlaceHolder, String)> {
+            let mut params = self.common.get_expand_fields();
+            params.push((
+                PlaceHolder::InputCorpus,
+                dunce::canonicalize(&self.inputs.local_path)
+                    .unwrap()
+                    .to_string_lossy()
+                    .to_string(),
+            ));
+            params.push((
+                PlaceHolder::Crashes,
+                dunce::canonicalize(&self.crashes.local_path)
+                    .unwrap()
+                    .to_string_lossy()
+                    .to_string(),
+            ));
+            params.push((
+                PlaceHolder::TargetExe,
+                dunce::canonicalize(&self.target_exe)
+                    .unwrap()
+                    .to_string_lossy()
+                    .to_string(),
+            ));
+            params.push((PlaceHolder::TargetOptions, self.target_options.join(" ")));
+            params.push((PlaceHolder::Input, AFL_INPUT_MARKER.to_string()));
+            if let Some(dir) = &self.tools {
+                params.push((
+                    PlaceHolder::ToolsDir,
+                    dunce::canonicalize(&dir.local_path)
+                        .unwrap()
+                        .to_string_lossy()
+                        .to_string(),
+                ));
+            }
+
+            params
+        }
+    }
+
+    config_test!(Config);
+
+    #[test]
+    fn test_instance_names() {
+        assert_eq!(instance_name(0), "main");
+        assert_eq!(instance_name(3), "secondary_3");
+    }
+
+    #[test]
+    fn test_should_harvest() {
+        assert!(should_harvest(
+            "id:000000,sig:11,src:000000,time:55,op:havoc,rep:2",
+            false
+        ));
+        assert!(!should_harvest("id:000000,time:0,execs:0,orig:seed", true));
+        assert!(!should_harvest(
+            "id:000004,sync:secondary_1,src:000002",
+            false
+        ));
+        assert!(should_harvest(
+            "id:000004,sync:secondary_1,src:000002",
+            true
+        ));
+        assert!(!should_harvest("id:000005,sync:foreign,src:000000", true));
+        assert!(!should_harvest("README.txt", false));
+        assert!(!should_harvest(".state", false));
+    }
+
+    #[tokio::test]
+    async fn test_harvest_dir() -> Result<()> {
+        let src = tempfile::tempdir()?;
+        let dst = tempfile::tempdir()?;
+
+        tokio::fs::write(src.path().join("README.txt"), "readme").await?;
+        tokio::fs::write(src.path().join("id:000000,sig:11,src:000000"), "crash").await?;
+        tokio::fs::write(src.path().join("id:000001,sync:main,src:000000"), "sync").await?;
+
+        harvest_dir(src.path(), dst.path(), "main", true).await?;
+        // harvesting is idempotent
+        harvest_dir(src.path(), dst.path(), "main", true).await?;
+
+        let files = list_files(dst.path()).await?;
+        assert_eq!(
+            files,
+            vec![dst.path().join("main-id_000000_sig_11_src_000000")]
+        );
+        assert_eq!(tokio::fs::read_to_string(&files[0]).await?, "crash");
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_total_stats() {
+        let mut total = TotalStats::default();
+        total.update(&[
+            EventData::Count(10),
+            EventData::ExecsSecond(1.5),
+            EventData::SavedCrashes(1),
+        ]);
+        total.update(&[
+            EventData::Count(5),
+            EventData::ExecsSecond(2.0),
+            EventData::SavedHangs(2),
+        ]);
+        assert_eq!(total.count, 15);
+        assert_eq!(total.execs_sec, 3.5);
+        assert_eq!(total.saved_crashes, 1);
+        assert_eq!(total.saved_hangs, 2);
+    }
+}
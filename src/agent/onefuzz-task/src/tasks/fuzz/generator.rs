@@ -3,10 +3,16 @@
 
 use crate::tasks::{
     config::CommonConfig,
+    coverage::generic::target_command,
     heartbeat::{HeartbeatSender, TaskHeartbeatClient},
     utils::{self, default_bool_true, try_resolve_setup_relative_path},
 };
 use anyhow::{Context, Result};
+use coverage::{
+    allowlist::AllowList,
+    binary::{BinaryCoverage, DebugInfoCache},
+    record::CoverageRecorder,
+};
 use onefuzz::{
     expand::Expand,
     fs::set_executable,
@@ -15,16 +21,20 @@ use onefuzz::{
     sha256,
     syncdir::{continuous_sync, SyncOperation::Pull, SyncedDir},
 };
-use onefuzz_telemetry::Event::new_result;
+use onefuzz_telemetry::Event::{new_coverage, new_result};
 use serde::Deserialize;
 use std::collections::HashMap;
 use std::{
     ffi::OsString,
     path::{Path, PathBuf},
     process::Stdio,
+    sync::Arc,
+    time::Duration,
 };
 use tempfile::tempdir;
-use tokio::{fs, process::Command};
+use tokio::{fs, process::Command, task::spawn_blocking};
+
+const DEFAULT_COVERAGE_TIMEOUT: Duration = Duration::from_secs(120);
 
 #[derive(Debug, Deserialize, Clone)]
 pub struct Config {
@@ -35,6 +45,14 @@ pub struct Config {
     pub crashes: SyncedDir,
     pub tools: Option<SyncedDir>,
 
+    /// If set, record the coverage of generated inputs that do not crash, and
+    /// save the inputs which reach new blocks here.
+    ///
+    /// Using one of the `readonly_inputs` containers makes the generator
+    /// mutate these inputs in turn.
+    #[serde(default)]
+    pub coverage_corpus: Option<SyncedDir>,
+
     pub target_exe: PathBuf,
     pub target_env: HashMap<String, String>,
     pub target_options: Vec<String>,
@@ -93,6 +111,9 @@ impl GeneratorTask {
         for dir in &self.config.readonly_inputs {
             dir.init_pull().await?;
         }
+        if let Some(coverage_corpus) = &self.config.coverage_corpus {
+            coverage_corpus.init_pull().await?;
+        }
 
         let sync_task = continuous_sync(
             &self.config.readonly_inputs,
@@ -105,9 +126,24 @@ impl GeneratorTask {
             .crashes
             .monitor_results(new_result, false, &jr_client);
 
+        let coverage_corpus_monitor = async {
+            if let Some(coverage_corpus) = &self.config.coverage_corpus {
+                coverage_corpus
+                    .monitor_results(new_coverage, false, &jr_client)
+                    .await
+            } else {
+                Ok(())
+            }
+        };
+
         let fuzzer = self.fuzzing_loop(hb_client);
 
-        futures::try_join!(fuzzer, sync_task, crash_dir_monitor)?;
+        futures::try_join!(
+            fuzzer,
+            sync_task,
+            crash_dir_monitor,
+            coverage_corpus_monitor
+        )?;
         Ok(())
     }
 
@@ -127,10 +163,21 @@ impl GeneratorTask {
         .check_asan_log(self.config.check_asan_log)
         .check_debugger(self.config.check_debugger)
         .check_retry_count(self.config.check_retry_count)
+        .sandbox(self.config.common.sandbox.clone())
         .set_optional(self.config.target_timeout, |tester, timeout| {
             tester.timeout(timeout)
         });
 
+        let mut coverage_guide = match &self.config.coverage_corpus {
+            Some(coverage_corpus) => {
+                let mut guide = CoverageGuide::new(&self.config, target_exe.clone());
+                let count = guide.record_corpus(&coverage_corpus.local_path).await?;
+                info!("recorded coverage of {} existing corpus inputs", count);
+                Some(guide)
+            }
+            None => None,
+        };
+
         loop {
             for corpus_dir in &self.config.readonly_inputs {
                 heartbeat_client.alive();
@@ -141,7 +188,7 @@ impl GeneratorTask {
                 self.generate_inputs(corpus_dir, &generated_inputs_path)
                     .await
                     .context("generate inputs failed")?;
-                self.test_inputs(&generated_inputs_path, &tester)
+                self.test_inputs(&generated_inputs_path, &tester, &mut coverage_guide)
                     .await
                     .context("test inputs failed")?;
             }
@@ -152,6 +199,7 @@ impl GeneratorTask {
         &self,
         generated_inputs: impl AsRef<Path>,
         tester: &Tester<'_>,
+        coverage_guide: &mut Option<CoverageGuide<'_>>,
     ) -> Result<()> {
         let mut read_dir = fs::read_dir(generated_inputs).await?;
         while let Some(file) = read_dir.next_entry().await? {
@@ -164,14 +212,22 @@ impl GeneratorTask {
                 file.file_name()
             };
 
-            let destination_file = self.config.crashes.local_path.join(destination_file);
             if tester
                 .is_crash(file.path())
                 .await
                 .with_context(|| format!("testing input failed: {}", file.path().display()))?
             {
+                let destination_file = self.config.crashes.local_path.join(destination_file);
                 fs::rename(file.path(), &destination_file).await?;
                 debug!("crash found {}", destination_file.display());
+            } else if let (Some(guide), Some(coverage_corpus)) =
+                (coverage_guide.as_mut(), &self.config.coverage_corpus)
+            {
+                if guide.is_new_coverage(&file.path()).await {
+                    let destination_file = coverage_corpus.local_path.join(destination_file);
+                    fs::rename(file.path(), &destination_file).await?;
+                    debug!("new coverage found {}", destination_file.display());
+                }
             }
         }
         Ok(())
@@ -222,6 +278,106 @@ impl GeneratorTask {
     }
 }
 
+/// Running coverage of the inputs saved by a coverage-guided generator task.
+struct CoverageGuide<'a> {
+    config: &'a Config,
+    target_exe: PathBuf,
+    coverage: BinaryCoverage,
+    cache: Arc<DebugInfoCache>,
+}
+
+impl<'a> CoverageGuide<'a> {
+    fn new(config: &'a Config, target_exe: PathBuf) -> Self {
+        Self {
+            config,
+            target_exe,
+            coverage: BinaryCoverage::default(),
+            cache: Arc::new(DebugInfoCache::new(AllowList::default())),
+        }
+    }
+
+    /// Seed the running coverage with the inputs already in the corpus.
+    async fn record_corpus(&mut self, dir: &Path) -> Result<usize> {
+        let mut count = 0;
+        let mut read_dir = fs::read_dir(dir).await?;
+        while let Some(file) = read_dir.next_entry().await? {
+            if file.file_type().await?.is_file() {
+                self.is_new_coverage(&file.path()).await;
+                count += 1;
+            }
+        }
+        Ok(count)
+    }
+
+    /// Record the coverage of `input`, and check if it reached any blocks not
+    /// reached by previous inputs.
+    ///
+    /// Recording failures are logged, and treated as reaching no new blocks.
+    async fn is_new_coverage(&mut self, input: &Path) -> bool {
+        let recorded = match self.record(input).await {
+            Ok(recorded) => recorded,
+            Err(err) => {
+                warn!(
+                    "error recording coverage for input = {}: {:?}",
+                    input.display(),
+                    err
+                );
+                return false;
+            }
+        };
+
+        let is_new = reaches_new_blocks(&self.coverage, &recorded);
+        self.coverage.merge(&recorded);
+        is_new
+    }
+
+    async fn record(&self, input: &Path) -> Result<BinaryCoverage> {
+        let expand = self
+            .config
+            .get_expand()
+            .target_exe(&self.target_exe)
+            .input_path(input);
+
+        let cmd = target_command(
+            &expand,
+            &self.target_exe,
+            &self.config.target_options,
+            &self.config.target_env,
+            &self.config.common.setup_dir,
+        )?;
+
+        let timeout = self
+            .config
+            .target_timeout
+            .map(Duration::from_secs)
+            .unwrap_or(DEFAULT_COVERAGE_TIMEOUT);
+        let cache = self.cache.clone();
+        let recorded = spawn_blocking(move || {
+            CoverageRecorder::new(cmd)
+                .debuginfo_cache(cache)
+                .timeout(timeout)
+                .record()
+        })
+        .await??;
+
+        Ok(recorded.coverage)
+    }
+}
+
+/// Whether `recorded` reaches any block not reached by `known`.
+fn reaches_new_blocks(known: &BinaryCoverage, recorded: &BinaryCoverage) -> bool {
+    recorded.modules.iter().any(|(path, module)| {
+        let known = known.modules.get(path);
+        module.offsets.iter().any(|(offset, count)| {
+            count.reached()
+                && !known
+                    .and_then(|known| known.offsets.get(offset))
+                    .map(|count| count.reached())
+                    .unwrap_or(false)
+        })
+    })
+}
+
 #[cfg(test)]
 mod tests {
     use onefuzz::expand::PlaceHolder;
@@ -229,7 +385,34 @@ mod tests {
 
     use crate::config_test_utils::GetExpandFields;
 
-    use super::Config;
+    use super::{reaches_new_blocks, Config};
+
+    #[test]
+    fn test_reaches_new_blocks() -> anyhow::Result<()> {
+        use coverage::binary::{BinaryCoverage, Count};
+        use debuggable_module::{path::FilePath, Offset};
+
+        let main = FilePath::new("/setup/main.exe")?;
+        let coverage = |counts: &[(u64, u32)]| {
+            let mut coverage = BinaryCoverage::default();
+            let module = coverage.modules.entry(main.clone()).or_default();
+            for &(offset, count) in counts {
+                module.offsets.insert(Offset(offset), Count(count));
+            }
+            coverage
+        };
+
+        let known = coverage(&[(1, 1), (2, 0)]);
+
+        // Only reaching a block not already reached counts as new coverage.
+        assert!(!reaches_new_blocks(&known, &coverage(&[(1, 3), (2, 0)])));
+        assert!(reaches_new_blocks(&known, &coverage(&[(1, 0), (2, 1)])));
+        assert!(reaches_new_blocks(&known, &coverage(&[(3, 1)])));
+        assert!(!reaches_new_blocks(&known, &coverage(&[(3, 0)])));
+        assert!(reaches_new_blocks(&BinaryCoverage::default(), &known));
+
+        Ok(())
+    }
 
     impl GetExpandFields for Config {
         fn get_expand_fields(&self) -> Vec<(PlaceHolder, String)> {
@@ -324,18 +507,24 @@ mod tests {
                     remote_path: Some(BlobContainerUrl::parse(
                         Url::from_directory_path(inputs).unwrap(),
                     )?),
+                    sync_mode: Default::default(),
+                    retention: Default::default(),
                 }],
                 crashes: SyncedDir {
                     local_path: crashes_local,
                     remote_path: Some(BlobContainerUrl::parse(
                         Url::from_directory_path(crashes).unwrap(),
                     )?),
+                    sync_mode: Default::default(),
+                    retention: Default::default(),
                 },
                 tools: Some(SyncedDir {
                     local_path: tools_local,
                     remote_path: Some(BlobContainerUrl::parse(
                         Url::from_directory_path(radamsa_dir).unwrap(),
                     )?),
+                    sync_mode: Default::default(),
+                    retention: Default::default(),
                 }),
                 target_exe: Default::default(),
                 target_env: Default::default(),
@@ -346,6 +535,7 @@ mod tests {
                 rename_output: false,
                 ensemble_sync_delay: None,
                 generator_env: HashMap::default(),
+                coverage_corpus: None,
                 check_retry_count: 0,
                 common: Default::default(),
             };
@@ -272,7 +272,7 @@ where
         info!("config is: {:?}", self.config);
 
         let fuzzer = L::from_config(&self.config).await?;
-        let mut running = fuzzer.fuzz(crash_dir.path(), local_inputs, &inputs)?;
+        let (mut running, sandbox) = fuzzer.fuzz(crash_dir.path(), local_inputs, &inputs)?;
 
         info!("child is: {:?}", running);
 
@@ -313,6 +313,8 @@ where
 
         let exit_status: ExitStatus = exit_status?.into();
 
+        let limit_kill = sandbox.limit_kill().await;
+
         info!(
             "fuzzer exited, here are the last {} lines of stderr:",
             libfuzzer_output.len()
@@ -331,6 +333,19 @@ where
         // 1. Exited cleanly (happens with -runs=N)
         // 2. expect_crash_on_failure is disabled
         if files.is_empty() && !exit_status.success {
+            // Restarting it would only hit the same limit again.
+            if let Some(limit_kill) = limit_kill {
+                bail!(
+                    "libfuzzer was killed by its sandbox: {}.  status:{} stderr:{:?}",
+                    limit_kill,
+                    serde_json::to_string(&exit_status)?,
+                    libfuzzer_output
+                        .into_iter()
+                        .collect::<Vec<String>>()
+                        .join("\n")
+                );
+            }
+
             if self.config.expect_crash_on_failure {
                 bail!(
                     "libfuzzer exited without generating crashes.  status:{} stderr:{:?}",
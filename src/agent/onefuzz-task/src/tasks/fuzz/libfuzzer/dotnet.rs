@@ -93,7 +93,8 @@ impl common::LibFuzzerType for LibFuzzerDotnet {
                 .as_ref()
                 .map(|x| x.local_path.clone()),
             config.common.machine_identity.clone(),
-        ))
+        )
+        .with_sandbox(config.common.sandbox.clone()))
     }
 
     async fn extra_setup(config: &common::Config<Self>) -> Result<()> {
@@ -37,7 +37,8 @@ impl common::LibFuzzerType for GenericLibFuzzer {
                 .as_ref()
                 .map(|x| x.local_path.clone()),
             config.common.machine_identity.clone(),
-        ))
+        )
+        .with_sandbox(config.common.sandbox.clone()))
     }
 
     async fn extra_setup(config: &common::Config<Self>) -> Result<()> {
@@ -1,6 +1,7 @@
 // Copyright (c) Microsoft Corporation.
 // Licensed under the MIT License.
 
+pub mod aflpp;
 pub mod generator;
 pub mod libfuzzer;
 pub mod supervisor;
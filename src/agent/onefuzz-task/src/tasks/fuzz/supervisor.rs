@@ -15,6 +15,7 @@ use onefuzz::{
     fs::{has_files, set_executable, OwnedDir},
     jitter::delay_with_jitter,
     process::monitor_process,
+    sandbox::Sandbox,
     syncdir::{
         SyncOperation::{Pull, Push},
         SyncedDir,
@@ -114,6 +115,8 @@ pub async fn spawn(config: SupervisorConfig) -> Result<(), Error> {
     let crashes = SyncedDir {
         local_path: runtime_dir.path().join("crashes"),
         remote_path: config.crashes.remote_path.clone(),
+        sync_mode: config.crashes.sync_mode,
+        retention: config.crashes.retention.clone(),
     };
     crashes.init().await?;
 
@@ -127,6 +130,8 @@ pub async fn spawn(config: SupervisorConfig) -> Result<(), Error> {
             let dir = SyncedDir {
                 local_path: runtime_dir.path().join("crashdumps"),
                 remote_path: crashdumps.remote_path.clone(),
+                sync_mode: crashdumps.sync_mode,
+                retention: crashdumps.retention.clone(),
             };
             dir.init().await?;
             Some(dir)
@@ -184,6 +189,8 @@ pub async fn spawn(config: SupervisorConfig) -> Result<(), Error> {
     let inputs = SyncedDir {
         local_path: runtime_dir.path().join("inputs"),
         remote_path: config.inputs.remote_path.clone(),
+        sync_mode: config.inputs.sync_mode,
+        retention: config.inputs.retention.clone(),
     };
 
     inputs.init().await?;
@@ -206,7 +213,7 @@ pub async fn spawn(config: SupervisorConfig) -> Result<(), Error> {
     let inputs_sync_task =
         inputs.continuous_sync(Pull, config.ensemble_sync_delay, &inputs_sync_cancellation);
 
-    let process = start_supervisor(
+    let (process, sandbox) = start_supervisor(
         &runtime_dir.path(),
         &config,
         &crashes,
@@ -217,8 +224,18 @@ pub async fn spawn(config: SupervisorConfig) -> Result<(), Error> {
     .await?;
 
     let stopped = Notify::new();
-    let monitor_supervisor =
-        monitor_process(process, "supervisor".to_string(), true, Some(&stopped));
+    let monitor_supervisor = async {
+        let result = monitor_process(process, "supervisor".to_string(), true, Some(&stopped)).await;
+
+        // Fail with the limit, even if the supervisor exited successfully, as
+        // its output was already logged by `monitor_process`.
+        match sandbox.limit_kill().await {
+            Some(limit_kill) => {
+                Err(Error::new(limit_kill).context("supervisor was killed by its sandbox"))
+            }
+            None => result,
+        }
+    };
     let hb = config.common.init_heartbeat(None).await?;
 
     let heartbeat_process = heartbeat_process(&stopped, hb);
@@ -235,7 +252,7 @@ pub async fn spawn(config: SupervisorConfig) -> Result<(), Error> {
         None
     };
 
-    let monitor_stats = monitor_stats(monitor_path, config.stats_format);
+    let monitor_stats = monitor_stats(monitor_path, config.stats_format, &jr_client);
 
     futures::try_join!(
         heartbeat_process.map_err(|e| e.context("Failure in heartbeat")),
@@ -282,7 +299,7 @@ async fn start_supervisor(
     crashdumps: Option<&SyncedDir>,
     inputs: &SyncedDir,
     reports_dir: PathBuf,
-) -> Result<Child> {
+) -> Result<(Child, Sandbox)> {
     let target_exe = if let Some(target_exe) = &config.target_exe {
         Some(try_resolve_setup_relative_path(&config.common.setup_dir, target_exe).await?)
     } else {
@@ -304,10 +321,8 @@ async fn start_supervisor(
         });
 
     let supervisor_path = expand.evaluate_value(&config.supervisor_exe)?;
-    let mut cmd = Command::new(supervisor_path);
-    let cmd = cmd
-        .kill_on_drop(true)
-        .env_remove("RUST_LOG")
+    let mut cmd = std::process::Command::new(supervisor_path);
+    cmd.env_remove("RUST_LOG")
         .stdin(Stdio::null())
         .stdout(Stdio::piped())
         .stderr(Stdio::piped());
@@ -319,11 +334,17 @@ async fn start_supervisor(
         cmd.env(k, expand.evaluate_value(v)?);
     }
 
+    let sandbox = Sandbox::new(&config.common.sandbox)?;
+    sandbox.apply(&mut cmd)?;
+
+    let mut cmd = Command::from(cmd);
+    cmd.kill_on_drop(true);
+
     info!("starting supervisor '{:?}'", cmd);
     let child = cmd
         .spawn()
         .with_context(|| format!("supervisor failed to start: {cmd:?}"))?;
-    Ok(child)
+    Ok((child, sandbox))
 }
 
 #[cfg(test)]
@@ -477,6 +498,8 @@ mod tests {
                     BlobContainerUrl::parse(Url::from_directory_path(fault_dir_temp).unwrap())
                         .unwrap(),
                 ),
+                sync_mode: Default::default(),
+                retention: Default::default(),
             };
 
             let crashdumps_dir_temp = tempfile::tempdir().unwrap();
@@ -487,6 +510,8 @@ mod tests {
                     BlobContainerUrl::parse(Url::from_directory_path(crashdumps_dir_temp).unwrap())
                         .unwrap(),
                 ),
+                sync_mode: Default::default(),
+                retention: Default::default(),
             };
 
             let corpus_dir_local = tempfile::tempdir().unwrap().path().into();
@@ -497,6 +522,8 @@ mod tests {
                     BlobContainerUrl::parse(Url::from_directory_path(corpus_dir_temp).unwrap())
                         .unwrap(),
                 ),
+                sync_mode: Default::default(),
+                retention: Default::default(),
             };
             let seed_file_name = corpus_dir.local_path.join("seed.txt");
             tokio::fs::write(seed_file_name, "xyz").await.unwrap();
@@ -542,7 +569,7 @@ mod tests {
                 common: Default::default(),
             };
 
-            let process = start_supervisor(
+            let (process, _sandbox) = start_supervisor(
                 runtime_dir,
                 &config,
                 &crashes,
@@ -14,6 +14,12 @@ pub use callback::*;
 
 const POLL_INTERVAL: Duration = Duration::from_secs(10);
 
+// Time for which a polled message is hidden from other consumers, so that it
+// is not popped again while its input is being processed. It is renewed every
+// `VISIBILITY_RENEWAL_INTERVAL` while processing takes longer.
+const VISIBILITY_TIMEOUT: Duration = Duration::from_secs(5 * 60);
+const VISIBILITY_RENEWAL_INTERVAL: Duration = Duration::from_secs(60);
+
 #[cfg(test)]
 mod tests;
 
@@ -61,7 +67,7 @@ pub enum Event<'a, M> {
     Poll(&'a mut dyn Queue<M>),
     Parse(&'a mut dyn Parser<M>),
     Download(&'a mut dyn Downloader),
-    Process(&'a mut dyn Processor),
+    Process(&'a mut dyn Processor, &'a mut dyn Queue<M>),
     Finish(&'a mut dyn Queue<M>),
 }
 
@@ -103,6 +109,9 @@ pub struct InputPoller<M> {
 
     batch_dir: Option<SyncedDir>,
 
+    /// Number of times a message can be popped before it is dead-lettered.
+    max_dequeue_count: Option<u32>,
+
     name: String,
 }
 
@@ -113,10 +122,18 @@ impl<M> InputPoller<M> {
         Self {
             state,
             batch_dir: None,
+            max_dequeue_count: None,
             name,
         }
     }
 
+    /// Dead-letter messages which were popped more than `max_dequeue_count`
+    /// times, as processing their input keeps failing, or killing the task.
+    pub fn with_max_dequeue_count(mut self, max_dequeue_count: u32) -> Self {
+        self.max_dequeue_count = Some(max_dequeue_count);
+        self
+    }
+
     /// Process a given SyncedDir in batch
     pub async fn batch_process(
         &mut self,
@@ -186,6 +203,10 @@ impl<M> InputPoller<M> {
         self.state.as_ref().unwrap_or_else(|| unreachable!())
     }
 
+    fn is_poison(&self, dequeue_count: u32) -> bool {
+        matches!(self.max_dequeue_count, Some(max) if dequeue_count > max)
+    }
+
     fn set_state(&mut self, state: impl Into<Option<State<M>>>) {
         self.state = state.into();
     }
@@ -224,7 +245,10 @@ impl<M> InputPoller<M> {
             Ready => self.trigger(Poll(cb.queue())).await?,
             Polled(..) => self.trigger(Parse(cb.parser())).await?,
             Parsed(..) => self.trigger(Download(cb.downloader())).await?,
-            Downloaded(..) => self.trigger(Process(cb.processor())).await?,
+            Downloaded(..) => {
+                let (processor, queue) = cb.processor_and_queue();
+                self.trigger(Process(processor, queue)).await?
+            }
             Processed(..) => self.trigger(Finish(cb.queue())).await?,
         }
 
@@ -270,7 +294,50 @@ impl<M> InputPoller<M> {
 
         match (state, event) {
             (Ready, Poll(queue)) => {
-                let msg = queue.pop().await?;
+                let mut msg = queue.pop().await?;
+
+                let dequeue_count = msg
+                    .as_ref()
+                    .map(|msg| queue.dequeue_count(msg))
+                    .unwrap_or_default();
+                let poison = if self.is_poison(dequeue_count) {
+                    msg.take()
+                } else {
+                    None
+                };
+                if let Some(poison) = poison {
+                    let mode = match queue.dead_letter(poison).await? {
+                        None => {
+                            warn!(
+                                "dead-lettered {} message popped {} times",
+                                self.name, dequeue_count
+                            );
+                            "dead_lettered"
+                        }
+                        // deleted, rather than processed again and again
+                        Some(poison) => {
+                            queue.delete(poison).await?;
+                            error!(
+                                "deleted {} message popped {} times, with no dead-letter queue to move it to",
+                                self.name, dequeue_count
+                            );
+                            "deleted"
+                        }
+                    };
+                    event!(
+                        onefuzz_telemetry::Event::poison_message;
+                        onefuzz_telemetry::EventData::Name = self.name.as_str(),
+                        onefuzz_telemetry::EventData::Mode = mode,
+                        onefuzz_telemetry::EventData::Count = dequeue_count
+                    );
+
+                    self.set_state(Ready);
+                    return Ok(());
+                }
+
+                if let Some(msg) = &mut msg {
+                    queue.extend_visibility(msg, VISIBILITY_TIMEOUT).await?;
+                }
 
                 self.set_state(Polled(msg));
             }
@@ -298,8 +365,24 @@ impl<M> InputPoller<M> {
             // NOTE: _download_dir is a TempDir, which the physical path gets
             // deleted automatically upon going out of scope.  Keep it in-scope until
             // here.
-            (Downloaded(msg, url, input, _download_dir), Process(processor)) => {
-                processor.process(Some(url), &input).await?;
+            (Downloaded(mut msg, url, input, _download_dir), Process(processor, queue)) => {
+                let process = processor.process(Some(url), &input);
+                tokio::pin!(process);
+
+                // keep the message hidden for as long as processing takes
+                loop {
+                    queue
+                        .extend_visibility(&mut msg, VISIBILITY_TIMEOUT)
+                        .await?;
+
+                    tokio::select! {
+                        result = &mut process => {
+                            result?;
+                            break;
+                        }
+                        _ = tokio::time::sleep(VISIBILITY_RENEWAL_INTERVAL) => {}
+                    }
+                }
 
                 self.set_state(Processed(msg));
             }
@@ -2,6 +2,7 @@
 // Licensed under the MIT License.
 
 use std::path::{Path, PathBuf};
+use std::time::Duration;
 
 use anyhow::Result;
 use async_trait::async_trait;
@@ -14,6 +15,15 @@ pub trait Queue<M>: Send {
     async fn pop(&mut self) -> Result<Option<M>>;
 
     async fn delete(&mut self, msg: M) -> Result<()>;
+
+    /// Number of times `msg` was popped, including this one.
+    fn dequeue_count(&self, msg: &M) -> u32;
+
+    /// Hide `msg` from other consumers for `timeout` from now on.
+    async fn extend_visibility(&mut self, msg: &mut M, timeout: Duration) -> Result<()>;
+
+    /// Move `msg` to the dead-letter queue, returning it if there is none.
+    async fn dead_letter(&mut self, msg: M) -> Result<Option<M>>;
 }
 
 pub trait Parser<M>: Send {
@@ -38,22 +48,32 @@ pub trait Callback<M> {
     fn downloader(&mut self) -> &mut dyn Downloader;
 
     fn processor(&mut self) -> &mut dyn Processor;
+
+    /// The processor, along with the queue, to keep the message of the input
+    /// being processed hidden from other consumers.
+    fn processor_and_queue(&mut self) -> (&mut dyn Processor, &mut dyn Queue<M>);
 }
 
 pub struct CallbackImpl<P>
 where
     P: Processor + Send,
 {
-    queue: QueueClient,
+    input_queue: InputQueue,
     pub processor: P,
 }
 
+/// Queue of inputs, with the queue its poison messages are moved to.
+pub struct InputQueue {
+    queue: QueueClient,
+    dead_letter_queue: Option<QueueClient>,
+}
+
 impl<P> Callback<Message> for CallbackImpl<P>
 where
     P: Processor + Send,
 {
     fn queue(&mut self) -> &mut dyn Queue<Message> {
-        self
+        &mut self.input_queue
     }
 
     fn parser(&mut self) -> &mut dyn Parser<Message> {
@@ -67,6 +87,10 @@ where
     fn processor(&mut self) -> &mut dyn Processor {
         &mut self.processor
     }
+
+    fn processor_and_queue(&mut self) -> (&mut dyn Processor, &mut dyn Queue<Message>) {
+        (&mut self.processor, &mut self.input_queue)
+    }
 }
 
 impl<P> CallbackImpl<P>
@@ -74,15 +98,23 @@ where
     P: Processor + Send,
 {
     pub fn new(queue: QueueClient, processor: P) -> Result<Self> {
-        Ok(Self { queue, processor })
+        Ok(Self {
+            input_queue: InputQueue {
+                queue,
+                dead_letter_queue: None,
+            },
+            processor,
+        })
+    }
+
+    pub fn with_dead_letter_queue(mut self, dead_letter_queue: Option<QueueClient>) -> Self {
+        self.input_queue.dead_letter_queue = dead_letter_queue;
+        self
     }
 }
 
 #[async_trait]
-impl<P> Queue<Message> for CallbackImpl<P>
-where
-    P: Processor + Send,
-{
+impl Queue<Message> for InputQueue {
     async fn pop(&mut self) -> Result<Option<Message>> {
         self.queue.pop().await
     }
@@ -90,6 +122,24 @@ where
     async fn delete(&mut self, msg: Message) -> Result<()> {
         msg.delete().await
     }
+
+    fn dequeue_count(&self, msg: &Message) -> u32 {
+        msg.dequeue_count()
+    }
+
+    async fn extend_visibility(&mut self, msg: &mut Message, timeout: Duration) -> Result<()> {
+        msg.extend_visibility(timeout).await
+    }
+
+    async fn dead_letter(&mut self, msg: Message) -> Result<Option<Message>> {
+        match &self.dead_letter_queue {
+            Some(dead_letter_queue) => {
+                msg.move_to(dead_letter_queue).await?;
+                Ok(None)
+            }
+            None => Ok(Some(msg)),
+        }
+    }
 }
 
 impl<P> Parser<Message> for CallbackImpl<P>
@@ -15,6 +15,10 @@ struct TestQueue {
     pending: Vec<Msg>,
     popped: Vec<Msg>,
     deleted: Vec<Msg>,
+    dequeue_count: u32,
+    extended: Vec<Msg>,
+    has_dead_letter_queue: bool,
+    dead_lettered: Vec<Msg>,
 }
 
 #[async_trait]
@@ -34,6 +38,26 @@ impl Queue<Msg> for TestQueue {
 
         Ok(())
     }
+
+    fn dequeue_count(&self, _msg: &Msg) -> u32 {
+        self.dequeue_count
+    }
+
+    async fn extend_visibility(&mut self, msg: &mut Msg, _timeout: Duration) -> Result<()> {
+        self.extended.push(*msg);
+
+        Ok(())
+    }
+
+    async fn dead_letter(&mut self, msg: Msg) -> Result<Option<Msg>> {
+        if !self.has_dead_letter_queue {
+            return Ok(Some(msg));
+        }
+
+        self.dead_lettered.push(msg);
+
+        Ok(None)
+    }
 }
 
 pub struct TestQueueAlwaysFails;
@@ -47,6 +71,18 @@ impl Queue<Msg> for TestQueueAlwaysFails {
     async fn delete(&mut self, _msg: Msg) -> Result<()> {
         bail!("simulated `Queue::delete()` failure")
     }
+
+    fn dequeue_count(&self, _msg: &Msg) -> u32 {
+        1
+    }
+
+    async fn extend_visibility(&mut self, _msg: &mut Msg, _timeout: Duration) -> Result<()> {
+        bail!("simulated `Queue::extend_visibility()` failure")
+    }
+
+    async fn dead_letter(&mut self, _msg: Msg) -> Result<Option<Msg>> {
+        bail!("simulated `Queue::dead_letter()` failure")
+    }
 }
 
 #[derive(Default)]
@@ -127,6 +163,49 @@ async fn test_ready_poll() {
 
     assert_eq!(task.state(), &State::Polled(Some(msg)));
     assert_eq!(queue.popped, vec![msg]);
+    assert_eq!(queue.extended, vec![msg]);
+}
+
+#[tokio::test]
+async fn test_ready_poll_poison() {
+    let mut task = fixture().with_max_dequeue_count(5);
+
+    let mut queue = TestQueue {
+        pending: vec![0, 1],
+        dequeue_count: 5,
+        has_dead_letter_queue: true,
+        ..Default::default()
+    };
+
+    task.trigger(Event::Poll(&mut queue)).await.unwrap();
+    assert_eq!(task.state(), &State::Polled(Some(1)));
+
+    queue.dequeue_count = 6;
+    task.set_state(State::Ready);
+    task.trigger(Event::Poll(&mut queue)).await.unwrap();
+
+    assert_eq!(task.state(), &State::Ready);
+    assert_eq!(queue.popped, vec![1, 0]);
+    assert_eq!(queue.extended, vec![1]);
+    assert_eq!(queue.dead_lettered, vec![0]);
+}
+
+#[tokio::test]
+async fn test_ready_poll_poison_without_dead_letter_queue() {
+    let mut task = fixture().with_max_dequeue_count(5);
+
+    let mut queue = TestQueue {
+        pending: vec![0],
+        dequeue_count: 6,
+        ..Default::default()
+    };
+
+    task.trigger(Event::Poll(&mut queue)).await.unwrap();
+
+    // deleted, rather than processed again
+    assert_eq!(task.state(), &State::Ready);
+    assert!(queue.extended.is_empty());
+    assert_eq!(queue.deleted, vec![0]);
 }
 
 #[tokio::test]
@@ -203,11 +282,15 @@ async fn test_downloaded_process() {
     task.set_state(State::Downloaded(msg, url.clone(), input.clone(), tmp_dir));
 
     let mut processor = TestProcessor::default();
+    let mut queue = TestQueue::default();
 
-    task.trigger(Event::Process(&mut processor)).await.unwrap();
+    task.trigger(Event::Process(&mut processor, &mut queue))
+        .await
+        .unwrap();
 
     assert_eq!(task.state(), &State::Processed(msg));
     assert_eq!(processor.processed, vec![(Some(url), input)]);
+    assert_eq!(queue.extended, vec![msg]);
 }
 
 #[tokio::test]
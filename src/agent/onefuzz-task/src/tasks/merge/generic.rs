@@ -118,6 +118,8 @@ async fn process_message(config: &Config, input_url: &Url, tmp_dir: &Path) -> Re
             let synced_dir = SyncedDir {
                 local_path: tmp_dir.to_path_buf(),
                 remote_path: config.unique_inputs.remote_path.clone(),
+                sync_mode: config.unique_inputs.sync_mode,
+                retention: config.unique_inputs.retention.clone(),
             };
             synced_dir.sync_push().await?
         }
@@ -52,7 +52,8 @@ pub async fn spawn(config: Config) -> Result<()> {
             .as_ref()
             .map(|x| x.local_path.clone()),
         config.common.machine_identity.clone(),
-    );
+    )
+    .with_sandbox(config.common.sandbox.clone());
     fuzzer.verify(config.check_fuzzer_help, None).await?;
 
     config.unique_inputs.init().await?;
@@ -172,7 +173,8 @@ pub async fn merge_inputs(
             .as_ref()
             .map(|x| x.local_path.clone()),
         config.common.machine_identity.clone(),
-    );
+    )
+    .with_sandbox(config.common.sandbox.clone());
     merger
         .merge(&config.unique_inputs.local_path, &candidates)
         .await
@@ -0,0 +1,360 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT License.
+
+use crate::tasks::report::crash_report::{CrashReport, InputBlob};
+use anyhow::{Context, Result};
+use async_trait::async_trait;
+use onefuzz::{blob::BlobUrl, sha256, syncdir::SyncedDir};
+use onefuzz_telemetry::{Event::new_result, EventData};
+use reqwest::Url;
+use stacktrace_parser::CrashLog;
+use std::path::{Path, PathBuf};
+use uuid::Uuid;
+
+pub fn default_minimize_max_tests() -> u64 {
+    1000
+}
+
+#[async_trait]
+pub trait CrashTester: Send {
+    /// Run the target on `input`, returning the crash log if it crashed.
+    async fn test_input(&mut self, input: &Path) -> Result<Option<CrashLog>>;
+}
+
+/// The key used to decide if two crashes are the same.
+///
+/// This is the `minimized_stack_sha256` of the resulting `CrashReport`, falling
+/// back to the `call_stack_sha256` if the minimized stack is empty.
+pub fn crash_signature(crash_log: &CrashLog, minimized_stack_depth: Option<usize>) -> String {
+    if crash_log.minimized_stack.is_empty() {
+        crash_log.call_stack_sha256()
+    } else {
+        crash_log.minimized_stack_sha256(minimized_stack_depth)
+    }
+}
+
+pub struct MinimizedInput {
+    pub data: Vec<u8>,
+    pub crash_log: CrashLog,
+}
+
+/// Shrinks crashing inputs while preserving their crash signature.
+pub struct Minimizer<T> {
+    tester: T,
+    scratch: PathBuf,
+    minimized_stack_depth: Option<usize>,
+    max_tests: u64,
+    tests: u64,
+}
+
+impl<T: CrashTester> Minimizer<T> {
+    /// `scratch` is the path candidate inputs are written to before testing.
+    pub fn new(
+        tester: T,
+        scratch: impl Into<PathBuf>,
+        minimized_stack_depth: Option<usize>,
+        max_tests: u64,
+    ) -> Self {
+        Self {
+            tester,
+            scratch: scratch.into(),
+            minimized_stack_depth,
+            max_tests,
+            tests: 0,
+        }
+    }
+
+    async fn crash_log(&mut self, data: &[u8]) -> Result<Option<CrashLog>> {
+        self.tests += 1;
+        tokio::fs::write(&self.scratch, data)
+            .await
+            .with_context(|| format!("unable to write input: {}", self.scratch.display()))?;
+        self.tester.test_input(&self.scratch).await
+    }
+
+    async fn reproduces(&mut self, data: &[u8], signature: &str) -> Result<Option<CrashLog>> {
+        let crash_log = self.crash_log(data).await?;
+        Ok(crash_log.filter(|x| crash_signature(x, self.minimized_stack_depth) == signature))
+    }
+
+    /// Minimize `data`, returning `None` if it does not crash.
+    ///
+    /// If `candidate` is provided (such as the output of a fuzzer's own crash
+    /// minimization), it is used as-is if it reproduces the same crash as
+    /// `data`.  Otherwise, `data` is minimized using delta debugging.
+    pub async fn minimize(
+        &mut self,
+        data: Vec<u8>,
+        candidate: Option<Vec<u8>>,
+    ) -> Result<Option<MinimizedInput>> {
+        self.tests = 0;
+
+        let Some(crash_log) = self.crash_log(&data).await? else {
+            return Ok(None);
+        };
+        let signature = crash_signature(&crash_log, self.minimized_stack_depth);
+
+        if let Some(candidate) = candidate {
+            if let Some(crash_log) = self.reproduces(&candidate, &signature).await? {
+                return Ok(Some(MinimizedInput {
+                    data: candidate,
+                    crash_log,
+                }));
+            }
+            warn!("minimized input does not reproduce the original crash, falling back to delta debugging");
+        }
+
+        let minimized = self.ddmin(data.clone(), &signature).await?;
+
+        // Flaky targets may only crash some of the time, so check the final
+        // result once more before replacing the original input.
+        if let Some(crash_log) = self.reproduces(&minimized, &signature).await? {
+            return Ok(Some(MinimizedInput {
+                data: minimized,
+                crash_log,
+            }));
+        }
+
+        warn!("minimized input is not reliable, keeping the original input");
+        Ok(Some(MinimizedInput { data, crash_log }))
+    }
+
+    // Delta debugging, as described by Zeller and Hildebrandt in "Simplifying
+    // and Isolating Failure-Inducing Input".  The input is split into `n`
+    // chunks, and each chunk, and then each chunk's complement, is tested.  If
+    // neither reduces the input, the granularity is doubled until chunks are
+    // single bytes.
+    async fn ddmin(&mut self, mut data: Vec<u8>, signature: &str) -> Result<Vec<u8>> {
+        let mut n = 2;
+
+        while data.len() >= 2 {
+            let chunk_size = data.len() / n + usize::from(data.len() % n != 0);
+            let chunks: Vec<_> = (0..data.len())
+                .step_by(chunk_size)
+                .map(|start| start..data.len().min(start + chunk_size))
+                .collect();
+
+            let mut reduced = false;
+
+            for chunk in &chunks {
+                if self.tests >= self.max_tests {
+                    debug!("minimization stopped after {} tests", self.tests);
+                    return Ok(data);
+                }
+
+                let subset = data[chunk.clone()].to_vec();
+                if self.reproduces(&subset, signature).await?.is_some() {
+                    data = subset;
+                    n = 2;
+                    reduced = true;
+                    break;
+                }
+            }
+
+            if !reduced && chunks.len() > 2 {
+                for chunk in &chunks {
+                    if self.tests >= self.max_tests {
+                        debug!("minimization stopped after {} tests", self.tests);
+                        return Ok(data);
+                    }
+
+                    let mut complement = data[..chunk.start].to_vec();
+                    complement.extend_from_slice(&data[chunk.end..]);
+                    if self.reproduces(&complement, signature).await?.is_some() {
+                        data = complement;
+                        n = (n - 1).max(2);
+                        reduced = true;
+                        break;
+                    }
+                }
+            }
+
+            if !reduced {
+                if n >= data.len() {
+                    break;
+                }
+                n = (n * 2).min(data.len());
+            }
+        }
+
+        Ok(data)
+    }
+}
+
+pub struct MinimizedCrash<'a> {
+    pub input_url: Option<Url>,
+    pub input: &'a Path,
+    pub minimized: MinimizedInput,
+    pub executable: &'a Path,
+    pub task_id: Uuid,
+    pub job_id: Uuid,
+    pub minimized_stack_depth: Option<usize>,
+    pub tool_name: &'a str,
+}
+
+/// Save a minimized input to `minimized_crashes`, and a crash report
+/// referencing both the original and the minimized inputs to `reports`.
+///
+/// The minimized input keeps the file name of the original input.  The report
+/// is named after both inputs, so it does not replace the report of the
+/// original input made by the crash report task.
+pub async fn save_minimized(
+    crash: MinimizedCrash<'_>,
+    minimized_crashes: &SyncedDir,
+    reports: &Option<SyncedDir>,
+) -> Result<()> {
+    let name = crash
+        .input
+        .file_name()
+        .ok_or_else(|| format_err!("invalid input path: {}", crash.input.display()))?
+        .to_string_lossy()
+        .to_string();
+
+    tokio::fs::write(
+        minimized_crashes.local_path.join(&name),
+        &crash.minimized.data,
+    )
+    .await?;
+    minimized_crashes.sync_push().await?;
+    event!(new_result; EventData::Path = name.clone());
+    metric!(new_result; 1.0; EventData::Path = name.clone());
+
+    let input_sha256 = sha256::digest_file(crash.input).await?;
+    let input_blob = crash
+        .input_url
+        .and_then(|u| BlobUrl::new(u).ok())
+        .map(InputBlob::from);
+    let minimized_input_blob = minimized_crashes
+        .try_url()
+        .map(|url| InputBlob::from(url.blob(&name)));
+
+    let mut report = CrashReport::new(
+        crash.minimized.crash_log,
+        crash.task_id,
+        crash.job_id,
+        crash.executable,
+        input_blob,
+        input_sha256,
+        crash.minimized_stack_depth,
+        crash.tool_name.into(),
+        env!("ONEFUZZ_VERSION").to_string(),
+        env!("ONEFUZZ_VERSION").to_string(),
+    );
+    report.minimized_input_blob = minimized_input_blob;
+    let minimized_sha256 = sha256::digest(&crash.minimized.data);
+    let report_name = format!("{}-{}.json", report.input_sha256, minimized_sha256);
+    report.minimized_input_sha256 = Some(minimized_sha256);
+
+    if let Some(reports) = reports {
+        if !reports.upload(&report_name, &report).await? {
+            info!("minimized crash report already exists: {}", report_name);
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    // Crashes if the input contains `bug`, with a stack that depends on
+    // whether the input also contains `!`.
+    struct FakeTester;
+
+    #[async_trait]
+    impl CrashTester for FakeTester {
+        async fn test_input(&mut self, input: &Path) -> Result<Option<CrashLog>> {
+            let data = tokio::fs::read(input).await?;
+            if !data.windows(3).any(|x| x == b"bug") {
+                return Ok(None);
+            }
+
+            let frame = if data.contains(&b'!') {
+                "abort"
+            } else {
+                "parse"
+            };
+
+            Ok(Some(CrashLog {
+                minimized_stack: vec![frame.to_string()],
+                ..Default::default()
+            }))
+        }
+    }
+
+    fn minimizer(dir: &Path, max_tests: u64) -> Minimizer<FakeTester> {
+        Minimizer::new(FakeTester, dir.join("input"), None, max_tests)
+    }
+
+    #[tokio::test]
+    async fn test_ddmin() -> Result<()> {
+        let dir = tempdir()?;
+        let mut minimizer = minimizer(dir.path(), 1000);
+
+        let minimized = minimizer
+            .minimize(
+                b"the quick brown bug jumps over the lazy dog".to_vec(),
+                None,
+            )
+            .await?
+            .unwrap();
+        assert_eq!(minimized.data, b"bug");
+        assert_eq!(minimized.crash_log.minimized_stack, vec!["parse"]);
+
+        let minimized = minimizer
+            .minimize(b"another bug report!".to_vec(), None)
+            .await?
+            .unwrap();
+        assert_eq!(minimized.data, b"bug!");
+        assert_eq!(minimized.crash_log.minimized_stack, vec!["abort"]);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_no_crash() -> Result<()> {
+        let dir = tempdir()?;
+        let mut minimizer = minimizer(dir.path(), 1000);
+        assert!(minimizer
+            .minimize(b"no crash here".to_vec(), None)
+            .await?
+            .is_none());
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_candidate() -> Result<()> {
+        let dir = tempdir()?;
+        let mut minimizer = minimizer(dir.path(), 1000);
+
+        // `bug!` crashes with a different stack, so the candidate is rejected.
+        let minimized = minimizer
+            .minimize(b"xxbugxx".to_vec(), Some(b"bug!".to_vec()))
+            .await?
+            .unwrap();
+        assert_eq!(minimized.data, b"bug");
+
+        let minimized = minimizer
+            .minimize(b"xxbugxx".to_vec(), Some(b"xbug".to_vec()))
+            .await?
+            .unwrap();
+        assert_eq!(minimized.data, b"xbug");
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_max_tests() -> Result<()> {
+        let dir = tempdir()?;
+        let data = b"the quick brown bug jumps over the lazy dog".to_vec();
+
+        let mut minimizer = minimizer(dir.path(), 2);
+        let minimized = minimizer.minimize(data.clone(), None).await?.unwrap();
+        assert!(minimized.data.len() < data.len());
+        assert!(minimized.data.len() > 3);
+
+        Ok(())
+    }
+}
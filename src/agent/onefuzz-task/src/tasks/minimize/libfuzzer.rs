@@ -0,0 +1,301 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT License.
+
+use super::common::*;
+use crate::tasks::{
+    config::CommonConfig,
+    generic::input_poller::{CallbackImpl, InputPoller, Processor},
+    heartbeat::{HeartbeatSender, TaskHeartbeatClient},
+    utils::{default_bool_true, try_resolve_setup_relative_path},
+};
+use anyhow::{Context, Result};
+use async_trait::async_trait;
+use onefuzz::{expand::Expand, libfuzzer::LibFuzzer, syncdir::SyncedDir};
+use reqwest::Url;
+use serde::Deserialize;
+use stacktrace_parser::CrashLog;
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+    time::Duration,
+};
+use storage_queue::{Message, QueueClient};
+use tempfile::tempdir;
+
+const LIBFUZZER_MINIMIZE_TOOL_NAME: &str = "libfuzzer_minimize";
+
+fn default_minimize_runs() -> u64 {
+    10000
+}
+
+fn default_minimize_timeout() -> u64 {
+    10 * 60
+}
+
+#[derive(Debug, Deserialize)]
+pub struct Config {
+    pub target_exe: PathBuf,
+    pub target_env: HashMap<String, String>,
+    pub target_options: Vec<String>,
+    pub target_timeout: Option<u64>,
+
+    pub input_queue: Option<QueueClient>,
+    pub crashes: Option<SyncedDir>,
+    pub minimized_crashes: SyncedDir,
+    pub reports: Option<SyncedDir>,
+
+    #[serde(default = "default_bool_true")]
+    pub check_fuzzer_help: bool,
+    #[serde(default)]
+    pub check_retry_count: u64,
+
+    #[serde(default = "default_bool_true")]
+    pub check_queue: bool,
+
+    #[serde(default)]
+    pub minimized_stack_depth: Option<usize>,
+
+    /// Value of `-runs=` passed to libFuzzer's `-minimize_crash=1` mode.
+    #[serde(default = "default_minimize_runs")]
+    pub minimize_runs: u64,
+
+    /// Seconds libFuzzer may spend minimizing a single input.
+    #[serde(default = "default_minimize_timeout")]
+    pub minimize_timeout: u64,
+
+    /// Maximum number of target executions used to minimize a single input,
+    /// if the input minimized by libFuzzer does not reproduce the same crash.
+    #[serde(default = "default_minimize_max_tests")]
+    pub minimize_max_tests: u64,
+
+    #[serde(flatten)]
+    pub common: CommonConfig,
+}
+
+impl Config {
+    pub fn get_expand(&self) -> Expand<'_> {
+        self.common
+            .get_expand()
+            .target_exe(&self.target_exe)
+            .target_options(&self.target_options)
+            .set_optional_ref(&self.reports, |expand, reports| {
+                expand.reports_dir(reports.local_path.as_path())
+            })
+            .set_optional_ref(&self.crashes, |expand, crashes| {
+                expand
+                    .set_optional_ref(
+                        &crashes.remote_path.clone().and_then(|u| u.account()),
+                        |expand, account| expand.crashes_account(account),
+                    )
+                    .set_optional_ref(
+                        &crashes.remote_path.clone().and_then(|u| u.container()),
+                        |expand, container| expand.crashes_container(container),
+                    )
+            })
+    }
+
+    fn fuzzer(&self, target_exe: PathBuf) -> LibFuzzer {
+        LibFuzzer::new(
+            target_exe,
+            self.target_options.clone(),
+            self.target_env.clone(),
+            self.common.setup_dir.clone(),
+            self.common.extra_setup_dir.clone(),
+            self.common
+                .extra_output
+                .as_ref()
+                .map(|x| x.local_path.clone()),
+            self.common.machine_identity.clone(),
+        )
+        .with_sandbox(self.common.sandbox.clone())
+    }
+}
+
+pub struct LibFuzzerMinimizeTask {
+    config: Config,
+    poller: InputPoller<Message>,
+}
+
+impl LibFuzzerMinimizeTask {
+    pub fn new(config: Config) -> Self {
+        let poller = InputPoller::new("libfuzzer-minimize")
+            .with_max_dequeue_count(config.common.max_dequeue_count);
+        Self { config, poller }
+    }
+
+    pub async fn run(&mut self) -> Result<()> {
+        info!("Starting libFuzzer crash minimization task");
+        let Self { config, poller } = self;
+        let heartbeat_client = config.common.init_heartbeat(None).await?;
+
+        let target_exe =
+            try_resolve_setup_relative_path(&config.common.setup_dir, &config.target_exe).await?;
+        config
+            .fuzzer(target_exe.clone())
+            .verify(config.check_fuzzer_help, None)
+            .await?;
+
+        config.minimized_crashes.init().await?;
+        if let Some(reports) = &config.reports {
+            reports.init().await?;
+        }
+
+        let mut processor = LibFuzzerMinimizeProcessor {
+            config,
+            target_exe,
+            heartbeat_client,
+        };
+
+        info!("minimizing existing crashes");
+        if let Some(crashes) = &config.crashes {
+            poller
+                .batch_process(&mut processor, crashes)
+                .await
+                .context("batch processing failed")?;
+        }
+
+        info!("minimizing crashes from queue");
+        if config.check_queue {
+            if let Some(queue) = &config.input_queue {
+                let callback = CallbackImpl::new(queue.clone(), processor)
+                    .context("processing from queue failed")?
+                    .with_dead_letter_queue(config.common.dead_letter_queue.clone());
+                poller.run(callback).await.context("poller failed")?;
+            }
+        }
+        Ok(())
+    }
+}
+
+struct LibFuzzerCrashTester<'a> {
+    fuzzer: LibFuzzer,
+    config: &'a Config,
+}
+
+#[async_trait]
+impl<'a> CrashTester for LibFuzzerCrashTester<'a> {
+    async fn test_input(&mut self, input: &Path) -> Result<Option<CrashLog>> {
+        let result = self
+            .fuzzer
+            .repro(
+                input,
+                self.config.target_timeout,
+                self.config.check_retry_count,
+            )
+            .await?;
+        Ok(result.crash_log)
+    }
+}
+
+pub struct LibFuzzerMinimizeProcessor<'a> {
+    config: &'a Config,
+    target_exe: PathBuf,
+    heartbeat_client: Option<TaskHeartbeatClient>,
+}
+
+#[async_trait]
+impl<'a> Processor for LibFuzzerMinimizeProcessor<'a> {
+    async fn process(&mut self, url: Option<Url>, input: &Path) -> Result<()> {
+        debug!("minimizing libfuzzer crash: {}", input.display());
+        self.heartbeat_client.alive();
+
+        let scratch = tempdir()?;
+        let fuzzer = self.config.fuzzer(self.target_exe.clone());
+
+        // libFuzzer's minimizer is much faster than delta debugging, as it
+        // mutates the input in-process.  However, it accepts any crash, so the
+        // result is only used if it reproduces the original crash.
+        let output = scratch.path().join("minimized");
+        let candidate = match fuzzer
+            .minimize_crash(
+                input,
+                &output,
+                self.config.minimize_runs,
+                Duration::from_secs(self.config.minimize_timeout),
+            )
+            .await
+        {
+            Ok(()) => Some(tokio::fs::read(&output).await?),
+            Err(err) => {
+                warn!("libFuzzer crash minimization failed: {:?}", err);
+                None
+            }
+        };
+
+        let tester = LibFuzzerCrashTester {
+            fuzzer,
+            config: self.config,
+        };
+        let mut minimizer = Minimizer::new(
+            tester,
+            scratch.path().join("input"),
+            self.config.minimized_stack_depth,
+            self.config.minimize_max_tests,
+        );
+
+        let data = tokio::fs::read(input).await?;
+        let Some(minimized) = minimizer.minimize(data, candidate).await? else {
+            warn!("unable to reproduce crash: {}", input.display());
+            return Ok(());
+        };
+
+        let crash = MinimizedCrash {
+            input_url: url,
+            input,
+            minimized,
+            executable: &self.target_exe,
+            task_id: self.config.common.task_id,
+            job_id: self.config.common.job_id,
+            minimized_stack_depth: self.config.minimized_stack_depth,
+            tool_name: LIBFUZZER_MINIMIZE_TOOL_NAME,
+        };
+        save_minimized(crash, &self.config.minimized_crashes, &self.config.reports)
+            .await
+            .context("saving minimized crash failed")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use onefuzz::expand::PlaceHolder;
+    use proptest::prelude::*;
+
+    use crate::config_test_utils::GetExpandFields;
+
+    use super::Config;
+
+    impl GetExpandFields for Config {
+        fn get_expand_fields(&self) -> Vec<(PlaceHolder, String)> {
+            let mut params = self.common.get_expand_fields();
+            params.push((
+                PlaceHolder::TargetExe,
+                dunce::canonicalize(&self.target_exe)
+                    .unwrap()
+                    .to_string_lossy()
+                    .to_string(),
+            ));
+            params.push((PlaceHolder::TargetOptions, self.target_options.join(" ")));
+            if let Some(reports) = &self.reports {
+                params.push((
+                    PlaceHolder::ReportsDir,
+                    dunce::canonicalize(&reports.local_path)
+                        .unwrap()
+                        .to_string_lossy()
+                        .to_string(),
+                ));
+            }
+            if let Some(crashes) = &self.crashes {
+                if let Some(account) = crashes.remote_path.clone().and_then(|u| u.account()) {
+                    params.push((PlaceHolder::CrashesAccount, account));
+                }
+                if let Some(container) = crashes.remote_path.clone().and_then(|u| u.container()) {
+                    params.push((PlaceHolder::CrashesContainer, container));
+                }
+            }
+
+            params
+        }
+    }
+
+    config_test!(Config);
+}
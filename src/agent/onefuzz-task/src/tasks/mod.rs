@@ -9,6 +9,7 @@ pub mod fuzz;
 pub mod generic;
 pub mod heartbeat;
 pub mod merge;
+pub mod minimize;
 pub mod regression;
 pub mod report;
 pub mod stats;
@@ -3,7 +3,7 @@
 
 use crate::tasks::{
     config::CommonConfig,
-    report::{crash_report::CrashTestResult, generic},
+    report::{crash_report::CrashTestResult, generic, symbolize::CrashLogSymbolizer},
     utils::{default_bool_true, try_resolve_setup_relative_path},
 };
 use anyhow::Result;
@@ -11,6 +11,7 @@ use async_trait::async_trait;
 use onefuzz::syncdir::SyncedDir;
 use reqwest::Url;
 use serde::Deserialize;
+use stacktrace_parser::StackFilter;
 use std::{collections::HashMap, path::PathBuf};
 
 use super::common::{self, RegressionHandler};
@@ -45,12 +46,18 @@ pub struct Config {
     #[serde(default)]
     pub minimized_stack_depth: Option<usize>,
 
+    /// Project-specific rules for minimizing call stacks, applied on top of
+    /// the stack frame filters of ClusterFuzz.
+    #[serde(default)]
+    pub stack_filter: StackFilter,
+
     #[serde(flatten)]
     pub common: CommonConfig,
 }
 
 pub struct GenericRegressionTask {
     config: Config,
+    symbolizer: CrashLogSymbolizer,
 }
 
 #[async_trait]
@@ -76,6 +83,9 @@ impl RegressionHandler for GenericRegressionTask {
             check_asan_log: self.config.check_asan_log,
             check_debugger: self.config.check_debugger,
             minimized_stack_depth: self.config.minimized_stack_depth,
+            stack_filter: &self.config.stack_filter,
+            symbolizer: &self.symbolizer,
+            sandbox: &self.config.common.sandbox,
             machine_identity: self.config.common.machine_identity.clone(),
         };
         generic::test_input(args).await
@@ -84,7 +94,10 @@ impl RegressionHandler for GenericRegressionTask {
 
 impl GenericRegressionTask {
     pub fn new(config: Config) -> Self {
-        Self { config }
+        Self {
+            config,
+            symbolizer: CrashLogSymbolizer::new(),
+        }
     }
 
     pub async fn run(&self) -> Result<()> {
@@ -3,7 +3,7 @@
 
 use crate::tasks::{
     config::CommonConfig,
-    report::{crash_report::CrashTestResult, libfuzzer_report},
+    report::{crash_report::CrashTestResult, libfuzzer_report, symbolize::CrashLogSymbolizer},
     utils::{default_bool_true, try_resolve_setup_relative_path},
 };
 
@@ -14,6 +14,7 @@ use super::common::{self, RegressionHandler};
 use async_trait::async_trait;
 use onefuzz::syncdir::SyncedDir;
 use serde::Deserialize;
+use stacktrace_parser::StackFilter;
 use std::{collections::HashMap, path::PathBuf};
 
 #[derive(Debug, Deserialize)]
@@ -44,12 +45,18 @@ pub struct Config {
     #[serde(default)]
     pub minimized_stack_depth: Option<usize>,
 
+    /// Project-specific rules for minimizing call stacks, applied on top of
+    /// the stack frame filters of ClusterFuzz.
+    #[serde(default)]
+    pub stack_filter: StackFilter,
+
     #[serde(flatten)]
     pub common: CommonConfig,
 }
 
 pub struct LibFuzzerRegressionTask {
     config: Config,
+    symbolizer: CrashLogSymbolizer,
 }
 
 #[async_trait]
@@ -78,6 +85,9 @@ impl RegressionHandler for LibFuzzerRegressionTask {
             target_timeout: self.config.target_timeout,
             check_retry_count: self.config.check_retry_count,
             minimized_stack_depth: self.config.minimized_stack_depth,
+            stack_filter: &self.config.stack_filter,
+            symbolizer: &self.symbolizer,
+            sandbox: &self.config.common.sandbox,
             machine_identity: self.config.common.machine_identity.clone(),
         };
 
@@ -87,7 +97,10 @@ impl RegressionHandler for LibFuzzerRegressionTask {
 
 impl LibFuzzerRegressionTask {
     pub fn new(config: Config) -> Self {
-        Self { config }
+        Self {
+            config,
+            symbolizer: CrashLogSymbolizer::new(),
+        }
     }
 
     pub async fn run(&self) -> Result<()> {
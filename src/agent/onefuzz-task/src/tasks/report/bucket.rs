@@ -0,0 +1,417 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT License.
+
+//! Clusters crash reports whose minimized stacks are similar, but not
+//! identical, into buckets.
+//!
+//! `unique_reports` are keyed by the exact call stack hash, so a stack that
+//! differs by a single inlined frame yields a new "unique" report.  Buckets
+//! group those reports under a stable id, which is recorded in each
+//! `CrashReport` and summarized in `unique_reports` as `<id>.bucket`.
+//!
+//! Summaries are not named `*.json`, so that the service does not parse them
+//! as crash reports.  They are loaded before the first report of a task is
+//! bucketed, so that tasks on other nodes, or restarted tasks, reuse the same
+//! buckets.
+
+use super::crash_report::{CrashReport, CrashTestResult};
+use anyhow::{Context, Result};
+use onefuzz::{blob::BlobClient, syncdir::SyncedDir};
+use serde::{Deserialize, Serialize};
+
+const DEFAULT_BUCKET_FRAMES: usize = 3;
+
+const BUCKET_EXTENSION: &str = ".bucket";
+
+fn default_bucket_frames() -> usize {
+    DEFAULT_BUCKET_FRAMES
+}
+
+/// How two minimized stacks are compared when assigning buckets.
+///
+/// Stacks are compared using the function names of the minimized stack, and
+/// only reports with the same `crash_type` can share a bucket.
+#[derive(Debug, Deserialize, Serialize, Clone, PartialEq, Eq)]
+#[serde(tag = "method", rename_all = "snake_case")]
+pub enum StackSimilarity {
+    /// Stacks are similar if their top `frames` function names are equal.
+    FramePrefix {
+        #[serde(default = "default_bucket_frames")]
+        frames: usize,
+    },
+
+    /// Stacks are similar if at most `max_distance` function names need to be
+    /// inserted, removed or replaced to turn the top `frames` of one stack
+    /// into the other.
+    EditDistance {
+        #[serde(default = "default_bucket_frames")]
+        frames: usize,
+        max_distance: usize,
+    },
+}
+
+impl Default for StackSimilarity {
+    fn default() -> Self {
+        Self::FramePrefix {
+            frames: DEFAULT_BUCKET_FRAMES,
+        }
+    }
+}
+
+impl StackSimilarity {
+    fn frames(&self) -> usize {
+        match self {
+            Self::FramePrefix { frames } => *frames,
+            Self::EditDistance { frames, .. } => *frames,
+        }
+    }
+
+    pub fn is_similar(&self, a: &[String], b: &[String]) -> bool {
+        let frames = self.frames();
+        let a = &a[..a.len().min(frames)];
+        let b = &b[..b.len().min(frames)];
+
+        match self {
+            Self::FramePrefix { .. } => a == b,
+            Self::EditDistance { max_distance, .. } => edit_distance(a, b) <= *max_distance,
+        }
+    }
+}
+
+/// Levenshtein distance between two sequences of frames.
+fn edit_distance(a: &[String], b: &[String]) -> usize {
+    let mut prev: Vec<usize> = (0..=b.len()).collect();
+    let mut cur = vec![0; b.len() + 1];
+
+    for (i, x) in a.iter().enumerate() {
+        cur[0] = i + 1;
+        for (j, y) in b.iter().enumerate() {
+            let substitution = prev[j] + usize::from(x != y);
+            cur[j + 1] = substitution.min(prev[j + 1] + 1).min(cur[j] + 1);
+        }
+        std::mem::swap(&mut prev, &mut cur);
+    }
+
+    prev[b.len()]
+}
+
+/// Summary of a bucket, written to `unique_reports` when the bucket is created.
+#[derive(Debug, Deserialize, Serialize, Clone, PartialEq, Eq)]
+pub struct BucketSummary {
+    pub bucket_id: String,
+    pub crash_type: String,
+    /// Minimized stack of the report that created the bucket.
+    pub signature: Vec<String>,
+    pub similarity: StackSimilarity,
+    /// Unique report name of the report that created the bucket.
+    pub first_report: String,
+}
+
+impl BucketSummary {
+    pub fn blob_name(&self) -> String {
+        format!("{}{BUCKET_EXTENSION}", self.bucket_id)
+    }
+}
+
+// Summaries of the buckets saved to `unique_reports`, by any task.
+async fn load_buckets(unique_reports: &SyncedDir) -> Result<Vec<BucketSummary>> {
+    let Some(url) = unique_reports.try_url() else {
+        return Ok(vec![]);
+    };
+
+    let mut summaries = vec![];
+    if let Some(path) = url.as_file_path() {
+        let mut entries = tokio::fs::read_dir(&path).await?;
+        while let Some(entry) = entries.next_entry().await? {
+            if entry
+                .file_name()
+                .to_string_lossy()
+                .ends_with(BUCKET_EXTENSION)
+            {
+                summaries.push(tokio::fs::read(entry.path()).await?);
+            }
+        }
+    } else {
+        let client = BlobClient::new();
+        for blob in client.list_blobs(&url.url()?).await? {
+            if blob.name.ends_with(BUCKET_EXTENSION) {
+                summaries.push(client.get_data(&url.blob(&blob.name).url()).await?);
+            }
+        }
+    }
+
+    summaries
+        .iter()
+        .map(|data| serde_json::from_slice(data).context("invalid bucket summary"))
+        .collect()
+}
+
+/// Assigns buckets to the crash reports generated by a single task.
+///
+/// Bucket ids are derived from the crash type and stack signature of the
+/// first report placed in a bucket, so that the same crash bucketed by
+/// different tasks ends up with the same id.  Existing buckets are kept
+/// sorted by id, so reports similar to several buckets are assigned the same
+/// one regardless of the order in which the buckets were created.
+#[derive(Debug, Default)]
+pub struct CrashBucketer {
+    similarity: StackSimilarity,
+    buckets: Vec<BucketSummary>,
+    loaded: bool,
+}
+
+impl CrashBucketer {
+    pub fn new(similarity: StackSimilarity) -> Self {
+        Self {
+            similarity,
+            buckets: vec![],
+            loaded: false,
+        }
+    }
+
+    // Add existing buckets, ignoring those of other similarity settings.
+    fn extend(&mut self, buckets: impl IntoIterator<Item = BucketSummary>) {
+        for bucket in buckets {
+            if bucket.similarity != self.similarity {
+                continue;
+            }
+            if let Err(index) = self
+                .buckets
+                .binary_search_by(|b| b.bucket_id.cmp(&bucket.bucket_id))
+            {
+                self.buckets.insert(index, bucket);
+            }
+        }
+    }
+
+    /// Assign a bucket to `report`, returning the summary of the bucket if it
+    /// was newly created.
+    pub fn assign(&mut self, report: &mut CrashReport) -> Option<BucketSummary> {
+        let signature = stack_signature(report);
+
+        if let Some(bucket) = self.buckets.iter().find(|bucket| {
+            bucket.crash_type == report.crash_type
+                && self.similarity.is_similar(&bucket.signature, &signature)
+        }) {
+            report.bucket_id = Some(bucket.bucket_id.clone());
+            return None;
+        }
+
+        let frames = self.similarity.frames();
+        let bucket_id = stacktrace_parser::digest_iter(
+            std::iter::once(&report.crash_type).chain(signature.iter().take(frames)),
+            None,
+        );
+
+        let bucket = BucketSummary {
+            bucket_id: bucket_id.clone(),
+            crash_type: report.crash_type.clone(),
+            signature,
+            similarity: self.similarity.clone(),
+            first_report: report.unique_blob_name(),
+        };
+        self.extend([bucket.clone()]);
+        report.bucket_id = Some(bucket_id);
+
+        Some(bucket)
+    }
+
+    /// Bucket a crash test result, saving a summary of any new bucket to
+    /// `unique_reports`.
+    pub async fn bucket(
+        &mut self,
+        result: &mut CrashTestResult,
+        unique_reports: &Option<SyncedDir>,
+    ) -> Result<()> {
+        let CrashTestResult::CrashReport(report) = result else {
+            return Ok(());
+        };
+
+        if !self.loaded {
+            if let Some(unique_reports) = unique_reports {
+                let buckets = load_buckets(unique_reports)
+                    .await
+                    .context("loading crash buckets failed")?;
+                self.extend(buckets);
+            }
+            self.loaded = true;
+        }
+
+        if let Some(bucket) = self.assign(report) {
+            info!(
+                "new crash bucket {} for report {}",
+                bucket.bucket_id, bucket.first_report
+            );
+            if let Some(unique_reports) = unique_reports {
+                unique_reports.upload(&bucket.blob_name(), &bucket).await?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+// The minimized stack function names are derived from
+// `CrashLog::minimized_stack_details`.  Reports without them (such as .NET
+// reports) fall back to the minimized stack, and then the full call stack.
+fn stack_signature(report: &CrashReport) -> Vec<String> {
+    [
+        &report.minimized_stack_function_names,
+        &report.minimized_stack,
+    ]
+    .into_iter()
+    .flatten()
+    .find(|stack| !stack.is_empty())
+    .unwrap_or(&report.call_stack)
+    .clone()
+}
+
+#[cfg(test)]
+mod tests {
+    use onefuzz::blob::BlobContainerUrl;
+    use reqwest::Url;
+
+    use super::*;
+
+    fn stack(frames: &[&str]) -> Vec<String> {
+        frames.iter().map(|x| x.to_string()).collect()
+    }
+
+    fn report(crash_type: &str, frames: &[&str]) -> CrashReport {
+        CrashReport {
+            crash_type: crash_type.to_string(),
+            call_stack: stack(frames),
+            call_stack_sha256: stacktrace_parser::digest_iter(frames, None),
+            minimized_stack_function_names: Some(stack(frames)),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_edit_distance() {
+        assert_eq!(edit_distance(&stack(&[]), &stack(&[])), 0);
+        assert_eq!(edit_distance(&stack(&["a", "b"]), &stack(&[])), 2);
+        assert_eq!(
+            edit_distance(&stack(&["a", "b", "c"]), &stack(&["a", "c"])),
+            1
+        );
+        assert_eq!(
+            edit_distance(&stack(&["a", "b", "c"]), &stack(&["a", "x", "c"])),
+            1
+        );
+        assert_eq!(edit_distance(&stack(&["a", "b"]), &stack(&["b", "a"])), 2);
+    }
+
+    #[test]
+    fn test_frame_prefix() {
+        let similarity = StackSimilarity::FramePrefix { frames: 2 };
+        assert!(similarity.is_similar(&stack(&["a", "b", "c"]), &stack(&["a", "b", "d"])));
+        assert!(!similarity.is_similar(&stack(&["a", "b"]), &stack(&["a", "c"])));
+        assert!(!similarity.is_similar(&stack(&["a"]), &stack(&["a", "b"])));
+    }
+
+    #[test]
+    fn test_bucket_inlined_frame() {
+        let mut bucketer = CrashBucketer::new(StackSimilarity::EditDistance {
+            frames: 4,
+            max_distance: 1,
+        });
+
+        let mut first = report("heap-buffer-overflow", &["parse", "read_header", "fuzz.c"]);
+        let mut inlined = report(
+            "heap-buffer-overflow",
+            &["parse", "read_u32", "read_header", "fuzz.c"],
+        );
+        let mut other_type = report("use-after-free", &["parse", "read_header", "fuzz.c"]);
+        let mut other_stack = report("heap-buffer-overflow", &["decode", "inflate", "fuzz.c"]);
+
+        let created = bucketer.assign(&mut first).unwrap();
+        assert_eq!(created.first_report, first.unique_blob_name());
+        assert!(bucketer.assign(&mut inlined).is_none());
+        assert!(bucketer.assign(&mut other_type).is_some());
+        assert!(bucketer.assign(&mut other_stack).is_some());
+
+        assert_eq!(first.bucket_id, inlined.bucket_id);
+        assert_ne!(first.bucket_id, other_type.bucket_id);
+        assert_ne!(first.bucket_id, other_stack.bucket_id);
+    }
+
+    #[test]
+    fn test_bucket_id_is_stable() {
+        let mut a = report("crash", &["f", "g", "h", "main"]);
+        let mut b = report("crash", &["f", "g", "h", "other"]);
+
+        CrashBucketer::default().assign(&mut a);
+        CrashBucketer::default().assign(&mut b);
+
+        assert!(a.bucket_id.is_some());
+        assert_eq!(a.bucket_id, b.bucket_id);
+    }
+
+    #[test]
+    fn test_existing_buckets_are_reused() {
+        let similarity = StackSimilarity::EditDistance {
+            frames: 4,
+            max_distance: 1,
+        };
+
+        let mut first = report("crash", &["parse", "read_header", "fuzz.c"]);
+        let created = CrashBucketer::new(similarity.clone())
+            .assign(&mut first)
+            .unwrap();
+        assert_eq!(created.blob_name(), format!("{}.bucket", created.bucket_id));
+
+        // A restarted task, with the buckets saved by the first one.
+        let mut bucketer = CrashBucketer::new(similarity);
+        bucketer.extend([created.clone()]);
+
+        let mut inlined = report("crash", &["parse", "read_u32", "read_header", "fuzz.c"]);
+        assert!(bucketer.assign(&mut inlined).is_none());
+        assert_eq!(inlined.bucket_id, Some(created.bucket_id.clone()));
+
+        // Buckets of other similarity settings are not reused.
+        let mut bucketer = CrashBucketer::default();
+        bucketer.extend([created]);
+        assert!(bucketer.buckets.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_load_buckets() -> Result<()> {
+        let remote = tempfile::tempdir()?;
+        let local = tempfile::tempdir()?;
+        let unique_reports = SyncedDir {
+            local_path: local.path().to_owned(),
+            remote_path: Some(BlobContainerUrl::parse(
+                Url::from_directory_path(remote.path()).unwrap(),
+            )?),
+            sync_mode: Default::default(),
+            retention: Default::default(),
+        };
+
+        let mut crash = report("crash", &["f", "g", "h"]);
+        let bucket = CrashBucketer::default().assign(&mut crash).unwrap();
+        unique_reports.upload(&bucket.blob_name(), &bucket).await?;
+        unique_reports.upload("report.json", &crash).await?;
+
+        assert_eq!(load_buckets(&unique_reports).await?, vec![bucket]);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_deserialize_similarity() -> Result<()> {
+        let similarity: StackSimilarity = serde_json::from_str(r#"{"method": "frame_prefix"}"#)?;
+        assert_eq!(similarity, StackSimilarity::default());
+
+        let similarity: StackSimilarity =
+            serde_json::from_str(r#"{"method": "edit_distance", "frames": 5, "max_distance": 2}"#)?;
+        assert_eq!(
+            similarity,
+            StackSimilarity::EditDistance {
+                frames: 5,
+                max_distance: 2
+            }
+        );
+        Ok(())
+    }
+}
@@ -2,7 +2,7 @@
 // Licensed under the MIT License.
 
 use anyhow::{Context, Result};
-use onefuzz::{blob::BlobUrl, monitor::DirectoryMonitor, syncdir::SyncedDir};
+use onefuzz::{blob::BlobUrl, monitor::DirectoryMonitor, sandbox::LimitKill, syncdir::SyncedDir};
 use onefuzz_result::job_result::{JobResultData, JobResultSender, TaskJobResultClient};
 use onefuzz_telemetry::{
     Event::{
@@ -12,7 +12,7 @@ use onefuzz_telemetry::{
     EventData,
 };
 use serde::{Deserialize, Serialize};
-use stacktrace_parser::CrashLog;
+use stacktrace_parser::{CrashLog, StackEntry, StackFilterRules};
 use std::collections::HashMap;
 use std::path::{Path, PathBuf};
 use uuid::Uuid;
@@ -47,6 +47,18 @@ pub struct CrashReport {
     #[serde(default, skip_serializing_if = "Option::is_none")]
     pub minimized_stack_function_lines_sha256: Option<String>,
 
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub minimized_stack_details: Option<Vec<StackEntry>>,
+
+    /// Project-specific rules applied when minimizing the call stack.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub stack_filter: Option<StackFilterRules>,
+
+    /// Stack which allocated the uninitialized memory used by the target, for
+    /// MemorySanitizer reports with origin tracking.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub origin_stack: Option<Vec<String>>,
+
     #[serde(skip_serializing_if = "Option::is_none")]
     pub asan_log: Option<String>,
 
@@ -68,6 +80,15 @@ pub struct CrashReport {
 
     #[serde(skip_serializing_if = "Option::is_none")]
     pub tool_version: Option<String>,
+
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub bucket_id: Option<String>,
+
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub minimized_input_blob: Option<InputBlob>,
+
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub minimized_input_sha256: Option<String>,
 }
 
 #[derive(Debug, Deserialize, Serialize)]
@@ -81,6 +102,11 @@ pub struct NoCrash {
     pub tries: u64,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub error: Option<String>,
+
+    /// Sandbox limit which killed the target, when not reproduced because
+    /// of it.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub limit_kill: Option<LimitKill>,
 }
 
 #[derive(Debug, Deserialize, Serialize)]
@@ -286,6 +312,12 @@ impl CrashReport {
                 Some(crash_log.minimized_stack_function_names_sha256(minimized_stack_depth))
             };
 
+        let minimized_stack_details = if crash_log.minimized_stack_details.is_empty() {
+            None
+        } else {
+            Some(crash_log.minimized_stack_details)
+        };
+
         let minimized_stack_function_lines = if crash_log.minimized_stack_function_lines.is_empty()
         {
             None
@@ -300,6 +332,18 @@ impl CrashReport {
             Some(crash_log.minimized_stack_function_names)
         };
 
+        let origin_stack = if crash_log.origin_stack.is_empty() {
+            None
+        } else {
+            Some(
+                crash_log
+                    .origin_stack
+                    .into_iter()
+                    .map(|entry| entry.line)
+                    .collect(),
+            )
+        };
+
         Self {
             input_sha256,
             input_blob,
@@ -313,6 +357,9 @@ impl CrashReport {
             minimized_stack_function_names_sha256,
             minimized_stack_function_lines,
             minimized_stack_function_lines_sha256,
+            minimized_stack_details,
+            stack_filter: crash_log.stack_filter,
+            origin_stack,
             call_stack: crash_log.call_stack,
             asan_log: crash_log.text,
             scariness_score: crash_log.scariness_score,
@@ -322,6 +369,9 @@ impl CrashReport {
             onefuzz_version: Some(onefuzz_version),
             tool_name: Some(tool_name),
             tool_version: Some(tool_version),
+            bucket_id: None,
+            minimized_input_blob: None,
+            minimized_input_sha256: None,
         }
     }
 
@@ -418,4 +468,34 @@ mod tests {
 
         Ok(())
     }
+
+    #[test]
+    fn test_report_origin_stack() -> Result<()> {
+        let text = std::fs::read_to_string(
+            "../stacktrace-parser/data/stack-traces/msan-use-of-uninitialized-value-origins.txt",
+        )?;
+        let crash_log = CrashLog::parse(text)?;
+        let origin_lines: Vec<_> = crash_log
+            .origin_stack
+            .iter()
+            .map(|x| x.line.clone())
+            .collect();
+        assert!(!origin_lines.is_empty());
+
+        let report = CrashReport::new(
+            crash_log,
+            Uuid::nil(),
+            Uuid::nil(),
+            "fuzz.exe",
+            None,
+            String::new(),
+            None,
+            "libfuzzer".to_string(),
+            "1.0".to_string(),
+            "1.0".to_string(),
+        );
+        assert_eq!(report.origin_stack, Some(origin_lines));
+
+        Ok(())
+    }
 }
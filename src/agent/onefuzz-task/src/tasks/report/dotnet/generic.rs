@@ -92,7 +92,8 @@ pub struct DotnetCrashReportTask {
 
 impl DotnetCrashReportTask {
     pub fn new(config: Config) -> Self {
-        let poller = InputPoller::new("libfuzzer-dotnet-crash-report");
+        let poller = InputPoller::new("libfuzzer-dotnet-crash-report")
+            .with_max_dequeue_count(config.common.max_dequeue_count);
         let config = Arc::new(config);
 
         Self { config, poller }
@@ -129,7 +130,8 @@ impl DotnetCrashReportTask {
 
         if self.config.check_queue {
             if let Some(url) = &self.config.input_queue {
-                let callback = CallbackImpl::new(url.clone(), processor)?;
+                let callback = CallbackImpl::new(url.clone(), processor)?
+                    .with_dead_letter_queue(self.config.common.dead_letter_queue.clone());
                 self.poller.run(callback).await?;
             }
         }
@@ -240,6 +242,9 @@ impl AsanProcessor {
                     minimized_stack_function_names_sha256: None,
                     minimized_stack_function_lines: None,
                     minimized_stack_function_lines_sha256: None,
+                    minimized_stack_details: None,
+                    stack_filter: None,
+                    origin_stack: None,
                     asan_log: None,
                     task_id,
                     job_id,
@@ -248,6 +253,9 @@ impl AsanProcessor {
                     onefuzz_version: Some(env!("ONEFUZZ_VERSION").to_owned()),
                     tool_name: Some(DOTNET_DUMP_TOOL_NAME.to_owned()),
                     tool_version: None,
+                    bucket_id: None,
+                    minimized_input_blob: None,
+                    minimized_input_sha256: None,
                 };
 
                 crash_report.into()
@@ -260,6 +268,7 @@ impl AsanProcessor {
                     task_id,
                     tries: 1,
                     error: None,
+                    limit_kill: None,
                 };
 
                 no_repro.into()
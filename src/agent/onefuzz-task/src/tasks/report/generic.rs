@@ -1,7 +1,12 @@
 // Copyright (c) Microsoft Corporation.
 // Licensed under the MIT License.
 
-use super::crash_report::{CrashReport, CrashTestResult, InputBlob, NoCrash};
+use super::{
+    bucket::{CrashBucketer, StackSimilarity},
+    crash_report::{CrashReport, CrashTestResult, InputBlob, NoCrash},
+    sarif::save_sarif,
+    symbolize::CrashLogSymbolizer,
+};
 use crate::tasks::{
     config::CommonConfig,
     generic::input_poller::{CallbackImpl, InputPoller, Processor},
@@ -11,16 +16,19 @@ use crate::tasks::{
 use anyhow::{Context, Result};
 use async_trait::async_trait;
 use onefuzz::{
-    blob::BlobUrl, input_tester::Tester, machine_id::MachineIdentity, sha256, syncdir::SyncedDir,
+    blob::BlobUrl, input_tester::Tester, machine_id::MachineIdentity, sandbox::SandboxConfig,
+    sha256, syncdir::SyncedDir,
 };
 use onefuzz_result::job_result::TaskJobResultClient;
 use reqwest::Url;
 use serde::Deserialize;
+use stacktrace_parser::StackFilter;
 use std::{
     collections::HashMap,
     path::{Path, PathBuf},
 };
 use storage_queue::{Message, QueueClient};
+use tokio::task::spawn_blocking;
 use uuid::Uuid;
 
 const GENERIC_TOOL_NAME: &str = "generic";
@@ -56,6 +64,20 @@ pub struct Config {
     #[serde(default)]
     pub minimized_stack_depth: Option<usize>,
 
+    /// Project-specific rules for minimizing call stacks, applied on top of
+    /// the stack frame filters of ClusterFuzz.
+    #[serde(default)]
+    pub stack_filter: StackFilter,
+
+    /// Group similar crash reports into buckets.  Bucketing is disabled when
+    /// not set.
+    #[serde(default)]
+    pub crash_bucketing: Option<StackSimilarity>,
+
+    /// Write a SARIF log alongside each unique report.
+    #[serde(default)]
+    pub sarif_output: bool,
+
     #[serde(flatten)]
     pub common: CommonConfig,
 }
@@ -67,7 +89,8 @@ pub struct ReportTask {
 
 impl ReportTask {
     pub fn new(config: Config) -> Self {
-        let poller = InputPoller::new("crash-report");
+        let poller = InputPoller::new("crash-report")
+            .with_max_dequeue_count(config.common.max_dequeue_count);
         Self { config, poller }
     }
 
@@ -101,7 +124,8 @@ impl ReportTask {
         if self.config.check_queue {
             if let Some(queue) = &self.config.input_queue {
                 let callback = CallbackImpl::new(queue.clone(), processor)
-                    .context("processing from queue failed")?;
+                    .context("processing from queue failed")?
+                    .with_dead_letter_queue(self.config.common.dead_letter_queue.clone());
                 self.poller.run(callback).await.context("poller failed")?;
             }
         }
@@ -124,6 +148,9 @@ pub struct TestInputArgs<'a> {
     pub check_asan_log: bool,
     pub check_debugger: bool,
     pub minimized_stack_depth: Option<usize>,
+    pub stack_filter: &'a StackFilter,
+    pub symbolizer: &'a CrashLogSymbolizer,
+    pub sandbox: &'a SandboxConfig,
     pub machine_identity: MachineIdentity,
 }
 
@@ -140,6 +167,7 @@ pub async fn test_input(args: TestInputArgs<'_>) -> Result<CrashTestResult> {
     .check_asan_log(args.check_asan_log)
     .check_debugger(args.check_debugger)
     .check_retry_count(args.check_retry_count)
+    .sandbox(args.sandbox.clone())
     .set_optional(args.target_timeout, |tester, timeout| {
         tester.timeout(timeout)
     });
@@ -155,6 +183,9 @@ pub async fn test_input(args: TestInputArgs<'_>) -> Result<CrashTestResult> {
     let test_report = tester.test_input(args.input).await?;
 
     if let Some(crash_log) = test_report.crash_log {
+        let symbolizer = args.symbolizer.clone();
+        let crash_log = spawn_blocking(move || symbolizer.symbolize(crash_log)).await??;
+        let crash_log = crash_log.with_stack_filter(args.stack_filter);
         let crash_report = CrashReport::new(
             crash_log,
             task_id,
@@ -177,6 +208,7 @@ pub async fn test_input(args: TestInputArgs<'_>) -> Result<CrashTestResult> {
             job_id,
             tries: 1 + args.check_retry_count,
             error: test_report.error.map(|e| format!("{e}")),
+            limit_kill: test_report.limit_kill,
         };
 
         Ok(CrashTestResult::NoRepro(Box::new(no_repro)))
@@ -187,6 +219,8 @@ pub struct GenericReportProcessor<'a> {
     config: &'a Config,
     heartbeat_client: Option<TaskHeartbeatClient>,
     job_result_client: Option<TaskJobResultClient>,
+    bucketer: Option<CrashBucketer>,
+    symbolizer: CrashLogSymbolizer,
 }
 
 impl<'a> GenericReportProcessor<'a> {
@@ -195,10 +229,13 @@ impl<'a> GenericReportProcessor<'a> {
         heartbeat_client: Option<TaskHeartbeatClient>,
         job_result_client: Option<TaskJobResultClient>,
     ) -> Self {
+        let bucketer = config.crash_bucketing.clone().map(CrashBucketer::new);
         Self {
             config,
             heartbeat_client,
             job_result_client,
+            bucketer,
+            symbolizer: CrashLogSymbolizer::new(),
         }
     }
 
@@ -229,6 +266,9 @@ impl<'a> GenericReportProcessor<'a> {
             check_asan_log: self.config.check_asan_log,
             check_debugger: self.config.check_debugger,
             minimized_stack_depth: self.config.minimized_stack_depth,
+            stack_filter: &self.config.stack_filter,
+            symbolizer: &self.symbolizer,
+            sandbox: &self.config.common.sandbox,
             machine_identity: self.config.common.machine_identity.clone(),
         };
         test_input(args).await.context("test input failed")
@@ -239,10 +279,16 @@ impl<'a> GenericReportProcessor<'a> {
 impl<'a> Processor for GenericReportProcessor<'a> {
     async fn process(&mut self, url: Option<Url>, input: &Path) -> Result<()> {
         debug!("generating crash report for: {}", input.display());
-        let report = self
+        let mut report = self
             .test_input(url, input)
             .await
             .context("test input failed")?;
+        if let Some(bucketer) = &mut self.bucketer {
+            bucketer
+                .bucket(&mut report, &self.config.unique_reports)
+                .await
+                .context("bucketing report failed")?;
+        }
         report
             .save(
                 &self.config.unique_reports,
@@ -251,6 +297,12 @@ impl<'a> Processor for GenericReportProcessor<'a> {
                 &self.job_result_client,
             )
             .await
-            .context("saving report failed")
+            .context("saving report failed")?;
+        if self.config.sarif_output {
+            save_sarif(&report, &self.config.unique_reports)
+                .await
+                .context("saving SARIF failed")?;
+        }
+        Ok(())
     }
 }
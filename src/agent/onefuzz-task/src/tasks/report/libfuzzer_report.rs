@@ -1,7 +1,7 @@
 // Copyright (c) Microsoft Corporation.
 // Licensed under the MIT License.
 
-use super::crash_report::*;
+use super::{bucket::*, crash_report::*, sarif::save_sarif, symbolize::CrashLogSymbolizer};
 use crate::tasks::{
     config::CommonConfig,
     generic::input_poller::*,
@@ -11,17 +11,20 @@ use crate::tasks::{
 use anyhow::{Context, Result};
 use async_trait::async_trait;
 use onefuzz::{
-    blob::BlobUrl, libfuzzer::LibFuzzer, machine_id::MachineIdentity, sha256, syncdir::SyncedDir,
+    blob::BlobUrl, libfuzzer::LibFuzzer, machine_id::MachineIdentity, sandbox::SandboxConfig,
+    sha256, syncdir::SyncedDir,
 };
 use onefuzz_result::job_result::TaskJobResultClient;
 use reqwest::Url;
 use serde::Deserialize;
+use stacktrace_parser::StackFilter;
 use std::{
     collections::HashMap,
     path::{Path, PathBuf},
     sync::Arc,
 };
 use storage_queue::{Message, QueueClient};
+use tokio::task::spawn_blocking;
 
 const LIBFUZZER_TOOL_NAME: &str = "libfuzzer";
 
@@ -47,6 +50,20 @@ pub struct Config {
     #[serde(default)]
     pub minimized_stack_depth: Option<usize>,
 
+    /// Project-specific rules for minimizing call stacks, applied on top of
+    /// the stack frame filters of ClusterFuzz.
+    #[serde(default)]
+    pub stack_filter: StackFilter,
+
+    /// Group similar crash reports into buckets.  Bucketing is disabled when
+    /// not set.
+    #[serde(default)]
+    pub crash_bucketing: Option<StackSimilarity>,
+
+    /// Write a SARIF log alongside each unique report.
+    #[serde(default)]
+    pub sarif_output: bool,
+
     #[serde(default = "default_bool_true")]
     pub check_queue: bool,
 
@@ -61,7 +78,8 @@ pub struct ReportTask {
 
 impl ReportTask {
     pub fn new(config: Config) -> Self {
-        let poller = InputPoller::new("libfuzzer-crash-report");
+        let poller = InputPoller::new("libfuzzer-crash-report")
+            .with_max_dequeue_count(config.common.max_dequeue_count);
         let config = Arc::new(config);
 
         Self { config, poller }
@@ -84,7 +102,8 @@ impl ReportTask {
                 .as_ref()
                 .map(|x| x.local_path.clone()),
             self.config.common.machine_identity.clone(),
-        );
+        )
+        .with_sandbox(self.config.common.sandbox.clone());
 
         fuzzer.verify(self.config.check_fuzzer_help, None).await
     }
@@ -111,7 +130,8 @@ impl ReportTask {
 
         if self.config.check_queue {
             if let Some(url) = &self.config.input_queue {
-                let callback = CallbackImpl::new(url.clone(), processor)?;
+                let callback = CallbackImpl::new(url.clone(), processor)?
+                    .with_dead_letter_queue(self.config.common.dead_letter_queue.clone());
                 self.poller.run(callback).await?;
             }
         }
@@ -133,6 +153,9 @@ pub struct TestInputArgs<'a> {
     pub target_timeout: Option<u64>,
     pub check_retry_count: u64,
     pub minimized_stack_depth: Option<usize>,
+    pub stack_filter: &'a StackFilter,
+    pub symbolizer: &'a CrashLogSymbolizer,
+    pub sandbox: &'a SandboxConfig,
     pub machine_identity: MachineIdentity,
 }
 
@@ -145,7 +168,8 @@ pub async fn test_input(args: TestInputArgs<'_>) -> Result<CrashTestResult> {
         args.extra_setup_dir.map(PathBuf::from),
         args.extra_output_dir.map(PathBuf::from),
         args.machine_identity,
-    );
+    )
+    .with_sandbox(args.sandbox.clone());
 
     let task_id = args.task_id;
     let job_id = args.job_id;
@@ -164,6 +188,9 @@ pub async fn test_input(args: TestInputArgs<'_>) -> Result<CrashTestResult> {
 
     match test_report.crash_log {
         Some(crash_log) => {
+            let symbolizer = args.symbolizer.clone();
+            let crash_log = spawn_blocking(move || symbolizer.symbolize(crash_log)).await??;
+            let crash_log = crash_log.with_stack_filter(args.stack_filter);
             let crash_report = CrashReport::new(
                 crash_log,
                 task_id,
@@ -187,6 +214,7 @@ pub async fn test_input(args: TestInputArgs<'_>) -> Result<CrashTestResult> {
                 job_id,
                 tries: 1 + args.check_retry_count,
                 error: test_report.error.map(|e| format!("{e}")),
+                limit_kill: test_report.limit_kill,
             };
 
             Ok(CrashTestResult::NoRepro(Box::new(no_repro)))
@@ -198,17 +226,22 @@ pub struct AsanProcessor {
     config: Arc<Config>,
     heartbeat_client: Option<TaskHeartbeatClient>,
     job_result_client: Option<TaskJobResultClient>,
+    bucketer: Option<CrashBucketer>,
+    symbolizer: CrashLogSymbolizer,
 }
 
 impl AsanProcessor {
     pub async fn new(config: Arc<Config>) -> Result<Self> {
         let heartbeat_client = config.common.init_heartbeat(None).await?;
         let job_result_client = config.common.init_job_result().await?;
+        let bucketer = config.crash_bucketing.clone().map(CrashBucketer::new);
 
         Ok(Self {
             config,
             heartbeat_client,
             job_result_client,
+            bucketer,
+            symbolizer: CrashLogSymbolizer::new(),
         })
     }
 
@@ -242,6 +275,9 @@ impl AsanProcessor {
             target_timeout: self.config.target_timeout,
             check_retry_count: self.config.check_retry_count,
             minimized_stack_depth: self.config.minimized_stack_depth,
+            stack_filter: &self.config.stack_filter,
+            symbolizer: &self.symbolizer,
+            sandbox: &self.config.common.sandbox,
             machine_identity: self.config.common.machine_identity.clone(),
         };
 
@@ -255,7 +291,12 @@ impl AsanProcessor {
 impl Processor for AsanProcessor {
     async fn process(&mut self, url: Option<Url>, input: &Path) -> Result<()> {
         debug!("processing libfuzzer crash url:{:?} path:{:?}", url, input);
-        let report = self.test_input(url, input).await?;
+        let mut report = self.test_input(url, input).await?;
+        if let Some(bucketer) = &mut self.bucketer {
+            bucketer
+                .bucket(&mut report, &self.config.unique_reports)
+                .await?;
+        }
         report
             .save(
                 &self.config.unique_reports,
@@ -263,6 +304,10 @@ impl Processor for AsanProcessor {
                 &self.config.no_repro,
                 &self.job_result_client,
             )
-            .await
+            .await?;
+        if self.config.sarif_output {
+            save_sarif(&report, &self.config.unique_reports).await?;
+        }
+        Ok(())
     }
 }
@@ -1,7 +1,10 @@
 // Copyright (c) Microsoft Corporation.
 // Licensed under the MIT License.
 
+pub mod bucket;
 pub mod crash_report;
 pub mod dotnet;
 pub mod generic;
 pub mod libfuzzer_report;
+pub mod sarif;
+pub mod symbolize;
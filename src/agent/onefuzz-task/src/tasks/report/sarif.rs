@@ -0,0 +1,364 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT License.
+
+//! Conversion of crash reports to [SARIF 2.1.0][sarif], for use with code
+//! scanning tools.
+//!
+//! [sarif]: https://docs.oasis-open.org/sarif/sarif/v2.1.0/sarif-v2.1.0.html
+
+use super::crash_report::{CrashReport, CrashTestResult};
+use anyhow::Result;
+use onefuzz::syncdir::SyncedDir;
+use serde::Serialize;
+use stacktrace_parser::StackEntry;
+use std::collections::BTreeMap;
+
+const SARIF_SCHEMA: &str = "https://json.schemastore.org/sarif-2.1.0.json";
+const SARIF_VERSION: &str = "2.1.0";
+const ONEFUZZ_URI: &str = "https://github.com/microsoft/onefuzz";
+
+#[derive(Debug, Serialize)]
+pub struct SarifLog {
+    #[serde(rename = "$schema")]
+    pub schema: &'static str,
+    pub version: &'static str,
+    pub runs: Vec<Run>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct Run {
+    pub tool: Tool,
+    pub results: Vec<SarifResult>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct Tool {
+    pub driver: ToolComponent,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ToolComponent {
+    pub name: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub version: Option<String>,
+    pub information_uri: &'static str,
+    pub rules: Vec<ReportingDescriptor>,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ReportingDescriptor {
+    pub id: String,
+    pub short_description: Message,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SarifResult {
+    pub rule_id: String,
+    pub level: &'static str,
+    pub message: Message,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub rank: Option<f64>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub locations: Vec<Location>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub code_flows: Vec<CodeFlow>,
+    pub partial_fingerprints: BTreeMap<&'static str, String>,
+    pub properties: BTreeMap<&'static str, serde_json::Value>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct Message {
+    pub text: String,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Location {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub physical_location: Option<PhysicalLocation>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub logical_locations: Vec<LogicalLocation>,
+    pub message: Message,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PhysicalLocation {
+    pub artifact_location: ArtifactLocation,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub region: Option<Region>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ArtifactLocation {
+    pub uri: String,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Region {
+    pub start_line: u64,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct LogicalLocation {
+    pub fully_qualified_name: String,
+    pub kind: &'static str,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CodeFlow {
+    pub thread_flows: Vec<ThreadFlow>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ThreadFlow {
+    pub locations: Vec<ThreadFlowLocation>,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ThreadFlowLocation {
+    pub location: Location,
+    pub nesting_level: usize,
+}
+
+impl From<&StackEntry> for Location {
+    fn from(entry: &StackEntry) -> Self {
+        let uri = entry
+            .source_file_path
+            .as_ref()
+            .or(entry.source_file_name.as_ref());
+        let physical_location = uri.map(|uri| PhysicalLocation {
+            artifact_location: ArtifactLocation {
+                uri: uri.replace('\\', "/"),
+            },
+            region: entry
+                .source_file_line
+                .map(|start_line| Region { start_line }),
+        });
+
+        let logical_locations = entry
+            .function_name
+            .iter()
+            .map(|name| LogicalLocation {
+                fully_qualified_name: name.clone(),
+                kind: "function",
+            })
+            .collect();
+
+        Self {
+            physical_location,
+            logical_locations,
+            message: Message {
+                text: entry.line.clone(),
+            },
+        }
+    }
+}
+
+impl From<&CrashReport> for SarifLog {
+    fn from(report: &CrashReport) -> Self {
+        let frames = report
+            .minimized_stack_details
+            .as_deref()
+            .unwrap_or_default();
+
+        // The result is located at the innermost frame with a known source
+        // location, falling back to the innermost frame.
+        let locations = frames
+            .iter()
+            .find(|x| x.source_file_path.is_some() || x.source_file_name.is_some())
+            .or_else(|| frames.first())
+            .map(Location::from)
+            .into_iter()
+            .collect();
+
+        // Stacks are innermost first, whereas thread flows are in execution
+        // order.
+        let code_flows = if frames.is_empty() {
+            vec![]
+        } else {
+            let locations = frames
+                .iter()
+                .rev()
+                .enumerate()
+                .map(|(nesting_level, entry)| ThreadFlowLocation {
+                    location: entry.into(),
+                    nesting_level,
+                })
+                .collect();
+            vec![CodeFlow {
+                thread_flows: vec![ThreadFlow { locations }],
+            }]
+        };
+
+        let mut partial_fingerprints = BTreeMap::new();
+        partial_fingerprints.insert("callStackSha256/v1", report.call_stack_sha256.clone());
+        if let Some(sha256) = &report.minimized_stack_sha256 {
+            partial_fingerprints.insert("minimizedStackSha256/v1", sha256.clone());
+        }
+
+        let mut properties = BTreeMap::new();
+        properties.insert("inputSha256", report.input_sha256.clone().into());
+        properties.insert(
+            "executable",
+            report.executable.to_string_lossy().to_string().into(),
+        );
+        if let Some(score) = report.scariness_score {
+            properties.insert("scarinessScore", score.into());
+        }
+        if let Some(description) = &report.scariness_description {
+            properties.insert("scarinessDescription", description.clone().into());
+        }
+
+        let result = SarifResult {
+            rule_id: report.crash_type.clone(),
+            level: "error",
+            message: Message {
+                text: report.crash_site.clone(),
+            },
+            // Scariness scores are unbounded, but SARIF ranks must be between
+            // 0 and 100.
+            rank: report.scariness_score.map(|x| f64::from(x.min(100))),
+            locations,
+            code_flows,
+            partial_fingerprints,
+            properties,
+        };
+
+        let driver = ToolComponent {
+            name: report
+                .tool_name
+                .clone()
+                .unwrap_or_else(|| "onefuzz".to_string()),
+            version: report.tool_version.clone(),
+            information_uri: ONEFUZZ_URI,
+            rules: vec![ReportingDescriptor {
+                id: report.crash_type.clone(),
+                short_description: Message {
+                    text: report.crash_type.clone(),
+                },
+            }],
+        };
+
+        Self {
+            schema: SARIF_SCHEMA,
+            version: SARIF_VERSION,
+            runs: vec![Run {
+                tool: Tool { driver },
+                results: vec![result],
+            }],
+        }
+    }
+}
+
+impl CrashReport {
+    pub fn unique_sarif_name(&self) -> String {
+        format!("{}.sarif", self.call_stack_sha256)
+    }
+}
+
+/// Save a SARIF log alongside the unique report of a crash.
+pub async fn save_sarif(
+    result: &CrashTestResult,
+    unique_reports: &Option<SyncedDir>,
+) -> Result<()> {
+    if let (CrashTestResult::CrashReport(report), Some(unique_reports)) = (result, unique_reports) {
+        let sarif = SarifLog::from(report.as_ref());
+        unique_reports
+            .upload(&report.unique_sarif_name(), &sarif)
+            .await?;
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use stacktrace_parser::CrashLog;
+
+    fn report() -> Result<CrashReport> {
+        let text = std::fs::read_to_string("data/sarif/libfuzzer-scariness.txt")?;
+        let crash_log = CrashLog::parse(text)?;
+        Ok(CrashReport::new(
+            crash_log,
+            Default::default(),
+            Default::default(),
+            "fuzz.exe",
+            None,
+            "0".repeat(64),
+            None,
+            "libfuzzer".to_string(),
+            "1.2.3".to_string(),
+            "1.2.3".to_string(),
+        ))
+    }
+
+    #[test]
+    fn test_sarif() -> Result<()> {
+        let report = report()?;
+        let sarif = serde_json::to_value(SarifLog::from(&report))?;
+
+        assert_eq!(sarif["version"], "2.1.0");
+        let run = &sarif["runs"][0];
+        assert_eq!(run["tool"]["driver"]["name"], "libfuzzer");
+        assert_eq!(run["tool"]["driver"]["rules"][0]["id"], "FPE");
+
+        let result = &run["results"][0];
+        assert_eq!(result["ruleId"], "FPE");
+        assert_eq!(result["level"], "error");
+        assert_eq!(result["rank"], 10.0);
+        assert_eq!(result["properties"]["scarinessScore"], 10);
+        assert_eq!(
+            result["partialFingerprints"]["callStackSha256/v1"],
+            report.call_stack_sha256
+        );
+
+        let location = &result["locations"][0]["physicalLocation"];
+        assert_eq!(
+            location["artifactLocation"]["uri"],
+            "/home/runner/work/onefuzz/onefuzz/src/integration-tests/libfuzzer/simple.c"
+        );
+        assert_eq!(location["region"]["startLine"], 58);
+
+        let flow = result["codeFlows"][0]["threadFlows"][0]["locations"]
+            .as_array()
+            .unwrap();
+        assert_eq!(
+            flow.len(),
+            report.minimized_stack_details.as_ref().unwrap().len()
+        );
+        assert_eq!(
+            flow.last().unwrap()["location"]["logicalLocations"][0]["fullyQualifiedName"],
+            "simple.c"
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_sarif_without_stack() -> Result<()> {
+        let report = CrashReport {
+            crash_type: "crash".to_string(),
+            crash_site: "unknown".to_string(),
+            ..Default::default()
+        };
+        let sarif = serde_json::to_value(SarifLog::from(&report))?;
+
+        let result = &sarif["runs"][0]["results"][0];
+        assert_eq!(result["message"]["text"], "unknown");
+        assert!(result.get("locations").is_none());
+        assert!(result.get("codeFlows").is_none());
+        assert!(result.get("rank").is_none());
+        assert_eq!(sarif["runs"][0]["tool"]["driver"]["name"], "onefuzz");
+
+        Ok(())
+    }
+}
@@ -0,0 +1,310 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT License.
+
+use std::collections::BTreeMap;
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+
+use anyhow::Result;
+use debuggable_module::load_module::LoadModule;
+use debuggable_module::loader::Loader;
+use debuggable_module::path::FilePath;
+use debuggable_module::symbolize::{SymbolizedFrame, Symbolizer};
+use debuggable_module::{Module, Offset};
+use regex::Regex;
+use stacktrace_parser::{CrashLog, StackEntry};
+
+const ELF_MAGIC: &[u8] = b"\x7fELF";
+
+lazy_static::lazy_static! {
+    // the frame number of an ASan stack frame, as in "#3 0x4f8a2 (...)"
+    static ref FRAME_NUMBER_REGEX: Regex = Regex::new(r"^#\d+").unwrap();
+}
+
+fn is_unsymbolized(entry: &StackEntry) -> bool {
+    entry.function_name.is_none() && entry.module_path.is_some() && entry.module_offset.is_some()
+}
+
+/// Symbolizes the frames of crash logs which only have a module and offset,
+/// as when the target crashed without `llvm-symbolizer` available.
+///
+/// The debuginfo of each module is read from the module itself, at the path
+/// it was loaded from, the first time one of its frames is symbolized. Its
+/// symbolizer is then kept for the lifetime of the task.
+#[derive(Clone, Default)]
+pub struct CrashLogSymbolizer {
+    modules: Arc<Mutex<BTreeMap<String, Option<ModuleSymbolizer>>>>,
+}
+
+impl CrashLogSymbolizer {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Symbolize the unsymbolized frames of `crash_log`.
+    ///
+    /// Frames which cannot be symbolized, such as those of modules which
+    /// cannot be loaded, are left as they are. Since the call stack changes,
+    /// its minimized stack and hashes are recomputed, so reports of the same
+    /// bug match whether or not the target was symbolized when it crashed.
+    pub fn symbolize(&self, crash_log: CrashLog) -> Result<CrashLog> {
+        if !crash_log.full_stack_details.iter().any(is_unsymbolized) {
+            return Ok(crash_log);
+        }
+
+        let mut modules = self
+            .modules
+            .lock()
+            .map_err(|_| format_err!("symbolizer cache lock poisoned"))?;
+        let mut stack = vec![];
+
+        for (index, entry) in crash_log.full_stack_details.iter().enumerate() {
+            let (Some(module_path), Some(module_offset)) =
+                (&entry.module_path, entry.module_offset)
+            else {
+                stack.push(entry.clone());
+                continue;
+            };
+
+            if entry.function_name.is_some() {
+                stack.push(entry.clone());
+                continue;
+            }
+
+            let symbolizer =
+                modules
+                    .entry(module_path.clone())
+                    .or_insert_with(|| match ModuleSymbolizer::load(module_path) {
+                        Ok(symbolizer) => Some(symbolizer),
+                        Err(err) => {
+                            warn!("unable to symbolize frames of {}: {:?}", module_path, err);
+                            None
+                        }
+                    });
+
+            // frames below the top of the stack are return addresses, which
+            // point just past the call instruction
+            let offset = if index == 0 {
+                module_offset
+            } else {
+                module_offset.saturating_sub(1)
+            };
+
+            let frames = match symbolizer {
+                Some(symbolizer) => symbolizer.symbolize(offset).unwrap_or_else(|err| {
+                    warn!(
+                        "unable to symbolize {}+0x{:x}: {:?}",
+                        module_path, offset, err
+                    );
+                    vec![]
+                }),
+                None => vec![],
+            };
+
+            if frames.is_empty() {
+                stack.push(entry.clone());
+            } else {
+                stack.extend(frames.iter().map(|frame| symbolized_entry(entry, frame)));
+            }
+        }
+
+        crash_log.with_call_stack(stack)
+    }
+}
+
+struct ModuleSymbolizer {
+    symbolizer: Symbolizer,
+
+    /// Virtual address the offsets reported by sanitizers are relative to,
+    /// rather than to the base of the module image.
+    image_base: u64,
+}
+
+impl ModuleSymbolizer {
+    fn load(module_path: &str) -> Result<Self> {
+        let loader = Loader::new();
+        let path = FilePath::new(module_path)?;
+        let module: Box<dyn Module> = Box::load(&loader, path)?;
+        let symbolizer = Symbolizer::new(&*module)?;
+
+        // Sanitizers report the offsets of ELF modules from their load bias,
+        // so they are virtual addresses, which only start at 0 for PIE
+        // modules. Offsets in PE modules are relative to the image base.
+        let image_base = if module.executable_data().starts_with(ELF_MAGIC) {
+            module.base_address().0
+        } else {
+            0
+        };
+
+        Ok(Self {
+            symbolizer,
+            image_base,
+        })
+    }
+
+    fn symbolize(&self, module_offset: u64) -> Result<Vec<SymbolizedFrame>> {
+        let offset = module_offset.checked_sub(self.image_base).ok_or_else(|| {
+            format_err!(
+                "offset 0x{:x} is below the image base 0x{:x}",
+                module_offset,
+                self.image_base
+            )
+        })?;
+
+        self.symbolizer.symbolize(Offset(offset))
+    }
+}
+
+// Builds a stack entry for a symbolized frame, formatted as ASan would have if
+// `llvm-symbolizer` had been available.
+fn symbolized_entry(entry: &StackEntry, frame: &SymbolizedFrame) -> StackEntry {
+    let mut line = match entry.address {
+        Some(address) => format!("0x{address:x} in {}", frame.function),
+        None => format!("in {}", frame.function),
+    };
+    if let Some(frame_number) = FRAME_NUMBER_REGEX.find(&entry.line) {
+        line = format!("{} {line}", frame_number.as_str());
+    }
+    if let Some(file) = &frame.file {
+        line = match frame.line {
+            Some(file_line) => format!("{line} {file}:{file_line}"),
+            None => format!("{line} {file}"),
+        };
+    }
+
+    StackEntry {
+        line,
+        function_name: Some(frame.function.clone()),
+        source_file_name: frame.file.as_deref().and_then(|file| {
+            Path::new(file)
+                .file_name()
+                .map(|name| name.to_string_lossy().into_owned())
+        }),
+        source_file_path: frame.file.clone(),
+        source_file_line: frame.line.map(u64::from),
+        ..entry.clone()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn test_symbolized_entry() {
+        let entry = StackEntry {
+            line: "#3 0x55d5c8d8b0c1 (/setup/fuzz.exe+0x4f8a2)".to_string(),
+            address: Some(0x55d5c8d8b0c1),
+            module_path: Some("/setup/fuzz.exe".to_string()),
+            module_offset: Some(0x4f8a2),
+            ..Default::default()
+        };
+        let frame = SymbolizedFrame {
+            function: "parse_header".to_string(),
+            file: Some("/src/image/header.c".to_string()),
+            line: Some(41),
+        };
+
+        let expected = StackEntry {
+            line: "#3 0x55d5c8d8b0c1 in parse_header /src/image/header.c:41".to_string(),
+            address: Some(0x55d5c8d8b0c1),
+            function_name: Some("parse_header".to_string()),
+            source_file_name: Some("header.c".to_string()),
+            source_file_path: Some("/src/image/header.c".to_string()),
+            source_file_line: Some(41),
+            module_path: Some("/setup/fuzz.exe".to_string()),
+            module_offset: Some(0x4f8a2),
+            ..Default::default()
+        };
+        assert_eq!(symbolized_entry(&entry, &frame), expected);
+    }
+
+    #[test]
+    fn test_missing_module() -> Result<()> {
+        let stack = vec![StackEntry {
+            line: "#0 0x1234 (/does/not/exist+0x1234)".to_string(),
+            module_path: Some("/does/not/exist".to_string()),
+            module_offset: Some(0x1234),
+            ..Default::default()
+        }];
+        let crash_log = CrashLog::new(
+            None,
+            Some("AddressSanitizer: SEGV".to_string()),
+            "AddressSanitizer".to_string(),
+            "SEGV".to_string(),
+            None,
+            None,
+            stack.clone(),
+        )?;
+
+        let symbolizer = CrashLogSymbolizer::new();
+        let symbolized = symbolizer.symbolize(crash_log.clone())?;
+        assert_eq!(symbolized.full_stack_details, stack);
+
+        // the failure to load the module is cached
+        assert!(symbolizer.modules.lock().unwrap()["/does/not/exist"].is_none());
+        let symbolized = symbolizer.symbolize(crash_log)?;
+        assert_eq!(symbolized.full_stack_details, stack);
+
+        Ok(())
+    }
+
+    #[cfg(target_os = "linux")]
+    #[inline(never)]
+    fn crashing_function() {}
+
+    // Load address of the test binary, which is a PIE, as found by the
+    // sanitizers.
+    #[cfg(target_os = "linux")]
+    fn load_address(exe: &Path) -> Result<u64> {
+        let maps = std::fs::read_to_string("/proc/self/maps")?;
+        for line in maps.lines() {
+            // "55d5c8d3c000-55d5c8d8b000 r--p 00000000 08:01 1234 /path/to/exe"
+            let fields: Vec<_> = line.split_whitespace().collect();
+            if fields.len() == 6 && Path::new(fields[5]) == exe && fields[2] == "00000000" {
+                let (start, _) = fields[0].split_once('-').unwrap();
+                return Ok(u64::from_str_radix(start, 16)?);
+            }
+        }
+        bail!("test binary not mapped")
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn test_symbolize_test_binary() -> Result<()> {
+        let exe = std::env::current_exe()?;
+        let module_path = exe.to_string_lossy().into_owned();
+        let address = crashing_function as usize as u64;
+        let module_offset = address - load_address(&exe)?;
+
+        let stack = vec![StackEntry {
+            line: format!("#0 0x{address:x} ({module_path}+0x{module_offset:x})"),
+            address: Some(address),
+            module_path: Some(module_path),
+            module_offset: Some(module_offset),
+            ..Default::default()
+        }];
+        let crash_log = CrashLog::new(
+            None,
+            Some("AddressSanitizer: SEGV".to_string()),
+            "AddressSanitizer".to_string(),
+            "SEGV".to_string(),
+            None,
+            None,
+            stack,
+        )?;
+
+        let symbolized = CrashLogSymbolizer::new().symbolize(crash_log)?;
+        let frame = &symbolized.full_stack_details[0];
+        assert!(frame
+            .function_name
+            .as_deref()
+            .unwrap()
+            .ends_with("tests::crashing_function"));
+        assert_eq!(frame.source_file_name.as_deref(), Some("symbolize.rs"));
+        assert!(frame.line.starts_with(&format!("#0 0x{address:x} in ")));
+
+        Ok(())
+    }
+}
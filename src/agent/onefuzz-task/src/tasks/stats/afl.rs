@@ -6,6 +6,8 @@ use onefuzz_telemetry::EventData;
 use std::path::Path;
 use tokio::io::AsyncBufReadExt;
 
+const BYTES_PER_MB: u64 = 1024 * 1024;
+
 pub async fn read_stats(output_path: impl AsRef<Path>) -> Result<Vec<EventData>, Error> {
     let output_path = output_path.as_ref();
     let f = tokio::fs::File::open(&output_path).await.with_context(|| {
@@ -19,15 +21,19 @@ pub async fn read_stats(output_path: impl AsRef<Path>) -> Result<Vec<EventData>,
     let reader = tokio::io::BufReader::new(f);
     let mut lines = reader.lines();
     while let Ok(Some(line)) = lines.next_line().await {
-        let mut name_value = line.split(':');
-        let name = name_value.next().unwrap().trim();
-        let value = name_value.next().unwrap().trim();
+        let (name, value) = match line.split_once(':') {
+            Some((name, value)) => (name.trim(), value.trim()),
+            None => continue,
+        };
 
+        // AFL++ renamed several of the AFL 2.x fields (`paths_*` -> `corpus_*`,
+        // `unique_crashes` -> `saved_crashes`, ...).  Both spellings are
+        // accepted so that either fuzzer can be monitored.
         match name {
             "target_mode" => {
                 stats.push(EventData::Mode(value.to_string()));
             }
-            "paths_total" => {
+            "paths_total" | "corpus_count" => {
                 if let Ok(value) = value.parse::<u64>() {
                     stats.push(EventData::CoveragePaths(value));
                 } else {
@@ -48,27 +54,34 @@ pub async fn read_stats(output_path: impl AsRef<Path>) -> Result<Vec<EventData>,
                     error!("unable to parse telemetry: {:?} {:?}", name, value);
                 }
             }
-            "paths_favored" => {
+            "paths_favored" | "corpus_favored" => {
                 if let Ok(value) = value.parse::<u64>() {
                     stats.push(EventData::CoveragePathsFavored(value));
                 } else {
                     error!("unable to parse telemetry: {:?} {:?}", name, value);
                 }
             }
-            "paths_found" => {
+            "paths_found" | "corpus_found" => {
                 if let Ok(value) = value.parse::<u64>() {
                     stats.push(EventData::CoveragePathsFound(value));
                 } else {
                     error!("unable to parse telemetry: {:?} {:?}", name, value);
                 }
             }
-            "paths_imported" => {
+            "paths_imported" | "corpus_imported" => {
                 if let Ok(value) = value.parse::<u64>() {
                     stats.push(EventData::CoveragePathsImported(value));
                 } else {
                     error!("unable to parse telemetry: {:?} {:?}", name, value);
                 }
             }
+            "max_depth" => {
+                if let Ok(value) = value.parse::<u64>() {
+                    stats.push(EventData::CoverageMaxDepth(value));
+                } else {
+                    error!("unable to parse telemetry: {:?} {:?}", name, value);
+                }
+            }
             "execs_per_sec" => {
                 if let Ok(value) = value.parse::<f64>() {
                     stats.push(EventData::ExecsSecond(value));
@@ -84,14 +97,68 @@ pub async fn read_stats(output_path: impl AsRef<Path>) -> Result<Vec<EventData>,
                     error!("unable to parse telemetry: {:?} {:?}", name, value);
                 }
             }
+            "stability" => {
+                let value = value.replace('%', "");
+                if let Ok(value) = value.parse::<f64>() {
+                    stats.push(EventData::Stability(value));
+                } else {
+                    error!("unable to parse telemetry: {:?} {:?}", name, value);
+                }
+            }
+            "cycles_done" => {
+                if let Ok(value) = value.parse::<u64>() {
+                    stats.push(EventData::CyclesDone(value));
+                } else {
+                    error!("unable to parse telemetry: {:?} {:?}", name, value);
+                }
+            }
+            "unique_crashes" | "saved_crashes" => {
+                if let Ok(value) = value.parse::<u64>() {
+                    stats.push(EventData::SavedCrashes(value));
+                } else {
+                    error!("unable to parse telemetry: {:?} {:?}", name, value);
+                }
+            }
+            "unique_hangs" | "saved_hangs" => {
+                if let Ok(value) = value.parse::<u64>() {
+                    stats.push(EventData::SavedHangs(value));
+                } else {
+                    error!("unable to parse telemetry: {:?} {:?}", name, value);
+                }
+            }
+            "edges_found" => {
+                if let Ok(value) = value.parse::<u64>() {
+                    stats.push(EventData::CoverageEdgesFound(value));
+                } else {
+                    error!("unable to parse telemetry: {:?} {:?}", name, value);
+                }
+            }
+            "total_edges" => {
+                if let Ok(value) = value.parse::<u64>() {
+                    stats.push(EventData::CoverageEdgesTotal(value));
+                } else {
+                    error!("unable to parse telemetry: {:?} {:?}", name, value);
+                }
+            }
+            "peak_rss_mb" => {
+                if let Ok(value) = value.parse::<u64>() {
+                    stats.push(EventData::PhysicalMemory(value * BYTES_PER_MB));
+                } else {
+                    error!("unable to parse telemetry: {:?} {:?}", name, value);
+                }
+            }
             "command_line" => {
                 stats.push(EventData::CommandLine(value.to_string()));
             }
             // ignored telemetry
-            "cycles_done" | "afl_banner" | "afl_version" | "start_time" | "last_update"
-            | "stability" | "unique_crashes" | "unique_hangs" | "pending_favs"
-            | "pending_total" | "variable_paths" | "last_path" | "last_crash" | "last_hang"
-            | "execs_since_crash" | "max_depth" | "cur_path" | "exec_timeout" => {}
+            "afl_banner" | "afl_version" | "start_time" | "last_update" | "run_time"
+            | "pending_favs" | "pending_total" | "variable_paths" | "corpus_variable"
+            | "last_path" | "last_find" | "last_crash" | "last_hang" | "execs_since_crash"
+            | "cur_path" | "cur_item" | "exec_timeout" | "cycles_wo_finds" | "time_wo_finds"
+            | "fuzz_time" | "calibration_time" | "cmplog_time" | "sync_time" | "trim_time"
+            | "execs_ps_last_min" | "slowest_exec_ms" | "cpu_affinity" | "var_byte_count"
+            | "havoc_expansion" | "auto_dict_entries" | "testcache_size" | "testcache_count"
+            | "testcache_evict" => {}
             _ => {
                 warn!("unsupported telemetry: {} {}", name, value);
             }
@@ -112,4 +179,21 @@ mod tests {
         assert!(results.contains(&EventData::ExecsSecond(2666.67)));
         assert!(results.contains(&EventData::Mode("default".to_string())));
     }
+
+    #[tokio::test]
+    async fn test_aflpp_stats_parse() {
+        let results = read_stats("data/aflpp-fuzzer_stats.txt").await.unwrap();
+        assert!(results.contains(&EventData::Pid(1830184)));
+        assert!(results.contains(&EventData::Count(4262883)));
+        assert!(results.contains(&EventData::ExecsSecond(4727.58)));
+        assert!(results.contains(&EventData::CoveragePaths(152)));
+        assert!(results.contains(&EventData::CoveragePathsFavored(28)));
+        assert!(results.contains(&EventData::CoverageMaxDepth(7)));
+        assert!(results.contains(&EventData::Stability(99.52)));
+        assert!(results.contains(&EventData::SavedCrashes(3)));
+        assert!(results.contains(&EventData::SavedHangs(1)));
+        assert!(results.contains(&EventData::CoverageEdgesFound(317)));
+        assert!(results.contains(&EventData::CoverageEdgesTotal(65536)));
+        assert!(results.contains(&EventData::PhysicalMemory(13 * BYTES_PER_MB)));
+    }
 }
@@ -1,11 +1,13 @@
 // Copyright (c) Microsoft Corporation.
 // Licensed under the MIT License.
 
-use super::afl;
+use super::{afl, honggfuzz};
 use anyhow::{Error, Result};
 use onefuzz::jitter::delay_with_jitter;
-use onefuzz_telemetry::Event::runtime_stats;
+use onefuzz_result::job_result::{JobResultData, JobResultSender, TaskJobResultClient};
+use onefuzz_telemetry::{Event::runtime_stats, EventData};
 use serde::Deserialize;
+use std::collections::HashMap;
 pub const STATS_DELAY: std::time::Duration = std::time::Duration::from_secs(30);
 
 // TODO - remove unkonwn_lints once GitHub build agents are at 1.51.0 or later
@@ -13,16 +15,24 @@ pub const STATS_DELAY: std::time::Duration = std::time::Duration::from_secs(30);
 pub enum StatsFormat {
     #[serde(alias = "AFL")]
     Afl,
+    #[serde(alias = "Honggfuzz", alias = "HONGGFUZZ")]
+    Honggfuzz,
 }
 
-pub async fn monitor_stats(path: Option<String>, format: Option<StatsFormat>) -> Result<(), Error> {
+pub async fn monitor_stats(
+    path: Option<String>,
+    format: Option<StatsFormat>,
+    jr_client: &Option<TaskJobResultClient>,
+) -> Result<(), Error> {
     if let Some(path) = path {
         if let Some(format) = format {
             loop {
                 let stats = match format {
                     StatsFormat::Afl => afl::read_stats(&path).await,
+                    StatsFormat::Honggfuzz => honggfuzz::read_stats(&path).await,
                 };
                 if let Ok(stats) = stats {
+                    send_job_result(&stats, jr_client).await;
                     log_events!(runtime_stats; stats);
                 }
                 delay_with_jitter(STATS_DELAY).await;
@@ -31,3 +41,29 @@ pub async fn monitor_stats(path: Option<String>, format: Option<StatsFormat>) ->
     }
     Ok(())
 }
+
+async fn send_job_result(stats: &[EventData], jr_client: &Option<TaskJobResultClient>) {
+    let Some(jr_client) = jr_client else {
+        return;
+    };
+
+    let values: HashMap<String, f64> = stats
+        .iter()
+        .filter_map(|stat| match stat {
+            EventData::Count(x) => Some(("total_count", *x as f64)),
+            EventData::ExecsSecond(x) => Some(("execs_sec", *x)),
+            EventData::SavedCrashes(x) => Some(("saved_crashes", *x as f64)),
+            EventData::SavedHangs(x) => Some(("saved_hangs", *x as f64)),
+            EventData::Covered(x) => Some(("covered", *x as f64)),
+            EventData::CoverageEdgesFound(x) => Some(("edges_found", *x as f64)),
+            _ => None,
+        })
+        .map(|(name, value)| (name.to_string(), value))
+        .collect();
+
+    if !values.is_empty() {
+        let _ = jr_client
+            .send_direct(JobResultData::RuntimeStats, values)
+            .await;
+    }
+}
@@ -0,0 +1,138 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT License.
+
+use anyhow::{Context, Error, Result};
+use onefuzz_telemetry::EventData;
+use std::{io::SeekFrom, path::Path};
+use tokio::io::{AsyncReadExt, AsyncSeekExt};
+
+/// Name of the crash report written by honggfuzz into its working directory.
+pub const REPORT_FILE_NAME: &str = "HONGGFUZZ.REPORT.TXT";
+
+// Only the tail of the stats file is read, as honggfuzz appends a line to it
+// every second for the lifetime of the fuzzer.
+const STATS_TAIL_BYTES: u64 = 4096;
+
+/// Read honggfuzz runtime stats.
+///
+/// `output_path` may either be the file passed to honggfuzz via `--statsfile`,
+/// in which case the most recent stats line is parsed, or its
+/// `HONGGFUZZ.REPORT.TXT` crash report, in which case the crashes recorded
+/// in the report are counted.
+pub async fn read_stats(output_path: impl AsRef<Path>) -> Result<Vec<EventData>, Error> {
+    let output_path = output_path.as_ref();
+
+    if output_path.file_name() == Some(REPORT_FILE_NAME.as_ref()) {
+        let report = tokio::fs::read_to_string(output_path)
+            .await
+            .with_context(|| {
+                format!(
+                    "unable to open honggfuzz report for read: {}",
+                    output_path.display()
+                )
+            })?;
+        return Ok(parse_report(&report));
+    }
+
+    let mut f = tokio::fs::File::open(&output_path).await.with_context(|| {
+        format!(
+            "unable to open honggfuzz stats for read: {}",
+            output_path.display()
+        )
+    })?;
+    let len = f.metadata().await?.len();
+    f.seek(SeekFrom::Start(len.saturating_sub(STATS_TAIL_BYTES)))
+        .await?;
+    let mut tail = Vec::new();
+    f.read_to_end(&mut tail).await?;
+
+    parse_stats(&String::from_utf8_lossy(&tail))
+}
+
+// The `--statsfile` format is a header comment followed by one line per
+// update:
+//
+// # unix_time, last_cov_update, total_exec, exec_per_sec, crashes, unique_crashes, hangs, edge_cov, block_cov
+// 1696862541, 1696862540, 1201, 600, 2, 1, 0, 310, 1011
+fn parse_stats(data: &str) -> Result<Vec<EventData>> {
+    let line = data
+        .lines()
+        .rev()
+        .map(str::trim)
+        .find(|line| !line.is_empty() && !line.starts_with('#'))
+        .ok_or_else(|| format_err!("no honggfuzz stats available"))?;
+
+    let fields = line
+        .split(',')
+        .map(|x| x.trim().parse::<u64>())
+        .collect::<Result<Vec<_>, _>>()
+        .with_context(|| format!("unable to parse honggfuzz stats: {line:?}"))?;
+
+    let &[_unix_time, _last_cov_update, total_exec, exec_per_sec, _crashes, unique_crashes, hangs, edge_cov, block_cov] =
+        fields.as_slice()
+    else {
+        bail!("unexpected honggfuzz stats format: {:?}", line);
+    };
+
+    Ok(vec![
+        EventData::Count(total_exec),
+        EventData::ExecsSecond(exec_per_sec as f64),
+        EventData::SavedCrashes(unique_crashes),
+        EventData::SavedHangs(hangs),
+        EventData::CoverageEdgesFound(edge_cov),
+        EventData::Covered(block_cov),
+    ])
+}
+
+// Each crash saved by honggfuzz appends a block to the report such as:
+//
+// =====================================================================
+// TIME: 2023-10-09.14:42:21
+// =====================================================================
+// FUZZER ARGS:
+//  ...
+// CRASH:
+// DESCRIPTION:
+// ORIG_FNAME: ...
+// FUZZ_FNAME: ...
+// ...
+fn parse_report(data: &str) -> Vec<EventData> {
+    let crashes = data
+        .lines()
+        .filter(|line| line.trim_end() == "CRASH:")
+        .count();
+
+    vec![EventData::SavedCrashes(crashes as u64)]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_stats_parse() {
+        let results = read_stats("data/honggfuzz-stats.txt").await.unwrap();
+        assert!(results.contains(&EventData::Count(120540)));
+        assert!(results.contains(&EventData::ExecsSecond(4012.0)));
+        assert!(results.contains(&EventData::SavedCrashes(2)));
+        assert!(results.contains(&EventData::SavedHangs(1)));
+        assert!(results.contains(&EventData::CoverageEdgesFound(412)));
+        assert!(results.contains(&EventData::Covered(1033)));
+    }
+
+    #[tokio::test]
+    async fn test_report_parse() {
+        let results = read_stats("data/honggfuzz/HONGGFUZZ.REPORT.TXT")
+            .await
+            .unwrap();
+        assert_eq!(results, vec![EventData::SavedCrashes(2)]);
+    }
+
+    #[test]
+    fn test_stats_parse_errors() {
+        assert!(parse_stats("").is_err());
+        assert!(parse_stats("# unix_time, last_cov_update\n").is_err());
+        assert!(parse_stats("1, 2, 3\n").is_err());
+        assert!(parse_stats("1, 2, 3, x, 5, 6, 7, 8, 9\n").is_err());
+    }
+}
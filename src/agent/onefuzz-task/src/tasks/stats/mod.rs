@@ -3,3 +3,4 @@
 
 pub mod afl;
 pub mod common;
+pub mod honggfuzz;
@@ -16,6 +16,8 @@ use tokio::sync::broadcast::{self, Receiver};
 #[macro_use]
 extern crate lazy_static;
 
+pub mod metrics;
+
 const DEAFAULT_CHANNEL_CLOSING_TIMEOUT: Duration = Duration::from_secs(30);
 
 #[derive(Clone, Debug, Deserialize, Serialize, PartialEq, Eq)]
@@ -87,6 +89,8 @@ pub enum Event {
     new_unable_to_reproduce,
     regression_report,
     regression_unable_to_reproduce,
+    retention_eviction,
+    poison_message,
 }
 
 impl Event {
@@ -106,6 +110,8 @@ impl Event {
             Self::new_unable_to_reproduce => "new_unable_to_reproduce",
             Self::regression_report => "regression_report",
             Self::regression_unable_to_reproduce => "regression_unable_to_reproduce",
+            Self::retention_eviction => "retention_eviction",
+            Self::poison_message => "poison_message",
         }
     }
 }
@@ -127,6 +133,7 @@ pub enum EventData {
     Covered(u64),
     Rate(f64),
     Count(u64),
+    Size(u64),
     ExecsSecond(f64),
     RunId(Uuid),
     Name(String),
@@ -141,6 +148,12 @@ pub enum EventData {
     CoveragePathsFound(u64),
     CoveragePathsImported(u64),
     CoverageMaxDepth(u64),
+    CoverageEdgesFound(u64),
+    CoverageEdgesTotal(u64),
+    CyclesDone(u64),
+    Stability(f64),
+    SavedCrashes(u64),
+    SavedHangs(u64),
     ToolName(String),
     Region(String),
     Role(Role),
@@ -163,6 +176,7 @@ impl EventData {
             Self::Covered(x) => ("covered", x.to_string()),
             Self::Rate(x) => ("rate", x.to_string()),
             Self::Count(x) => ("count", x.to_string()),
+            Self::Size(x) => ("size", x.to_string()),
             Self::ExecsSecond(x) => ("execs_sec", x.to_string()),
             Self::WorkerId(x) => ("worker_id", x.to_string()),
             Self::RunId(x) => ("run_id", x.to_string()),
@@ -177,6 +191,12 @@ impl EventData {
             Self::CoveragePathsFound(x) => ("coverage_paths_found", x.to_string()),
             Self::CoveragePathsImported(x) => ("coverage_paths_imported", x.to_string()),
             Self::CoverageMaxDepth(x) => ("coverage_paths_depth", x.to_string()),
+            Self::CoverageEdgesFound(x) => ("coverage_edges_found", x.to_string()),
+            Self::CoverageEdgesTotal(x) => ("coverage_edges_total", x.to_string()),
+            Self::CyclesDone(x) => ("cycles_done", x.to_string()),
+            Self::Stability(x) => ("stability", x.to_string()),
+            Self::SavedCrashes(x) => ("saved_crashes", x.to_string()),
+            Self::SavedHangs(x) => ("saved_hangs", x.to_string()),
             Self::Coverage(x) => ("coverage", x.to_string()),
             Self::ToolName(x) => ("tool_name", x.to_owned()),
             Self::Region(x) => ("region", x.to_owned()),
@@ -200,6 +220,7 @@ impl EventData {
             Self::Covered(_) => true,
             Self::Rate(_) => true,
             Self::Count(_) => true,
+            Self::Size(_) => true,
             Self::ExecsSecond(_) => true,
             Self::WorkerId(_) => true,
             Self::RunId(_) => true,
@@ -214,6 +235,12 @@ impl EventData {
             Self::CoveragePathsFound(_) => true,
             Self::CoveragePathsImported(_) => true,
             Self::CoverageMaxDepth(_) => true,
+            Self::CoverageEdgesFound(_) => true,
+            Self::CoverageEdgesTotal(_) => true,
+            Self::CyclesDone(_) => true,
+            Self::Stability(_) => true,
+            Self::SavedCrashes(_) => true,
+            Self::SavedHangs(_) => true,
             Self::Coverage(_) => true,
             Self::ToolName(_) => true,
             Self::Region(_) => false,
@@ -418,6 +445,8 @@ pub fn property(client_type: ClientType, key: impl AsRef<str>) -> Option<String>
 }
 
 pub fn set_property(entry: EventData) {
+    metrics::set_label(&entry);
+
     let (key, value) = entry.as_values();
 
     if entry.can_share_with_microsoft() {
@@ -518,6 +547,7 @@ pub fn track_event(event: &Event, properties: &[EventData]) {
         }
         client.track(evt);
     }
+    metrics::record(event, properties, true);
     try_broadcast_event(chrono::Utc::now(), event, properties);
 }
 
@@ -546,6 +576,8 @@ pub fn track_metric(metric: &Event, value: f64, properties: &[EventData]) {
         }
         client.track(mtr);
     }
+
+    metrics::record(metric, properties, false);
 }
 
 pub fn to_log_level(level: &appinsights::telemetry::SeverityLevel) -> log::Level {
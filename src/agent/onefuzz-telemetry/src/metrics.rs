@@ -0,0 +1,331 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT License.
+
+//! Export of telemetry as Prometheus metrics.
+//!
+//! When enabled, every tracked event increments the `onefuzz_events_total`
+//! counter, and every numeric `EventData` value of a tracked event or metric
+//! sets a gauge named after its key, such as `onefuzz_execs_sec`.  Samples are
+//! labeled by event, and by job, task, machine and worker id when known.
+
+use std::collections::BTreeMap;
+use std::convert::Infallible;
+use std::fmt::Write;
+use std::net::SocketAddr;
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, RwLock};
+
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+
+use crate::{Event, EventData};
+
+const EVENTS_TOTAL: &str = "onefuzz_events_total";
+const CONTENT_TYPE: &str = "text/plain; version=0.0.4";
+
+#[derive(Clone, Copy, Debug, Deserialize, Eq, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum MetricKind {
+    Counter,
+    Gauge,
+}
+
+impl MetricKind {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Counter => "counter",
+            Self::Gauge => "gauge",
+        }
+    }
+}
+
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+pub struct Sample {
+    pub name: String,
+    pub kind: MetricKind,
+    pub labels: BTreeMap<String, String>,
+    pub value: f64,
+}
+
+type SampleKey = (String, BTreeMap<String, String>);
+
+#[derive(Default)]
+pub struct Registry {
+    enabled: bool,
+    labels: BTreeMap<String, String>,
+    samples: BTreeMap<SampleKey, (MetricKind, f64)>,
+}
+
+impl Registry {
+    /// Record a sample for each numeric property, and optionally count the event.
+    pub fn record(&mut self, event: &Event, properties: &[EventData], count: bool) {
+        if !self.enabled {
+            return;
+        }
+
+        let mut labels = self.labels.clone();
+        labels.insert("event".to_owned(), event.as_str().to_owned());
+        for property in properties {
+            if let Some((key, value)) = label(property) {
+                labels.insert(key.to_owned(), value);
+            }
+        }
+
+        if count {
+            let key = (EVENTS_TOTAL.to_owned(), labels.clone());
+            let (_, total) = self
+                .samples
+                .entry(key)
+                .or_insert((MetricKind::Counter, 0.0));
+            *total += 1.0;
+        }
+
+        for property in properties {
+            if let Some(value) = numeric(property) {
+                let name = format!("onefuzz_{}", property.as_values().0);
+                let key = (name, labels.clone());
+                self.samples.insert(key, (MetricKind::Gauge, value));
+            }
+        }
+    }
+
+    pub fn set_label(&mut self, entry: &EventData) {
+        if let Some((key, value)) = label(entry) {
+            self.labels.insert(key.to_owned(), value);
+        }
+    }
+
+    pub fn snapshot(&self) -> Vec<Sample> {
+        self.samples
+            .iter()
+            .map(|((name, labels), (kind, value))| Sample {
+                name: name.clone(),
+                kind: *kind,
+                labels: labels.clone(),
+                value: *value,
+            })
+            .collect()
+    }
+}
+
+fn label(entry: &EventData) -> Option<(&'static str, String)> {
+    match entry {
+        EventData::JobId(x) => Some(("job_id", x.to_string())),
+        EventData::TaskId(x) => Some(("task_id", x.to_string())),
+        EventData::MachineId(x) => Some(("machine_id", x.to_string())),
+        EventData::WorkerId(x) => Some(("worker_id", x.to_string())),
+        _ => None,
+    }
+}
+
+fn numeric(entry: &EventData) -> Option<f64> {
+    let value = match entry {
+        EventData::Features(x)
+        | EventData::Covered(x)
+        | EventData::Count(x)
+        | EventData::Size(x)
+        | EventData::VirtualMemory(x)
+        | EventData::PhysicalMemory(x)
+        | EventData::CoveragePaths(x)
+        | EventData::CoveragePathsFavored(x)
+        | EventData::CoveragePathsFound(x)
+        | EventData::CoveragePathsImported(x)
+        | EventData::CoverageMaxDepth(x)
+        | EventData::CoverageEdgesFound(x)
+        | EventData::CoverageEdgesTotal(x)
+        | EventData::CyclesDone(x)
+        | EventData::SavedCrashes(x)
+        | EventData::SavedHangs(x) => *x as f64,
+        EventData::Rate(x)
+        | EventData::ExecsSecond(x)
+        | EventData::Coverage(x)
+        | EventData::Stability(x) => *x,
+        EventData::CpuUsage(x) => f64::from(*x),
+        _ => return None,
+    };
+
+    Some(value)
+}
+
+lazy_static! {
+    static ref REGISTRY: RwLock<Registry> = RwLock::new(Registry::default());
+}
+
+// Checked before locking the registry, so every event does not contend on the
+// lock when metrics are disabled.
+static ENABLED: AtomicBool = AtomicBool::new(false);
+
+/// Start recording tracked events and metrics.
+pub fn enable() {
+    if let Ok(mut registry) = REGISTRY.write() {
+        registry.enabled = true;
+        ENABLED.store(true, Ordering::Release);
+    }
+}
+
+pub fn is_enabled() -> bool {
+    ENABLED.load(Ordering::Acquire)
+}
+
+pub(crate) fn record(event: &Event, properties: &[EventData], count: bool) {
+    if !is_enabled() {
+        return;
+    }
+
+    if let Ok(mut registry) = REGISTRY.write() {
+        registry.record(event, properties, count);
+    }
+}
+
+pub(crate) fn set_label(entry: &EventData) {
+    // Labels may be set before metrics are enabled, so they are always kept.
+    if let Ok(mut registry) = REGISTRY.write() {
+        registry.set_label(entry);
+    }
+}
+
+/// Samples recorded by this process.
+pub fn snapshot() -> Vec<Sample> {
+    REGISTRY.read().map(|r| r.snapshot()).unwrap_or_default()
+}
+
+/// Save the samples recorded by this process, to be exported by another one.
+pub async fn write_snapshot(path: impl AsRef<Path>) -> Result<()> {
+    let path = path.as_ref();
+    let data = serde_json::to_vec(&snapshot())?;
+
+    // Write then rename, so readers never observe a partial snapshot.
+    let tmp = path.with_extension("tmp");
+    tokio::fs::write(&tmp, data).await?;
+    tokio::fs::rename(&tmp, path).await?;
+    Ok(())
+}
+
+pub fn read_snapshot(path: impl AsRef<Path>) -> Result<Vec<Sample>> {
+    let data = std::fs::read(path)?;
+    Ok(serde_json::from_slice(&data)?)
+}
+
+/// Render samples in the Prometheus text exposition format.
+pub fn render(samples: &[Sample]) -> String {
+    // Samples of a metric family must be contiguous, and follow its `TYPE`.
+    let mut families: BTreeMap<&str, Vec<&Sample>> = BTreeMap::new();
+    for sample in samples {
+        families.entry(&sample.name).or_default().push(sample);
+    }
+
+    let mut text = String::new();
+    for (name, samples) in families {
+        let _ = writeln!(text, "# TYPE {name} {}", samples[0].kind.as_str());
+
+        for sample in samples {
+            let labels = sample
+                .labels
+                .iter()
+                .map(|(k, v)| format!("{k}=\"{}\"", escape(v)))
+                .collect::<Vec<_>>()
+                .join(",");
+            let _ = writeln!(text, "{name}{{{labels}}} {}", sample.value);
+        }
+    }
+
+    text
+}
+
+fn escape(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
+
+/// Serve the samples returned by `collect` at `http://{addr}/metrics`.
+pub async fn serve<F>(addr: SocketAddr, collect: F) -> Result<()>
+where
+    F: Fn() -> Vec<Sample> + Send + Sync + 'static,
+{
+    use hyper::header::CONTENT_TYPE as CONTENT_TYPE_HEADER;
+    use hyper::service::{make_service_fn, service_fn};
+    use hyper::{Body, Method, Request, Response, Server, StatusCode};
+
+    let collect = Arc::new(collect);
+
+    let make_service = make_service_fn(move |_| {
+        let collect = collect.clone();
+
+        async move {
+            Ok::<_, Infallible>(service_fn(move |req: Request<Body>| {
+                let collect = collect.clone();
+
+                async move {
+                    if req.method() == Method::GET && req.uri().path() == "/metrics" {
+                        Response::builder()
+                            .header(CONTENT_TYPE_HEADER, CONTENT_TYPE)
+                            .body(Body::from(render(&collect())))
+                    } else {
+                        Response::builder()
+                            .status(StatusCode::NOT_FOUND)
+                            .body(Body::empty())
+                    }
+                }
+            }))
+        }
+    });
+
+    Server::try_bind(&addr)?.serve(make_service).await?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use uuid::Uuid;
+
+    use super::*;
+
+    #[test]
+    fn test_record_disabled() {
+        let mut registry = Registry::default();
+        registry.record(&Event::new_result, &[EventData::Count(1)], true);
+        assert!(registry.snapshot().is_empty());
+    }
+
+    #[test]
+    fn test_record_and_render() {
+        let task_id = Uuid::nil();
+
+        let mut registry = Registry {
+            enabled: true,
+            ..Default::default()
+        };
+        registry.set_label(&EventData::TaskId(task_id));
+        registry.set_label(&EventData::Version("1.0".to_owned()));
+
+        registry.record(&Event::new_result, &[EventData::Path("a".into())], true);
+        registry.record(&Event::new_result, &[EventData::Path("b".into())], true);
+        registry.record(
+            &Event::runtime_stats,
+            &[EventData::WorkerId(1), EventData::ExecsSecond(2.5)],
+            false,
+        );
+        registry.record(
+            &Event::runtime_stats,
+            &[EventData::WorkerId(1), EventData::ExecsSecond(3.0)],
+            false,
+        );
+
+        let expected = format!(
+            "# TYPE onefuzz_events_total counter
+onefuzz_events_total{{event=\"new_result\",task_id=\"{task_id}\"}} 2
+# TYPE onefuzz_execs_sec gauge
+onefuzz_execs_sec{{event=\"runtime_stats\",task_id=\"{task_id}\",worker_id=\"1\"}} 3
+"
+        );
+        assert_eq!(render(&registry.snapshot()), expected);
+    }
+
+    #[test]
+    fn test_escape() {
+        assert_eq!(escape("a\"b\\c\nd"), "a\\\"b\\\\c\\nd");
+    }
+}
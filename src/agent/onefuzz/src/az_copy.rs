@@ -1,132 +1,572 @@
 // Copyright (c) Microsoft Corporation.
 // Licensed under the MIT License.
 
+//! Copy and sync between local directories and Azure Blob Storage containers,
+//! with the semantics of the `azcopy copy` and `azcopy sync` commands.
+//!
+//! Containers are addressed by URL, authorized by the SAS token in its query,
+//! if any. URLs of the address of a storage emulator such as Azurite, which is
+//! `127.0.0.1:10000` or `localhost:10000` unless set by the
+//! `ONEFUZZ_STORAGE_EMULATOR` environment variable, have the account name in
+//! the path, as in `http://127.0.0.1:10000/devstoreaccount1/container`.
+
 use crate::blob::url::redact_query_sas_sig;
 use anyhow::{Context, Result};
+use azure_core::error::HttpError;
+use azure_core::StatusCode;
+use azure_storage::{CloudLocation, StorageCredentials};
+use azure_storage_blobs::prelude::{
+    BlobBlockType, BlobClient, BlockId, BlockList, ClientBuilder, ContainerClient,
+};
 use backoff::{self, future::retry_notify, ExponentialBackoff};
+use bytes::Bytes;
+use futures::{stream, StreamExt, TryStreamExt};
 use std::{
-    ffi::{OsStr, OsString},
+    collections::BTreeMap,
+    ffi::OsStr,
     fmt,
-    path::Path,
-    process::Stdio,
+    path::{Path, PathBuf},
     sync::atomic::{AtomicUsize, Ordering},
-    time::Duration,
+    time::{Duration, SystemTime},
 };
-use tempfile::tempdir;
 use tokio::fs;
-use tokio::process::Command;
+use tokio::io::{AsyncReadExt, AsyncSeekExt, AsyncWriteExt};
 use url::Url;
 
 const RETRY_INTERVAL: Duration = Duration::from_secs(5);
 const MAX_FAILURE_COUNT: usize = 5;
 const MAX_RETRY_COUNT: usize = 10;
 
+// Blobs larger than this are uploaded in blocks of this size.
+const BLOCK_SIZE: u64 = 4 * 1024 * 1024;
+const MAX_CONCURRENT_FILES: usize = 8;
+const MAX_CONCURRENT_BLOCKS: usize = 4;
+
+// Addresses of a local Azurite, whose blob service listens on port 10000.
+const DEFAULT_EMULATOR_ADDRESSES: &[&str] = &["127.0.0.1:10000", "localhost:10000"];
+
+// Environment variable with the `host:port` address of a storage emulator.
+const EMULATOR_ADDRESS_VAR: &str = "ONEFUZZ_STORAGE_EMULATOR";
+
+// Prefix of the files being downloaded, as used by azcopy.
+const DOWNLOAD_PREFIX: &str = ".azDownload-";
+
+const SOURCE_MODIFIED: &str = "source modified during transfer";
+
 const ALWAYS_RETRY_ERROR_STRINGS: &[&str] = &[
     // There isn't an ergonomic method to sync between the OneFuzz agent and fuzzers generating
-    // data.  As such, we should always retry azcopy commands that fail with errors that occur due
+    // data.  As such, we should always retry transfers that fail with errors that occur due
     // to the fuzzers writing files while a sync is occurring.
     // ref: https://github.com/microsoft/onefuzz/issues/1189
-    "source modified during transfer",
+    SOURCE_MODIFIED,
 ];
 
 #[derive(Clone, Copy)]
 enum Mode {
-    Copy,
-    Sync,
+    Copy { recursive: bool },
+    Sync { delete_dst: bool },
 }
 
 impl fmt::Display for Mode {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         let as_str = match self {
-            Mode::Copy => "copy",
-            Mode::Sync => "sync",
+            Mode::Copy { .. } => "copy",
+            Mode::Sync { .. } => "sync",
         };
         write!(f, "{as_str}")
     }
 }
 
-// NOTE, this is intended to read a single file in a tempdir managed by the
-// caller, rather than the default AZCOPY log location.
-async fn read_azcopy_log_file(path: &Path) -> Result<String> {
-    let mut entries = fs::read_dir(path).await?;
-    // There should 2 files in azcopy_log dir, one is the log file,
-    // the other is scanning log file (added in 10.9.0)
-    while let Some(file) = entries.next_entry().await? {
-        if file.path().to_string_lossy().contains("scanning") {
-            continue;
+enum Location {
+    Local(PathBuf),
+    Remote(RemoteDir),
+}
+
+impl Location {
+    fn parse(value: &OsStr) -> Result<Self> {
+        if let Some(Ok(url)) = value.to_str().map(Url::parse) {
+            match url.scheme() {
+                "http" | "https" => return Ok(Self::Remote(RemoteDir::new(&url)?)),
+                "file" => {
+                    if let Ok(path) = url.to_file_path() {
+                        return Ok(Self::Local(path));
+                    }
+                }
+                // drive letters of Windows paths are parsed as schemes
+                _ => {}
+            }
+        }
+
+        Ok(Self::Local(PathBuf::from(value)))
+    }
+}
+
+// The parts of a blob or container URL.
+#[derive(Debug, PartialEq, Eq)]
+struct BlobLocation {
+    // Address and port of a storage emulator, if not Azure Storage itself.
+    emulator: Option<(String, u16)>,
+    account: String,
+    container: String,
+    // Path of a blob or virtual directory in the container, which may be empty.
+    path: String,
+}
+
+impl BlobLocation {
+    fn parse(url: &Url) -> Result<Self> {
+        let emulator_address = std::env::var(EMULATOR_ADDRESS_VAR).ok();
+        Self::parse_with_emulator(url, emulator_address.as_deref())
+    }
+
+    fn parse_with_emulator(url: &Url, emulator_address: Option<&str>) -> Result<Self> {
+        let mut segments = url
+            .path_segments()
+            .into_iter()
+            .flatten()
+            .filter(|segment| !segment.is_empty())
+            .map(|segment| url_escape::decode(segment).into_owned());
+
+        let host = url.host_str().ok_or_else(|| format_err!("missing host"))?;
+        let port = url.port_or_known_default().unwrap_or(80);
+        let address = format!("{host}:{port}");
+        let is_emulator = match emulator_address {
+            Some(emulator_address) => address == emulator_address,
+            None => DEFAULT_EMULATOR_ADDRESSES.contains(&address.as_str()),
+        };
+        let emulator = is_emulator.then(|| (host.to_owned(), port));
+
+        let account = match &emulator {
+            Some(_) => segments.next(),
+            None => url
+                .domain()
+                .and_then(|domain| domain.split('.').next())
+                .map(|account| account.to_owned()),
+        }
+        .ok_or_else(|| format_err!("missing storage account"))?;
+
+        let container = segments
+            .next()
+            .ok_or_else(|| format_err!("missing container"))?;
+        let path = segments.collect::<Vec<_>>().join("/");
+
+        Ok(Self {
+            emulator,
+            account,
+            container,
+            path,
+        })
+    }
+}
+
+struct RemoteBlob {
+    size: u64,
+    modified: SystemTime,
+}
+
+struct LocalFile {
+    path: PathBuf,
+    size: u64,
+    modified: SystemTime,
+}
+
+// Files and blobs are compared by their size and time of last modification.
+trait Versioned {
+    fn size(&self) -> u64;
+    fn modified(&self) -> SystemTime;
+}
+
+impl Versioned for RemoteBlob {
+    fn size(&self) -> u64 {
+        self.size
+    }
+
+    fn modified(&self) -> SystemTime {
+        self.modified
+    }
+}
+
+impl Versioned for LocalFile {
+    fn size(&self) -> u64 {
+        self.size
+    }
+
+    fn modified(&self) -> SystemTime {
+        self.modified
+    }
+}
+
+// A blob, or a virtual directory of blobs, in a container.
+struct RemoteDir {
+    container: ContainerClient,
+    path: String,
+}
+
+impl RemoteDir {
+    fn new(url: &Url) -> Result<Self> {
+        let location = BlobLocation::parse(url)
+            .with_context(|| format!("invalid blob URL: {}", redact_query_sas_sig(url)))?;
+
+        let credentials = match url.query() {
+            Some(sas) if !sas.is_empty() => StorageCredentials::sas_token(sas)?,
+            _ => StorageCredentials::anonymous(),
+        };
+
+        let builder = match location.emulator {
+            Some((address, port)) => {
+                ClientBuilder::with_location(CloudLocation::Emulator { address, port }, credentials)
+            }
+            None => ClientBuilder::new(location.account, credentials),
+        };
+
+        Ok(Self {
+            container: builder.container_client(location.container),
+            path: location.path,
+        })
+    }
+
+    // Name of the blob at `name`, relative to the virtual directory.
+    fn blob_name(&self, name: &str) -> String {
+        if self.path.is_empty() {
+            name.to_owned()
+        } else if name.is_empty() {
+            self.path.clone()
+        } else {
+            format!("{}/{name}", self.path)
+        }
+    }
+
+    fn blob_client(&self, name: &str) -> BlobClient {
+        self.container.blob_client(self.blob_name(name))
+    }
+
+    // Lists the blobs of the virtual directory, by name relative to it.
+    async fn list(&self) -> Result<BTreeMap<String, RemoteBlob>> {
+        let prefix = if self.path.is_empty() {
+            String::new()
+        } else {
+            format!("{}/", self.path)
+        };
+
+        let mut blobs = BTreeMap::new();
+        let mut pages = self
+            .container
+            .list_blobs()
+            .prefix(prefix.clone())
+            .into_stream();
+
+        while let Some(page) = pages.next().await {
+            for blob in page?.blobs.blobs() {
+                let name = blob
+                    .name
+                    .strip_prefix(prefix.as_str())
+                    .unwrap_or(&blob.name);
+                let remote_blob = RemoteBlob {
+                    size: blob.properties.content_length,
+                    modified: blob.properties.last_modified.into(),
+                };
+                blobs.insert(name.to_owned(), remote_blob);
+            }
+        }
+
+        Ok(blobs)
+    }
+
+    async fn upload(&self, name: &str, path: &Path) -> Result<()> {
+        let blob = self.blob_client(name);
+        let before = fs::metadata(path).await?;
+        let size = before.len();
+
+        if size <= BLOCK_SIZE {
+            let data = Bytes::from(fs::read(path).await?);
+            blob.put_block_blob(data).await?;
+        } else {
+            // Blocks of other uploads of the blob, which may be left uncommitted
+            // by an interrupted upload, must not be committed instead.
+            let upload_id = uuid::Uuid::new_v4().simple().to_string();
+            let block_ids: Vec<BlockId> = (0..(size + BLOCK_SIZE - 1) / BLOCK_SIZE)
+                .map(|index| BlockId::new(format!("{upload_id}-{index:08}")))
+                .collect();
+
+            stream::iter(block_ids.iter().enumerate())
+                .map(|(index, block_id)| {
+                    let blob = &blob;
+                    async move {
+                        let data = read_block(path, index as u64 * BLOCK_SIZE, size).await?;
+                        blob.put_block(block_id.clone(), data).await?;
+                        Ok::<_, anyhow::Error>(())
+                    }
+                })
+                .buffer_unordered(MAX_CONCURRENT_BLOCKS)
+                .try_collect::<Vec<_>>()
+                .await?;
+
+            let block_list = BlockList {
+                blocks: block_ids
+                    .into_iter()
+                    .map(BlobBlockType::Uncommitted)
+                    .collect(),
+            };
+            blob.put_block_list(block_list).await?;
+        }
+
+        let after = fs::metadata(path).await?;
+        if after.len() != size || after.modified()? != before.modified()? {
+            bail!("{}: {}", SOURCE_MODIFIED, path.display());
+        }
+
+        Ok(())
+    }
+
+    async fn download(&self, name: &str, dst: PathBuf) -> Result<()> {
+        let file_name = dst
+            .file_name()
+            .ok_or_else(|| format_err!("invalid file path: {}", dst.display()))?;
+        let mut tmp_name = std::ffi::OsString::from(DOWNLOAD_PREFIX);
+        tmp_name.push(file_name);
+        let tmp = dst.with_file_name(tmp_name);
+
+        if let Some(parent) = dst.parent() {
+            fs::create_dir_all(parent).await?;
         }
 
-        return fs::read_to_string(file.path())
+        let mut file = fs::File::create(&tmp)
             .await
-            .with_context(|| format!("unable to read file: {}", file.path().display()));
+            .with_context(|| format!("unable to create file: {}", tmp.display()))?;
+
+        let mut responses = self.blob_client(name).get().into_stream();
+        while let Some(response) = responses.next().await {
+            let mut body = response?.data;
+            while let Some(chunk) = body.next().await {
+                file.write_all(&chunk?).await?;
+            }
+        }
+        file.flush().await?;
+        drop(file);
+
+        fs::rename(&tmp, &dst).await?;
+        Ok(())
     }
 
-    bail!("no log file in path: {}", path.display());
+    async fn delete(&self, name: &str) -> Result<()> {
+        match self.blob_client(name).delete().await {
+            Ok(_) => Ok(()),
+            Err(err) => match err.downcast_ref::<HttpError>() {
+                // already deleted
+                Some(http_err) if http_err.status() == StatusCode::NotFound => Ok(()),
+                _ => Err(err.into()),
+            },
+        }
+    }
 }
 
-// attempt to redact an azcopy argument if it could possibly be a SAS URL
-fn redact_azcopy_sas_arg(value: &OsStr) -> OsString {
-    match value.to_str().map(Url::parse) {
-        Some(Ok(url)) => redact_query_sas_sig(&url).to_string().into(),
-        _ => value.to_owned(),
-    }
-}
-
-async fn az_impl(mode: Mode, src: &OsStr, dst: &OsStr, args: &[&str]) -> Result<()> {
-    let temp_dir = tempdir()?;
-
-    // https://docs.microsoft.com/en-us/azure/storage/common/storage-use-azcopy-configure#change-the-location-of-log-files
-    let mut cmd = Command::new("azcopy");
-    cmd.kill_on_drop(true)
-        .stdin(Stdio::null())
-        .stdout(Stdio::piped())
-        .stderr(Stdio::piped())
-        .env("AZCOPY_LOG_LOCATION", temp_dir.path())
-        .env("AZCOPY_CONCURRENCY_VALUE", "32")
-        .env("AZCOPY_BUFFER_GB", "0.5") // Limit azcopy to just half a gig of RAM
-        .arg(mode.to_string())
-        .arg(src)
-        .arg(dst)
-        .args(args);
-
-    let output = cmd
-        .spawn()
-        .context("azcopy failed to start")?
-        .wait_with_output()
+async fn read_block(path: &Path, offset: u64, size: u64) -> Result<Bytes> {
+    let len = BLOCK_SIZE.min(size - offset) as usize;
+    let mut data = vec![0; len];
+
+    let mut file = fs::File::open(path).await?;
+    file.seek(std::io::SeekFrom::Start(offset)).await?;
+    file.read_exact(&mut data)
         .await
-        .context("azcopy failed to run")?;
-    if !output.status.success() {
-        let stdout = String::from_utf8_lossy(&output.stdout);
-        let stderr = String::from_utf8_lossy(&output.stderr);
-        let logfile = read_azcopy_log_file(temp_dir.path())
+        .with_context(|| format!("{}: {}", SOURCE_MODIFIED, path.display()))?;
+
+    Ok(data.into())
+}
+
+// Lists the files of `dir` and its subdirectories, by their path relative to
+// `dir`, with `/` as the separator.
+async fn list_local(dir: &Path) -> Result<BTreeMap<String, LocalFile>> {
+    let mut files = BTreeMap::new();
+    let mut dirs = vec![(String::new(), dir.to_owned())];
+
+    while let Some((prefix, dir)) = dirs.pop() {
+        let mut entries = fs::read_dir(&dir)
             .await
-            .unwrap_or_else(|e| format!("unable to read azcopy log file from: {e:?}"));
-
-        let src = redact_azcopy_sas_arg(src);
-        let dst = redact_azcopy_sas_arg(dst);
-
-        anyhow::bail!(
-            "azcopy {} failed src:{:?} dst:{:?} stdout:{:?} stderr:{:?} log:{:?}",
-            mode,
-            src,
-            dst,
-            stdout,
-            stderr,
-            logfile,
-        );
+            .with_context(|| format!("unable to list files: {}", dir.display()))?;
+
+        while let Some(entry) = entries.next_entry().await? {
+            let name = format!("{prefix}{}", entry.file_name().to_string_lossy());
+            let metadata = entry.metadata().await?;
+
+            if metadata.is_dir() {
+                dirs.push((format!("{name}/"), entry.path()));
+            } else if !entry
+                .file_name()
+                .to_string_lossy()
+                .starts_with(DOWNLOAD_PREFIX)
+            {
+                let file = LocalFile {
+                    path: entry.path(),
+                    size: metadata.len(),
+                    modified: metadata.modified()?,
+                };
+                files.insert(name, file);
+            }
+        }
+    }
+
+    Ok(files)
+}
+
+// As with `azcopy sync`, files are transferred if their size differs, or if
+// the source is newer than the destination.
+fn is_newer(size: u64, modified: SystemTime, dst_size: u64, dst_modified: SystemTime) -> bool {
+    size != dst_size || modified > dst_modified
+}
+
+// The files of a directory copy or sync, by their name relative to the
+// directory.
+#[derive(Debug, Default, PartialEq, Eq)]
+struct Plan {
+    transfer: Vec<String>,
+    delete: Vec<String>,
+}
+
+impl Plan {
+    // Unless `sync`, all the files of `src` are transferred, as with `azcopy
+    // copy`. With `delete_dst`, the files of `dst` not in `src` are deleted.
+    fn new(
+        src: &BTreeMap<String, impl Versioned>,
+        dst: &BTreeMap<String, impl Versioned>,
+        sync: bool,
+        delete_dst: bool,
+    ) -> Self {
+        let transfer = src
+            .iter()
+            .filter(|(name, file)| match dst.get(*name) {
+                Some(dst_file) if sync => is_newer(
+                    file.size(),
+                    file.modified(),
+                    dst_file.size(),
+                    dst_file.modified(),
+                ),
+                _ => true,
+            })
+            .map(|(name, _)| name.clone())
+            .collect();
+
+        let delete = if delete_dst {
+            dst.keys()
+                .filter(|name| !src.contains_key(*name))
+                .cloned()
+                .collect()
+        } else {
+            vec![]
+        };
+
+        Self { transfer, delete }
+    }
+
+    // Paths in `dir` of the blobs to download, skipping those whose names are
+    // not relative paths within it.
+    fn download_paths(&self, dir: &Path) -> Vec<(&str, PathBuf)> {
+        self.transfer
+            .iter()
+            .filter_map(|name| match crate::fs::join_relative(dir, name) {
+                Ok(path) => Some((name.as_str(), path)),
+                Err(err) => {
+                    warn!("skipping blob: {:?}", err);
+                    None
+                }
+            })
+            .collect()
+    }
+}
+
+async fn upload_dir(dir: &Path, remote: &RemoteDir, sync: bool, delete_dst: bool) -> Result<()> {
+    let local = list_local(dir).await?;
+    let remote_blobs = if sync || delete_dst {
+        remote.list().await?
+    } else {
+        BTreeMap::new()
+    };
+    let plan = Plan::new(&local, &remote_blobs, sync, delete_dst);
+
+    stream::iter(plan.transfer.iter())
+        .map(|name| remote.upload(name, &local[name].path))
+        .buffer_unordered(MAX_CONCURRENT_FILES)
+        .try_collect::<Vec<_>>()
+        .await?;
+
+    stream::iter(plan.delete.iter())
+        .map(|name| remote.delete(name))
+        .buffer_unordered(MAX_CONCURRENT_FILES)
+        .try_collect::<Vec<_>>()
+        .await?;
+
+    Ok(())
+}
+
+async fn download_dir(remote: &RemoteDir, dir: &Path, sync: bool, delete_dst: bool) -> Result<()> {
+    fs::create_dir_all(dir).await?;
+
+    let remote_blobs = remote.list().await?;
+    let local = list_local(dir).await?;
+    let plan = Plan::new(&remote_blobs, &local, sync, delete_dst);
+
+    stream::iter(plan.download_paths(dir))
+        .map(|(name, path)| remote.download(name, path))
+        .buffer_unordered(MAX_CONCURRENT_FILES)
+        .try_collect::<Vec<_>>()
+        .await?;
+
+    for name in &plan.delete {
+        fs::remove_file(&local[name].path).await?;
     }
 
     Ok(())
 }
 
-// Work around issues where azcopy fails with an error we should consider
+async fn az_impl(mode: Mode, src: &OsStr, dst: &OsStr) -> Result<()> {
+    match (mode, Location::parse(src)?, Location::parse(dst)?) {
+        (Mode::Sync { delete_dst }, Location::Local(src), Location::Remote(dst)) => {
+            upload_dir(&src, &dst, true, delete_dst).await
+        }
+        (Mode::Sync { delete_dst }, Location::Remote(src), Location::Local(dst)) => {
+            download_dir(&src, &dst, true, delete_dst).await
+        }
+        (Mode::Copy { recursive }, Location::Local(src), Location::Remote(dst)) => {
+            if fs::metadata(&src).await?.is_dir() {
+                if !recursive {
+                    bail!("cannot copy directory without recursive: {}", src.display());
+                }
+                upload_dir(&src, &dst, false, false).await
+            } else if dst.path.is_empty() {
+                // copied to a blob of the same name, as with azcopy
+                let name = src
+                    .file_name()
+                    .ok_or_else(|| format_err!("invalid file path: {}", src.display()))?;
+                dst.upload(&name.to_string_lossy(), &src).await
+            } else {
+                dst.upload("", &src).await
+            }
+        }
+        (Mode::Copy { recursive }, Location::Remote(src), Location::Local(dst)) => {
+            if recursive {
+                download_dir(&src, &dst, false, false).await
+            } else if fs::metadata(&dst)
+                .await
+                .map(|x| x.is_dir())
+                .unwrap_or(false)
+            {
+                // copied to a file of the same name, as with azcopy
+                let name = src.path.rsplit('/').next().unwrap_or_default().to_owned();
+                src.download("", dst.join(name)).await
+            } else {
+                src.download("", dst).await
+            }
+        }
+        _ => bail!("unsupported {mode}: exactly one of the source and destination must be a URL"),
+    }
+}
+
+// Work around issues where transfers fail with an error we should consider
 // "acceptable" to always retry on.
 fn should_always_retry(err: &anyhow::Error) -> bool {
     let as_string = format!("{err:?}");
     for value in ALWAYS_RETRY_ERROR_STRINGS {
         if as_string.contains(value) {
             info!(
-                "azcopy failed with an error that always triggers a retry: {} - {:?}",
+                "transfer failed with an error that always triggers a retry: {} - {:?}",
                 value, err
             );
             return true;
@@ -135,18 +575,28 @@ fn should_always_retry(err: &anyhow::Error) -> bool {
     false
 }
 
-async fn retry_az_impl(mode: Mode, src: &OsStr, dst: &OsStr, args: &[&str]) -> Result<()> {
+// attempt to redact an argument if it could possibly be a SAS URL
+fn redact_sas_arg(value: &OsStr) -> String {
+    match value.to_str().map(Url::parse) {
+        Some(Ok(url)) => redact_query_sas_sig(&url).to_string(),
+        _ => value.to_string_lossy().into_owned(),
+    }
+}
+
+async fn retry_az_impl(mode: Mode, src: &OsStr, dst: &OsStr) -> Result<()> {
     let attempt_counter = AtomicUsize::new(0);
     let failure_counter = AtomicUsize::new(0);
 
     let operation = || async {
         let attempt_count = attempt_counter.fetch_add(1, Ordering::SeqCst);
         let mut failure_count = failure_counter.load(Ordering::SeqCst);
-        let result = az_impl(mode, src, dst, args).await.with_context(|| {
+        let result = az_impl(mode, src, dst).await.with_context(|| {
             format!(
-                "azcopy {} attempt {} failed.  (failure {})",
+                "{} attempt {} failed src:{} dst:{}.  (failure {})",
                 mode,
                 attempt_count + 1,
+                redact_sas_arg(src),
+                redact_sas_arg(dst),
                 failure_count + 1
             )
         });
@@ -175,7 +625,7 @@ async fn retry_az_impl(mode: Mode, src: &OsStr, dst: &OsStr, args: &[&str]) -> R
         operation,
         |err, dur| {
             info!(
-                "azcopy attempt failed after {:?}: {:?} {} {}",
+                "transfer attempt failed after {:?}: {:?} {} {}",
                 dur,
                 err,
                 attempt_counter.load(Ordering::SeqCst),
@@ -184,27 +634,215 @@ async fn retry_az_impl(mode: Mode, src: &OsStr, dst: &OsStr, args: &[&str]) -> R
         },
     )
     .await
-    .with_context(|| format!("azcopy failed after retrying.  mode: {mode}"))?;
+    .with_context(|| format!("transfer failed after retrying.  mode: {mode}"))?;
 
     Ok(())
 }
 
 pub async fn sync(src: impl AsRef<OsStr>, dst: impl AsRef<OsStr>, delete_dst: bool) -> Result<()> {
-    let args = if delete_dst {
-        vec!["--delete-destination=true"]
-    } else {
-        vec![]
-    };
-
-    retry_az_impl(Mode::Sync, src.as_ref(), dst.as_ref(), &args).await
+    retry_az_impl(Mode::Sync { delete_dst }, src.as_ref(), dst.as_ref()).await
 }
 
 pub async fn copy(src: impl AsRef<OsStr>, dst: impl AsRef<OsStr>, recursive: bool) -> Result<()> {
-    let args = if recursive {
-        vec!["--recursive=true"]
-    } else {
-        vec![]
-    };
+    retry_az_impl(Mode::Copy { recursive }, src.as_ref(), dst.as_ref()).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
 
-    retry_az_impl(Mode::Copy, src.as_ref(), dst.as_ref(), &args).await
+    #[test]
+    fn test_blob_location() -> Result<()> {
+        let url =
+            Url::parse("https://myaccount.blob.core.windows.net/mycontainer/dir/a%3Ab?sig=x")?;
+        assert_eq!(
+            BlobLocation::parse_with_emulator(&url, None)?,
+            BlobLocation {
+                emulator: None,
+                account: "myaccount".to_owned(),
+                container: "mycontainer".to_owned(),
+                path: "dir/a:b".to_owned(),
+            }
+        );
+
+        let url = Url::parse("http://127.0.0.1:10000/devstoreaccount1/mycontainer/")?;
+        assert_eq!(
+            BlobLocation::parse_with_emulator(&url, None)?,
+            BlobLocation {
+                emulator: Some(("127.0.0.1".to_owned(), 10000)),
+                account: "devstoreaccount1".to_owned(),
+                container: "mycontainer".to_owned(),
+                path: String::new(),
+            }
+        );
+
+        let url = Url::parse("https://myaccount.blob.core.windows.net/")?;
+        assert!(BlobLocation::parse_with_emulator(&url, None).is_err());
+
+        // only the address of the emulator is taken to be that of an emulator
+        let url = Url::parse("http://10.0.0.4:10000/devstoreaccount1/mycontainer")?;
+        assert!(BlobLocation::parse_with_emulator(&url, None).is_err());
+
+        let location = BlobLocation::parse_with_emulator(&url, Some("10.0.0.4:10000"))?;
+        assert_eq!(location.emulator, Some(("10.0.0.4".to_owned(), 10000)));
+        assert_eq!(location.account, "devstoreaccount1");
+
+        let url = Url::parse("http://127.0.0.1:10000/devstoreaccount1/mycontainer")?;
+        let location = BlobLocation::parse_with_emulator(&url, Some("10.0.0.4:10000"));
+        assert!(location.is_err());
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_list_local() -> Result<()> {
+        let dir = tempdir()?;
+        fs::create_dir_all(dir.path().join("sub")).await?;
+        fs::write(dir.path().join("a"), "1").await?;
+        fs::write(dir.path().join("sub/b"), "22").await?;
+        fs::write(dir.path().join(".azDownload-c"), "3").await?;
+
+        let files = list_local(dir.path()).await?;
+        assert_eq!(files.keys().collect::<Vec<_>>(), ["a", "sub/b"]);
+        assert_eq!(files["sub/b"].size, 2);
+
+        Ok(())
+    }
+
+    struct Version(u64, SystemTime);
+
+    impl Versioned for Version {
+        fn size(&self) -> u64 {
+            self.0
+        }
+
+        fn modified(&self) -> SystemTime {
+            self.1
+        }
+    }
+
+    #[test]
+    fn test_plan() {
+        let now = SystemTime::now();
+        let earlier = now - Duration::from_secs(60);
+
+        let src = BTreeMap::from([
+            ("changed".to_owned(), Version(1, now)),
+            ("new".to_owned(), Version(1, now)),
+            ("resized".to_owned(), Version(2, earlier)),
+            ("same".to_owned(), Version(1, earlier)),
+        ]);
+        let dst = BTreeMap::from([
+            ("changed".to_owned(), Version(1, earlier)),
+            ("resized".to_owned(), Version(1, now)),
+            ("same".to_owned(), Version(1, earlier)),
+            ("stale".to_owned(), Version(1, earlier)),
+        ]);
+
+        assert_eq!(
+            Plan::new(&src, &dst, true, false),
+            Plan {
+                transfer: vec!["changed".into(), "new".into(), "resized".into()],
+                delete: vec![],
+            }
+        );
+        assert_eq!(
+            Plan::new(&src, &dst, true, true).delete,
+            vec!["stale".to_owned()]
+        );
+
+        // copies transfer all the files
+        let plan = Plan::new(&src, &dst, false, false);
+        assert_eq!(plan.transfer, src.keys().cloned().collect::<Vec<_>>());
+        assert!(plan.delete.is_empty());
+
+        let empty = BTreeMap::<String, Version>::new();
+        assert_eq!(Plan::new(&empty, &dst, true, true).delete.len(), 4);
+    }
+
+    #[test]
+    fn test_plan_download_paths() {
+        let plan = Plan {
+            transfer: [
+                "../escaped",
+                "/etc/escaped",
+                "a",
+                "sub/../../escaped",
+                "sub/b",
+            ]
+            .map(String::from)
+            .to_vec(),
+            delete: vec![],
+        };
+
+        let dir = Path::new("dir");
+        assert_eq!(
+            plan.download_paths(dir),
+            [("a", dir.join("a")), ("sub/b", dir.join("sub/b"))]
+        );
+    }
+
+    #[test]
+    fn test_is_newer() {
+        let now = SystemTime::now();
+        let earlier = now - Duration::from_secs(60);
+
+        assert!(is_newer(1, now, 1, earlier));
+        assert!(is_newer(1, earlier, 2, now));
+        assert!(!is_newer(1, earlier, 1, now));
+        assert!(!is_newer(1, now, 1, now));
+    }
+
+    // Run against Azurite, with `AZURITE_CONTAINER_URL` set to the SAS URL of
+    // an existing container, as in
+    // `http://127.0.0.1:10000/devstoreaccount1/test?sv=...&sig=...`.
+    #[tokio::test]
+    #[ignore]
+    async fn test_azurite_sync() -> Result<()> {
+        let container = std::env::var("AZURITE_CONTAINER_URL")?;
+        let mut url = Url::parse(&container)?;
+        url.path_segments_mut()
+            .map_err(|_| format_err!("invalid container URL"))?
+            .pop_if_empty()
+            .push("az-copy-test");
+
+        let src = tempdir()?;
+        fs::create_dir_all(src.path().join("sub")).await?;
+        fs::write(src.path().join("small"), "data").await?;
+        // uploaded in several blocks
+        let large = vec![7u8; BLOCK_SIZE as usize * 2 + 1];
+        fs::write(src.path().join("sub/large"), &large).await?;
+
+        sync(src.path(), url.as_str(), true).await?;
+
+        let dst = tempdir()?;
+        fs::write(dst.path().join("stale"), "x").await?;
+        sync(url.as_str(), dst.path(), true).await?;
+
+        assert_eq!(fs::read(dst.path().join("small")).await?, b"data");
+        assert_eq!(fs::read(dst.path().join("sub/large")).await?, large);
+        assert!(!dst.path().join("stale").exists());
+
+        // with delete_dst, removes the blobs of deleted files
+        fs::remove_file(src.path().join("small")).await?;
+        sync(src.path(), url.as_str(), true).await?;
+        let remote = RemoteDir::new(&url)?;
+        assert_eq!(
+            remote.list().await?.keys().collect::<Vec<_>>(),
+            ["sub/large"]
+        );
+
+        let file = dst.path().join("copied");
+        let mut blob_url = url.clone();
+        blob_url
+            .path_segments_mut()
+            .map_err(|_| format_err!("invalid container URL"))?
+            .push("sub")
+            .push("large");
+        copy(blob_url.as_str(), &file, false).await?;
+        assert_eq!(fs::read(&file).await?, large);
+
+        Ok(())
+    }
 }
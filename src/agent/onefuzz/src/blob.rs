@@ -2,6 +2,9 @@
 // Licensed under the MIT License.
 
 pub mod client;
+#[cfg(test)]
+pub mod fake_s3;
+pub mod s3;
 pub mod url;
 
 pub use self::client::BlobClient;
@@ -4,13 +4,70 @@
 use std::path::{Path, PathBuf};
 
 use anyhow::{Context, Result};
+use base64::Engine;
 use futures::stream::TryStreamExt;
-use reqwest::{Body, RequestBuilder, Response, Url};
-use reqwest_retry::SendRetry;
-use serde::Serialize;
+use reqwest::{Body, Method, RequestBuilder, Response, StatusCode, Url};
+use reqwest_retry::{RetryCheck, SendRetry, DEFAULT_RETRY_PERIOD, MAX_RETRY_ATTEMPTS};
+use serde::{Deserialize, Serialize};
 use tokio::{fs, io};
 use tokio_util::codec;
 
+use super::s3;
+
+/// A blob, as listed by `BlobClient::list_blobs()`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct BlobItem {
+    pub name: String,
+    pub content_length: u64,
+    /// ETag of the blob, without quotes.
+    pub etag: String,
+}
+
+/// Upload of a blob in blocks, which can be resumed until it is committed.
+///
+/// Blobs of Azure Blob Storage are uploaded as uncommitted blocks, and objects
+/// of S3 as multipart uploads.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct BlockUpload {
+    /// Upload ID of an S3 multipart upload, or prefix of the block IDs of an
+    /// Azure blob, so blocks of other uploads of the same blob are not used.
+    pub id: String,
+
+    /// IDs of the uploaded blocks of an Azure blob, or ETags of the uploaded
+    /// parts of an S3 object, in order.
+    #[serde(default)]
+    pub blocks: Vec<String>,
+}
+
+// https://learn.microsoft.com/en-us/rest/api/storageservices/list-blobs#response-body
+#[derive(Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct EnumerationResults {
+    blobs: Blobs,
+    next_marker: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct Blobs {
+    #[serde(rename = "Blob", default)]
+    blobs: Vec<Blob>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct Blob {
+    name: String,
+    properties: BlobProperties,
+}
+
+#[derive(Deserialize)]
+struct BlobProperties {
+    #[serde(rename = "Content-Length")]
+    content_length: u64,
+    #[serde(rename = "Etag")]
+    etag: String,
+}
+
 #[derive(Clone)]
 pub struct BlobClient {
     client: reqwest::Client,
@@ -29,12 +86,18 @@ impl BlobClient {
         Self { client }
     }
 
-    pub async fn get(&self, url: &Url) -> Result<Response> {
-        let url = url.clone();
+    // Requests to `s3://` URLs are signed, and sent to the S3 endpoint.
+    fn request(&self, method: Method, url: &Url) -> Result<RequestBuilder> {
+        if s3::is_s3_url(url) {
+            s3::signed_request(&self.client, method, url)
+        } else {
+            Ok(self.client.request(method, url.clone()))
+        }
+    }
 
+    pub async fn get(&self, url: &Url) -> Result<Response> {
         let r = self
-            .client
-            .get(url)
+            .request(Method::GET, url)?
             .send_retry_default()
             .await
             .context("BlobClient.get")?
@@ -60,12 +123,95 @@ impl BlobClient {
         Ok(dst.to_owned())
     }
 
-    pub fn put(&self, url: Url) -> RequestBuilder {
-        self.client.put(url).header("x-ms-blob-type", "BlockBlob")
+    /// Get the data of a blob from `offset` on, if its ETag still matches
+    /// `etag`.
+    ///
+    /// Returns `None` if the blob has changed since, or no longer exists.
+    pub async fn get_range(&self, url: &Url, offset: u64, etag: &str) -> Result<Option<Response>> {
+        let r = self
+            .request(Method::GET, url)?
+            .header("Range", format!("bytes={offset}-"))
+            .header("If-Match", format!("\"{}\"", etag.trim_matches('"')))
+            .send_retry(
+                |code| match code {
+                    StatusCode::PRECONDITION_FAILED | StatusCode::NOT_FOUND => RetryCheck::Succeed,
+                    _ => RetryCheck::Retry,
+                },
+                DEFAULT_RETRY_PERIOD,
+                MAX_RETRY_ATTEMPTS,
+            )
+            .await
+            .context("BlobClient.get_range")?;
+
+        match r.status() {
+            StatusCode::PRECONDITION_FAILED | StatusCode::NOT_FOUND => Ok(None),
+            _ => Ok(Some(r)),
+        }
+    }
+
+    /// List the blobs of the container at `container_url`.
+    pub async fn list_blobs(&self, container_url: &Url) -> Result<Vec<BlobItem>> {
+        if s3::is_s3_url(container_url) {
+            return s3::list_objects(&self.client, container_url).await;
+        }
+
+        let mut blobs = vec![];
+        let mut marker: Option<String> = None;
+
+        loop {
+            let mut url = container_url.clone();
+            url.query_pairs_mut()
+                .append_pair("restype", "container")
+                .append_pair("comp", "list");
+            if let Some(marker) = &marker {
+                url.query_pairs_mut().append_pair("marker", marker);
+            }
+
+            let body = self.get(&url).await?.text().await?;
+            let (page, next_marker) = parse_blob_list(&body).context("BlobClient.list_blobs")?;
+            blobs.extend(page);
+
+            marker = next_marker;
+            if marker.is_none() {
+                break;
+            }
+        }
+
+        Ok(blobs)
+    }
+
+    pub async fn delete(&self, url: Url) -> Result<()> {
+        if s3::is_s3_url(&url) {
+            return s3::delete_object(&self.client, &url).await;
+        }
+
+        self.client
+            .delete(url)
+            .send_retry(
+                |code| match code {
+                    // already deleted
+                    StatusCode::NOT_FOUND => RetryCheck::Succeed,
+                    _ => RetryCheck::Retry,
+                },
+                DEFAULT_RETRY_PERIOD,
+                MAX_RETRY_ATTEMPTS,
+            )
+            .await
+            .context("BlobClient.delete")?;
+
+        Ok(())
+    }
+
+    pub fn put(&self, url: Url) -> Result<RequestBuilder> {
+        if s3::is_s3_url(&url) {
+            return s3::signed_request(&self.client, Method::PUT, &url);
+        }
+
+        Ok(self.client.put(url).header("x-ms-blob-type", "BlockBlob"))
     }
 
     pub async fn put_data(&self, url: Url, data: impl Into<Body>) -> Result<Response> {
-        self.put(url)
+        self.put(url)?
             .body(data)
             .send_retry_default()
             .await
@@ -76,13 +222,105 @@ impl BlobClient {
     where
         I: Serialize,
     {
-        self.put(url)
+        self.put(url)?
             .json(&item)
             .send_retry_default()
             .await
             .context("BlobClient.put_json")
     }
 
+    /// Start an upload of the blob at `url` in blocks.
+    pub async fn start_block_upload(&self, url: &Url) -> Result<BlockUpload> {
+        let id = if s3::is_s3_url(url) {
+            s3::create_multipart_upload(&self.client, url).await?
+        } else {
+            uuid::Uuid::new_v4().simple().to_string()
+        };
+
+        Ok(BlockUpload { id, blocks: vec![] })
+    }
+
+    /// Upload the next block of the blob at `url`.
+    ///
+    /// Blocks of S3 objects, but the last, must be at least 5 MiB.
+    pub async fn put_block(
+        &self,
+        url: &Url,
+        upload: &mut BlockUpload,
+        data: Vec<u8>,
+    ) -> Result<()> {
+        let index = upload.blocks.len();
+
+        let block = if s3::is_s3_url(url) {
+            s3::upload_part(&self.client, url, &upload.id, index + 1, data).await?
+        } else {
+            // block IDs must all have the same length
+            let block = base64::engine::general_purpose::STANDARD
+                .encode(format!("{}-{index:08}", upload.id));
+
+            let mut block_url = url.clone();
+            block_url
+                .query_pairs_mut()
+                .append_pair("comp", "block")
+                .append_pair("blockid", &block);
+
+            self.client
+                .put(block_url)
+                .header("Content-Length", data.len())
+                .body(data)
+                .send_retry_default()
+                .await
+                .context("BlobClient.put_block")?
+                .error_for_status()
+                .context("BlobClient.put_block status")?;
+
+            block
+        };
+
+        upload.blocks.push(block);
+        Ok(())
+    }
+
+    /// Commit the uploaded blocks of the blob at `url`, returning its ETag,
+    /// without quotes.
+    pub async fn commit_block_upload(&self, url: &Url, upload: &BlockUpload) -> Result<String> {
+        if s3::is_s3_url(url) {
+            return s3::complete_multipart_upload(&self.client, url, &upload.id, &upload.blocks)
+                .await;
+        }
+
+        let blocks: String = upload
+            .blocks
+            .iter()
+            .map(|block| format!("<Latest>{block}</Latest>"))
+            .collect();
+        let body =
+            format!("<?xml version=\"1.0\" encoding=\"utf-8\"?><BlockList>{blocks}</BlockList>");
+
+        let mut block_list_url = url.clone();
+        block_list_url
+            .query_pairs_mut()
+            .append_pair("comp", "blocklist");
+
+        let response = self
+            .client
+            .put(block_list_url)
+            .body(body)
+            .send_retry_default()
+            .await
+            .context("BlobClient.commit_block_upload")?
+            .error_for_status()
+            .context("BlobClient.commit_block_upload status")?;
+
+        let etag = response
+            .headers()
+            .get("ETag")
+            .and_then(|x| x.to_str().ok())
+            .ok_or_else(|| format_err!("missing ETag in response for blob: {url}"))?;
+
+        Ok(etag.trim_matches('"').to_owned())
+    }
+
     pub async fn put_file(&self, file_url: Url, file_path: impl AsRef<Path>) -> Result<Response> {
         let file_path = file_path.as_ref();
 
@@ -97,7 +335,7 @@ impl BlobClient {
         let body = reqwest::Body::wrap_stream(file_stream);
         let content_length = format!("{file_len}");
 
-        self.put(file_url)
+        self.put(file_url)?
             .header("Content-Length", &content_length)
             .body(body)
             .send_retry_default()
@@ -105,3 +343,79 @@ impl BlobClient {
             .context("BlobClient.put_file")
     }
 }
+
+// Parses a page of the results of List Blobs, along with the marker of the
+// next page, if any.
+fn parse_blob_list(body: &str) -> Result<(Vec<BlobItem>, Option<String>)> {
+    let results: EnumerationResults = quick_xml::de::from_str(body)?;
+
+    let blobs = results
+        .blobs
+        .blobs
+        .into_iter()
+        .map(|blob| BlobItem {
+            name: blob.name,
+            content_length: blob.properties.content_length,
+            etag: blob.properties.etag.trim_matches('"').to_owned(),
+        })
+        .collect();
+    let next_marker = results.next_marker.filter(|x| !x.is_empty());
+
+    Ok((blobs, next_marker))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_blob_list() -> Result<()> {
+        let body = r#"<?xml version="1.0" encoding="utf-8"?>
+<EnumerationResults ServiceEndpoint="https://myaccount.blob.core.windows.net/" ContainerName="inputs">
+  <Blobs>
+    <Blob>
+      <Name>seed-1</Name>
+      <Properties>
+        <Last-Modified>Wed, 09 Sep 2009 09:20:02 GMT</Last-Modified>
+        <Etag>0x8CBFF45D8A29A19</Etag>
+        <Content-Length>100</Content-Length>
+        <BlobType>BlockBlob</BlobType>
+      </Properties>
+    </Blob>
+    <Blob>
+      <Name>dir/seed-2</Name>
+      <Properties>
+        <Etag>0x8CBFF45D8A29A20</Etag>
+        <Content-Length>0</Content-Length>
+      </Properties>
+    </Blob>
+  </Blobs>
+  <NextMarker>2!80!MDAwMDE0</NextMarker>
+</EnumerationResults>"#;
+
+        let (blobs, next_marker) = parse_blob_list(body)?;
+        assert_eq!(
+            blobs,
+            [
+                BlobItem {
+                    name: "seed-1".to_string(),
+                    content_length: 100,
+                    etag: "0x8CBFF45D8A29A19".to_string(),
+                },
+                BlobItem {
+                    name: "dir/seed-2".to_string(),
+                    content_length: 0,
+                    etag: "0x8CBFF45D8A29A20".to_string(),
+                },
+            ]
+        );
+        assert_eq!(next_marker.as_deref(), Some("2!80!MDAwMDE0"));
+
+        let body = "<EnumerationResults><Blobs /><NextMarker /></EnumerationResults>";
+        let (blobs, next_marker) = parse_blob_list(body)?;
+        assert!(blobs.is_empty());
+        assert!(next_marker.is_none());
+
+        Ok(())
+    }
+}
@@ -0,0 +1,312 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT License.
+
+//! An in-memory S3 endpoint for tests, serving the requests made by
+//! `BlobClient`, `BlobUploader` and manifest syncs of `s3://` URLs.
+//!
+//! The endpoint is shared by all the tests of the process, and is set as that
+//! of S3 by `AWS_ENDPOINT_URL_S3`, so each test should use a bucket of its own,
+//! such as one from `FakeS3::bucket()`.
+
+use std::collections::{BTreeMap, HashMap};
+use std::sync::{Arc, Mutex, OnceLock};
+
+use wiremock::matchers::any;
+use wiremock::{Mock, MockServer, Request, Respond, ResponseTemplate};
+
+use crate::sha256;
+
+// Objects listed per page, so that listings of a few objects are paginated.
+const LIST_PAGE_SIZE: usize = 2;
+
+pub struct FakeS3 {
+    state: Arc<Mutex<State>>,
+    // kept alive for the whole test process
+    _server: MockServer,
+}
+
+impl FakeS3 {
+    /// Start the shared endpoint, if not already started.
+    pub fn get() -> &'static Self {
+        static FAKE_S3: OnceLock<FakeS3> = OnceLock::new();
+
+        FAKE_S3.get_or_init(|| {
+            // tests run in a runtime of their own, which cannot be blocked on
+            std::thread::spawn(|| {
+                let runtime = tokio::runtime::Runtime::new().expect("unable to start runtime");
+                runtime.block_on(Self::start())
+            })
+            .join()
+            .expect("unable to start fake S3 endpoint")
+        })
+    }
+
+    async fn start() -> Self {
+        let state = Arc::new(Mutex::new(State::default()));
+        let server = MockServer::start().await;
+        Mock::given(any())
+            .respond_with(Responder(state.clone()))
+            .mount(&server)
+            .await;
+
+        std::env::set_var("AWS_ENDPOINT_URL_S3", server.uri());
+        std::env::set_var("AWS_ACCESS_KEY_ID", "fake-access-key");
+        std::env::set_var("AWS_SECRET_ACCESS_KEY", "fake-secret-key");
+
+        Self {
+            state,
+            _server: server,
+        }
+    }
+
+    /// Name of a new, empty bucket.
+    pub fn bucket(&self) -> String {
+        format!("test-{}", uuid::Uuid::new_v4().simple())
+    }
+
+    /// Keys of the objects of `bucket`, in order.
+    pub fn keys(&self, bucket: &str) -> Vec<String> {
+        let state = self.state.lock().unwrap();
+        state
+            .objects
+            .keys()
+            .filter(|(b, _)| b == bucket)
+            .map(|(_, key)| key.clone())
+            .collect()
+    }
+
+    pub fn object(&self, bucket: &str, key: &str) -> Option<Vec<u8>> {
+        let state = self.state.lock().unwrap();
+        state
+            .objects
+            .get(&(bucket.to_owned(), key.to_owned()))
+            .map(|object| object.data.clone())
+    }
+
+    pub fn put_object(&self, bucket: &str, key: &str, data: impl Into<Vec<u8>>) {
+        let mut state = self.state.lock().unwrap();
+        state.objects.insert(
+            (bucket.to_owned(), key.to_owned()),
+            Object::new(data.into()),
+        );
+    }
+}
+
+struct Object {
+    data: Vec<u8>,
+    etag: String,
+}
+
+impl Object {
+    fn new(data: Vec<u8>) -> Self {
+        let etag = sha256::digest(&data);
+        Self { data, etag }
+    }
+}
+
+#[derive(Default)]
+struct State {
+    // objects, by bucket and key
+    objects: BTreeMap<(String, String), Object>,
+    // data of the uploaded parts of multipart uploads, by part number
+    uploads: HashMap<String, BTreeMap<usize, Vec<u8>>>,
+}
+
+struct Responder(Arc<Mutex<State>>);
+
+impl Respond for Responder {
+    fn respond(&self, request: &Request) -> ResponseTemplate {
+        // signatures are not checked, only that requests are signed
+        if header(request, "authorization").is_none() {
+            return ResponseTemplate::new(403);
+        }
+
+        let mut state = self.0.lock().unwrap();
+
+        let path = request.url.path().trim_start_matches('/');
+        let (bucket, key) = path.split_once('/').unwrap_or((path, ""));
+        let object = (bucket.to_owned(), url_escape::decode(key).into_owned());
+        let query: HashMap<String, String> = request.url.query_pairs().into_owned().collect();
+
+        match (request.method.as_ref(), key.is_empty()) {
+            ("GET", true) => state.list(bucket, query.get("continuation-token")),
+            ("GET", false) => state.get(&object, request),
+            ("PUT", false) => match (query.get("uploadId"), query.get("partNumber")) {
+                (Some(upload_id), Some(part_number)) => {
+                    state.upload_part(upload_id, part_number, request)
+                }
+                _ => state.put(object, request),
+            },
+            ("POST", false) if query.contains_key("uploads") => state.create_upload(&object),
+            ("POST", false) => match query.get("uploadId") {
+                Some(upload_id) => state.complete_upload(object, upload_id, request),
+                None => ResponseTemplate::new(400),
+            },
+            ("DELETE", false) => {
+                state.objects.remove(&object);
+                ResponseTemplate::new(204)
+            }
+            _ => ResponseTemplate::new(400),
+        }
+    }
+}
+
+impl State {
+    fn list(&self, bucket: &str, continuation_token: Option<&String>) -> ResponseTemplate {
+        let keys: Vec<_> = self
+            .objects
+            .iter()
+            .filter(|((b, key), _)| b == bucket && Some(key) > continuation_token)
+            .map(|((_, key), object)| (key, object))
+            .collect();
+        let is_truncated = keys.len() > LIST_PAGE_SIZE;
+
+        let mut body = String::from("<ListBucketResult>");
+        body.push_str(&format!("<IsTruncated>{is_truncated}</IsTruncated>"));
+        if is_truncated {
+            let (last, _) = keys[LIST_PAGE_SIZE - 1];
+            body.push_str(&format!(
+                "<NextContinuationToken>{}</NextContinuationToken>",
+                xml_escape(last)
+            ));
+        }
+        for (key, object) in keys.into_iter().take(LIST_PAGE_SIZE) {
+            body.push_str(&format!(
+                "<Contents><Key>{}</Key><ETag>\"{}\"</ETag><Size>{}</Size></Contents>",
+                xml_escape(key),
+                object.etag,
+                object.data.len()
+            ));
+        }
+        body.push_str("</ListBucketResult>");
+
+        ResponseTemplate::new(200).set_body_string(body)
+    }
+
+    fn get(&self, object: &(String, String), request: &Request) -> ResponseTemplate {
+        let Object { data, etag } = match self.objects.get(object) {
+            Some(object) => object,
+            None => return ResponseTemplate::new(404),
+        };
+
+        if let Some(if_match) = header(request, "if-match") {
+            if if_match.trim_matches('"') != etag.as_str() {
+                return ResponseTemplate::new(412);
+            }
+        }
+
+        let offset: Option<usize> = header(request, "range").and_then(|range| {
+            range
+                .strip_prefix("bytes=")?
+                .strip_suffix('-')?
+                .parse()
+                .ok()
+        });
+
+        let etag = format!("\"{etag}\"");
+        match offset {
+            Some(offset) if offset <= data.len() => ResponseTemplate::new(206)
+                .insert_header("ETag", etag.as_str())
+                .set_body_bytes(&data[offset..]),
+            Some(_) => ResponseTemplate::new(416),
+            None => ResponseTemplate::new(200)
+                .insert_header("ETag", etag.as_str())
+                .set_body_bytes(data.clone()),
+        }
+    }
+
+    fn put(&mut self, object: (String, String), request: &Request) -> ResponseTemplate {
+        if header(request, "if-none-match") == Some("*") && self.objects.contains_key(&object) {
+            return ResponseTemplate::new(412);
+        }
+
+        let etag = format!("\"{}\"", sha256::digest(&request.body));
+        self.objects
+            .insert(object, Object::new(request.body.clone()));
+
+        ResponseTemplate::new(200).insert_header("ETag", etag.as_str())
+    }
+
+    fn create_upload(&mut self, object: &(String, String)) -> ResponseTemplate {
+        let upload_id = uuid::Uuid::new_v4().simple().to_string();
+        self.uploads.insert(upload_id.clone(), BTreeMap::new());
+
+        ResponseTemplate::new(200).set_body_string(format!(
+            "<InitiateMultipartUploadResult><Bucket>{}</Bucket><Key>{}</Key><UploadId>{upload_id}</UploadId></InitiateMultipartUploadResult>",
+            xml_escape(&object.0),
+            xml_escape(&object.1)
+        ))
+    }
+
+    fn upload_part(
+        &mut self,
+        upload_id: &str,
+        part_number: &str,
+        request: &Request,
+    ) -> ResponseTemplate {
+        let (upload, part_number) = match (self.uploads.get_mut(upload_id), part_number.parse()) {
+            (Some(upload), Ok(part_number)) => (upload, part_number),
+            _ => return ResponseTemplate::new(404),
+        };
+
+        let etag = format!("\"{}\"", sha256::digest(&request.body));
+        upload.insert(part_number, request.body.clone());
+
+        ResponseTemplate::new(200).insert_header("ETag", etag.as_str())
+    }
+
+    fn complete_upload(
+        &mut self,
+        object: (String, String),
+        upload_id: &str,
+        request: &Request,
+    ) -> ResponseTemplate {
+        let parts = match self.uploads.remove(upload_id) {
+            Some(parts) => parts,
+            None => return ResponseTemplate::new(404),
+        };
+
+        // only complete uploads of the parts listed in the request
+        let body = String::from_utf8_lossy(&request.body);
+        let listed: Vec<_> = parts
+            .values()
+            .map(|data| format!("<ETag>\"{}\"</ETag>", sha256::digest(data)))
+            .collect();
+        if body.matches("<Part>").count() != parts.len()
+            || listed.iter().any(|etag| !body.contains(etag.as_str()))
+        {
+            return ResponseTemplate::new(400);
+        }
+
+        // as with S3, the ETag of the object is not the digest of its data
+        let data: Vec<u8> = parts.into_values().flatten().collect();
+        let etag = format!("{}-{}", sha256::digest(&data), listed.len());
+        let key = xml_escape(&object.1);
+        self.objects.insert(
+            object,
+            Object {
+                data,
+                etag: etag.clone(),
+            },
+        );
+
+        ResponseTemplate::new(200).set_body_string(format!(
+            "<CompleteMultipartUploadResult><Key>{key}</Key><ETag>\"{etag}\"</ETag></CompleteMultipartUploadResult>"
+        ))
+    }
+}
+
+fn header<'a>(request: &'a Request, name: &str) -> Option<&'a str> {
+    request
+        .headers
+        .iter()
+        .find(|(header, _)| header.as_str().eq_ignore_ascii_case(name))
+        .map(|(_, values)| values.last().as_str())
+}
+
+fn xml_escape(value: &str) -> String {
+    value
+        .replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+}
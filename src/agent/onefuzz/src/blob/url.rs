@@ -10,14 +10,24 @@ use anyhow::Result;
 use reqwest::Url;
 use serde::{de, Serialize, Serializer};
 
+use super::s3;
+
 #[derive(Clone, Eq, PartialEq)]
 pub enum BlobUrl {
     AzureBlob(Url),
     LocalFile(PathBuf),
+    S3(Url),
 }
 
 impl BlobUrl {
     pub fn new(url: Url) -> Result<Self> {
+        if s3::is_s3_url(&url) {
+            if s3::bucket(&url).is_some() && !s3::key(&url).is_empty() {
+                return Ok(Self::S3(url));
+            }
+            bail!("Invalid S3 object URL: {}", url)
+        }
+
         if possible_blob_storage_url(&url, false) {
             if let Ok(path) = url.to_file_path() {
                 return Ok(Self::LocalFile(path));
@@ -47,7 +57,7 @@ impl BlobUrl {
             Self::LocalFile(path) => {
                 Url::from_file_path(path).expect("Could not convert path to url")
             }
-            Self::AzureBlob(url) => url.clone(),
+            Self::AzureBlob(url) | Self::S3(url) => url.clone(),
         }
     }
 
@@ -57,7 +67,7 @@ impl BlobUrl {
                 // Ctor checks that domain has at least one subdomain.
                 Some(url.domain().unwrap().split('.').next().unwrap().to_owned())
             }
-            Self::LocalFile(_) => None,
+            Self::LocalFile(_) | Self::S3(_) => None,
         }
     }
 
@@ -68,6 +78,7 @@ impl BlobUrl {
                 Some(url.path_segments().unwrap().next().unwrap().to_owned())
             }
             Self::LocalFile(_) => None,
+            Self::S3(url) => s3::bucket(url).map(|bucket| bucket.to_owned()),
         }
     }
 
@@ -89,6 +100,7 @@ impl BlobUrl {
                 .to_str()
                 .expect("Invalid file path")
                 .into(),
+            Self::S3(url) => s3::key(url),
         }
     }
 }
@@ -110,21 +122,31 @@ impl fmt::Display for BlobUrl {
                 self.name()
             ),
             Self::LocalFile(path) => write!(f, "{}", path.display()),
+            Self::S3(url) => write!(f, "{url}"),
         }
     }
 }
 
-/// URL for accessing an Azure Blob Storage container.
+/// URL for accessing an Azure Blob Storage container, a local directory, or
+/// an S3 bucket.
 ///
 /// Use to validate a URL and address contained blobs.
 #[derive(Clone, Eq, PartialEq)]
 pub enum BlobContainerUrl {
     BlobContainer(Url),
     Path(PathBuf),
+    S3(Url),
 }
 
 impl BlobContainerUrl {
     pub fn new(url: Url) -> Result<Self> {
+        if s3::is_s3_url(&url) {
+            if s3::bucket(&url).is_some() && s3::key(&url).is_empty() {
+                return Ok(Self::S3(url));
+            }
+            bail!("Invalid S3 bucket URL: {}", url);
+        }
+
         if !possible_blob_container_url(&url) {
             bail!("Invalid container URL: {}", url);
         }
@@ -152,7 +174,7 @@ impl BlobContainerUrl {
 
     pub fn url(&self) -> Result<Url> {
         match self {
-            Self::BlobContainer(url) => Ok(url.clone()),
+            Self::BlobContainer(url) | Self::S3(url) => Ok(url.clone()),
             Self::Path(p) => Ok(Url::from_file_path(p)
                 .map_err(|err| anyhow!("invalid path.  path:{} error:{:?}", p.display(), err))?),
         }
@@ -164,7 +186,7 @@ impl BlobContainerUrl {
                 // Ctor checks that domain has at least one subdomain.
                 Some(url.domain().unwrap().split('.').next().unwrap().to_owned())
             }
-            Self::Path(_p) => None,
+            Self::Path(_p) | Self::S3(_) => None,
         }
     }
 
@@ -174,6 +196,7 @@ impl BlobContainerUrl {
                 Some(url.path_segments().unwrap().next().unwrap().to_owned())
             }
             Self::Path(_p) => None,
+            Self::S3(url) => s3::bucket(url).map(|bucket| bucket.to_owned()),
         }
     }
 
@@ -188,10 +211,26 @@ impl BlobContainerUrl {
                 BlobUrl::AzureBlob(url)
             }
             Self::Path(p) => BlobUrl::LocalFile(p.join(name.as_ref())),
+            Self::S3(url) => {
+                let key: Vec<_> = name
+                    .as_ref()
+                    .split('/')
+                    .map(url_escape::encode_component)
+                    .collect();
+                let mut url = url.clone();
+                url.set_path(&format!("/{}", key.join("/")));
+                BlobUrl::S3(url)
+            }
         }
     }
 
     pub fn as_path(&self, prefix: impl AsRef<Path>) -> Result<PathBuf> {
+        if let Self::S3(_) = self {
+            // buckets have no account, but are all in the same namespace
+            let bucket = self.container().unwrap_or_default();
+            return Ok(prefix.as_ref().join("s3").join(bucket));
+        }
+
         match (self.account(), self.container()) {
             (Some(account), Some(container)) => {
                 let mut path = PathBuf::new();
@@ -209,6 +248,7 @@ impl fmt::Debug for BlobContainerUrl {
         match self {
             Self::BlobContainer(url) => write!(f, "{}", redact_query_sas_sig(url)),
             Self::Path(p) => write!(f, "{}", p.display()),
+            Self::S3(url) => write!(f, "{url}"),
         }
     }
 }
@@ -217,6 +257,8 @@ impl fmt::Display for BlobContainerUrl {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         if let Some(file_path) = self.as_file_path() {
             write!(f, "{file_path:?}")
+        } else if let Self::S3(url) = self {
+            write!(f, "{url}")
         } else if let (Some(account), Some(container)) = (self.account(), self.container()) {
             write!(f, "{account}:{container}")
         } else {
@@ -310,7 +352,7 @@ impl Serialize for BlobContainerUrl {
     {
         match self {
             Self::Path(p) => serializer.serialize_str(p.to_str().unwrap_or_default()),
-            Self::BlobContainer(url) => {
+            Self::BlobContainer(url) | Self::S3(url) => {
                 let url = url.to_string();
                 serializer.serialize_str(&url)
             }
@@ -532,6 +574,33 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_s3_url() -> Result<()> {
+        let container = BlobContainerUrl::parse("s3://corpus")?;
+        assert_eq!(container.container(), Some("corpus".into()));
+        assert_eq!(container.account(), None);
+        assert_eq!(container.to_string(), "s3://corpus");
+
+        let blob = container.blob("dir/id:000000,op:havoc");
+        assert!(matches!(blob, BlobUrl::S3(_)));
+        assert_eq!(blob.container(), Some("corpus".into()));
+        assert_eq!(blob.name(), "dir/id:000000,op:havoc");
+        assert_eq!(BlobUrl::new(blob.url())?, blob);
+
+        // no object in a bucket URL, and no bucket URL for an object
+        assert!(BlobContainerUrl::parse("s3://corpus/input").is_err());
+        assert!(BlobUrl::parse("s3://corpus").is_err());
+        assert!(BlobContainerUrl::parse("s3:///input").is_err());
+
+        let root = PathBuf::from(r"/onefuzz");
+        assert_eq!(
+            container.as_path(root)?,
+            PathBuf::from(r"/onefuzz/s3/corpus")
+        );
+
+        Ok(())
+    }
+
     #[test]
     fn test_as_path() -> Result<()> {
         let root = PathBuf::from(r"/onefuzz");
@@ -0,0 +1,340 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT License.
+
+//! Control groups (cgroup v2), to limit the resources of processes.
+//!
+//! Cgroups are created under the `onefuzz` cgroup of the unified hierarchy,
+//! which has no processes of its own, so that controllers can be enabled for
+//! its children. Processes which are themselves in a cgroup under it, such as
+//! tasks, create the cgroups of their children under their own, so that those
+//! stay within its limits.
+
+use std::ffi::OsStr;
+use std::fs;
+use std::io::ErrorKind;
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context, Result};
+
+const CGROUP_ROOT: &str = "/sys/fs/cgroup";
+
+const ONEFUZZ_CGROUP: &str = "onefuzz";
+
+// Child to which the processes of a cgroup are moved, so that controllers can
+// be enabled for its other children.
+const LEADER_CGROUP: &str = "leader";
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Cgroup {
+    path: PathBuf,
+}
+
+impl Cgroup {
+    /// Whether the unified cgroup v2 hierarchy is mounted.
+    pub fn is_supported() -> bool {
+        Path::new(CGROUP_ROOT).join("cgroup.controllers").exists()
+    }
+
+    /// Create the cgroup `name`, if missing, under the `onefuzz` cgroup, with
+    /// `controllers` enabled for it, such as `["memory"]`.
+    pub fn create(name: &str, controllers: &[&str]) -> Result<Self> {
+        Self::onefuzz()?.create_child(name, controllers)
+    }
+
+    /// Cgroup under which to create the cgroups of the children of the
+    /// current process.
+    ///
+    /// This is the cgroup of the current process if it is under the `onefuzz`
+    /// cgroup, such as that of a task, and otherwise the `onefuzz` cgroup. In
+    /// the first case, the processes of the cgroup are moved to a child of it,
+    /// as a cgroup with controllers enabled for its children cannot have
+    /// processes of its own.
+    pub fn for_children() -> Result<Self> {
+        let onefuzz = Self::onefuzz()?;
+
+        let data = fs::read_to_string("/proc/self/cgroup")
+            .context("unable to read cgroup of current process")?;
+        let Some(current) = parse_proc_cgroup(&data) else {
+            return Ok(onefuzz);
+        };
+
+        if !current.starts_with(&onefuzz.path) || current == onefuzz.path {
+            return Ok(onefuzz);
+        }
+
+        Self::for_children_of(&current)
+    }
+
+    // Cgroup under which to create the cgroups of the children of the
+    // processes of the cgroup at `current`, which is under the `onefuzz` one.
+    fn for_children_of(current: &Path) -> Result<Self> {
+        // Already moved by a previous call.
+        if current.file_name() == Some(OsStr::new(LEADER_CGROUP)) {
+            if let Some(path) = current.parent() {
+                return Ok(Self {
+                    path: path.to_owned(),
+                });
+            }
+        }
+
+        let leader = current.join(LEADER_CGROUP);
+        create_dir(&leader)?;
+
+        let procs = current.join("cgroup.procs");
+        let pids = fs::read_to_string(&procs)
+            .with_context(|| format!("unable to read {}", procs.display()))?;
+        for pid in pids.lines() {
+            if let Err(err) = write(&leader, "cgroup.procs", pid) {
+                // Unless it has exited since it was listed.
+                if Path::new("/proc").join(pid).exists() {
+                    return Err(err);
+                }
+            }
+        }
+
+        Ok(Self {
+            path: current.to_owned(),
+        })
+    }
+
+    /// Create the cgroup `name`, if missing, as a child of this one, with
+    /// `controllers` enabled for it.
+    pub fn create_child(&self, name: &str, controllers: &[&str]) -> Result<Self> {
+        self.enable_controllers(controllers)?;
+
+        let path = self.path.join(name);
+        create_dir(&path)?;
+
+        Ok(Self { path })
+    }
+
+    fn onefuzz() -> Result<Self> {
+        let path = Path::new(CGROUP_ROOT).join(ONEFUZZ_CGROUP);
+        create_dir(&path)?;
+        Ok(Self { path })
+    }
+
+    // Enable `controllers` for the children of the cgroup, which requires
+    // them to be enabled for the children of each of its ancestors too.
+    fn enable_controllers(&self, controllers: &[&str]) -> Result<()> {
+        let root = Path::new(CGROUP_ROOT);
+        let relative = self
+            .path
+            .strip_prefix(root)
+            .with_context(|| format!("cgroup is not in the hierarchy: {}", self.path.display()))?;
+
+        let mut path = root.to_owned();
+        enable_controllers(&path, controllers)?;
+        for component in relative.components() {
+            path.push(component);
+            enable_controllers(&path, controllers)?;
+        }
+
+        Ok(())
+    }
+
+    #[cfg(test)]
+    pub(crate) fn from_path(path: impl Into<PathBuf>) -> Self {
+        Self { path: path.into() }
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Limit the memory of the cgroup, past which its processes are killed.
+    pub fn set_memory_max(&self, bytes: u64) -> Result<()> {
+        write(&self.path, "memory.max", &bytes.to_string())
+    }
+
+    /// Limit the number of processes and threads of the cgroup.
+    pub fn set_pids_max(&self, max: u64) -> Result<()> {
+        write(&self.path, "pids.max", &max.to_string())
+    }
+
+    /// Limit the CPU time of the cgroup to `quota_us` in each `period_us`.
+    pub fn set_cpu_max(&self, quota_us: u64, period_us: u64) -> Result<()> {
+        write(&self.path, "cpu.max", &format!("{quota_us} {period_us}"))
+    }
+
+    /// Move the process `pid`, with all of its threads, to the cgroup.
+    pub fn add_process(&self, pid: u32) -> Result<()> {
+        write(&self.path, "cgroup.procs", &pid.to_string())
+    }
+
+    /// Open the process list of the cgroup, to which writing `0` moves the
+    /// writing process into the cgroup.
+    pub fn open_procs(&self) -> Result<fs::File> {
+        let path = self.path.join("cgroup.procs");
+        fs::OpenOptions::new()
+            .write(true)
+            .open(&path)
+            .with_context(|| format!("unable to open {}", path.display()))
+    }
+
+    /// Number of processes of the cgroup killed for exceeding its memory
+    /// limit.
+    pub fn oom_kills(&self) -> Result<u64> {
+        let path = self.path.join("memory.events");
+        let events = fs::read_to_string(&path)
+            .with_context(|| format!("unable to read {}", path.display()))?;
+
+        let count = events
+            .lines()
+            .find_map(|line| line.strip_prefix("oom_kill "))
+            .map(|count| count.trim().parse())
+            .transpose()?
+            .unwrap_or_default();
+
+        Ok(count)
+    }
+
+    /// Kill all of the processes of the cgroup.
+    pub fn kill(&self) -> Result<()> {
+        write(&self.path, "cgroup.kill", "1")
+    }
+
+    /// Remove the cgroup and its descendants, such as the child its processes
+    /// were moved to by `for_children()`, none of which must have any
+    /// processes left.
+    pub fn remove(&self) -> Result<()> {
+        remove_dir(&self.path)
+    }
+}
+
+// Remove the cgroup at `path` bottom-up, as a cgroup with children cannot be
+// removed.
+fn remove_dir(path: &Path) -> Result<()> {
+    let entries = match fs::read_dir(path) {
+        Ok(entries) => entries,
+        Err(err) if err.kind() == ErrorKind::NotFound => return Ok(()),
+        Err(err) => {
+            return Err(err).with_context(|| format!("unable to read cgroup: {}", path.display()))
+        }
+    };
+
+    for entry in entries {
+        let entry = entry.with_context(|| format!("unable to read cgroup: {}", path.display()))?;
+        if entry.file_type()?.is_dir() {
+            remove_dir(&entry.path())?;
+        }
+    }
+
+    match fs::remove_dir(path) {
+        Ok(()) => Ok(()),
+        Err(err) if err.kind() == ErrorKind::NotFound => Ok(()),
+        Err(err) => {
+            Err(err).with_context(|| format!("unable to remove cgroup: {}", path.display()))
+        }
+    }
+}
+
+fn create_dir(path: &Path) -> Result<()> {
+    match fs::create_dir(path) {
+        Ok(()) => Ok(()),
+        Err(err) if err.kind() == ErrorKind::AlreadyExists => Ok(()),
+        Err(err) => {
+            Err(err).with_context(|| format!("unable to create cgroup: {}", path.display()))
+        }
+    }
+}
+
+// Enable each of `controllers` which is not already enabled for the children
+// of the cgroup at `path`.
+fn enable_controllers(path: &Path, controllers: &[&str]) -> Result<()> {
+    let file = path.join("cgroup.subtree_control");
+    let enabled =
+        fs::read_to_string(&file).with_context(|| format!("unable to read {}", file.display()))?;
+
+    for controller in controllers {
+        if !enabled.split_whitespace().any(|c| c == *controller) {
+            write(path, "cgroup.subtree_control", &format!("+{controller}"))?;
+        }
+    }
+
+    Ok(())
+}
+
+// Path of the cgroup v2 of a process, from its `/proc/<pid>/cgroup`.
+fn parse_proc_cgroup(data: &str) -> Option<PathBuf> {
+    let path = data.lines().find_map(|line| line.strip_prefix("0::"))?;
+    Some(Path::new(CGROUP_ROOT).join(path.trim_start_matches('/')))
+}
+
+fn write(cgroup: &Path, file: &str, value: &str) -> Result<()> {
+    let path = cgroup.join(file);
+    fs::write(&path, value)
+        .with_context(|| format!("unable to write `{value}` to {}", path.display()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_oom_kills() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let cgroup = Cgroup {
+            path: dir.path().to_owned(),
+        };
+        assert!(cgroup.oom_kills().is_err());
+
+        fs::write(
+            dir.path().join("memory.events"),
+            "low 0\nhigh 0\nmax 12\noom 3\noom_kill 2\noom_group_kill 0\n",
+        )?;
+        assert_eq!(cgroup.oom_kills()?, 2);
+
+        fs::write(dir.path().join("memory.events"), "low 0\nhigh 0\n")?;
+        assert_eq!(cgroup.oom_kills()?, 0);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_remove_for_children() -> Result<()> {
+        // Requires a writable cgroup v2 hierarchy, as when run as root.
+        if !Cgroup::is_supported() {
+            return Ok(());
+        }
+        let name = format!("test-{}", uuid::Uuid::new_v4().simple());
+        let Ok(task) = Cgroup::create(&name, &["pids"]) else {
+            return Ok(());
+        };
+
+        let mut process = std::process::Command::new("sleep").arg("60").spawn()?;
+        task.add_process(process.id())?;
+
+        let parent = Cgroup::for_children_of(task.path())?;
+        assert_eq!(parent, task);
+        let procs = fs::read_to_string(task.path().join(LEADER_CGROUP).join("cgroup.procs"))?;
+        assert_eq!(procs.trim(), process.id().to_string());
+        let child = parent.create_child("target", &["pids"])?;
+
+        task.kill()?;
+        process.wait()?;
+
+        task.remove()?;
+        assert!(!task.path().exists());
+        assert!(!child.path().exists());
+
+        // Already removed.
+        task.remove()?;
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_parse_proc_cgroup() {
+        assert_eq!(
+            parse_proc_cgroup("0::/onefuzz/task-1/leader\n"),
+            Some(PathBuf::from("/sys/fs/cgroup/onefuzz/task-1/leader"))
+        );
+        assert_eq!(
+            parse_proc_cgroup("0::/\n"),
+            Some(PathBuf::from("/sys/fs/cgroup"))
+        );
+        // cgroup v1 only
+        assert_eq!(parse_proc_cgroup("12:memory:/user.slice\n"), None);
+    }
+}
@@ -5,7 +5,7 @@ use anyhow::{Context, Result};
 use futures::stream::StreamExt;
 use std::{
     ffi::OsStr,
-    path::{Path, PathBuf},
+    path::{Component, Path, PathBuf},
 };
 use tokio_stream::wrappers::ReadDirStream;
 
@@ -116,6 +116,23 @@ pub async fn exists(entry: impl AsRef<Path>) -> Result<bool> {
     Ok(true)
 }
 
+/// Joins `name`, such as the name of a remote file, to `dir`, failing unless it
+/// is a relative path which stays within `dir`.
+pub fn join_relative(dir: impl AsRef<Path>, name: &str) -> Result<PathBuf> {
+    let path = Path::new(name);
+
+    let is_relative = path.components().next().is_some()
+        && path
+            .components()
+            .all(|component| matches!(component, Component::Normal(_)));
+
+    if !is_relative {
+        bail!("invalid relative path: {name}");
+    }
+
+    Ok(dir.as_ref().join(path))
+}
+
 pub async fn write_file(path: impl AsRef<Path>, content: &str) -> Result<()> {
     let path = path.as_ref();
     let parent = path
@@ -307,6 +324,17 @@ mod tests {
 
     use super::*;
 
+    #[test]
+    fn test_join_relative() {
+        let dir = Path::new("dir");
+        assert_eq!(join_relative(dir, "a").unwrap(), dir.join("a"));
+        assert_eq!(join_relative(dir, "sub/a").unwrap(), dir.join("sub/a"));
+
+        for name in ["", ".", "../a", "sub/../../a", "/etc/a", "./a"] {
+            assert!(join_relative(dir, name).is_err(), "{name}");
+        }
+    }
+
     async fn dir_len(dir: &Path) -> usize {
         let mut len = 0;
         let mut entries = fs::read_dir(dir).await.unwrap();
@@ -8,7 +8,8 @@ use crate::{
     env::{get_path_with_directory, update_path, LD_LIBRARY_PATH, PATH},
     expand::Expand,
     machine_id::MachineIdentity,
-    process::run_cmd,
+    process::{run_cmd_in_sandbox, TimedOut},
+    sandbox::{container::parent_dir, LimitKill, Sandbox, SandboxConfig},
 };
 use anyhow::{Context, Error, Result};
 #[cfg(target_os = "linux")]
@@ -37,6 +38,7 @@ pub struct Tester<'a> {
     check_retry_count: u64,
     add_setup_to_ld_library_path: bool,
     add_setup_to_path: bool,
+    sandbox: SandboxConfig,
     machine_identity: MachineIdentity,
 }
 
@@ -51,6 +53,9 @@ pub struct Crash {
 pub struct TestResult {
     pub crash_log: Option<CrashLog>,
     pub error: Option<Error>,
+
+    /// Limit which killed the target, if any, on the last attempt.
+    pub limit_kill: Option<LimitKill>,
 }
 
 impl<'a> Tester<'a> {
@@ -75,6 +80,7 @@ impl<'a> Tester<'a> {
             check_retry_count: 0,
             add_setup_to_ld_library_path: false,
             add_setup_to_path: false,
+            sandbox: SandboxConfig::default(),
             machine_identity,
         }
     }
@@ -128,6 +134,13 @@ impl<'a> Tester<'a> {
         }
     }
 
+    pub fn sandbox(self, value: SandboxConfig) -> Self {
+        Self {
+            sandbox: value,
+            ..self
+        }
+    }
+
     pub fn set_optional<T>(self, value: Option<T>, setter: impl FnOnce(Self, T) -> Self) -> Self {
         if let Some(value) = value {
             setter(self, value)
@@ -141,6 +154,7 @@ impl<'a> Tester<'a> {
         &self,
         argv: &[impl AsRef<OsStr>],
         env: &HashMap<String, String>,
+        _sandbox: &Sandbox,
     ) -> Result<Option<CrashLog>> {
         const IGNORE_FIRST_CHANCE_EXCEPTIONS: bool = true;
         let report = input_tester::crash_detector::test_process(
@@ -199,10 +213,12 @@ impl<'a> Tester<'a> {
         &self,
         args: &[impl AsRef<OsStr>],
         env: &HashMap<String, String>,
+        sandbox: &Sandbox,
     ) -> Result<Option<CrashLog>> {
         let mut cmd = std::process::Command::new(self.exe_path);
         cmd.args(args).stdin(Stdio::null());
         cmd.envs(env);
+        sandbox.apply(&mut cmd)?;
 
         let (sender, receiver) = tokio::sync::oneshot::channel();
 
@@ -240,7 +256,7 @@ impl<'a> Tester<'a> {
         if timeout.is_err() {
             // Yes. Try to kill the target process, if hung.
             kill(target_pid, Signal::SIGKILL)?;
-            bail!("process timed out");
+            return Err(TimedOut.into());
         }
 
         let report = timeout???;
@@ -281,11 +297,17 @@ impl<'a> Tester<'a> {
     }
 
     pub async fn test_input(&self, input_file: impl AsRef<Path>) -> Result<TestResult> {
+        let input_dir = parent_dir(input_file.as_ref());
+        let mut mounts = vec![self.setup_dir];
+        mounts.extend(self.extra_setup_dir);
+        mounts.extend(input_dir.as_deref());
+
         let asan_dir = if self.check_asan_log {
             Some(tempdir()?)
         } else {
             None
         };
+        mounts.extend(asan_dir.as_ref().map(|dir| dir.path()));
 
         let (argv, env) = {
             let expand = Expand::new(&self.machine_identity)
@@ -302,8 +324,11 @@ impl<'a> Tester<'a> {
                 env.insert(k.clone(), expand.evaluate_value(v)?);
             }
 
+            // The search paths of the node are not those of the images of
+            // containers, so they are only extended for targets on the node.
+            let in_containers = self.sandbox.container.is_some();
             let setup_dir = &self.setup_dir.to_path_buf();
-            if self.add_setup_to_path {
+            if self.add_setup_to_path && !in_containers {
                 let new_path = match env.get(PATH) {
                     Some(v) => update_path(v.clone().into(), setup_dir)?,
                     None => get_path_with_directory(PATH, setup_dir)?,
@@ -311,7 +336,7 @@ impl<'a> Tester<'a> {
 
                 env.insert(PATH.to_string(), new_path.to_string_lossy().to_string());
             }
-            if self.add_setup_to_ld_library_path {
+            if self.add_setup_to_ld_library_path && !in_containers {
                 let new_path = match env.get(LD_LIBRARY_PATH) {
                     Some(v) => update_path(v.clone().into(), setup_dir)?,
                     None => get_path_with_directory(LD_LIBRARY_PATH, setup_dir)?,
@@ -331,16 +356,32 @@ impl<'a> Tester<'a> {
 
         let mut error = None;
         let mut crash_log = None;
+        let mut limit_kill = None;
 
         let attempts = 1 + self.check_retry_count;
         for _ in 0..attempts {
-            let result = if self.check_debugger {
-                match self.test_input_debugger(&argv, &env).await {
+            // Each attempt gets a new sandbox, so limits hit by one attempt
+            // are not reported for the next.
+            let sandbox = Sandbox::new(&self.sandbox)?;
+
+            // Targets in containers are run without a debugger, which is
+            // not available in their images.
+            let result = if self.check_debugger && !sandbox.in_containers() {
+                match self.test_input_debugger(&argv, &env, &sandbox).await {
                     Ok(crash) => (crash, None, None),
                     Err(error) => (None, Some(error), None),
                 }
             } else {
-                match run_cmd(self.exe_path, argv.clone(), &env, self.timeout).await {
+                match run_cmd_in_sandbox(
+                    self.exe_path,
+                    argv.clone(),
+                    &env,
+                    self.timeout,
+                    &sandbox,
+                    &mounts,
+                )
+                .await
+                {
                     Ok(output) => (None, None, Some(output)),
                     Err(error) => (None, Some(error), None),
                 }
@@ -350,6 +391,11 @@ impl<'a> Tester<'a> {
             error = result.1;
             let output = result.2;
 
+            limit_kill = match &error {
+                Some(error) if error.is::<TimedOut>() => Some(LimitKill::Timeout),
+                _ => sandbox.limit_kill().await,
+            };
+
             // order of operations for checking for crashes:
             // 1. if we ran under a debugger, and that caught a crash
             // 2. if we have an ASAN log in our temp directory
@@ -377,7 +423,11 @@ impl<'a> Tester<'a> {
             }
         }
 
-        Ok(TestResult { crash_log, error })
+        Ok(TestResult {
+            crash_log,
+            error,
+            limit_kill,
+        })
     }
 
     pub async fn is_crash(&self, input_file: impl AsRef<Path>) -> Result<bool> {
@@ -389,3 +439,53 @@ impl<'a> Tester<'a> {
         Ok(test_result.crash_log.is_some())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[cfg(target_os = "linux")]
+    #[tokio::test]
+    async fn test_input_timeout_is_limit_kill() -> Result<()> {
+        let setup_dir = tempdir()?;
+        let input = setup_dir.path().join("input");
+        std::fs::write(&input, "")?;
+
+        let environ = HashMap::new();
+        let machine_identity = MachineIdentity {
+            machine_id: uuid::Uuid::new_v4(),
+            machine_name: "test".to_owned(),
+            scaleset_name: None,
+        };
+
+        let arguments = vec!["10".to_owned()];
+        let tester = Tester::new(
+            setup_dir.path(),
+            None,
+            Path::new("sleep"),
+            &arguments,
+            &environ,
+            machine_identity.clone(),
+        )
+        .timeout(1);
+        let result = tester.test_input(&input).await?;
+        assert!(result.error.map(|e| e.is::<TimedOut>()).unwrap_or(false));
+        assert_eq!(result.limit_kill, Some(LimitKill::Timeout));
+
+        let arguments = vec!["0".to_owned()];
+        let tester = Tester::new(
+            setup_dir.path(),
+            None,
+            Path::new("sleep"),
+            &arguments,
+            &environ,
+            machine_identity,
+        )
+        .timeout(1);
+        let result = tester.test_input(&input).await?;
+        assert!(result.error.is_none());
+        assert_eq!(result.limit_kill, None);
+
+        Ok(())
+    }
+}
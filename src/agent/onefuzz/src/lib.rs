@@ -24,11 +24,14 @@ pub mod machine_id;
 pub mod memory;
 pub mod monitor;
 pub mod process;
+pub mod sandbox;
 pub mod sanitizer;
 pub mod sha256;
 pub mod syncdir;
 pub mod utils;
 
+#[cfg(target_os = "linux")]
+pub mod cgroup;
 #[cfg(target_os = "linux")]
 pub mod triage;
 pub mod uploader;
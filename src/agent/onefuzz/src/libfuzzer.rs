@@ -7,6 +7,7 @@ use crate::{
     fs::{list_files, write_file},
     input_tester::{TestResult, Tester},
     machine_id::MachineIdentity,
+    sandbox::{container::parent_dir, Sandbox, SandboxConfig},
 };
 use anyhow::{Context, Result};
 use rand::seq::SliceRandom;
@@ -23,6 +24,10 @@ use tokio::process::{Child, Command};
 
 const DEFAULT_MAX_TOTAL_SECONDS: i32 = 10 * 60;
 
+// Time libFuzzer is given past `-max_total_time=` to write its result, before
+// it is killed.
+const MINIMIZE_GRACE_PERIOD: Duration = Duration::from_secs(30);
+
 use lazy_static::lazy_static;
 
 lazy_static! {
@@ -44,6 +49,7 @@ pub struct LibFuzzer {
     options: Vec<String>,
     env: HashMap<String, String>,
     machine_identity: MachineIdentity,
+    sandbox: SandboxConfig,
 }
 
 impl LibFuzzer {
@@ -64,10 +70,16 @@ impl LibFuzzer {
             extra_setup_dir,
             extra_output_dir,
             machine_identity,
+            sandbox: SandboxConfig::default(),
         }
     }
 
-    // Build an async `Command`.
+    /// Run the libFuzzer processes in sandboxes configured by `sandbox`.
+    pub fn with_sandbox(self, sandbox: SandboxConfig) -> Self {
+        Self { sandbox, ..self }
+    }
+
+    // Build an async `Command`, in a new sandbox which must outlive its process.
     fn build_command(
         &self,
         fault_dir: Option<&Path>,
@@ -75,13 +87,17 @@ impl LibFuzzer {
         extra_corpus_dirs: Option<&[&Path]>,
         extra_args: Option<&[&OsStr]>,
         custom_arg_filter: Option<&dyn Fn(String) -> Option<String>>,
-    ) -> Result<Command> {
+        extra_mounts: &[&Path],
+    ) -> Result<(Command, Sandbox)> {
+        let sandbox = Sandbox::new(&self.sandbox)?;
         let std_cmd = self.build_std_command(
             fault_dir,
             corpus_dir,
             extra_corpus_dirs,
             extra_args,
             custom_arg_filter,
+            &sandbox,
+            extra_mounts,
         )?;
 
         // Make async (turn into tokio::process::Command):
@@ -90,10 +106,12 @@ impl LibFuzzer {
         // Terminate the process if the `Child` handle is dropped.
         cmd.kill_on_drop(true);
 
-        Ok(cmd)
+        Ok((cmd, sandbox))
     }
 
-    // Build a non-async `Command`.
+    // Build a non-async `Command`, run in `sandbox`.
+    //
+    // `extra_mounts` are the host directories of any paths in `extra_args`.
     pub fn build_std_command(
         &self,
         fault_dir: Option<&Path>,
@@ -101,20 +119,23 @@ impl LibFuzzer {
         extra_corpus_dirs: Option<&[&Path]>,
         extra_args: Option<&[&OsStr]>,
         custom_arg_filter: Option<&dyn Fn(String) -> Option<String>>,
+        sandbox: &Sandbox,
+        extra_mounts: &[&Path],
     ) -> Result<std::process::Command> {
         let mut cmd = std::process::Command::new(&self.exe);
-        cmd.env(PATH, get_path_with_directory(PATH, &self.setup_dir)?)
-            .env_remove("RUST_LOG")
-            .stdin(Stdio::null())
-            .stdout(Stdio::piped())
-            .stderr(Stdio::piped())
-            .arg("-workers=1");
-
-        if cfg!(target_family = "unix") {
-            cmd.env(
-                LD_LIBRARY_PATH,
-                get_path_with_directory(LD_LIBRARY_PATH, &self.setup_dir)?,
-            );
+        cmd.env_remove("RUST_LOG").arg("-workers=1");
+
+        // Extending the search paths of the node, which are not those of the
+        // images of containers.
+        if !sandbox.in_containers() {
+            cmd.env(PATH, get_path_with_directory(PATH, &self.setup_dir)?);
+
+            if cfg!(target_family = "unix") {
+                cmd.env(
+                    LD_LIBRARY_PATH,
+                    get_path_with_directory(LD_LIBRARY_PATH, &self.setup_dir)?,
+                );
+            }
         }
 
         let expand = Expand::new(&self.machine_identity)
@@ -170,9 +191,32 @@ impl LibFuzzer {
                 .filter_map(custom_arg_filter.unwrap_or(&Some)),
         );
 
+        let mut mounts = self.mounts(fault_dir, corpus_dir, extra_corpus_dirs);
+        mounts.extend(extra_mounts);
+        let mut cmd = sandbox.command(cmd, &mounts)?;
+        cmd.stdin(Stdio::null())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped());
+
         Ok(cmd)
     }
 
+    // Host directories used by the target, to mount if it runs in containers.
+    fn mounts<'b>(
+        &'b self,
+        fault_dir: Option<&'b Path>,
+        corpus_dir: Option<&'b Path>,
+        extra_corpus_dirs: Option<&[&'b Path]>,
+    ) -> Vec<&'b Path> {
+        let mut mounts = vec![self.setup_dir.as_path()];
+        mounts.extend(self.extra_setup_dir.as_deref());
+        mounts.extend(self.extra_output_dir.as_deref());
+        mounts.extend(fault_dir);
+        mounts.extend(corpus_dir);
+        mounts.extend(extra_corpus_dirs.unwrap_or_default());
+        mounts
+    }
+
     pub(crate) async fn verify_once(
         &self,
         check_fuzzer_help: bool,
@@ -250,7 +294,8 @@ impl LibFuzzer {
     // Verify that the libfuzzer exits with a zero return code with a known
     // good input, which libfuzzer works as we expect.
     async fn check_input(&self, input: &Path) -> Result<()> {
-        let mut cmd = self.build_command(
+        let input_dir = parent_dir(input);
+        let (mut cmd, sandbox) = self.build_command(
             None,
             None,
             None,
@@ -267,6 +312,7 @@ impl LibFuzzer {
                     Some(arg)
                 }
             }),
+            &input_dir.as_deref().into_iter().collect::<Vec<_>>(),
         )?;
 
         let result = cmd
@@ -277,11 +323,13 @@ impl LibFuzzer {
             .with_context(|| format_err!("libfuzzer failed to run: {}", self.exe.display()))?;
 
         if !result.status.success() {
+            let limit_kill = sandbox.limit_kill().await;
             bail!(
-                "libFuzzer failed when parsing an initial seed {:?}: cmd:{:?} exit_code: {:?} stdout:{:?} stderr:{:?}",
+                "libFuzzer failed when parsing an initial seed {:?}: cmd:{:?} exit_code: {:?} limit_kill: {:?} stdout:{:?} stderr:{:?}",
                 input.file_name().unwrap_or_else(|| input.as_ref()),
                 cmd,
                 result.status,
+                limit_kill,
                 String::from_utf8_lossy(&result.stdout),
                 String::from_utf8_lossy(&result.stderr),
             );
@@ -294,7 +342,8 @@ impl LibFuzzer {
     /// least able to satisfy the fuzzer's shared library dependencies. User-authored
     /// dynamic loading may still fail later on, e.g. in `LLVMFuzzerInitialize()`.
     async fn check_help(&self) -> Result<()> {
-        let mut cmd = self.build_command(None, None, None, Some(&["-help=1".as_ref()]), None)?;
+        let (mut cmd, _sandbox) =
+            self.build_command(None, None, None, Some(&["-help=1".as_ref()]), None, &[])?;
 
         let result = cmd
             .spawn()
@@ -326,10 +375,27 @@ impl LibFuzzer {
     }
 
     async fn find_missing_libraries(&self) -> Result<(Vec<String>, Vec<String>)> {
-        let cmd = self.build_std_command(None, None, None, None, None)?;
+        let cmd = self.build_std_command(None, None, None, None, None, &Sandbox::default(), &[])?;
 
+        // Check the libraries of the image, rather than of the node.
         #[cfg(target_os = "linux")]
-        let blocking = move || dynamic_library::linux::find_missing(cmd);
+        let blocking = {
+            let sandbox = Sandbox::new(&self.sandbox)?;
+            let mounts: Vec<PathBuf> = self
+                .mounts(None, None, None)
+                .into_iter()
+                .map(Path::to_owned)
+                .collect();
+
+            move || {
+                let mounts: Vec<&Path> = mounts.iter().map(PathBuf::as_path).collect();
+                dynamic_library::linux::find_missing_with_launcher(cmd, |cmd| {
+                    sandbox
+                        .command(cmd, &mounts)
+                        .map_err(|err| std::io::Error::new(std::io::ErrorKind::Other, err))
+                })
+            }
+        };
 
         #[cfg(target_os = "windows")]
         let blocking = move || dynamic_library::windows::find_missing(cmd);
@@ -346,7 +412,7 @@ impl LibFuzzer {
         fault_dir: impl AsRef<Path>,
         corpus_dir: impl AsRef<Path>,
         extra_corpus_dirs: &[impl AsRef<Path>],
-    ) -> Result<Child> {
+    ) -> Result<(Child, Sandbox)> {
         let extra_corpus_dirs: Vec<&Path> = extra_corpus_dirs.iter().map(|x| x.as_ref()).collect();
 
         // When writing a new faulting input, the libFuzzer runtime _exactly_
@@ -356,12 +422,13 @@ impl LibFuzzer {
         // trailing path separator.
         let artifact_prefix = artifact_prefix(fault_dir.as_ref());
 
-        let mut cmd = self.build_command(
+        let (mut cmd, sandbox) = self.build_command(
             Some(fault_dir.as_ref()),
             Some(corpus_dir.as_ref()),
             Some(&extra_corpus_dirs),
             Some(&[&artifact_prefix]),
             None,
+            &[],
         )?;
 
         info!("Running command: {:?}", &cmd);
@@ -369,7 +436,7 @@ impl LibFuzzer {
         let child = cmd
             .spawn()
             .with_context(|| format_err!("libfuzzer failed to start: {}", self.exe.display()))?;
-        Ok(child)
+        Ok((child, sandbox))
     }
 
     pub async fn repro(
@@ -392,6 +459,7 @@ impl LibFuzzer {
         .check_asan_stderr(true)
         .check_retry_count(retry)
         .add_setup_to_path(true)
+        .sandbox(self.sandbox.clone())
         .set_optional(timeout, Tester::timeout);
 
         if cfg!(target_family = "unix") {
@@ -401,18 +469,104 @@ impl LibFuzzer {
         tester.test_input(test_input).await
     }
 
+    /// Minimize a crashing input with `-minimize_crash=1`, writing the result
+    /// to `output`.
+    ///
+    /// libFuzzer only checks that the minimized input still crashes, not that
+    /// it crashes in the same way, so callers should verify the result.
+    ///
+    /// libFuzzer stops minimizing after `timeout`, and is killed if it has not
+    /// exited shortly after.
+    pub async fn minimize_crash(
+        &self,
+        test_input: impl AsRef<Path>,
+        output: impl AsRef<Path>,
+        runs: u64,
+        timeout: Duration,
+    ) -> Result<()> {
+        let test_input = test_input.as_ref();
+        let output = output.as_ref();
+
+        let runs: OsString = format!("-runs={runs}").into();
+        let max_total_time: OsString = format!("-max_total_time={}", timeout.as_secs()).into();
+        let exact_artifact_path: OsString =
+            format!("-exact_artifact_path={}", output.display()).into();
+        let mounts: Vec<PathBuf> = [test_input, output]
+            .into_iter()
+            .filter_map(parent_dir)
+            .collect();
+
+        let (mut cmd, sandbox) = self.build_command(
+            None,
+            None,
+            None,
+            Some(&[
+                OsStr::new("-minimize_crash=1"),
+                runs.as_os_str(),
+                max_total_time.as_os_str(),
+                exact_artifact_path.as_os_str(),
+                test_input.as_os_str(),
+            ]),
+            // `-runs=` and `-max_total_time=` bound the minimizer, so they must
+            // not be overridden by the custom target options.
+            Some(&|arg: String| {
+                if arg.starts_with("-runs=") || arg.starts_with("-max_total_time=") {
+                    None
+                } else {
+                    Some(arg)
+                }
+            }),
+            &mounts.iter().map(PathBuf::as_path).collect::<Vec<_>>(),
+        )?;
+
+        info!("Running command: {:?}", &cmd);
+
+        let child = cmd
+            .spawn()
+            .with_context(|| format_err!("libfuzzer failed to start: {}", self.exe.display()))?;
+
+        // The child is killed when dropped on timeout.
+        let result =
+            match tokio::time::timeout(timeout + MINIMIZE_GRACE_PERIOD, child.wait_with_output())
+                .await
+            {
+                Ok(result) => result.with_context(|| {
+                    format_err!("libfuzzer failed to run: {}", self.exe.display())
+                })?,
+                Err(_) => bail!(
+                    "libFuzzer timed out minimizing crash {} after {:?}",
+                    test_input.display(),
+                    timeout
+                ),
+            };
+
+        if tokio::fs::metadata(output).await.is_err() {
+            let limit_kill = sandbox.limit_kill().await;
+            bail!(
+                "libFuzzer did not minimize crash {}: exit_code: {:?} limit_kill: {:?} stderr:{:?}",
+                test_input.display(),
+                result.status,
+                limit_kill,
+                String::from_utf8_lossy(&result.stderr),
+            );
+        }
+
+        Ok(())
+    }
+
     pub async fn merge(
         &self,
         corpus_dir: impl AsRef<Path>,
         extra_corpus_dirs: &[impl AsRef<Path>],
     ) -> Result<LibFuzzerMergeOutput> {
         let extra_corpus_dirs: Vec<&Path> = extra_corpus_dirs.iter().map(|x| x.as_ref()).collect();
-        let mut cmd = self.build_command(
+        let (mut cmd, sandbox) = self.build_command(
             None,
             Some(corpus_dir.as_ref()),
             Some(&extra_corpus_dirs),
             Some(&["-merge=1".as_ref()]),
             None,
+            &[],
         )?;
 
         let output = cmd
@@ -422,6 +576,10 @@ impl LibFuzzer {
             .await
             .with_context(|| format_err!("libfuzzer failed to run: {}", self.exe.display()))?;
 
+        if let Some(limit_kill) = sandbox.limit_kill().await {
+            bail!("libfuzzer merge was killed by its sandbox: {}", limit_kill);
+        }
+
         let output_text = String::from_utf8_lossy(&output.stderr);
         let pat = r"MERGE-OUTER: (\d+) new files with (\d+) new features added";
         let re = regex::Regex::new(pat).unwrap();
@@ -1,8 +1,10 @@
 // Copyright (c) Microsoft Corporation.
 // Licensed under the MIT License.
 
+use crate::sandbox::Sandbox;
 use anyhow::{Context, Result};
 use process_control::{self, ChildExt, Control};
+use std::fmt;
 use std::path::Path;
 use std::process::Command;
 use std::time::Duration;
@@ -101,11 +103,37 @@ impl From<process_control::ExitStatus> for ExitStatus {
     }
 }
 
+/// Error of a process killed for not exiting within its timeout.
+#[derive(Debug)]
+pub struct TimedOut;
+
+impl fmt::Display for TimedOut {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "process timed out")
+    }
+}
+
+impl std::error::Error for TimedOut {}
+
 pub async fn run_cmd<S: ::std::hash::BuildHasher>(
     program: &Path,
     argv: Vec<String>,
     env: &HashMap<String, String, S>,
     timeout: Duration,
+) -> Result<Output> {
+    run_cmd_in_sandbox(program, argv, env, timeout, &Sandbox::default(), &[]).await
+}
+
+/// Run a command as `run_cmd` does, in `sandbox`, which must not be reused
+/// for other processes, with the host directories `mounts` if it runs in
+/// containers.
+pub async fn run_cmd_in_sandbox<S: ::std::hash::BuildHasher>(
+    program: &Path,
+    argv: Vec<String>,
+    env: &HashMap<String, String, S>,
+    timeout: Duration,
+    sandbox: &Sandbox,
+    mounts: &[&Path],
 ) -> Result<Output> {
     debug!(
         "running command with timeout: cmd:{:?} argv:{:?} env:{:?} timeout:{:?}",
@@ -113,12 +141,12 @@ pub async fn run_cmd<S: ::std::hash::BuildHasher>(
     );
 
     let mut cmd = Command::new(program);
-    cmd.env_remove("RUST_LOG")
-        .stdin(Stdio::null())
+    cmd.env_remove("RUST_LOG").args(argv).envs(env);
+
+    let mut cmd = sandbox.command(cmd, mounts)?;
+    cmd.stdin(Stdio::null())
         .stdout(Stdio::piped())
-        .stderr(Stdio::piped())
-        .args(argv)
-        .envs(env);
+        .stderr(Stdio::piped());
 
     // make a stringified version to save in the context of spawn_blocking
     let program_name = program.display().to_string();
@@ -133,7 +161,7 @@ pub async fn run_cmd<S: ::std::hash::BuildHasher>(
             .time_limit(timeout)
             .terminate_for_timeout()
             .wait()?
-            .ok_or_else(|| format_err!("process timed out"))
+            .ok_or_else(|| anyhow::Error::new(TimedOut))
     });
 
     // convert processcontrol::Output into our Output
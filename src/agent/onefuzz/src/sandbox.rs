@@ -0,0 +1,372 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT License.
+
+//! Optional sandboxing of target processes.
+//!
+//! On Linux, each sandboxed target runs in a cgroup of its own, which limits
+//! its memory, processes and CPU time, and optionally in new network and mount
+//! namespaces. The cgroup is nested in that of the caller, so the target also
+//! counts towards the limits of its task. Sandboxing is not supported on other
+//! platforms, where targets run unconfined.
+//!
+//! On any platform, targets may instead run in containers of an OCI image,
+//! which are then given the limits of the sandbox.
+
+pub mod container;
+
+use std::fmt;
+use std::path::Path;
+use std::process::Command;
+
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+
+use self::container::{ContainerConfig, Containers};
+#[cfg(target_os = "linux")]
+use crate::cgroup::Cgroup;
+
+#[cfg(target_os = "linux")]
+const BYTES_PER_MB: u64 = 1_000_000;
+
+#[cfg(target_os = "linux")]
+const CPU_PERIOD_US: u64 = 100_000;
+
+#[derive(Clone, Debug, Default, Deserialize, Serialize, PartialEq, Eq)]
+pub struct SandboxConfig {
+    /// Memory limit of the target, past which it is killed.
+    #[serde(default)]
+    pub memory_limit_mb: Option<u64>,
+
+    /// Maximum number of processes and threads of the target.
+    #[serde(default)]
+    pub pids_limit: Option<u64>,
+
+    /// CPU time the target may use, in percent of a core, such as `200` for
+    /// two cores.
+    #[serde(default)]
+    pub cpu_limit_percent: Option<u64>,
+
+    /// Run the target in a new network namespace, without network access.
+    #[serde(default)]
+    pub isolate_network: bool,
+
+    /// Run the target in a new mount namespace, so its mounts are private.
+    #[serde(default)]
+    pub isolate_mounts: bool,
+
+    /// Run the target in containers of an OCI image, instead of directly on
+    /// the node. Only applies to targets run by the agent, not supervisors.
+    #[serde(default)]
+    pub container: Option<ContainerConfig>,
+}
+
+impl SandboxConfig {
+    pub fn is_enabled(&self) -> bool {
+        self.has_limits() || self.isolate_network || self.isolate_mounts || self.container.is_some()
+    }
+
+    fn has_limits(&self) -> bool {
+        self.memory_limit_mb.is_some()
+            || self.pids_limit.is_some()
+            || self.cpu_limit_percent.is_some()
+    }
+}
+
+/// Limit which caused a target to be killed.
+#[derive(Clone, Copy, Debug, Deserialize, Serialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum LimitKill {
+    OutOfMemory,
+    Timeout,
+}
+
+impl fmt::Display for LimitKill {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::OutOfMemory => write!(f, "out of memory"),
+            Self::Timeout => write!(f, "timed out"),
+        }
+    }
+}
+
+impl std::error::Error for LimitKill {}
+
+/// Sandbox of a single target process.
+///
+/// The sandbox must outlive the process, as dropping it kills any of its
+/// processes which are left, and removes its cgroup or containers.
+#[derive(Debug, Default)]
+pub struct Sandbox {
+    containers: Option<Containers>,
+
+    #[cfg(target_os = "linux")]
+    cgroup: Option<Cgroup>,
+
+    #[cfg(target_os = "linux")]
+    isolate_network: bool,
+
+    #[cfg(target_os = "linux")]
+    isolate_mounts: bool,
+}
+
+impl Sandbox {
+    #[cfg(target_os = "linux")]
+    pub fn new(config: &SandboxConfig) -> Result<Self> {
+        if let Some(container) = &config.container {
+            return Ok(Self::in_container(config, container));
+        }
+
+        let mut sandbox = Self {
+            containers: None,
+            cgroup: None,
+            isolate_network: config.isolate_network,
+            isolate_mounts: config.isolate_mounts,
+        };
+
+        if !config.has_limits() {
+            return Ok(sandbox);
+        }
+
+        if !Cgroup::is_supported() {
+            bail!("unable to sandbox target: cgroup v2 not mounted");
+        }
+
+        let mut controllers = vec![];
+        if config.memory_limit_mb.is_some() {
+            controllers.push("memory");
+        }
+        if config.pids_limit.is_some() {
+            controllers.push("pids");
+        }
+        if config.cpu_limit_percent.is_some() {
+            controllers.push("cpu");
+        }
+
+        // Owned by the sandbox first, so it is removed if a limit is invalid.
+        // Created under the cgroup of the caller, such as that of its task, so
+        // that the target is also within the limits of the caller.
+        let name = format!("target-{}", uuid::Uuid::new_v4());
+        let parent = Cgroup::for_children()?;
+        let cgroup = sandbox
+            .cgroup
+            .insert(parent.create_child(&name, &controllers)?);
+
+        if let Some(memory_limit_mb) = config.memory_limit_mb {
+            cgroup.set_memory_max(memory_limit_mb * BYTES_PER_MB)?;
+        }
+        if let Some(pids_limit) = config.pids_limit {
+            cgroup.set_pids_max(pids_limit)?;
+        }
+        if let Some(cpu_limit_percent) = config.cpu_limit_percent {
+            let quota_us = cpu_limit_percent * CPU_PERIOD_US / 100;
+            cgroup.set_cpu_max(quota_us, CPU_PERIOD_US)?;
+        }
+
+        Ok(sandbox)
+    }
+
+    #[cfg(not(target_os = "linux"))]
+    pub fn new(config: &SandboxConfig) -> Result<Self> {
+        if let Some(container) = &config.container {
+            return Ok(Self::in_container(config, container));
+        }
+
+        if config.is_enabled() {
+            warn!("sandboxing targets is only supported on Linux, running unconfined");
+        }
+
+        Ok(Self::default())
+    }
+
+    fn in_container(config: &SandboxConfig, container: &ContainerConfig) -> Self {
+        // Limits are enforced by the container runtime instead.
+        Self {
+            containers: Some(Containers::new(config, container)),
+            #[cfg(target_os = "linux")]
+            cgroup: None,
+            #[cfg(target_os = "linux")]
+            isolate_network: false,
+            #[cfg(target_os = "linux")]
+            isolate_mounts: false,
+        }
+    }
+
+    /// Command running `target` in the sandbox.
+    ///
+    /// When running in containers, `mounts` are the host directories used by
+    /// the target, and stdio must be set on the returned command.
+    pub fn command(&self, mut target: Command, mounts: &[&Path]) -> Result<Command> {
+        match &self.containers {
+            Some(containers) => containers.command(&target, mounts),
+            None => {
+                self.apply(&mut target)?;
+                Ok(target)
+            }
+        }
+    }
+
+    /// Whether targets run in containers, rather than directly on the node.
+    pub fn in_containers(&self) -> bool {
+        self.containers.is_some()
+    }
+
+    /// Run the process spawned by `cmd` in the sandbox, ignoring containers.
+    #[cfg(target_os = "linux")]
+    pub fn apply(&self, cmd: &mut Command) -> Result<()> {
+        use nix::mount::{mount, MsFlags};
+        use nix::sched::{unshare, CloneFlags};
+        use std::io::Write;
+        use std::os::unix::process::CommandExt;
+
+        let procs = self.cgroup.as_ref().map(Cgroup::open_procs).transpose()?;
+
+        let mut namespaces = CloneFlags::empty();
+        if self.isolate_network {
+            namespaces |= CloneFlags::CLONE_NEWNET;
+        }
+        if self.isolate_mounts {
+            namespaces |= CloneFlags::CLONE_NEWNS;
+        }
+
+        if procs.is_none() && namespaces.is_empty() {
+            return Ok(());
+        }
+
+        // Safety: only makes system calls, without allocating.
+        unsafe {
+            cmd.pre_exec(move || {
+                if let Some(mut procs) = procs.as_ref() {
+                    procs.write_all(b"0")?;
+                }
+
+                if !namespaces.is_empty() {
+                    unshare(namespaces)?;
+                }
+
+                // Keep mounts made by the target from propagating to the host.
+                if namespaces.contains(CloneFlags::CLONE_NEWNS) {
+                    mount::<str, str, str, str>(
+                        None,
+                        "/",
+                        None,
+                        MsFlags::MS_REC | MsFlags::MS_PRIVATE,
+                        None,
+                    )?;
+                }
+
+                Ok(())
+            });
+        }
+
+        Ok(())
+    }
+
+    #[cfg(not(target_os = "linux"))]
+    pub fn apply(&self, _cmd: &mut Command) -> Result<()> {
+        Ok(())
+    }
+
+    /// The limit which killed a process of the sandbox, if any.
+    ///
+    /// Timeouts are enforced by the callers running the target, so are not
+    /// reported here.
+    #[cfg(target_os = "linux")]
+    pub async fn limit_kill(&self) -> Option<LimitKill> {
+        if let Some(containers) = &self.containers {
+            return containers
+                .oom_killed()
+                .await
+                .then_some(LimitKill::OutOfMemory);
+        }
+
+        let cgroup = self.cgroup.as_ref()?;
+
+        match cgroup.oom_kills() {
+            Ok(0) => None,
+            Ok(_) => Some(LimitKill::OutOfMemory),
+            // The memory controller is only enabled with a memory limit.
+            Err(_) => None,
+        }
+    }
+
+    #[cfg(not(target_os = "linux"))]
+    pub async fn limit_kill(&self) -> Option<LimitKill> {
+        let containers = self.containers.as_ref()?;
+        containers
+            .oom_killed()
+            .await
+            .then_some(LimitKill::OutOfMemory)
+    }
+}
+
+#[cfg(target_os = "linux")]
+impl Drop for Sandbox {
+    fn drop(&mut self) {
+        let Some(cgroup) = &self.cgroup else {
+            return;
+        };
+
+        if let Err(err) = cgroup.kill() {
+            debug!("unable to kill processes of sandbox: {:?}", err);
+        }
+
+        if let Err(err) = cgroup.remove() {
+            warn!("unable to remove sandbox: {:?}", err);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_sandbox_config_defaults() {
+        let config: SandboxConfig = serde_json::from_str("{}").unwrap();
+        assert_eq!(config, SandboxConfig::default());
+        assert!(!config.is_enabled());
+
+        let config: SandboxConfig =
+            serde_json::from_str(r#"{"memory_limit_mb": 1024, "isolate_network": true}"#).unwrap();
+        assert_eq!(config.memory_limit_mb, Some(1024));
+        assert!(config.is_enabled());
+    }
+
+    #[tokio::test]
+    async fn test_disabled_sandbox() {
+        let sandbox = Sandbox::new(&SandboxConfig::default()).unwrap();
+
+        let mut cmd = Command::new("true");
+        sandbox.apply(&mut cmd).unwrap();
+        assert_eq!(sandbox.limit_kill().await, None);
+        assert!(!sandbox.in_containers());
+
+        let cmd = sandbox.command(cmd, &[]).unwrap();
+        assert_eq!(cmd.get_program(), "true");
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn test_apply_moves_target_to_cgroup() {
+        let dir = tempfile::tempdir().unwrap();
+        let procs = dir.path().join("cgroup.procs");
+        std::fs::write(&procs, "").unwrap();
+
+        let mut sandbox = Sandbox {
+            containers: None,
+            cgroup: Some(Cgroup::from_path(dir.path())),
+            isolate_network: false,
+            isolate_mounts: false,
+        };
+
+        let mut cmd = Command::new("true");
+        sandbox.apply(&mut cmd).unwrap();
+        assert!(cmd.status().unwrap().success());
+
+        // Written by the target before it is run.
+        assert_eq!(std::fs::read_to_string(&procs).unwrap(), "0");
+
+        // Not a real cgroup, so there is nothing to remove.
+        sandbox.cgroup.take();
+    }
+}
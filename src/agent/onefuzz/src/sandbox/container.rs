@@ -0,0 +1,381 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT License.
+
+//! Running targets in OCI container images, using a container runtime with a
+//! Docker-compatible CLI, such as `docker` or `podman`.
+//!
+//! Host directories used by a target are bind-mounted at the same paths in
+//! its container, so paths expanded from placeholders such as `{input}` or
+//! `{setup_dir}` are valid both on the host and in the container.
+
+use std::collections::BTreeSet;
+use std::ffi::OsString;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+use std::sync::Mutex;
+
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use tempfile::{NamedTempFile, TempPath};
+
+use super::SandboxConfig;
+use crate::expand::Expand;
+
+const BYTES_PER_MB: u64 = 1_000_000;
+
+fn default_runtime() -> String {
+    "docker".to_owned()
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize, PartialEq, Eq)]
+pub struct ContainerConfig {
+    /// Image the target runs in, such as `localhost:5000/target:latest`.
+    pub image: String,
+
+    /// Path of an archive of the image, such as `{setup_dir}/image.tar`,
+    /// loaded instead of pulling the image from its registry.
+    #[serde(default)]
+    pub image_archive: Option<String>,
+
+    /// Container runtime, which must have a Docker-compatible CLI.
+    #[serde(default = "default_runtime")]
+    pub runtime: String,
+}
+
+impl ContainerConfig {
+    /// Make the image available to the container runtime, by loading its
+    /// archive if set, or else by pulling it if it is not already present.
+    pub async fn prepare(&self, expand: &Expand<'_>) -> Result<()> {
+        if let Some(image_archive) = &self.image_archive {
+            let image_archive = expand.evaluate_value(image_archive)?;
+            info!("loading container image from {}", image_archive);
+            return self.run_runtime(&["load", "--input", &image_archive]).await;
+        }
+
+        let inspect = tokio::process::Command::new(&self.runtime)
+            .args(["image", "inspect", self.image.as_str()])
+            .stdin(Stdio::null())
+            .output()
+            .await
+            .with_context(|| format!("unable to run container runtime: {}", self.runtime))?;
+        if inspect.status.success() {
+            return Ok(());
+        }
+
+        info!("pulling container image {}", self.image);
+        self.run_runtime(&["pull", &self.image]).await
+    }
+
+    async fn run_runtime(&self, args: &[&str]) -> Result<()> {
+        let output = tokio::process::Command::new(&self.runtime)
+            .args(args)
+            .stdin(Stdio::null())
+            .output()
+            .await
+            .with_context(|| format!("unable to run container runtime: {}", self.runtime))?;
+
+        if !output.status.success() {
+            bail!(
+                "container runtime failed: {} {}: {}",
+                self.runtime,
+                args.join(" "),
+                String::from_utf8_lossy(&output.stderr)
+            );
+        }
+
+        Ok(())
+    }
+}
+
+/// Containers of a sandbox, all of which are removed when it is dropped.
+#[derive(Debug)]
+pub(crate) struct Containers {
+    runtime: String,
+    image: String,
+    options: Vec<OsString>,
+    names: Mutex<Vec<String>>,
+
+    // Removed when dropped, along with the containers.
+    env_files: Mutex<Vec<TempPath>>,
+}
+
+impl Containers {
+    pub(crate) fn new(config: &SandboxConfig, container: &ContainerConfig) -> Self {
+        let mut options: Vec<OsString> = vec![];
+        if let Some(memory_limit_mb) = config.memory_limit_mb {
+            // Without swap, so the limit is enforced like that of a cgroup.
+            let memory = (memory_limit_mb * BYTES_PER_MB).to_string();
+            options.extend(["--memory".into(), memory.clone().into()]);
+            options.extend(["--memory-swap".into(), memory.into()]);
+        }
+        if let Some(pids_limit) = config.pids_limit {
+            options.extend(["--pids-limit".into(), pids_limit.to_string().into()]);
+        }
+        if let Some(cpu_limit_percent) = config.cpu_limit_percent {
+            let cpus = format!("{}.{:02}", cpu_limit_percent / 100, cpu_limit_percent % 100);
+            options.extend(["--cpus".into(), cpus.into()]);
+        }
+        if config.isolate_network {
+            options.extend(["--network".into(), "none".into()]);
+        }
+
+        Self {
+            runtime: container.runtime.clone(),
+            image: container.image.clone(),
+            options,
+            names: Mutex::new(vec![]),
+            env_files: Mutex::new(vec![]),
+        }
+    }
+
+    /// Wrap `target` in a command running it in a new container.
+    ///
+    /// The current directory is mounted along with `mounts`, which must
+    /// include the directories of any other paths used by the target, such
+    /// as that of its input. Only the program, arguments and environment of `target` are kept, and
+    /// of its environment, only the variables set for it, not those of the
+    /// node.
+    pub(crate) fn command(&self, target: &Command, mounts: &[&Path]) -> Result<Command> {
+        let current_dir = std::env::current_dir()?;
+
+        let mut dirs = BTreeSet::new();
+        dirs.insert(current_dir.clone());
+        dirs.extend(mounts.iter().map(|mount| current_dir.join(mount)));
+
+        let name = format!("onefuzz-target-{}", uuid::Uuid::new_v4());
+
+        let mut cmd = Command::new(&self.runtime);
+        cmd.args(["run", "--interactive", "--init", "--name", name.as_str()])
+            .arg("--workdir")
+            .arg(&current_dir)
+            .args(&self.options);
+
+        for dir in dirs {
+            cmd.arg("--volume").arg(volume(&dir));
+        }
+
+        // In a file rather than in arguments, which are visible to all users
+        // of the node. It is read by the runtime, so it is not mounted.
+        let env_file = env_file(target)?;
+        cmd.arg("--env-file").arg(&env_file);
+
+        cmd.arg("--entrypoint")
+            .arg(target.get_program())
+            .arg(&self.image)
+            .args(target.get_args());
+
+        self.names
+            .lock()
+            .map_err(|_| format_err!("container names lock poisoned"))?
+            .push(name);
+        self.env_files
+            .lock()
+            .map_err(|_| format_err!("container env files lock poisoned"))?
+            .push(env_file);
+
+        Ok(cmd)
+    }
+
+    /// Whether any container was killed for running out of memory.
+    pub(crate) async fn oom_killed(&self) -> bool {
+        // Not locked while inspecting the containers.
+        let names = match self.names.lock() {
+            Ok(names) => names.clone(),
+            Err(_) => return false,
+        };
+
+        for name in names {
+            let output = tokio::process::Command::new(&self.runtime)
+                .args(["inspect", "--format", "{{.State.OOMKilled}}", name.as_str()])
+                .stdin(Stdio::null())
+                .output()
+                .await;
+
+            if let Ok(output) = output {
+                if String::from_utf8_lossy(&output.stdout).trim() == "true" {
+                    return true;
+                }
+            }
+        }
+
+        false
+    }
+}
+
+impl Drop for Containers {
+    fn drop(&mut self) {
+        let names = match self.names.get_mut() {
+            Ok(names) => names,
+            Err(poisoned) => poisoned.into_inner(),
+        };
+
+        if names.is_empty() {
+            return;
+        }
+
+        // Also kills containers left running, such as those of timed out
+        // targets, as killing the runtime client does not stop them.
+        let result = Command::new(&self.runtime)
+            .args(["rm", "--force"])
+            .args(names.iter())
+            .stdin(Stdio::null())
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .status();
+
+        if let Err(err) = result {
+            warn!("unable to remove target containers: {:?}", err);
+        }
+    }
+}
+
+// Write the variables set for `target` to an env file of the runtime.
+fn env_file(target: &Command) -> Result<TempPath> {
+    let mut file = NamedTempFile::new().context("unable to create container env file")?;
+
+    for (key, value) in target.get_envs() {
+        // Variables removed from `target` are not set in the image anyway.
+        let Some(value) = value else {
+            continue;
+        };
+
+        let (Some(key), Some(value)) = (key.to_str(), value.to_str()) else {
+            bail!("environment variable is not valid UTF-8: {:?}", key);
+        };
+
+        // Env files have a variable per line, without any quoting.
+        if value.contains('\n') {
+            bail!("environment variable has multiple lines: {}", key);
+        }
+
+        writeln!(file, "{key}={value}")?;
+    }
+
+    Ok(file.into_temp_path())
+}
+
+fn volume(dir: &Path) -> OsString {
+    let mut volume = dir.as_os_str().to_owned();
+    volume.push(":");
+    volume.push(dir);
+    volume
+}
+
+/// Absolute path of the directory containing `path`, to mount it.
+pub(crate) fn parent_dir(path: &Path) -> Option<PathBuf> {
+    std::env::current_dir()
+        .ok()?
+        .join(path)
+        .parent()
+        .map(Path::to_owned)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_container_command() {
+        let config = SandboxConfig {
+            memory_limit_mb: Some(1024),
+            isolate_network: true,
+            ..Default::default()
+        };
+        let container: ContainerConfig =
+            serde_json::from_str(r#"{"image": "localhost:5000/target:latest"}"#).unwrap();
+        assert_eq!(container.runtime, "docker");
+
+        let containers = Containers::new(&config, &container);
+
+        let mut target = Command::new("/setup/fuzz.exe");
+        target
+            .arg("/setup/input")
+            .env("ASAN_OPTIONS", "detect_leaks=0");
+        let cmd = containers.command(&target, &[Path::new("/setup")]).unwrap();
+
+        assert_eq!(cmd.get_program(), "docker");
+        let args: Vec<_> = cmd.get_args().map(|a| a.to_string_lossy()).collect();
+        assert_eq!(args[0], "run");
+        assert!(args.windows(2).any(|a| a == ["--volume", "/setup:/setup"]));
+        let temp_dir = volume(&std::env::temp_dir());
+        assert!(!cmd.get_args().any(|a| a == temp_dir));
+        let env_file = args
+            .windows(2)
+            .find(|a| a[0] == "--env-file")
+            .map(|a| a[1].to_string())
+            .unwrap();
+        assert_eq!(
+            std::fs::read_to_string(env_file).unwrap(),
+            "ASAN_OPTIONS=detect_leaks=0\n"
+        );
+        assert!(args.windows(2).any(|a| a == ["--network", "none"]));
+        assert!(args.windows(2).any(|a| a == ["--memory", "1024000000"]));
+        assert_eq!(
+            args[args.len() - 4..],
+            [
+                "--entrypoint",
+                "/setup/fuzz.exe",
+                "localhost:5000/target:latest",
+                "/setup/input"
+            ]
+        );
+
+        // Not run, so there is nothing to remove.
+        containers.names.lock().unwrap().clear();
+    }
+
+    #[test]
+    fn test_container_env_file_rejects_multiple_lines() {
+        let containers = Containers::new(
+            &SandboxConfig::default(),
+            &serde_json::from_str(r#"{"image": "target"}"#).unwrap(),
+        );
+
+        let mut target = Command::new("/setup/fuzz.exe");
+        target.env("OPTIONS", "a\nb");
+        assert!(containers.command(&target, &[]).is_err());
+    }
+
+    #[tokio::test]
+    #[ignore = "requires docker, and pulls the busybox image"]
+    async fn test_run_in_container() -> Result<()> {
+        let config = SandboxConfig {
+            memory_limit_mb: Some(64),
+            isolate_network: true,
+            ..Default::default()
+        };
+        let container: ContainerConfig =
+            serde_json::from_str(r#"{"image": "docker.io/library/busybox:latest"}"#)?;
+
+        let machine_identity = crate::machine_id::MachineIdentity {
+            machine_id: uuid::Uuid::new_v4(),
+            machine_name: "test".to_owned(),
+            scaleset_name: None,
+        };
+        container.prepare(&Expand::new(&machine_identity)).await?;
+
+        let dir = tempfile::tempdir()?;
+        let input = dir.path().join("input");
+        std::fs::write(&input, "hello")?;
+
+        let containers = Containers::new(&config, &container);
+        let mut target = Command::new("/bin/sh");
+        target
+            .arg("-c")
+            .arg(r#"cat "$1" && echo " $GREETING""#)
+            .arg("sh")
+            .arg(&input)
+            .env("GREETING", "world");
+        let output = containers
+            .command(&target, &[dir.path()])?
+            .stdin(Stdio::null())
+            .output()?;
+
+        assert!(output.status.success(), "{output:?}");
+        assert_eq!(String::from_utf8_lossy(&output.stdout), "hello world\n");
+        assert!(!containers.oom_killed().await);
+
+        Ok(())
+    }
+}
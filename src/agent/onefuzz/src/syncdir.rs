@@ -1,6 +1,9 @@
 // Copyright (c) Microsoft Corporation.
 // Licensed under the MIT License.
 
+pub mod manifest;
+pub mod retention;
+
 use crate::{
     az_copy,
     blob::{BlobClient, BlobContainerUrl},
@@ -17,16 +20,36 @@ use reqwest::{StatusCode, Url};
 use reqwest_retry::{RetryCheck, SendRetry, DEFAULT_RETRY_PERIOD, MAX_RETRY_ATTEMPTS};
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
-use std::{env::current_dir, path::PathBuf, str, time::Duration};
+use std::{
+    env::current_dir,
+    path::PathBuf,
+    str,
+    time::{Duration, SystemTime},
+};
 use tokio::{fs, select};
 use tokio_util::sync::CancellationToken;
 
+use self::manifest::{SyncStats, PARTIAL_FILE_PREFIX};
+use self::retention::{Eviction, RetentionPolicy};
+
 #[derive(Debug, Clone, Copy)]
 pub enum SyncOperation {
     Push,
     Pull,
 }
 
+#[derive(Debug, Default, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum SyncMode {
+    /// Sync the whole directory each time, comparing the files of both sides.
+    /// Local remotes are synced using `rsync` or `robocopy`.
+    #[default]
+    Full,
+    /// Only transfer the files which changed since the last sync, as recorded
+    /// in a manifest of the SHA-256 digests of the files.
+    Manifest,
+}
+
 const DELAY: Duration = Duration::from_secs(10);
 const DEFAULT_CONTINUOUS_SYNC_DELAY_SECONDS: u64 = 60;
 
@@ -36,6 +59,10 @@ pub struct SyncedDir {
     pub local_path: PathBuf,
     #[serde(alias = "remote_path", alias = "url")]
     pub remote_path: Option<BlobContainerUrl>,
+    #[serde(default)]
+    pub sync_mode: SyncMode,
+    #[serde(default)]
+    pub retention: RetentionPolicy,
 }
 
 impl SyncedDir {
@@ -69,12 +96,35 @@ impl SyncedDir {
         Ok(url)
     }
 
-    pub async fn sync(&self, operation: SyncOperation, delete_dst: bool) -> Result<()> {
+    /// Sync the directory with its remote.
+    ///
+    /// Only syncs using a manifest count the files they transferred, so the
+    /// stats of other syncs are empty.
+    pub async fn sync(&self, operation: SyncOperation, delete_dst: bool) -> Result<SyncStats> {
+        if let SyncOperation::Pull = operation {
+            retention::record_pull(&self.local_path);
+        }
+
+        // `az_copy` only supports Azure Blob Storage, so S3 buckets are always
+        // synced using a manifest
+        let is_s3 = matches!(self.remote_path, Some(BlobContainerUrl::S3(_)));
+
+        if self.sync_mode == SyncMode::Manifest || is_s3 {
+            let stats = self.sync_manifest(operation, delete_dst).await?;
+            debug!(
+                "synced {:?} {}: {:?}",
+                operation,
+                self.local_path.display(),
+                stats
+            );
+            return Ok(stats);
+        }
+
         let dir = &self.local_path.join("");
 
         if let Some(dest) = self.remote_path.clone().and_then(|u| u.as_file_path()) {
             debug!("syncing {:?} {}", operation, dest.display());
-            match operation {
+            let result = match operation {
                 SyncOperation::Push => {
                     sync(
                         SyncPath::dir(dir),
@@ -91,24 +141,53 @@ impl SyncedDir {
                     )
                     .await
                 }
-            }
+            };
+            result.map(|_| SyncStats::default())
         } else if let Some(url) = self.remote_path.clone().and_then(|u| u.url().ok()) {
             let url = url.as_ref();
             debug!("syncing {:?} {}", operation, dir.display());
-            match operation {
-                SyncOperation::Push => az_copy::sync(dir, url, delete_dst).await.context(format!(
-                    "Failed sync push from {} to {}",
-                    dir.display(),
-                    url
-                )),
-                SyncOperation::Pull => az_copy::sync(url, dir, delete_dst).await.context(format!(
-                    "Failed sync pull from {} to {}",
-                    url,
-                    dir.display()
-                )),
-            }
+            let result =
+                match operation {
+                    SyncOperation::Push => az_copy::sync(dir, url, delete_dst).await.context(
+                        format!("Failed sync push from {} to {}", dir.display(), url),
+                    ),
+                    SyncOperation::Pull => az_copy::sync(url, dir, delete_dst).await.context(
+                        format!("Failed sync pull from {} to {}", url, dir.display()),
+                    ),
+                };
+            result.map(|_| SyncStats::default())
         } else {
-            Ok(())
+            Ok(SyncStats::default())
+        }
+    }
+
+    /// Sync only the files which were added, changed or removed since the last
+    /// sync, regardless of `sync_mode`.
+    ///
+    /// An interrupted sync resumes where it left off, including any partially
+    /// downloaded files.
+    pub async fn sync_manifest(
+        &self,
+        operation: SyncOperation,
+        delete_dst: bool,
+    ) -> Result<SyncStats> {
+        if let SyncOperation::Pull = operation {
+            retention::record_pull(&self.local_path);
+        }
+
+        match &self.remote_path {
+            Some(remote_path) => {
+                manifest::sync(&self.local_path, remote_path, operation, delete_dst)
+                    .await
+                    .with_context(|| {
+                        format!(
+                            "manifest sync {:?} failed: {}",
+                            operation,
+                            self.local_path.display()
+                        )
+                    })
+            }
+            None => Ok(SyncStats::default()),
         }
     }
 
@@ -120,7 +199,8 @@ impl SyncedDir {
         self.init().await.context("init failed")?;
         self.sync(SyncOperation::Pull, false)
             .await
-            .context("pull failed")
+            .context("pull failed")?;
+        Ok(())
     }
 
     pub async fn init(&self) -> Result<()> {
@@ -153,29 +233,59 @@ impl SyncedDir {
     pub async fn sync_pull(&self) -> Result<()> {
         self.sync(SyncOperation::Pull, false)
             .await
-            .context("sync pull failed")
+            .context("sync pull failed")?;
+        Ok(())
     }
 
     pub async fn sync_push(&self) -> Result<()> {
         self.sync(SyncOperation::Push, false)
             .await
-            .context("sync push failed")
+            .context("sync push failed")?;
+        Ok(())
     }
 
+    /// Evict the local files beyond the limits of the retention policy.
+    pub async fn enforce_retention(&self) -> Result<Vec<Eviction>> {
+        self.retention.enforce(&self.local_path).await
+    }
+
+    // Files are only evicted once pushed, and only those modified before the
+    // push started, which it included.
+    async fn enforce_retention_after(&self, operation: SyncOperation, started: SystemTime) {
+        if let SyncOperation::Push = operation {
+            let result = self
+                .retention
+                .enforce_pushed(&self.local_path, |_, modified| modified < started)
+                .await;
+            if let Err(err) = result {
+                warn!(
+                    "unable to enforce retention policy of {}: {:?}",
+                    self.local_path.display(),
+                    err
+                );
+            }
+        }
+    }
+
+    /// Sync the directory until cancelled, returning the total stats of its
+    /// syncs.
     pub async fn continuous_sync(
         &self,
         operation: SyncOperation,
         delay_seconds: Option<u64>,
         cancellation_token: &CancellationToken,
-    ) -> Result<()> {
+    ) -> Result<SyncStats> {
+        let mut stats = SyncStats::default();
         let delay_seconds = delay_seconds.unwrap_or(DEFAULT_CONTINUOUS_SYNC_DELAY_SECONDS);
         if delay_seconds == 0 {
-            return Ok(());
+            return Ok(stats);
         }
         let delay = Duration::from_secs(delay_seconds);
 
         loop {
-            self.sync(operation, false).await?;
+            let started = SystemTime::now();
+            stats += self.sync(operation, false).await?;
+            self.enforce_retention_after(operation, started).await;
             select! {
                 _ = cancellation_token.cancelled() => {
                     break;
@@ -186,7 +296,7 @@ impl SyncedDir {
             }
         }
 
-        Ok(())
+        Ok(stats)
     }
 
     // Conditionally upload a report, if it would not be a duplicate.
@@ -207,14 +317,17 @@ impl SyncedDir {
                     let url = url.blob(name).url();
                     let blob = BlobClient::new();
                     let result = blob
-                        .put(url.clone())
+                        .put(url.clone())?
                         .json(data)
                         // Conditional PUT, only if-not-exists.
                         // https://docs.microsoft.com/en-us/rest/api/storageservices/specifying-conditional-headers-for-blob-service-operations
                         .header("If-None-Match", "*")
                         .send_retry(
                             |code| match code {
-                                StatusCode::CONFLICT => RetryCheck::Succeed,
+                                // S3 fails with 412 Precondition Failed instead
+                                StatusCode::CONFLICT | StatusCode::PRECONDITION_FAILED => {
+                                    RetryCheck::Succeed
+                                }
                                 _ => RetryCheck::Retry,
                             },
                             DEFAULT_RETRY_PERIOD,
@@ -223,7 +336,8 @@ impl SyncedDir {
                         .await
                         .context("SyncedDir.upload")?;
 
-                    Ok(result.status() == StatusCode::CREATED)
+                    // 201 Created for Azure, and 200 OK for S3
+                    Ok(result.status().is_success())
                 }
             }
         } else {
@@ -243,11 +357,13 @@ impl SyncedDir {
         url: BlobContainerUrl,
         event: Event,
         ignore_dotfiles: bool,
+        retention: &RetentionPolicy,
         jr_client: &Option<TaskJobResultClient>,
     ) -> Result<()> {
         debug!("monitoring {}", path.display());
 
         let mut monitor = DirectoryMonitor::new(path.clone()).await?;
+        let mut enforcer = retention.enforcer(&path);
 
         if let Some(path) = url.as_file_path() {
             fs::create_dir_all(&path).await?;
@@ -265,6 +381,11 @@ impl SyncedDir {
                     continue;
                 }
 
+                // and those of manifest syncs
+                if file_name_event_str.starts_with(PARTIAL_FILE_PREFIX) {
+                    continue;
+                }
+
                 if ignore_dotfiles && file_name_event_str.starts_with('.') {
                     continue;
                 }
@@ -315,6 +436,8 @@ impl SyncedDir {
                     }
                     bail!("{}", error_message);
                 }
+
+                enforcer.tick(&item).await;
             }
         } else {
             let mut uploader = BlobUploader::new(url.url()?);
@@ -332,6 +455,11 @@ impl SyncedDir {
                     continue;
                 }
 
+                // and those of manifest syncs
+                if file_name_event_str.starts_with(PARTIAL_FILE_PREFIX) {
+                    continue;
+                }
+
                 if ignore_dotfiles && file_name_event_str.starts_with('.') {
                     continue;
                 }
@@ -377,6 +505,8 @@ impl SyncedDir {
                     }
                     bail!("{}", error_message);
                 }
+
+                enforcer.tick(&item).await;
             }
         }
         Ok(())
@@ -416,6 +546,7 @@ impl SyncedDir {
                     url.clone(),
                     event.clone(),
                     ignore_dotfiles,
+                    &self.retention,
                     job_result_client,
                 )
                 .await?;
@@ -440,7 +571,9 @@ pub async fn continuous_sync(
 
     loop {
         for dir in dirs {
+            let started = SystemTime::now();
             dir.sync(operation, false).await?;
+            dir.enforce_retention_after(operation, started).await;
         }
         delay_with_jitter(delay).await;
     }
@@ -448,11 +581,17 @@ pub async fn continuous_sync(
 
 #[cfg(test)]
 mod tests {
-    use super::SyncedDir;
+    use super::{SyncOperation, SyncedDir};
+    use crate::blob::fake_s3::FakeS3;
+    use crate::blob::BlobContainerUrl;
+    use crate::syncdir::manifest::UPLOAD_BLOCK_SIZE;
+    use crate::uploader::BlobUploader;
     use anyhow::{anyhow, Result};
     use dunce::canonicalize;
     use std::env::current_dir;
     use std::path::PathBuf;
+    use tempfile::tempdir;
+    use tokio::fs;
 
     #[test]
     fn test_synceddir_relative_remote_url() -> Result<()> {
@@ -461,6 +600,8 @@ mod tests {
         let dir = SyncedDir {
             local_path: path,
             remote_path: None,
+            sync_mode: Default::default(),
+            retention: Default::default(),
         };
         let blob_path = dir
             .remote_url()?
@@ -469,4 +610,71 @@ mod tests {
         assert_eq!(expected, blob_path);
         Ok(())
     }
+
+    #[tokio::test]
+    async fn test_synceddir_s3() -> Result<()> {
+        let fake = FakeS3::get();
+        let bucket = fake.bucket();
+        let remote_path = BlobContainerUrl::parse(format!("s3://{bucket}"))?;
+
+        // the manifests are kept next to the dirs
+        let dir = tempdir()?;
+        let src = dir.path().join("src");
+        let dst = dir.path().join("dst");
+
+        let pushed = SyncedDir {
+            local_path: src.clone(),
+            remote_path: Some(remote_path.clone()),
+            sync_mode: Default::default(),
+            retention: Default::default(),
+        };
+        // uploaded in parts
+        let large = vec![7u8; UPLOAD_BLOCK_SIZE as usize + 1];
+        fs::create_dir_all(src.join("sub")).await?;
+        fs::write(src.join("a"), "1").await?;
+        fs::write(src.join("b"), "2").await?;
+        fs::write(src.join("sub/large"), &large).await?;
+
+        let stats = pushed.sync(SyncOperation::Push, false).await?;
+        assert_eq!(stats.added, 3);
+        assert_eq!(fake.keys(&bucket), ["a", "b", "sub/large"]);
+        assert_eq!(fake.object(&bucket, "sub/large"), Some(large.clone()));
+
+        // listed over several pages
+        let pulled = SyncedDir {
+            local_path: dst.clone(),
+            ..pushed.clone()
+        };
+        let stats = pulled.sync(SyncOperation::Pull, false).await?;
+        assert_eq!(stats.added, 3);
+        assert_eq!(fs::read(dst.join("sub/large")).await?, large);
+
+        // unchanged files are not sent again
+        let stats = pushed.sync(SyncOperation::Push, false).await?;
+        assert_eq!(stats.unchanged, 3);
+
+        fs::remove_file(src.join("a")).await?;
+        let stats = pushed.sync(SyncOperation::Push, true).await?;
+        assert_eq!(stats.removed, 1);
+        let stats = pulled.sync(SyncOperation::Pull, true).await?;
+        assert_eq!(stats.removed, 1);
+        assert!(!dst.join("a").exists());
+
+        // reports are not replaced
+        assert!(pushed.upload("report.json", &"first").await?);
+        assert!(!pushed.upload("report.json", &"second").await?);
+        assert_eq!(
+            fake.object(&bucket, "report.json").as_deref(),
+            Some(&b"\"first\""[..])
+        );
+
+        let mut uploader = BlobUploader::new(remote_path.url()?);
+        let response = uploader.upload(src.join("b")).await?;
+        assert_eq!(response.status(), reqwest::StatusCode::PRECONDITION_FAILED);
+        fs::write(src.join("c"), "3").await?;
+        uploader.upload(src.join("c")).await?.error_for_status()?;
+        assert_eq!(fake.object(&bucket, "c").as_deref(), Some(&b"3"[..]));
+
+        Ok(())
+    }
 }
@@ -0,0 +1,937 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT License.
+
+//! Manifest-based sync of a `SyncedDir`.
+//!
+//! The manifest records the SHA-256 digest of each file of the local
+//! directory, along with the version of the remote copy of the file when it
+//! was last transferred. Each sync only transfers the files which are new or
+//! have changed since, and records its progress as it goes, so an interrupted
+//! sync picks up where it left off. Large files are uploaded in blocks, so
+//! even the upload of a file which was interrupted is resumed.
+
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use std::time::UNIX_EPOCH;
+
+use anyhow::{Context, Result};
+use futures::StreamExt;
+use serde::{Deserialize, Serialize};
+use tokio::fs;
+use tokio::io::{AsyncReadExt, AsyncSeekExt, AsyncWriteExt};
+
+use crate::blob::client::BlockUpload;
+use crate::blob::{BlobClient, BlobContainerUrl};
+use crate::sha256;
+
+use super::SyncOperation;
+
+/// Prefix of the files being downloaded, which are renamed once complete.
+pub const PARTIAL_FILE_PREFIX: &str = ".onefuzz-partial-";
+
+// Number of transfers after which the manifest is saved, to record progress.
+const SAVE_INTERVAL: usize = 100;
+
+/// Size of the blocks of the files which are uploaded in blocks, which are
+/// those larger than one block. S3 requires parts of at least 5 MiB.
+pub const UPLOAD_BLOCK_SIZE: u64 = 8 * 1024 * 1024;
+
+/// Counts of the files transferred by a sync.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct SyncStats {
+    /// Files which were not at the destination.
+    pub added: usize,
+    /// Files which had changed since they were last transferred.
+    pub updated: usize,
+    /// Files which were deleted from the destination.
+    pub removed: usize,
+    /// Files which were already up to date.
+    pub unchanged: usize,
+}
+
+impl std::ops::AddAssign for SyncStats {
+    fn add_assign(&mut self, other: Self) {
+        self.added += other.added;
+        self.updated += other.updated;
+        self.removed += other.removed;
+        self.unchanged += other.unchanged;
+    }
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Manifest {
+    /// Files of the local directory, by path relative to it.
+    pub files: BTreeMap<String, FileEntry>,
+
+    /// Versions of the remote files whose download was interrupted.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub partial: BTreeMap<String, String>,
+
+    /// Uploads of local files which were interrupted.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub partial_uploads: BTreeMap<String, PartialUpload>,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PartialUpload {
+    /// Digest of the file being uploaded, as the upload is only resumed if
+    /// the file has not changed since.
+    pub sha256: String,
+
+    #[serde(flatten)]
+    pub upload: BlockUpload,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct FileEntry {
+    pub sha256: String,
+    pub size: u64,
+    /// Modification time of the local file, in nanoseconds since the epoch.
+    pub modified: u64,
+    /// Version of the remote copy of the file, such as its ETag, when it was
+    /// last transferred. Not set if the file has yet to be transferred.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub remote_version: Option<String>,
+}
+
+impl Manifest {
+    pub async fn load(path: &Path) -> Result<Self> {
+        if !crate::fs::exists(path).await? {
+            return Ok(Self::default());
+        }
+
+        let data = fs::read(path)
+            .await
+            .with_context(|| format!("unable to read sync manifest: {}", path.display()))?;
+
+        match serde_json::from_slice(&data) {
+            Ok(manifest) => Ok(manifest),
+            Err(err) => {
+                // the files are digested again, but nothing is lost otherwise
+                warn!("ignoring invalid sync manifest {}: {}", path.display(), err);
+                Ok(Self::default())
+            }
+        }
+    }
+
+    pub async fn save(&self, path: &Path) -> Result<()> {
+        // write to a temporary file first, so the manifest is never truncated
+        let tmp = path.with_extension("tmp");
+        fs::write(&tmp, serde_json::to_vec(self)?)
+            .await
+            .with_context(|| format!("unable to write sync manifest: {}", tmp.display()))?;
+        fs::rename(&tmp, path)
+            .await
+            .with_context(|| format!("unable to save sync manifest: {}", path.display()))?;
+        Ok(())
+    }
+
+    /// Scan the files of `dir`.
+    ///
+    /// Files whose size and modification time match those recorded in
+    /// `previous` are not digested again.
+    pub async fn scan(dir: &Path, previous: &Self) -> Result<Self> {
+        let mut files = BTreeMap::new();
+
+        for (name, path) in list_files(dir).await? {
+            let metadata = fs::metadata(&path).await?;
+            let size = metadata.len();
+            let modified = modified_ns(&metadata)?;
+
+            let entry = match previous.files.get(&name) {
+                Some(entry) if entry.size == size && entry.modified == modified => entry.clone(),
+                previous => {
+                    let sha256 = sha256::digest_file(&path).await?;
+                    // content which is unchanged does not have to be sent again
+                    let remote_version = previous
+                        .filter(|x| x.sha256 == sha256)
+                        .and_then(|x| x.remote_version.clone());
+
+                    FileEntry {
+                        sha256,
+                        size,
+                        modified,
+                        remote_version,
+                    }
+                }
+            };
+
+            files.insert(name, entry);
+        }
+
+        // uploads of files which were since removed are not resumed
+        let partial_uploads = previous
+            .partial_uploads
+            .iter()
+            .filter(|(name, _)| files.contains_key(*name))
+            .map(|(name, partial)| (name.clone(), partial.clone()))
+            .collect();
+
+        Ok(Self {
+            files,
+            partial: previous.partial.clone(),
+            partial_uploads,
+        })
+    }
+}
+
+/// Path of the manifest of a local directory, which is kept next to it so it
+/// is not synced along with the files.
+pub fn manifest_path(local_path: &Path) -> Result<PathBuf> {
+    let name = local_path
+        .file_name()
+        .ok_or_else(|| format_err!("invalid SyncedDir path: {}", local_path.display()))?;
+
+    let mut manifest = std::ffi::OsString::from(".");
+    manifest.push(name);
+    manifest.push(".sync-manifest.json");
+
+    Ok(local_path.with_file_name(manifest))
+}
+
+pub async fn sync(
+    local_path: &Path,
+    remote_path: &BlobContainerUrl,
+    operation: SyncOperation,
+    delete_dst: bool,
+) -> Result<SyncStats> {
+    let manifest_path = manifest_path(local_path)?;
+    let remote = Remote::new(remote_path);
+
+    match operation {
+        SyncOperation::Push => push(local_path, &remote, &manifest_path, delete_dst).await,
+        SyncOperation::Pull => pull(local_path, &remote, &manifest_path, delete_dst).await,
+    }
+}
+
+async fn push(
+    local_path: &Path,
+    remote: &Remote,
+    manifest_path: &Path,
+    delete_dst: bool,
+) -> Result<SyncStats> {
+    let previous = Manifest::load(manifest_path).await?;
+    let mut manifest = Manifest::scan(local_path, &previous).await?;
+    let mut stats = SyncStats::default();
+
+    // deleting the remote files which are not local requires knowing them all
+    let remote_files = if delete_dst {
+        Some(remote.list().await?)
+    } else {
+        None
+    };
+
+    let pending: Vec<String> = manifest
+        .files
+        .iter()
+        .filter(|(name, entry)| {
+            let missing = remote_files
+                .as_ref()
+                .map(|remote_files| !remote_files.contains_key(*name))
+                .unwrap_or_default();
+            entry.remote_version.is_none() || missing
+        })
+        .map(|(name, _)| name.clone())
+        .collect();
+    stats.unchanged = manifest.files.len() - pending.len();
+
+    for (count, name) in pending.into_iter().enumerate() {
+        let path = local_path.join(&name);
+        let version = if fs::metadata(&path).await?.len() > UPLOAD_BLOCK_SIZE {
+            upload_in_blocks(remote, &name, &path, &mut manifest, manifest_path).await?
+        } else {
+            remote.upload(&name, &path).await?
+        };
+        if let Some(entry) = manifest.files.get_mut(&name) {
+            entry.remote_version = Some(version);
+        }
+
+        if previous.files.contains_key(&name) {
+            stats.updated += 1;
+        } else {
+            stats.added += 1;
+        }
+
+        if (count + 1) % SAVE_INTERVAL == 0 {
+            manifest.save(manifest_path).await?;
+        }
+    }
+
+    if let Some(remote_files) = remote_files {
+        for name in remote_files.keys() {
+            if !manifest.files.contains_key(name) {
+                remote.delete(name).await?;
+                stats.removed += 1;
+            }
+        }
+    }
+
+    manifest.save(manifest_path).await?;
+    Ok(stats)
+}
+
+// Uploads a large file in blocks, recording each uploaded block in the
+// manifest, so an interrupted upload of the same file is resumed.
+async fn upload_in_blocks(
+    remote: &Remote,
+    name: &str,
+    path: &Path,
+    manifest: &mut Manifest,
+    manifest_path: &Path,
+) -> Result<String> {
+    let sha256 = match manifest.files.get(name) {
+        Some(entry) => entry.sha256.clone(),
+        None => bail!("file missing from sync manifest: {name}"),
+    };
+
+    let partial = manifest
+        .partial_uploads
+        .remove(name)
+        .filter(|partial| partial.sha256 == sha256);
+
+    if let Some(partial) = partial {
+        let result = upload_blocks(remote, name, path, partial, manifest, manifest_path).await;
+
+        // the upload may have expired, such as uncommitted Azure blocks after
+        // a week, so it is started over rather than resumed again
+        match result {
+            Ok(version) => return Ok(version),
+            Err(err) => {
+                warn!("restarting interrupted upload of {}: {:?}", name, err);
+                manifest.partial_uploads.remove(name);
+            }
+        }
+    }
+
+    let partial = PartialUpload {
+        sha256,
+        upload: remote.start_upload(name).await?,
+    };
+    upload_blocks(remote, name, path, partial, manifest, manifest_path).await
+}
+
+async fn upload_blocks(
+    remote: &Remote,
+    name: &str,
+    path: &Path,
+    mut partial: PartialUpload,
+    manifest: &mut Manifest,
+    manifest_path: &Path,
+) -> Result<String> {
+    let mut file = fs::File::open(path)
+        .await
+        .with_context(|| format!("unable to open file: {}", path.display()))?;
+    let offset = partial.upload.blocks.len() as u64 * UPLOAD_BLOCK_SIZE;
+    file.seek(std::io::SeekFrom::Start(offset)).await?;
+
+    loop {
+        let mut block = vec![];
+        (&mut file)
+            .take(UPLOAD_BLOCK_SIZE)
+            .read_to_end(&mut block)
+            .await?;
+        if block.is_empty() {
+            break;
+        }
+
+        remote
+            .upload_block(name, &mut partial.upload, block)
+            .await?;
+        manifest
+            .partial_uploads
+            .insert(name.to_owned(), partial.clone());
+        manifest.save(manifest_path).await?;
+    }
+
+    let version = remote.commit_upload(name, &partial.upload).await?;
+    manifest.partial_uploads.remove(name);
+    Ok(version)
+}
+
+async fn pull(
+    local_path: &Path,
+    remote: &Remote,
+    manifest_path: &Path,
+    delete_dst: bool,
+) -> Result<SyncStats> {
+    fs::create_dir_all(local_path).await?;
+
+    let previous = Manifest::load(manifest_path).await?;
+    let mut manifest = Manifest::scan(local_path, &previous).await?;
+    let mut stats = SyncStats::default();
+
+    let remote_files = remote.list().await?;
+    let mut count = 0;
+
+    for (name, remote_file) in &remote_files {
+        // the names of remote files may not be those of local paths
+        let path = match crate::fs::join_relative(local_path, name) {
+            Ok(path) => path,
+            Err(err) => {
+                warn!("skipping remote file: {:?}", err);
+                continue;
+            }
+        };
+
+        let existing = manifest.files.get(name);
+        if existing.and_then(|x| x.remote_version.as_ref()) == Some(&remote_file.version) {
+            stats.unchanged += 1;
+            continue;
+        }
+        let is_update = existing.is_some();
+
+        let partial = partial_path(&path)?;
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent).await?;
+        }
+
+        // only resume the download of the same version of the file
+        let resume = manifest.partial.get(name) == Some(&remote_file.version);
+        if !resume && crate::fs::exists(&partial).await? {
+            fs::remove_file(&partial).await?;
+        }
+        manifest
+            .partial
+            .insert(name.clone(), remote_file.version.clone());
+        manifest.save(manifest_path).await?;
+
+        remote.download(name, remote_file, &partial).await?;
+        fs::rename(&partial, &path).await?;
+        manifest.partial.remove(name);
+
+        let metadata = fs::metadata(&path).await?;
+        let entry = FileEntry {
+            sha256: sha256::digest_file(&path).await?,
+            size: metadata.len(),
+            modified: modified_ns(&metadata)?,
+            remote_version: Some(remote_file.version.clone()),
+        };
+        manifest.files.insert(name.clone(), entry);
+
+        if is_update {
+            stats.updated += 1;
+        } else {
+            stats.added += 1;
+        }
+
+        count += 1;
+        if count % SAVE_INTERVAL == 0 {
+            manifest.save(manifest_path).await?;
+        }
+    }
+
+    if delete_dst {
+        let removed: Vec<String> = manifest
+            .files
+            .keys()
+            .filter(|name| !remote_files.contains_key(*name))
+            .cloned()
+            .collect();
+
+        for name in removed {
+            fs::remove_file(local_path.join(&name)).await?;
+            manifest.files.remove(&name);
+            stats.removed += 1;
+        }
+    }
+
+    manifest.save(manifest_path).await?;
+    Ok(stats)
+}
+
+fn partial_path(path: &Path) -> Result<PathBuf> {
+    let name = path
+        .file_name()
+        .ok_or_else(|| format_err!("invalid file path: {}", path.display()))?;
+
+    let mut partial = std::ffi::OsString::from(PARTIAL_FILE_PREFIX);
+    partial.push(name);
+
+    Ok(path.with_file_name(partial))
+}
+
+fn modified_ns(metadata: &std::fs::Metadata) -> Result<u64> {
+    let modified = metadata.modified()?.duration_since(UNIX_EPOCH)?;
+    Ok(modified.as_nanos() as u64)
+}
+
+fn is_partial_file(path: &Path) -> bool {
+    path.file_name()
+        .map(|name| name.to_string_lossy().starts_with(PARTIAL_FILE_PREFIX))
+        .unwrap_or_default()
+}
+
+// Lists the files of `dir` and its subdirectories, by their path relative to
+// `dir`, with `/` as the separator.
+async fn list_files(dir: &Path) -> Result<Vec<(String, PathBuf)>> {
+    let mut files = vec![];
+    let mut dirs = vec![(String::new(), dir.to_owned())];
+
+    while let Some((prefix, dir)) = dirs.pop() {
+        if !crate::fs::exists(&dir).await? {
+            continue;
+        }
+
+        let mut entries = fs::read_dir(&dir)
+            .await
+            .with_context(|| format!("unable to list files: {}", dir.display()))?;
+
+        while let Some(entry) = entries.next_entry().await? {
+            let path = entry.path();
+            let name = format!("{prefix}{}", entry.file_name().to_string_lossy());
+
+            if entry.file_type().await?.is_dir() {
+                dirs.push((format!("{name}/"), path));
+            } else if !is_partial_file(&path) {
+                files.push((name, path));
+            }
+        }
+    }
+
+    Ok(files)
+}
+
+struct RemoteFile {
+    size: u64,
+    version: String,
+}
+
+// Operations on the remote copy of a `SyncedDir`, where `Blob` is either an
+// Azure Blob Storage container or an S3 bucket.
+enum Remote {
+    Path(PathBuf),
+    Blob {
+        client: BlobClient,
+        container: BlobContainerUrl,
+    },
+}
+
+impl Remote {
+    fn new(remote_path: &BlobContainerUrl) -> Self {
+        match remote_path.as_file_path() {
+            Some(path) => Self::Path(path),
+            None => Self::Blob {
+                client: BlobClient::new(),
+                container: remote_path.clone(),
+            },
+        }
+    }
+
+    async fn list(&self) -> Result<BTreeMap<String, RemoteFile>> {
+        let mut remote_files = BTreeMap::new();
+
+        match self {
+            Self::Path(dir) => {
+                for (name, path) in list_files(dir).await? {
+                    let metadata = fs::metadata(&path).await?;
+                    let remote_file = RemoteFile {
+                        size: metadata.len(),
+                        version: file_version(&metadata)?,
+                    };
+                    remote_files.insert(name, remote_file);
+                }
+            }
+            Self::Blob { client, container } => {
+                for blob in client.list_blobs(&container.url()?).await? {
+                    let remote_file = RemoteFile {
+                        size: blob.content_length,
+                        version: blob.etag,
+                    };
+                    remote_files.insert(blob.name, remote_file);
+                }
+            }
+        }
+
+        Ok(remote_files)
+    }
+
+    // Returns the version of the uploaded file.
+    async fn upload(&self, name: &str, src: &Path) -> Result<String> {
+        match self {
+            Self::Path(dir) => {
+                let dst = dir.join(name);
+                if let Some(parent) = dst.parent() {
+                    fs::create_dir_all(parent).await?;
+                }
+
+                // copy next to the destination, so it is replaced all at once
+                let partial = partial_path(&dst)?;
+                fs::copy(src, &partial)
+                    .await
+                    .with_context(|| format!("unable to copy file: {}", src.display()))?;
+                fs::rename(&partial, &dst).await?;
+
+                file_version(&fs::metadata(&dst).await?)
+            }
+            Self::Blob { client, container } => {
+                let url = container.blob(name).url();
+                let response = client.put_file(url, src).await?.error_for_status()?;
+
+                let etag = response
+                    .headers()
+                    .get("ETag")
+                    .and_then(|x| x.to_str().ok())
+                    .ok_or_else(|| format_err!("missing ETag in response for blob: {name}"))?;
+                // as listed by `BlobClient::list_blobs()`
+                Ok(etag.trim_matches('"').to_string())
+            }
+        }
+    }
+
+    async fn start_upload(&self, name: &str) -> Result<BlockUpload> {
+        match self {
+            // blocks are appended to a partial copy of the file
+            Self::Path(_) => Ok(BlockUpload {
+                id: String::new(),
+                blocks: vec![],
+            }),
+            Self::Blob { client, container } => {
+                client.start_block_upload(&container.blob(name).url()).await
+            }
+        }
+    }
+
+    async fn upload_block(
+        &self,
+        name: &str,
+        upload: &mut BlockUpload,
+        data: Vec<u8>,
+    ) -> Result<()> {
+        match self {
+            Self::Path(dir) => {
+                let dst = dir.join(name);
+                if let Some(parent) = dst.parent() {
+                    fs::create_dir_all(parent).await?;
+                }
+
+                // drop any data written after the last recorded block
+                let partial = partial_path(&dst)?;
+                let mut file = fs::OpenOptions::new()
+                    .create(true)
+                    .write(true)
+                    .open(&partial)
+                    .await
+                    .with_context(|| format!("unable to open file: {}", partial.display()))?;
+                let offset = upload.blocks.len() as u64 * UPLOAD_BLOCK_SIZE;
+                file.set_len(offset).await?;
+                file.seek(std::io::SeekFrom::Start(offset)).await?;
+                file.write_all(&data).await?;
+                file.flush().await?;
+
+                upload.blocks.push(String::new());
+                Ok(())
+            }
+            Self::Blob { client, container } => {
+                client
+                    .put_block(&container.blob(name).url(), upload, data)
+                    .await
+            }
+        }
+    }
+
+    // Returns the version of the uploaded file.
+    async fn commit_upload(&self, name: &str, upload: &BlockUpload) -> Result<String> {
+        match self {
+            Self::Path(dir) => {
+                let dst = dir.join(name);
+                fs::rename(partial_path(&dst)?, &dst).await?;
+                file_version(&fs::metadata(&dst).await?)
+            }
+            Self::Blob { client, container } => {
+                client
+                    .commit_block_upload(&container.blob(name).url(), upload)
+                    .await
+            }
+        }
+    }
+
+    // Downloads the remote file to `partial`, resuming from the data already
+    // there, if any.
+    async fn download(&self, name: &str, remote_file: &RemoteFile, partial: &Path) -> Result<()> {
+        let mut file = fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(partial)
+            .await
+            .with_context(|| format!("unable to open file: {}", partial.display()))?;
+        let offset = file.metadata().await?.len();
+
+        match self {
+            Self::Path(dir) => {
+                let mut src = fs::File::open(dir.join(name)).await?;
+                src.seek(std::io::SeekFrom::Start(offset)).await?;
+                tokio::io::copy(&mut src, &mut file).await?;
+            }
+            // otherwise, the download was interrupted just before the rename
+            Self::Blob { client, container } if offset < remote_file.size => {
+                let url = container.blob(name).url();
+
+                let response = client
+                    .get_range(&url, offset, &remote_file.version)
+                    .await?
+                    .ok_or_else(|| format_err!("blob changed during sync: {name}"))?;
+
+                let mut stream = response.bytes_stream();
+                while let Some(chunk) = stream.next().await {
+                    file.write_all(&chunk?).await?;
+                }
+            }
+            Self::Blob { .. } => {}
+        }
+
+        file.flush().await?;
+
+        let size = file.metadata().await?.len();
+        if size != remote_file.size {
+            bail!(
+                "incomplete download of {}: {} of {} bytes",
+                name,
+                size,
+                remote_file.size
+            );
+        }
+
+        Ok(())
+    }
+
+    async fn delete(&self, name: &str) -> Result<()> {
+        match self {
+            Self::Path(dir) => {
+                let path = dir.join(name);
+                if crate::fs::exists(&path).await? {
+                    fs::remove_file(&path).await?;
+                }
+                Ok(())
+            }
+            Self::Blob { client, container } => client.delete(container.blob(name).url()).await,
+        }
+    }
+}
+
+// Version of a file in a local remote, which changes whenever it is written.
+fn file_version(metadata: &std::fs::Metadata) -> Result<String> {
+    Ok(format!("{}-{}", metadata.len(), modified_ns(metadata)?))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    async fn write_files(dir: &Path, files: &[(&str, &str)]) -> Result<()> {
+        for (name, data) in files {
+            let path = dir.join(name);
+            fs::create_dir_all(path.parent().unwrap()).await?;
+            fs::write(path, data).await?;
+        }
+        Ok(())
+    }
+
+    fn remote(path: &Path) -> Result<BlobContainerUrl> {
+        BlobContainerUrl::new(reqwest::Url::from_file_path(path).unwrap())
+    }
+
+    #[tokio::test]
+    async fn test_push() -> Result<()> {
+        let dir = tempdir()?;
+        let local = dir.path().join("inputs");
+        let remote_dir = dir.path().join("remote");
+        let remote = remote(&remote_dir)?;
+
+        write_files(&local, &[("a", "1"), ("b", "2"), ("sub/c", "3")]).await?;
+        let stats = sync(&local, &remote, SyncOperation::Push, false).await?;
+        assert_eq!(stats.added, 3);
+        assert_eq!(fs::read_to_string(remote_dir.join("sub/c")).await?, "3");
+
+        // unchanged files are not sent again
+        let stats = sync(&local, &remote, SyncOperation::Push, false).await?;
+        assert_eq!(
+            stats,
+            SyncStats {
+                unchanged: 3,
+                ..Default::default()
+            }
+        );
+
+        write_files(&local, &[("a", "changed"), ("d", "4")]).await?;
+        fs::remove_file(local.join("b")).await?;
+        write_files(&remote_dir, &[("other", "5")]).await?;
+
+        let stats = sync(&local, &remote, SyncOperation::Push, true).await?;
+        assert_eq!(
+            stats,
+            SyncStats {
+                added: 1,
+                updated: 1,
+                removed: 2,
+                unchanged: 1,
+            }
+        );
+        assert_eq!(fs::read_to_string(remote_dir.join("a")).await?, "changed");
+        assert!(!crate::fs::exists(remote_dir.join("b")).await?);
+        assert!(!crate::fs::exists(remote_dir.join("other")).await?);
+
+        let manifest = Manifest::load(&manifest_path(&local)?).await?;
+        assert_eq!(
+            manifest.files["d"].sha256,
+            sha256::digest(b"4"),
+            "the manifest records the digest of each file"
+        );
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_pull() -> Result<()> {
+        let dir = tempdir()?;
+        let local = dir.path().join("inputs");
+        let remote_dir = dir.path().join("remote");
+        let remote = remote(&remote_dir)?;
+
+        write_files(&remote_dir, &[("a", "1"), ("b", "2")]).await?;
+        let stats = sync(&local, &remote, SyncOperation::Pull, false).await?;
+        assert_eq!(stats.added, 2);
+        assert_eq!(fs::read_to_string(local.join("b")).await?, "2");
+
+        let stats = sync(&local, &remote, SyncOperation::Pull, false).await?;
+        assert_eq!(stats.unchanged, 2);
+
+        fs::remove_file(remote_dir.join("a")).await?;
+        let stats = sync(&local, &remote, SyncOperation::Pull, true).await?;
+        assert_eq!(stats.removed, 1);
+        assert!(!crate::fs::exists(local.join("a")).await?);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_pull_skips_paths_outside_dir() -> Result<()> {
+        use crate::blob::fake_s3::FakeS3;
+
+        let fake = FakeS3::get();
+        let bucket = fake.bucket();
+        fake.put_object(&bucket, "../escaped", "1");
+        fake.put_object(&bucket, "/etc/escaped", "2");
+        fake.put_object(&bucket, "a", "3");
+
+        let dir = tempdir()?;
+        let local = dir.path().join("inputs");
+        let remote = BlobContainerUrl::parse(format!("s3://{bucket}"))?;
+
+        let stats = sync(&local, &remote, SyncOperation::Pull, false).await?;
+        assert_eq!(stats.added, 1);
+        assert_eq!(fs::read_to_string(local.join("a")).await?, "3");
+        assert!(!crate::fs::exists(dir.path().join("escaped")).await?);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_push_resumes_partial_upload() -> Result<()> {
+        let dir = tempdir()?;
+        let local = dir.path().join("inputs");
+        let remote_dir = dir.path().join("remote");
+        let remote = remote(&remote_dir)?;
+
+        let block_size = UPLOAD_BLOCK_SIZE as usize;
+        let data: Vec<u8> = (0..block_size * 2 + 10).map(|x| x as u8).collect();
+        fs::create_dir_all(&local).await?;
+        fs::write(local.join("a"), &data).await?;
+
+        // an earlier sync was interrupted after the first block of the file,
+        // and the start of the second
+        fs::create_dir_all(&remote_dir).await?;
+        fs::write(
+            remote_dir.join(".onefuzz-partial-a"),
+            &data[..block_size + 10],
+        )
+        .await?;
+        let mut manifest = Manifest::default();
+        manifest.partial_uploads.insert(
+            "a".to_string(),
+            PartialUpload {
+                sha256: sha256::digest(&data),
+                upload: BlockUpload {
+                    id: String::new(),
+                    blocks: vec![String::new()],
+                },
+            },
+        );
+        manifest.save(&manifest_path(&local)?).await?;
+
+        let stats = sync(&local, &remote, SyncOperation::Push, false).await?;
+        assert_eq!(stats.added, 1);
+        assert_eq!(fs::read(remote_dir.join("a")).await?, data);
+        assert!(!crate::fs::exists(remote_dir.join(".onefuzz-partial-a")).await?);
+
+        let manifest = Manifest::load(&manifest_path(&local)?).await?;
+        assert!(manifest.partial_uploads.is_empty());
+        assert!(manifest.files["a"].remote_version.is_some());
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_push_restarts_upload_of_changed_file() -> Result<()> {
+        let dir = tempdir()?;
+        let local = dir.path().join("inputs");
+        let remote_dir = dir.path().join("remote");
+        let remote = remote(&remote_dir)?;
+
+        let block_size = UPLOAD_BLOCK_SIZE as usize;
+        let data = vec![1u8; block_size + 10];
+        fs::create_dir_all(&local).await?;
+        fs::write(local.join("a"), &data).await?;
+
+        // the file changed since its upload was interrupted
+        fs::create_dir_all(&remote_dir).await?;
+        fs::write(remote_dir.join(".onefuzz-partial-a"), vec![0u8; block_size]).await?;
+        let mut manifest = Manifest::default();
+        manifest.partial_uploads.insert(
+            "a".to_string(),
+            PartialUpload {
+                sha256: sha256::digest(b"previous"),
+                upload: BlockUpload {
+                    id: String::new(),
+                    blocks: vec![String::new()],
+                },
+            },
+        );
+        manifest.save(&manifest_path(&local)?).await?;
+
+        sync(&local, &remote, SyncOperation::Push, false).await?;
+        assert_eq!(fs::read(remote_dir.join("a")).await?, data);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_pull_resumes_partial_download() -> Result<()> {
+        let dir = tempdir()?;
+        let local = dir.path().join("inputs");
+        let remote_dir = dir.path().join("remote");
+        let remote = remote(&remote_dir)?;
+
+        write_files(&remote_dir, &[("a", "0123456789")]).await?;
+        let metadata = fs::metadata(remote_dir.join("a")).await?;
+
+        // an earlier sync was interrupted after the first half of the file
+        fs::create_dir_all(&local).await?;
+        fs::write(local.join(".onefuzz-partial-a"), "01234").await?;
+        let mut manifest = Manifest::default();
+        manifest
+            .partial
+            .insert("a".to_string(), file_version(&metadata)?);
+        manifest.save(&manifest_path(&local)?).await?;
+
+        let stats = sync(&local, &remote, SyncOperation::Pull, false).await?;
+        assert_eq!(stats.added, 1);
+        assert_eq!(fs::read_to_string(local.join("a")).await?, "0123456789");
+        assert!(!crate::fs::exists(local.join(".onefuzz-partial-a")).await?);
+
+        let manifest = Manifest::load(&manifest_path(&local)?).await?;
+        assert!(manifest.partial.is_empty());
+
+        Ok(())
+    }
+}
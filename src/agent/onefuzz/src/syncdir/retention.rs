@@ -0,0 +1,515 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT License.
+
+//! Retention policies, which bound the local copy of a `SyncedDir` so corpora
+//! and crashes of long-running jobs do not fill the disk of the node.
+
+use std::collections::{HashMap, HashSet};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::{Duration, Instant, SystemTime};
+
+use anyhow::{Context, Result};
+use onefuzz_telemetry::{Event, EventData};
+use regex::Regex;
+use serde::Deserialize;
+use tokio::fs;
+
+// Minimum time between evictions of a monitored directory, as each one lists
+// the whole directory.
+const ENFORCE_INTERVAL: Duration = Duration::from_secs(60);
+
+lazy_static::lazy_static! {
+    // Local paths of the dirs which are pulled from their remote copy, whose
+    // files are not evicted, as the next pull would download them again.
+    static ref PULLED_DIRS: Mutex<HashSet<PathBuf>> = Mutex::default();
+}
+
+/// Record that `dir` is pulled from its remote copy, so that the files of
+/// `dir` are no longer evicted.
+pub(crate) fn record_pull(dir: &Path) {
+    PULLED_DIRS.lock().unwrap().insert(dir.to_owned());
+}
+
+fn is_pulled(dir: &Path) -> bool {
+    PULLED_DIRS.lock().unwrap().contains(dir)
+}
+
+/// Limits on the files kept in the local copy of a `SyncedDir`.
+///
+/// Files are only evicted once pushed to the remote copy, which keeps them all,
+/// and never from directories which are also pulled from it. Within the limits of `max_files` and `max_bytes`, the oldest files are
+/// evicted first. Hidden files are neither counted nor evicted.
+#[derive(Clone, Debug, Default, Deserialize, PartialEq, Eq)]
+#[serde(default)]
+pub struct RetentionPolicy {
+    /// Maximum number of files to keep.
+    pub max_files: Option<u64>,
+
+    /// Maximum total size of the files to keep, in bytes.
+    pub max_bytes: Option<u64>,
+
+    /// Maximum time since a file was last modified, in seconds.
+    pub max_age_secs: Option<u64>,
+
+    /// Regex which extracts a hash from file names, such as
+    /// `^crash-([0-9a-f]+)$`, from its first group if any, or its whole match
+    /// otherwise. Of the files with the same hash, only the smallest is kept.
+    pub keep_smallest_per_hash: Option<String>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum EvictionReason {
+    MaxAge,
+    Duplicate,
+    MaxFiles,
+    MaxBytes,
+}
+
+impl EvictionReason {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::MaxAge => "max_age",
+            Self::Duplicate => "duplicate",
+            Self::MaxFiles => "max_files",
+            Self::MaxBytes => "max_bytes",
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Eviction {
+    pub path: PathBuf,
+    pub size: u64,
+    pub reason: EvictionReason,
+}
+
+#[derive(Clone, Debug)]
+struct FileInfo {
+    path: PathBuf,
+    name: String,
+    size: u64,
+    modified: SystemTime,
+}
+
+impl RetentionPolicy {
+    pub fn is_empty(&self) -> bool {
+        self.max_files.is_none()
+            && self.max_bytes.is_none()
+            && self.max_age_secs.is_none()
+            && self.keep_smallest_per_hash.is_none()
+    }
+
+    /// Evict the files of `dir` which are beyond the limits of the policy.
+    ///
+    /// Evictions are logged, and reported as telemetry.
+    pub async fn enforce(&self, dir: &Path) -> Result<Vec<Eviction>> {
+        self.enforce_pushed(dir, |_, _| true).await
+    }
+
+    /// Evict the files of `dir` beyond the limits of the policy, of only those
+    /// for which `pushed` is true, given their path relative to `dir` and
+    /// modification time.
+    pub(crate) async fn enforce_pushed(
+        &self,
+        dir: &Path,
+        pushed: impl Fn(&Path, SystemTime) -> bool,
+    ) -> Result<Vec<Eviction>> {
+        if self.is_empty() || !crate::fs::exists(dir).await? {
+            return Ok(vec![]);
+        }
+
+        if is_pulled(dir) {
+            debug!("not evicting files of pulled dir: {}", dir.display());
+            return Ok(vec![]);
+        }
+
+        let mut files = list_files(dir).await?;
+        files.retain(|file| {
+            let path = file.path.strip_prefix(dir).unwrap_or(&file.path);
+            pushed(path, file.modified)
+        });
+
+        let evictions = self.plan(files, SystemTime::now())?;
+
+        for eviction in &evictions {
+            match fs::remove_file(&eviction.path).await {
+                Ok(()) => {}
+                // removed since it was listed
+                Err(err) if err.kind() == std::io::ErrorKind::NotFound => {}
+                Err(err) => {
+                    return Err(err).with_context(|| {
+                        format!("unable to evict file: {}", eviction.path.display())
+                    })
+                }
+            }
+            debug!(
+                "evicted {} ({} bytes): {}",
+                eviction.path.display(),
+                eviction.size,
+                eviction.reason.as_str()
+            );
+        }
+
+        report(dir, &evictions);
+
+        Ok(evictions)
+    }
+
+    // Selects the files to evict, of `files` as of `now`.
+    fn plan(&self, mut files: Vec<FileInfo>, now: SystemTime) -> Result<Vec<Eviction>> {
+        let mut evictions = vec![];
+        let mut evict = |file: &FileInfo, reason| {
+            evictions.push(Eviction {
+                path: file.path.clone(),
+                size: file.size,
+                reason,
+            })
+        };
+
+        // oldest first
+        files.sort_by(|a, b| (a.modified, &a.path).cmp(&(b.modified, &b.path)));
+
+        if let Some(max_age) = self.max_age_secs.map(Duration::from_secs) {
+            files.retain(|file| {
+                let expired = now
+                    .duration_since(file.modified)
+                    .map(|age| age > max_age)
+                    .unwrap_or(false);
+                if expired {
+                    evict(file, EvictionReason::MaxAge);
+                }
+                !expired
+            });
+        }
+
+        if let Some(pattern) = &self.keep_smallest_per_hash {
+            let regex = Regex::new(pattern)
+                .with_context(|| format!("invalid keep_smallest_per_hash regex: {pattern}"))?;
+
+            let hashes: Vec<Option<String>> = files
+                .iter()
+                .map(|file| file_hash(&regex, &file.name))
+                .collect();
+
+            // of files of the same size, the oldest is kept
+            let mut smallest: HashMap<&str, usize> = HashMap::new();
+            for (index, hash) in hashes.iter().enumerate() {
+                if let Some(hash) = hash {
+                    let kept = smallest.entry(hash.as_str()).or_insert(index);
+                    if files[index].size < files[*kept].size {
+                        *kept = index;
+                    }
+                }
+            }
+            let kept: HashSet<usize> = smallest.into_values().collect();
+
+            let mut index = 0;
+            files.retain(|file| {
+                let keep = hashes[index].is_none() || kept.contains(&index);
+                index += 1;
+                if !keep {
+                    evict(file, EvictionReason::Duplicate);
+                }
+                keep
+            });
+        }
+
+        let mut count = files.len() as u64;
+        let mut total: u64 = files.iter().map(|file| file.size).sum();
+
+        for file in &files {
+            let reason = if self.max_files.map(|max| count > max).unwrap_or(false) {
+                EvictionReason::MaxFiles
+            } else if self.max_bytes.map(|max| total > max).unwrap_or(false) {
+                EvictionReason::MaxBytes
+            } else {
+                break;
+            };
+
+            evict(file, reason);
+            count -= 1;
+            total -= file.size;
+        }
+
+        Ok(evictions)
+    }
+
+    pub(crate) fn enforcer<'a>(&'a self, dir: &'a Path) -> Enforcer<'a> {
+        Enforcer {
+            policy: self,
+            dir,
+            pushed: HashSet::new(),
+            last: None,
+        }
+    }
+}
+
+/// Enforces a policy on a directory as its files are pushed, at most once per
+/// `ENFORCE_INTERVAL`.
+///
+/// Only the files which were pushed are evicted, as files which were added
+/// since may not have been pushed yet.
+pub(crate) struct Enforcer<'a> {
+    policy: &'a RetentionPolicy,
+    dir: &'a Path,
+    // Paths of the pushed files, relative to `dir`.
+    pushed: HashSet<PathBuf>,
+    last: Option<Instant>,
+}
+
+impl<'a> Enforcer<'a> {
+    /// Record that the file at `path` in the directory was pushed.
+    pub(crate) async fn tick(&mut self, path: &Path) {
+        if self.policy.is_empty() {
+            return;
+        }
+
+        if let Ok(path) = path.strip_prefix(self.dir) {
+            self.pushed.insert(path.to_owned());
+        }
+
+        if let Some(last) = self.last {
+            if last.elapsed() < ENFORCE_INTERVAL {
+                return;
+            }
+        }
+        self.last = Some(Instant::now());
+
+        let pushed = &self.pushed;
+        let result = self
+            .policy
+            .enforce_pushed(self.dir, |path, _| pushed.contains(path))
+            .await;
+
+        match result {
+            Ok(evictions) => {
+                for eviction in evictions {
+                    if let Ok(path) = eviction.path.strip_prefix(self.dir) {
+                        self.pushed.remove(path);
+                    }
+                }
+            }
+            Err(err) => warn!(
+                "unable to enforce retention policy of {}: {:?}",
+                self.dir.display(),
+                err
+            ),
+        }
+    }
+}
+
+fn file_hash(regex: &Regex, name: &str) -> Option<String> {
+    let captures = regex.captures(name)?;
+    let hash = captures.get(1).or_else(|| captures.get(0))?;
+    Some(hash.as_str().to_owned())
+}
+
+fn report(dir: &Path, evictions: &[Eviction]) {
+    let mut by_reason: HashMap<EvictionReason, (u64, u64)> = HashMap::new();
+    for eviction in evictions {
+        let (count, size) = by_reason.entry(eviction.reason).or_default();
+        *count += 1;
+        *size += eviction.size;
+    }
+
+    for (reason, (count, size)) in by_reason {
+        info!(
+            "evicted {} files ({} bytes) from {}: {}",
+            count,
+            size,
+            dir.display(),
+            reason.as_str()
+        );
+        event!(
+            Event::retention_eviction;
+            EventData::Path = dir.display().to_string(),
+            EventData::Mode = reason.as_str(),
+            EventData::Count = count,
+            EventData::Size = size
+        );
+        metric!(
+            Event::retention_eviction;
+            count as f64;
+            EventData::Path = dir.display().to_string(),
+            EventData::Mode = reason.as_str()
+        );
+    }
+}
+
+// Lists the files of `dir` and its subdirectories, other than hidden files,
+// which include the temporary files of syncs.
+async fn list_files(dir: &Path) -> Result<Vec<FileInfo>> {
+    let mut files = vec![];
+    let mut dirs = vec![dir.to_owned()];
+
+    while let Some(dir) = dirs.pop() {
+        let mut entries = fs::read_dir(&dir)
+            .await
+            .with_context(|| format!("unable to list files: {}", dir.display()))?;
+
+        while let Some(entry) = entries.next_entry().await? {
+            let name = entry.file_name().to_string_lossy().into_owned();
+            if name.starts_with('.') {
+                continue;
+            }
+
+            let metadata = entry.metadata().await?;
+            if metadata.is_dir() {
+                dirs.push(entry.path());
+            } else {
+                files.push(FileInfo {
+                    path: entry.path(),
+                    name,
+                    size: metadata.len(),
+                    modified: metadata.modified()?,
+                });
+            }
+        }
+    }
+
+    Ok(files)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    fn file(name: &str, size: u64, age_secs: u64, now: SystemTime) -> FileInfo {
+        FileInfo {
+            path: PathBuf::from(name),
+            name: name.to_owned(),
+            size,
+            modified: now - Duration::from_secs(age_secs),
+        }
+    }
+
+    fn evicted(evictions: &[Eviction]) -> Vec<(&str, EvictionReason)> {
+        evictions
+            .iter()
+            .map(|x| (x.path.to_str().unwrap(), x.reason))
+            .collect()
+    }
+
+    #[test]
+    fn test_plan() -> Result<()> {
+        let now = SystemTime::now();
+        let files = vec![
+            file("crash-aaaa-1", 30, 50, now),
+            file("crash-aaaa-2", 10, 40, now),
+            file("crash-bbbb-1", 20, 30, now),
+            file("input-1", 40, 20, now),
+            file("input-2", 50, 10, now),
+            file("input-old", 1, 1000, now),
+        ];
+
+        let policy = RetentionPolicy {
+            max_age_secs: Some(100),
+            keep_smallest_per_hash: Some("^crash-([a-f]+)-".to_owned()),
+            max_files: Some(3),
+            max_bytes: Some(90),
+        };
+
+        let evictions = policy.plan(files.clone(), now)?;
+        assert_eq!(
+            evicted(&evictions),
+            [
+                ("input-old", EvictionReason::MaxAge),
+                ("crash-aaaa-1", EvictionReason::Duplicate),
+                // oldest first
+                ("crash-aaaa-2", EvictionReason::MaxFiles),
+                ("crash-bbbb-1", EvictionReason::MaxBytes),
+            ]
+        );
+
+        assert!(RetentionPolicy::default().plan(files, now)?.is_empty());
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_enforce() -> Result<()> {
+        let dir = tempdir()?;
+        for (name, data) in [("a", "1"), ("b", "22"), ("c", "333"), (".state", "4444")] {
+            fs::write(dir.path().join(name), data).await?;
+        }
+
+        let policy = RetentionPolicy {
+            max_bytes: Some(3),
+            ..Default::default()
+        };
+        let evictions = policy.enforce(dir.path()).await?;
+        assert_eq!(evictions.len(), 2);
+
+        let mut remaining = vec![];
+        let mut entries = fs::read_dir(dir.path()).await?;
+        while let Some(entry) = entries.next_entry().await? {
+            remaining.push(entry.file_name().to_string_lossy().into_owned());
+        }
+        remaining.sort();
+        assert_eq!(remaining.len(), 2, "{remaining:?}");
+        assert!(remaining.contains(&".state".to_owned()));
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_enforce_skips_pulled_dirs() -> Result<()> {
+        let dir = tempdir()?;
+        fs::write(dir.path().join("a"), "1").await?;
+
+        let policy = RetentionPolicy {
+            max_files: Some(0),
+            ..Default::default()
+        };
+        record_pull(dir.path());
+        assert!(policy.enforce(dir.path()).await?.is_empty());
+        assert!(dir.path().join("a").exists());
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_enforcer_only_evicts_pushed_files() -> Result<()> {
+        let dir = tempdir()?;
+        for name in ["a", "b", "c"] {
+            fs::write(dir.path().join(name), "1").await?;
+        }
+
+        let policy = RetentionPolicy {
+            max_files: Some(0),
+            ..Default::default()
+        };
+        let mut enforcer = policy.enforcer(dir.path());
+        enforcer.tick(&dir.path().join("b")).await;
+
+        assert!(!dir.path().join("b").exists());
+        assert!(dir.path().join("a").exists());
+        assert!(dir.path().join("c").exists());
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_enforcer_evicts_pushed_files_of_subdirs() -> Result<()> {
+        let dir = tempdir()?;
+        fs::create_dir_all(dir.path().join("sub")).await?;
+        for name in ["a", "sub/a", "sub/b"] {
+            fs::write(dir.path().join(name), "1").await?;
+        }
+
+        let policy = RetentionPolicy {
+            max_files: Some(0),
+            ..Default::default()
+        };
+        let mut enforcer = policy.enforcer(dir.path());
+        enforcer.tick(&dir.path().join("sub/a")).await;
+
+        assert!(!dir.path().join("sub/a").exists());
+        assert!(dir.path().join("sub/b").exists());
+        assert!(dir.path().join("a").exists());
+
+        Ok(())
+    }
+}
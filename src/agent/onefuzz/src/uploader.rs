@@ -5,7 +5,7 @@ use std::path::Path;
 
 use anyhow::{Context, Result};
 use futures::stream::TryStreamExt;
-use reqwest::{Body, Client, Response, StatusCode, Url};
+use reqwest::{Body, Client, Method, RequestBuilder, Response, StatusCode, Url};
 use reqwest_retry::{
     send_retry_reqwest, RetryCheck, SendRetry, DEFAULT_RETRY_PERIOD, MAX_RETRY_ATTEMPTS,
 };
@@ -13,6 +13,8 @@ use serde::Serialize;
 use tokio::{fs, io};
 use tokio_util::codec;
 
+use crate::blob::s3;
+
 #[derive(Clone)]
 pub struct BlobUploader {
     client: Client,
@@ -26,6 +28,17 @@ impl BlobUploader {
         Self { client, url }
     }
 
+    fn put(&self, url: &Url) -> Result<RequestBuilder> {
+        if s3::is_s3_url(url) {
+            return s3::signed_request(&self.client, Method::PUT, url);
+        }
+
+        Ok(self
+            .client
+            .put(url.clone())
+            .header("x-ms-blob-type", "BlockBlob"))
+    }
+
     pub async fn upload(&mut self, file_path: impl AsRef<Path>) -> Result<Response> {
         let file_path = file_path.as_ref();
 
@@ -54,11 +67,9 @@ impl BlobUploader {
                     .into_stream();
 
                 let request_builder = self
-                    .client
-                    .put(url.clone())
+                    .put(&url)?
                     // https://learn.microsoft.com/en-us/rest/api/storageservices/put-blob-from-url#request-headers
                     .header("Content-Length", &content_length)
-                    .header("x-ms-blob-type", "BlockBlob")
                     // upload only if the the destination blob does not exist
                     .header("If-None-Match", "*")
                     .body(Body::wrap_stream(file_stream));
@@ -95,9 +106,7 @@ impl BlobUploader {
         };
 
         let resp = self
-            .client
-            .put(url)
-            .header("x-ms-blob-type", "BlockBlob")
+            .put(&url)?
             .json(&data)
             .send_retry_default()
             .await
@@ -179,10 +179,16 @@ pub(crate) fn parse_summary(text: &str) -> Option<CrashLogSummary> {
     .find_map(|f| f(text))
 }
 
+// Whether the log has an AddressSanitizer error report, such as
+// "==1234==ERROR: AddressSanitizer: heap-buffer-overflow on address ...".
+pub(crate) fn has_error(text: &str) -> bool {
+    text.contains("ERROR: AddressSanitizer")
+}
+
 // Unfortunately, we can't just use Path's split as we want to
 // parse stack frames from OSes other than OS the app is running
 // on
-fn get_call_stack_file_name(file_path: &str) -> String {
+pub(crate) fn get_call_stack_file_name(file_path: &str) -> String {
     let split: Vec<_> = file_path.rsplitn(2, '/').collect();
     if split.len() == 2 {
         return split[0].to_string();
@@ -0,0 +1,196 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT License.
+
+use regex::{Captures, Regex};
+
+use crate::asan::get_call_stack_file_name;
+use crate::{CrashLogSummary, StackEntry};
+
+const SANITIZER: &str = "Go";
+
+lazy_static::lazy_static! {
+    // "panic: runtime error: index out of range" or "fatal error: stack overflow"
+    static ref ERROR_REGEX: Regex =
+        Regex::new(r"(?m)^(?P<kind>panic|fatal error): (?P<message>.*?)\s*$").unwrap();
+    // "goroutine 17 [running, locked to thread]:"
+    static ref GOROUTINE_REGEX: Regex =
+        Regex::new(r"(?m)^goroutine \d+ \[[^\]]*\]:\s*$").unwrap();
+    // "main.(*Manager).httpPrio(0xc4201dab60, 0xf2dac0)"
+    static ref FUNCTION_REGEX: Regex =
+        Regex::new(r"^(?P<function_name>\S.*?)\([^()]*\)\s*$").unwrap();
+    // "  /src/go/src/encoding/json/decode.go:1264 +0xb3b"
+    static ref LOCATION_REGEX: Regex = Regex::new(
+        r"^\s*(?P<file_path>\S+\.(?:go|s)):(?P<file_line>\d+)(?:\s+\+0x[0-9a-fA-F]+)?(?:\s.*)?$"
+    ).unwrap();
+}
+
+// Fault types for the errors reported by the Go runtime, matched by a
+// substring of the error message.
+const FAULT_TYPES: &[(&str, &str)] = &[
+    ("slice bounds out of range", "slice-bounds-out-of-range"),
+    ("index out of range", "index-out-of-range"),
+    ("integer divide by zero", "integer-divide-by-zero"),
+    ("nil pointer dereference", "nil-pointer-dereference"),
+    ("makeslice: len out of range", "makeslice-len-out-of-range"),
+    ("makeslice: cap out of range", "makeslice-cap-out-of-range"),
+    ("stack overflow", "stack-overflow"),
+    ("out of memory", "out-of-memory"),
+    ("concurrent map", "concurrent-map-access"),
+    ("deadlock", "deadlock"),
+];
+
+fn fault_type(kind: &str, message: &str) -> &'static str {
+    FAULT_TYPES
+        .iter()
+        .find(|(pattern, _)| message.contains(pattern))
+        .map(|(_, fault_type)| *fault_type)
+        .unwrap_or(match kind {
+            "panic" => "panic",
+            _ => "fatal-error",
+        })
+}
+
+// Finds a panic or fatal error, followed by a goroutine dump.
+fn find_error(text: &str) -> Option<Captures<'_>> {
+    let captures = ERROR_REGEX.captures(text)?;
+    // only treat this as a Go crash if there is a goroutine dump to go with it
+    GOROUTINE_REGEX.find_at(text, captures.get(0)?.end())?;
+    Some(captures)
+}
+
+// Finds the start of the first goroutine dump. The Go runtime dumps the
+// goroutines after reporting other errors than panics and fatal errors too,
+// such as when it is aborted by a signal.
+fn find_goroutine(text: &str) -> Option<usize> {
+    GOROUTINE_REGEX
+        .find(text)
+        .map(|goroutine| goroutine.start())
+}
+
+// Finds the start of the Go report, from its error if any.
+pub(crate) fn report_start(text: &str) -> Option<usize> {
+    find_error(text)
+        .and_then(|captures| captures.get(0))
+        .map(|error| error.start())
+        .or_else(|| find_goroutine(text))
+}
+
+// Summarizes a Go panic or fatal runtime error, as reported by go-fuzz or
+// the Go runtime.
+pub(crate) fn parse_summary(text: &str) -> Option<CrashLogSummary> {
+    let captures = find_error(text)?;
+    let kind = captures.name("kind")?.as_str();
+    let message = captures.name("message")?.as_str();
+
+    Some(CrashLogSummary {
+        summary: format!("{kind}: {message}"),
+        sanitizer: SANITIZER.to_string(),
+        fault_type: fault_type(kind, message).to_string(),
+    })
+}
+
+// Parses the stack of the goroutine which panicked, which is the first one in
+// the dump. Each frame is a function call, with its arguments, followed by
+// its source location.
+pub(crate) fn parse_call_stack(text: &str) -> Vec<StackEntry> {
+    let start = match find_goroutine(text) {
+        Some(start) => start,
+        None => return vec![],
+    };
+
+    let mut stack = vec![];
+    let mut function: Option<(&str, &str)> = None;
+
+    for line in text[start..].lines().skip(1) {
+        if let Some(location) = LOCATION_REGEX.captures(line) {
+            if let Some((function_line, function_name)) = function.take() {
+                let source_file_path = location["file_path"].to_string();
+
+                stack.push(StackEntry {
+                    line: format!("{} {}", function_line.trim(), line.trim()),
+                    function_name: Some(function_name.to_string()),
+                    source_file_name: Some(get_call_stack_file_name(&source_file_path)),
+                    source_file_path: Some(source_file_path),
+                    source_file_line: location["file_line"].parse().ok(),
+                    ..Default::default()
+                });
+            }
+        } else if line.starts_with("created by ") {
+            // the goroutine which started this one is not part of its stack
+            break;
+        } else if let Some(captures) = FUNCTION_REGEX.captures(line) {
+            let function_name = captures.name("function_name").map(|x| x.as_str());
+            function = function_name.map(|name| (line, name));
+        } else {
+            break;
+        }
+    }
+
+    stack
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    const TEXT: &str = r"panic: runtime error: index out of range [3] with length 3
+
+goroutine 17 [running, locked to thread]:
+github.com/example/parser.(*Reader).next(0xc000010018, 0x3)
+	/src/parser/reader.go:42 +0x1d5
+github.com/example/parser.Fuzz(0x6030001458a0, 0x20, 0x20, 0x10c0000c8ea0)
+	/src/parser/fuzz.go:11 +0x3d
+created by main.main
+	/src/main.go:8 +0x25
+
+goroutine 1 [chan receive]:
+main.main()
+	/src/main.go:9 +0x47
+";
+
+    #[test]
+    fn test_parse_summary() {
+        let summary = parse_summary(TEXT).unwrap();
+        assert_eq!(summary.sanitizer, "Go");
+        assert_eq!(summary.fault_type, "index-out-of-range");
+        assert_eq!(
+            summary.summary,
+            "panic: runtime error: index out of range [3] with length 3"
+        );
+
+        assert!(parse_summary("panic: no goroutine dump follows\n").is_none());
+    }
+
+    #[test]
+    fn test_report_start() {
+        assert_eq!(report_start(TEXT), Some(0));
+
+        let unknown = TEXT.replace("panic: runtime error", "unknown error");
+        assert_eq!(unknown.find("goroutine 17"), report_start(&unknown));
+        assert!(parse_summary(&unknown).is_none());
+        assert_eq!(parse_call_stack(&unknown).len(), 2);
+
+        assert_eq!(report_start("panic: no goroutine dump follows\n"), None);
+    }
+
+    #[test]
+    fn test_parse_call_stack() {
+        let stack = parse_call_stack(TEXT);
+        assert_eq!(stack.len(), 2);
+
+        let expected = StackEntry {
+            line: "github.com/example/parser.(*Reader).next(0xc000010018, 0x3) /src/parser/reader.go:42 +0x1d5".to_string(),
+            function_name: Some("github.com/example/parser.(*Reader).next".to_string()),
+            source_file_name: Some("reader.go".to_string()),
+            source_file_path: Some("/src/parser/reader.go".to_string()),
+            source_file_line: Some(42),
+            ..Default::default()
+        };
+        assert_eq!(stack[0], expected);
+        assert_eq!(
+            stack[1].function_name.as_deref(),
+            Some("github.com/example/parser.Fuzz")
+        );
+    }
+}
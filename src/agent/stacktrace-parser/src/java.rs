@@ -0,0 +1,201 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT License.
+
+use regex::{Regex, RegexSet};
+
+use crate::{CrashLogSummary, StackEntry};
+
+const SANITIZER: &str = "Java";
+
+const CAUSED_BY: &str = "Caused by: ";
+
+// Frames of the JDK and of Jazzer, which are not part of the code under test.
+const STACK_FRAME_IGNORE_REGEXES: &[&str] = &[
+    r"^java\.",
+    r"^javax\.",
+    r"^jdk\.",
+    r"^sun\.",
+    r"^com\.sun\.",
+    r"^com\.code_intelligence\.jazzer\.",
+];
+
+lazy_static::lazy_static! {
+    // "== Java Exception: java.lang.IllegalStateException: message", as
+    // reported by Jazzer, or "Exception in thread "main" java.lang.Error"
+    static ref EXCEPTION_REGEX: Regex = Regex::new(
+        r#"(?m)^(?:== Java Exception: |Exception in thread "[^"]*" )(?P<exception>[\w.$]+)(?::\s*(?P<message>.*?))?\s*$"#
+    ).unwrap();
+    // "\tat java.base/java.util.ArrayList.get(ArrayList.java:427)"
+    static ref FRAME_REGEX: Regex = Regex::new(
+        r"^\s+at (?:(?P<module>[^/\s(]*)/{1,2})?(?P<function_name>[^/\s(]+)\((?P<location>[^)]*)\)\s*$"
+    ).unwrap();
+    // "ExampleFuzzer.java:51"
+    static ref LOCATION_REGEX: Regex =
+        Regex::new(r"^(?P<file_name>[^:]+):(?P<file_line>\d+)$").unwrap();
+    // "\t... 4 more"
+    static ref ELIDED_REGEX: Regex = Regex::new(r"^\s+\.\.\. (?P<count>\d+) more\s*$").unwrap();
+    static ref STACK_FILTER: RegexSet = RegexSet::new(STACK_FRAME_IGNORE_REGEXES).unwrap();
+}
+
+// Whether a crash log was parsed as an uncaught Java exception.
+pub(crate) fn is_java(sanitizer: &str) -> bool {
+    sanitizer == SANITIZER
+}
+
+pub(crate) fn is_ignored_frame(function_name: &str) -> bool {
+    STACK_FILTER.is_match(function_name)
+}
+
+// Summarizes an uncaught Java exception, such as those reported by Jazzer.
+pub(crate) fn parse_summary(text: &str) -> Option<CrashLogSummary> {
+    let captures = EXCEPTION_REGEX.captures(text)?;
+    let exception = captures.name("exception")?.as_str();
+
+    let summary = match captures.name("message").map(|x| x.as_str()) {
+        Some(message) if !message.is_empty() => format!("{exception}: {message}"),
+        _ => exception.to_string(),
+    };
+
+    Some(CrashLogSummary {
+        summary,
+        sanitizer: SANITIZER.to_string(),
+        fault_type: exception.to_string(),
+    })
+}
+
+fn parse_frame(line: &str) -> Option<StackEntry> {
+    let captures = FRAME_REGEX.captures(line)?;
+    let location = LOCATION_REGEX.captures(&captures["location"]);
+
+    Some(StackEntry {
+        line: line.trim().to_string(),
+        function_name: Some(captures["function_name"].to_string()),
+        source_file_name: location.as_ref().map(|x| x["file_name"].to_string()),
+        source_file_line: location.and_then(|x| x["file_line"].parse().ok()),
+        module_path: captures
+            .name("module")
+            .map(|x| x.as_str())
+            .filter(|x| !x.is_empty())
+            .map(|x| x.to_string()),
+        ..Default::default()
+    })
+}
+
+// Parses the stack of the root cause of an uncaught exception, which is the
+// last of its `Caused by:` chain.
+//
+// Java elides the frames a cause has in common with the exception it caused,
+// as in `... 4 more`. These are restored from the enclosing stack, so the
+// root cause's stack runs all the way to the fuzz target.
+pub(crate) fn parse_call_stack(text: &str) -> Vec<StackEntry> {
+    let start = match EXCEPTION_REGEX.find(text) {
+        Some(header) => header.end(),
+        None => return vec![],
+    };
+
+    let mut traces: Vec<Vec<StackEntry>> = vec![vec![]];
+    let mut suppressed = false;
+
+    for line in text[start..].lines().skip(1) {
+        if line.starts_with(CAUSED_BY) {
+            suppressed = false;
+            traces.push(vec![]);
+        } else if suppressed && line.starts_with(char::is_whitespace) {
+            // exceptions suppressed while handling this one are not its causes
+            continue;
+        } else if line.trim_start().starts_with("Suppressed: ") {
+            suppressed = true;
+        } else if let Some(entry) = parse_frame(line) {
+            if let Some(trace) = traces.last_mut() {
+                trace.push(entry);
+            }
+        } else if let Some(captures) = ELIDED_REGEX.captures(line) {
+            let count: usize = captures["count"].parse().unwrap_or_default();
+            if let [.., enclosing, trace] = traces.as_mut_slice() {
+                let elided = enclosing.len().saturating_sub(count);
+                trace.extend_from_slice(&enclosing[elided..]);
+            }
+        } else {
+            break;
+        }
+    }
+
+    traces.pop().unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    const TEXT: &str = "== Java Exception: com.code_intelligence.jazzer.api.FuzzerSecurityIssueLow: Uncaught exception
+\tat com.example.ConfigFuzzer.fuzzerTestOneInput(ConfigFuzzer.java:21)
+Caused by: java.lang.NumberFormatException: For input string: \"0x\"
+\tat java.base/java.lang.NumberFormatException.forInputString(NumberFormatException.java:67)
+\tat java.base/java.lang.Integer.parseInt(Integer.java:668)
+\tat app//com.example.config.Parser.parsePort(Parser.java:112)
+\tat com.example.ConfigFuzzer.fuzzerTestOneInput(ConfigFuzzer.java:19)
+\tat jdk.internal.reflect.GeneratedMethodAccessor1.invoke(Unknown Source)
+\t... 1 more
+DEDUP_TOKEN: 5c1d6b8ee4d2b2a3
+";
+
+    #[test]
+    fn test_parse_summary() {
+        let summary = parse_summary(TEXT).unwrap();
+        assert_eq!(summary.sanitizer, "Java");
+        assert_eq!(
+            summary.fault_type,
+            "com.code_intelligence.jazzer.api.FuzzerSecurityIssueLow"
+        );
+        assert_eq!(
+            summary.summary,
+            "com.code_intelligence.jazzer.api.FuzzerSecurityIssueLow: Uncaught exception"
+        );
+
+        let text = "Exception in thread \"main\" java.lang.StackOverflowError\n";
+        let summary = parse_summary(text).unwrap();
+        assert_eq!(summary.summary, "java.lang.StackOverflowError");
+    }
+
+    #[test]
+    fn test_parse_call_stack() {
+        let stack = parse_call_stack(TEXT);
+        let names: Vec<_> = stack
+            .iter()
+            .filter_map(|x| x.function_name.as_deref())
+            .collect();
+        assert_eq!(
+            names,
+            [
+                "java.lang.NumberFormatException.forInputString",
+                "java.lang.Integer.parseInt",
+                "com.example.config.Parser.parsePort",
+                "com.example.ConfigFuzzer.fuzzerTestOneInput",
+                "jdk.internal.reflect.GeneratedMethodAccessor1.invoke",
+                // restored from the enclosing stack
+                "com.example.ConfigFuzzer.fuzzerTestOneInput",
+            ]
+        );
+
+        let expected = StackEntry {
+            line: "at app//com.example.config.Parser.parsePort(Parser.java:112)".to_string(),
+            function_name: Some("com.example.config.Parser.parsePort".to_string()),
+            source_file_name: Some("Parser.java".to_string()),
+            source_file_line: Some(112),
+            module_path: Some("app".to_string()),
+            ..Default::default()
+        };
+        assert_eq!(stack[2], expected);
+        assert_eq!(stack[4].source_file_name, None);
+    }
+
+    #[test]
+    fn test_is_ignored_frame() {
+        assert!(is_ignored_frame("java.lang.Integer.parseInt"));
+        assert!(is_ignored_frame(
+            "com.code_intelligence.jazzer.driver.FuzzTargetRunner.runOne"
+        ));
+        assert!(!is_ignored_frame("com.example.config.Parser.parsePort"));
+    }
+}
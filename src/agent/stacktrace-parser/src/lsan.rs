@@ -0,0 +1,38 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT License.
+
+use regex::{Captures, Regex};
+
+use crate::CrashLogSummary;
+
+const SANITIZER: &str = "LeakSanitizer";
+
+lazy_static::lazy_static! {
+    static ref ERROR_REGEX: Regex =
+        Regex::new(r"==\d+==\s*ERROR: LeakSanitizer: detected memory leaks").unwrap();
+    static ref LEAK_REGEX: Regex = Regex::new(
+        r"(?m)^(?P<summary>(?P<kind>Direct|Indirect) leak of \d+ byte\(s\) in \d+ object\(s\)) allocated from:"
+    ).unwrap();
+}
+
+fn summary(captures: &Captures<'_>) -> CrashLogSummary {
+    let fault_type = match &captures["kind"] {
+        "Direct" => "direct-leak",
+        _ => "indirect-leak",
+    };
+
+    CrashLogSummary {
+        summary: format!("{SANITIZER}: {}", &captures["summary"]),
+        sanitizer: SANITIZER.to_string(),
+        fault_type: fault_type.to_string(),
+    }
+}
+
+// Summarizes the first leak of a LeakSanitizer report.
+pub(crate) fn parse_summary(text: &str) -> Option<CrashLogSummary> {
+    if !ERROR_REGEX.is_match(text) {
+        return None;
+    }
+
+    LEAK_REGEX.captures(text).map(|c| summary(&c))
+}
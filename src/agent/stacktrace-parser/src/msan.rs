@@ -0,0 +1,103 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT License.
+
+use anyhow::Result;
+use regex::Regex;
+
+use crate::asan::parse_asan_call_stack;
+use crate::{CrashLogSummary, StackEntry};
+
+const SANITIZER: &str = "MemorySanitizer";
+
+// Origin where the uninitialized value was allocated.
+const CREATED_ORIGIN: &str = "Uninitialized value was created by";
+
+// Origin where the uninitialized value was most recently copied, when MSan is
+// run with `track_origins=2`.
+const STORED_ORIGIN: &str = "Uninitialized value was stored to memory at";
+
+lazy_static::lazy_static! {
+    static ref WARNING_REGEX: Regex =
+        Regex::new(r"==\d+==\s*WARNING: MemorySanitizer: (?P<fault_type>[\w-]+)").unwrap();
+    static ref SUMMARY_REGEX: Regex =
+        Regex::new(r"SUMMARY: (?P<summary>MemorySanitizer: [^\n]*)").unwrap();
+}
+
+pub(crate) fn parse_summary(text: &str) -> Option<CrashLogSummary> {
+    let captures = WARNING_REGEX.captures(text)?;
+    let fault_type = captures.name("fault_type")?.as_str();
+
+    let summary = SUMMARY_REGEX
+        .captures(text)
+        .and_then(|c| c.name("summary"))
+        .map(|s| s.as_str().trim().to_string())
+        .unwrap_or_else(|| format!("{SANITIZER}: {fault_type}"));
+
+    Some(CrashLogSummary {
+        summary,
+        sanitizer: SANITIZER.to_string(),
+        fault_type: fault_type.to_string(),
+    })
+}
+
+// Extracts the stack of the origin of the uninitialized value, when MSan is
+// run with `track_origins`. The allocation origin is preferred, since it is
+// usually where the missing initialization belongs.
+pub(crate) fn parse_origin_stack(text: &str) -> Result<Vec<StackEntry>> {
+    let start = text
+        .find(CREATED_ORIGIN)
+        .or_else(|| text.find(STORED_ORIGIN));
+
+    match start {
+        Some(start) => parse_asan_call_stack(&text[start..]),
+        None => Ok(vec![]),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    const TEXT: &str = r"==42==WARNING: MemorySanitizer: use-of-uninitialized-value
+    #0 0x4a1d3c in parse_header /src/lib/header.c:41:7
+    #1 0x4a0f21 in LLVMFuzzerTestOneInput /src/fuzz.c:12:3
+
+  Uninitialized value was stored to memory at
+    #0 0x4a1b77 in copy_header /src/lib/header.c:28:15
+
+  Uninitialized value was created by a heap allocation
+    #0 0x43c6f0 in malloc (/out/fuzz+0x43c6f0)
+    #1 0x4a1a02 in new_header /src/lib/header.c:17:20
+
+SUMMARY: MemorySanitizer: use-of-uninitialized-value /src/lib/header.c:41:7 in parse_header
+";
+
+    #[test]
+    fn test_parse_summary() {
+        let summary = parse_summary(TEXT).unwrap();
+        assert_eq!(summary.sanitizer, "MemorySanitizer");
+        assert_eq!(summary.fault_type, "use-of-uninitialized-value");
+        assert_eq!(
+            summary.summary,
+            "MemorySanitizer: use-of-uninitialized-value /src/lib/header.c:41:7 in parse_header"
+        );
+    }
+
+    #[test]
+    fn test_parse_origin_stack() -> Result<()> {
+        let stack = parse_origin_stack(TEXT)?;
+        let names: Vec<_> = stack
+            .iter()
+            .filter_map(|x| x.function_name.as_deref())
+            .collect();
+        assert_eq!(names, ["malloc", "new_header"]);
+
+        let text = TEXT.replace(CREATED_ORIGIN, "Uninitialized value");
+        let stack = parse_origin_stack(&text)?;
+        assert_eq!(stack.len(), 1);
+        assert_eq!(stack[0].function_name.as_deref(), Some("copy_header"));
+
+        Ok(())
+    }
+}
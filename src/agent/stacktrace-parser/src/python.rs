@@ -0,0 +1,124 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT License.
+
+use regex::Regex;
+
+use crate::asan::get_call_stack_file_name;
+use crate::{CrashLogSummary, StackEntry};
+
+const SANITIZER: &str = "Python";
+
+const TRACEBACK_HEADER: &str = "Traceback (most recent call last):";
+
+lazy_static::lazy_static! {
+    // `  File "/fuzzers/fuzz_tar.py", line 24, in TestOneInput`
+    static ref FRAME_REGEX: Regex = Regex::new(
+        r#"^\s+File "(?P<file_path>[^"]+)", line (?P<file_line>\d+), in (?P<function_name>.+?)\s*$"#
+    ).unwrap();
+    // "EOFError: Compressed file ended before the end-of-stream marker was reached"
+    static ref EXCEPTION_REGEX: Regex =
+        Regex::new(r"^(?P<exception>[\w.]+)(?::\s|$)").unwrap();
+}
+
+// Splits the last traceback of a crash log into its frames, outermost first,
+// and the line describing the exception which was raised.
+//
+// With chained exceptions, the last traceback is the one which went unhandled.
+fn last_traceback(text: &str) -> Option<(Vec<StackEntry>, Option<&str>)> {
+    let start = report_start(text)? + TRACEBACK_HEADER.len();
+
+    let mut frames = vec![];
+    for line in text[start..].lines().skip(1) {
+        if let Some(captures) = FRAME_REGEX.captures(line) {
+            let source_file_path = captures["file_path"].to_string();
+
+            frames.push(StackEntry {
+                line: line.trim().to_string(),
+                function_name: Some(captures["function_name"].to_string()),
+                source_file_name: Some(get_call_stack_file_name(&source_file_path)),
+                source_file_path: Some(source_file_path),
+                source_file_line: captures["file_line"].parse().ok(),
+                ..Default::default()
+            });
+        } else if !line.is_empty() && !line.starts_with(char::is_whitespace) {
+            // the source of each frame is indented, the exception is not
+            return Some((frames, Some(line.trim_end())));
+        }
+    }
+
+    Some((frames, None))
+}
+
+// Finds the start of the last traceback, which is the one reported.
+pub(crate) fn report_start(text: &str) -> Option<usize> {
+    text.rfind(TRACEBACK_HEADER)
+}
+
+// Summarizes an uncaught Python exception, such as those reported by Atheris.
+pub(crate) fn parse_summary(text: &str) -> Option<CrashLogSummary> {
+    let (_, exception) = last_traceback(text)?;
+    let exception = exception?;
+    let captures = EXCEPTION_REGEX.captures(exception)?;
+
+    Some(CrashLogSummary {
+        summary: exception.to_string(),
+        sanitizer: SANITIZER.to_string(),
+        fault_type: captures["exception"].to_string(),
+    })
+}
+
+// Parses the frames of the last traceback, innermost first to match the
+// order of the other call stacks.
+pub(crate) fn parse_call_stack(text: &str) -> Vec<StackEntry> {
+    let mut stack = last_traceback(text)
+        .map(|(frames, _)| frames)
+        .unwrap_or_default();
+    stack.reverse();
+    stack
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    const TEXT: &str = r#"Traceback (most recent call last):
+  File "/fuzz/fuzz_json.py", line 12, in TestOneInput
+    json.loads(data)
+  File "/usr/lib/python3.10/json/__init__.py", line 346, in loads
+    return _default_decoder.decode(s)
+KeyError: 'name'
+
+During handling of the above exception, another exception occurred:
+
+Traceback (most recent call last):
+  File "/fuzz/fuzz_json.py", line 14, in TestOneInput
+    raise ValueError("invalid document")
+ValueError: invalid document
+"#;
+
+    #[test]
+    fn test_parse_summary() {
+        let summary = parse_summary(TEXT).unwrap();
+        assert_eq!(summary.sanitizer, "Python");
+        assert_eq!(summary.fault_type, "ValueError");
+        assert_eq!(summary.summary, "ValueError: invalid document");
+
+        assert_eq!(report_start(TEXT), TEXT.rfind("Traceback"));
+    }
+
+    #[test]
+    fn test_parse_call_stack() {
+        let stack = parse_call_stack(TEXT);
+
+        let expected = StackEntry {
+            line: r#"File "/fuzz/fuzz_json.py", line 14, in TestOneInput"#.to_string(),
+            function_name: Some("TestOneInput".to_string()),
+            source_file_name: Some("fuzz_json.py".to_string()),
+            source_file_path: Some("/fuzz/fuzz_json.py".to_string()),
+            source_file_line: Some(14),
+            ..Default::default()
+        };
+        assert_eq!(stack, [expected]);
+    }
+}
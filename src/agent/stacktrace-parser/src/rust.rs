@@ -0,0 +1,248 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT License.
+
+use regex::{Captures, Regex};
+
+use crate::asan::get_call_stack_file_name;
+use crate::{CrashLogSummary, StackEntry};
+
+const SANITIZER: &str = "Rust";
+
+const BACKTRACE_HEADER: &str = "stack backtrace:";
+
+lazy_static::lazy_static! {
+    // "thread '<unnamed>' panicked at 'attempt to add with overflow', src/lib.rs:12:5"
+    static ref PANIC_REGEX: Regex = Regex::new(
+        r"(?ms)^thread '[^']*' panicked at '(?P<message>.*?)', (?P<location>\S+:\d+:\d+)$"
+    ).unwrap();
+    // Since Rust 1.73, the message follows the location on its own line:
+    //
+    // "thread 'main' panicked at src/lib.rs:12:5:
+    // attempt to add with overflow"
+    static ref PANIC_LOCATION_FIRST_REGEX: Regex = Regex::new(
+        r"(?m)^thread '[^']*' panicked at (?P<location>\S+:\d+:\d+):\n(?P<message>.*)$"
+    ).unwrap();
+    // "   2: fuzz::parse" or, with `RUST_BACKTRACE=full`,
+    // "   2:     0x55d5c8d8b0c1 - fuzz::parse::h0c4ab3b3ed6d7c6e"
+    static ref FRAME_REGEX: Regex = Regex::new(
+        r"^\s*\d+:\s+(?:0x(?P<address>[0-9a-fA-F]+) - )?(?P<function_name>.+?)\s*$"
+    ).unwrap();
+    // "             at ./src/lib.rs:12:5"
+    static ref FRAME_LOCATION_REGEX: Regex = Regex::new(
+        r"^\s+at (?P<file_path>.+?):(?P<file_line>\d+)(?::(?P<column>\d+))?\s*$"
+    ).unwrap();
+    // the hash of the crate, appended to symbols by `RUST_BACKTRACE=full`
+    static ref SYMBOL_HASH_REGEX: Regex = Regex::new(r"::h[0-9a-f]{16}$").unwrap();
+}
+
+// Fault types for common panics raised by the standard library, matched by a
+// substring of the panic message.
+const FAULT_TYPES: &[(&str, &str)] = &[
+    ("on a `None` value", "unwrap-none"),
+    ("on an `Err` value", "unwrap-err"),
+    ("with overflow", "arithmetic-overflow"),
+    ("attempt to divide by zero", "divide-by-zero"),
+    ("with a divisor of zero", "divide-by-zero"),
+    ("index out of bounds", "index-out-of-bounds"),
+    ("out of range for slice", "slice-index-out-of-bounds"),
+    ("slice index starts at", "slice-index-out-of-bounds"),
+    ("is not a char boundary", "char-boundary"),
+    ("assertion failed", "assertion-failed"),
+    ("assertion `", "assertion-failed"),
+    ("entered unreachable code", "unreachable"),
+    ("not implemented", "unimplemented"),
+    ("not yet implemented", "todo"),
+];
+
+const DEFAULT_FAULT_TYPE: &str = "panic";
+
+// Frames of the panic machinery, which are on top of the stack of every panic.
+const PANIC_FRAME_PREFIXES: &[&str] = &[
+    "rust_begin_unwind",
+    "rust_panic",
+    "__rust_",
+    "std::",
+    "core::",
+    "alloc::",
+    "<std::",
+    "<core::",
+    "<alloc::",
+];
+
+fn fault_type(message: &str) -> &'static str {
+    FAULT_TYPES
+        .iter()
+        .find(|(pattern, _)| message.contains(pattern))
+        .map(|(_, fault_type)| *fault_type)
+        .unwrap_or(DEFAULT_FAULT_TYPE)
+}
+
+fn panic(text: &str) -> Option<Captures<'_>> {
+    PANIC_REGEX
+        .captures(text)
+        .or_else(|| PANIC_LOCATION_FIRST_REGEX.captures(text))
+}
+
+// Finds the start of the panic message.
+pub(crate) fn report_start(text: &str) -> Option<usize> {
+    panic(text)?.get(0).map(|panic| panic.start())
+}
+
+// Summarizes a Rust panic, such as one reported by cargo-fuzz.
+pub(crate) fn parse_summary(text: &str) -> Option<CrashLogSummary> {
+    let captures = panic(text)?;
+    let location = captures.name("location")?.as_str();
+    // panic messages may span multiple lines
+    let message = captures.name("message")?.as_str().lines().next()?;
+
+    Some(CrashLogSummary {
+        summary: format!("panicked at {location}: {message}"),
+        sanitizer: SANITIZER.to_string(),
+        fault_type: fault_type(message).to_string(),
+    })
+}
+
+// Parses the backtrace printed with the panic when `RUST_BACKTRACE` is set,
+// skipping the frames of the panic machinery.
+pub(crate) fn parse_call_stack(text: &str) -> Vec<StackEntry> {
+    let start = match panic(text).and_then(|c| c.get(0)) {
+        Some(panic) => panic.end(),
+        None => return vec![],
+    };
+    let start = match text[start..].find(BACKTRACE_HEADER) {
+        Some(header) => start + header + BACKTRACE_HEADER.len(),
+        None => return vec![],
+    };
+
+    let mut stack: Vec<StackEntry> = vec![];
+    for line in text[start..].lines().skip(1) {
+        if let Some(location) = FRAME_LOCATION_REGEX.captures(line) {
+            // inlined frames may list more than one location, keep the first
+            let entry = match stack.last_mut() {
+                Some(entry) if entry.source_file_path.is_none() => entry,
+                _ => continue,
+            };
+            let source_file_path = location["file_path"].to_string();
+
+            entry.line = format!("{} {}", entry.line, line.trim());
+            entry.source_file_name = Some(get_call_stack_file_name(&source_file_path));
+            entry.source_file_path = Some(source_file_path);
+            entry.source_file_line = location["file_line"].parse().ok();
+            entry.function_offset = location
+                .name("column")
+                .and_then(|x| x.as_str().parse().ok());
+        } else if let Some(frame) = FRAME_REGEX.captures(line) {
+            let function_name = SYMBOL_HASH_REGEX.replace(&frame["function_name"], "");
+
+            stack.push(StackEntry {
+                line: line.trim().to_string(),
+                address: frame
+                    .name("address")
+                    .and_then(|x| u64::from_str_radix(x.as_str(), 16).ok()),
+                function_name: Some(function_name.into_owned()),
+                ..Default::default()
+            });
+        } else {
+            break;
+        }
+    }
+
+    let panic_frames = stack
+        .iter()
+        .take_while(|entry| {
+            let name = entry.function_name.as_deref().unwrap_or_default();
+            PANIC_FRAME_PREFIXES.iter().any(|x| name.starts_with(x))
+        })
+        .count();
+
+    stack.split_off(panic_frames)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    const TEXT: &str = r"thread '<unnamed>' panicked at src/header.rs:41:23:
+index out of bounds: the len is 4 but the index is 4
+stack backtrace:
+   0: rust_begin_unwind
+             at /rustc/90c541806f23a127002de5b4038be731ba1458ca/library/std/src/panicking.rs:645:5
+   1: core::panicking::panic_fmt
+             at /rustc/90c541806f23a127002de5b4038be731ba1458ca/library/core/src/panicking.rs:72:14
+   2: core::panicking::panic_bounds_check
+             at /rustc/90c541806f23a127002de5b4038be731ba1458ca/library/core/src/panicking.rs:208:5
+   3: image::header::parse
+             at ./src/header.rs:41:23
+   4: fuzz_header::_::__libfuzzer_sys_run
+             at ./fuzz/fuzz_targets/fuzz_header.rs:6:5
+   5: rust_fuzzer_test_input
+note: Some details are omitted, run with `RUST_BACKTRACE=full` for a verbose backtrace.
+";
+
+    #[test]
+    fn test_parse_summary() {
+        let summary = parse_summary(TEXT).unwrap();
+        assert_eq!(summary.sanitizer, "Rust");
+        assert_eq!(summary.fault_type, "index-out-of-bounds");
+        assert_eq!(
+            summary.summary,
+            "panicked at src/header.rs:41:23: index out of bounds: the len is 4 but the index is 4"
+        );
+
+        let text = "thread 'main' panicked at 'called `Option::unwrap()` on a `None` value', src/main.rs:7:37\n";
+        let summary = parse_summary(text).unwrap();
+        assert_eq!(summary.fault_type, "unwrap-none");
+        assert_eq!(
+            summary.summary,
+            "panicked at src/main.rs:7:37: called `Option::unwrap()` on a `None` value"
+        );
+    }
+
+    #[test]
+    fn test_parse_call_stack() {
+        let stack = parse_call_stack(TEXT);
+        let names: Vec<_> = stack
+            .iter()
+            .filter_map(|x| x.function_name.as_deref())
+            .collect();
+        assert_eq!(
+            names,
+            [
+                "image::header::parse",
+                "fuzz_header::_::__libfuzzer_sys_run",
+                "rust_fuzzer_test_input"
+            ]
+        );
+
+        let expected = StackEntry {
+            line: "3: image::header::parse at ./src/header.rs:41:23".to_string(),
+            function_name: Some("image::header::parse".to_string()),
+            function_offset: Some(23),
+            source_file_name: Some("header.rs".to_string()),
+            source_file_path: Some("./src/header.rs".to_string()),
+            source_file_line: Some(41),
+            ..Default::default()
+        };
+        assert_eq!(stack[0], expected);
+    }
+
+    #[test]
+    fn test_parse_full_backtrace() {
+        let text = r"thread 'main' panicked at 'attempt to add with overflow', src/lib.rs:3:5
+stack backtrace:
+   0:     0x55d5c8d8b0c1 - std::backtrace_rs::backtrace::libunwind::trace::h5e9d00f0cdf4f57e
+                               at /rustc/90c541806f23a127002de5b4038be731ba1458ca/library/std/src/../../backtrace/src/backtrace/libunwind.rs:93:5
+   1:     0x55d5c8d8c2a7 - checksum::add::h0c4ab3b3ed6d7c6e
+                               at /src/checksum/src/lib.rs:3:5
+";
+        let summary = parse_summary(text).unwrap();
+        assert_eq!(summary.fault_type, "arithmetic-overflow");
+
+        let stack = parse_call_stack(text);
+        assert_eq!(stack.len(), 1);
+        assert_eq!(stack[0].address, Some(0x55d5c8d8c2a7));
+        assert_eq!(stack[0].function_name.as_deref(), Some("checksum::add"));
+        assert_eq!(stack[0].source_file_line, Some(3));
+    }
+}
@@ -0,0 +1,105 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT License.
+
+use anyhow::{format_err, Result};
+use regex::RegexSet;
+use serde::{Deserialize, Serialize};
+
+use crate::StackEntry;
+
+/// Project-specific rules for minimizing call stacks, applied in addition to
+/// the stack frame filters of ClusterFuzz.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct StackFilterRules {
+    /// Regexes of function names or module paths of frames to leave out of
+    /// the minimized stack, such as custom allocators or assert helpers.
+    #[serde(default)]
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub ignore: Vec<String>,
+
+    /// Regexes of function names of frames to treat as the top of the stack.
+    /// The frames above the first frame which matches are left out of the
+    /// minimized stack, while the frame itself is always kept.
+    #[serde(default)]
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub top_frame: Vec<String>,
+}
+
+impl StackFilterRules {
+    pub fn is_empty(&self) -> bool {
+        self.ignore.is_empty() && self.top_frame.is_empty()
+    }
+}
+
+/// Stack filter rules compiled to regexes.
+///
+/// The rules are compiled when deserialized, such as with the config of a
+/// task, so invalid rules are found before any crash is reported.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(try_from = "StackFilterRules", into = "StackFilterRules")]
+pub struct StackFilter {
+    rules: StackFilterRules,
+    ignore: RegexSet,
+    top_frame: RegexSet,
+}
+
+impl StackFilter {
+    pub fn new(rules: StackFilterRules) -> Result<Self> {
+        let ignore = RegexSet::new(&rules.ignore)
+            .map_err(|err| format_err!("invalid stack filter ignore rule: {err}"))?;
+        let top_frame = RegexSet::new(&rules.top_frame)
+            .map_err(|err| format_err!("invalid stack filter top_frame rule: {err}"))?;
+
+        Ok(Self {
+            rules,
+            ignore,
+            top_frame,
+        })
+    }
+
+    pub fn rules(&self) -> &StackFilterRules {
+        &self.rules
+    }
+
+    pub(crate) fn is_ignored(&self, entry: &StackEntry) -> bool {
+        [&entry.function_name, &entry.module_path]
+            .into_iter()
+            .flatten()
+            .any(|name| self.ignore.is_match(name))
+    }
+
+    /// Finds the first frame which matches a `top_frame` rule.
+    pub(crate) fn top_frame(&self, stack: &[StackEntry]) -> Option<usize> {
+        stack.iter().position(|entry| {
+            entry
+                .function_name
+                .as_deref()
+                .map(|name| self.top_frame.is_match(name))
+                .unwrap_or_default()
+        })
+    }
+}
+
+impl Default for StackFilter {
+    fn default() -> Self {
+        Self {
+            rules: StackFilterRules::default(),
+            ignore: RegexSet::empty(),
+            top_frame: RegexSet::empty(),
+        }
+    }
+}
+
+impl TryFrom<StackFilterRules> for StackFilter {
+    type Error = anyhow::Error;
+
+    fn try_from(rules: StackFilterRules) -> Result<Self> {
+        Self::new(rules)
+    }
+}
+
+impl From<StackFilter> for StackFilterRules {
+    fn from(filter: StackFilter) -> Self {
+        filter.rules
+    }
+}
@@ -0,0 +1,180 @@
+// Copyright (c) Microsoft Corporation.
+// Licensed under the MIT License.
+
+use regex::{Captures, Regex};
+
+use crate::asan::get_call_stack_file_name;
+use crate::{CrashLogSummary, StackEntry};
+
+const SANITIZER: &str = "UndefinedBehaviorSanitizer";
+
+lazy_static::lazy_static! {
+    // "path/to/file.c:12:34: runtime error: signed integer overflow: ..."
+    static ref RUNTIME_ERROR_REGEX: Regex = Regex::new(
+        r"(?m)^(?P<location>(?P<file_path>.+?):(?P<file_line>\d+):(?P<column>\d+)): runtime error: (?P<message>.*)$"
+    ).unwrap();
+}
+
+// Fault types are named after the `-fsanitize=` check which reports the
+// error, matched by a substring of the error message. Order matters, since
+// some messages contain the substrings of others.
+const FAULT_TYPES: &[(&str, &str)] = &[
+    ("failed during cast to unrelated type", "cfi-unrelated-cast"),
+    ("failed during base-to-derived cast", "cfi-derived-cast"),
+    ("failed during virtual call", "cfi-vcall"),
+    ("failed during indirect function call", "cfi-icall"),
+    ("control flow integrity check", "cfi"),
+    ("unsigned integer overflow", "unsigned-integer-overflow"),
+    ("signed integer overflow", "signed-integer-overflow"),
+    ("negation of", "signed-integer-overflow"),
+    ("division by zero", "integer-divide-by-zero"),
+    ("divide by zero", "integer-divide-by-zero"),
+    ("division of", "signed-integer-overflow"),
+    ("shift exponent", "shift-exponent"),
+    ("left shift of", "shift-base"),
+    ("out of bounds for type", "array-bounds"),
+    ("null pointer passed as argument", "nonnull-attribute"),
+    ("null pointer returned from", "returns-nonnull-attribute"),
+    ("applying zero offset to null pointer", "pointer-overflow"),
+    ("applying non-zero offset", "pointer-overflow"),
+    ("addition of unsigned offset", "pointer-overflow"),
+    ("subtraction of unsigned offset", "pointer-overflow"),
+    ("pointer index expression", "pointer-overflow"),
+    ("null pointer", "null"),
+    ("misaligned address", "alignment"),
+    ("not a valid value for type 'bool'", "bool"),
+    ("not a valid value for type", "enum"),
+    ("variable length array bound", "vla-bound"),
+    ("insufficient space for an object of type", "object-size"),
+    ("does not point to an object of type", "vptr"),
+    ("pointer to incorrect function type", "function"),
+    ("end of a value-returning function", "return"),
+    ("unreachable program point", "unreachable"),
+    ("outside the range of representable", "float-cast-overflow"),
+    ("implicit conversion", "implicit-conversion"),
+    ("passing zero to", "builtin"),
+];
+
+const DEFAULT_FAULT_TYPE: &str = "undefined-behavior";
+
+fn fault_type(message: &str) -> &'static str {
+    FAULT_TYPES
+        .iter()
+        .find(|(pattern, _)| message.contains(pattern))
+        .map(|(_, fault_type)| *fault_type)
+        .unwrap_or(DEFAULT_FAULT_TYPE)
+}
+
+fn runtime_error(text: &str) -> Option<Captures<'_>> {
+    RUNTIME_ERROR_REGEX.captures(text)
+}
+
+// Summarizes the first UBSan check failure, as reported by its
+// `runtime error:` line, including the source location of the check.
+pub(crate) fn parse_summary(text: &str) -> Option<CrashLogSummary> {
+    let captures = runtime_error(text)?;
+    let location = captures.name("location")?.as_str();
+    let fault_type = fault_type(captures.name("message")?.as_str().trim());
+
+    Some(CrashLogSummary {
+        summary: format!("{SANITIZER}: {fault_type} {location}"),
+        sanitizer: SANITIZER.to_string(),
+        fault_type: fault_type.to_string(),
+    })
+}
+
+// UBSan only prints a stack trace when `print_stacktrace=1`. Without one, the
+// source location of the first failed check is the best available frame.
+pub(crate) fn parse_location(text: &str) -> Option<StackEntry> {
+    let captures = runtime_error(text)?;
+    let source_file_path = captures.name("file_path")?.as_str().to_string();
+
+    Some(StackEntry {
+        line: captures.name("location")?.as_str().to_string(),
+        source_file_name: Some(get_call_stack_file_name(&source_file_path)),
+        source_file_path: Some(source_file_path),
+        source_file_line: captures.name("file_line")?.as_str().parse().ok(),
+        function_offset: captures.name("column")?.as_str().parse().ok(),
+        ..Default::default()
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn test_fault_type() {
+        let test_cases = [
+            (
+                "signed integer overflow: -2147483648 + -132 cannot be represented in type int",
+                "signed-integer-overflow",
+            ),
+            (
+                "unsigned integer overflow: 1 + 18446744073709551615 cannot be represented in type 'unsigned long'",
+                "unsigned-integer-overflow",
+            ),
+            ("division by zero", "integer-divide-by-zero"),
+            (
+                "shift exponent 40 is too large for 32-bit type 'int'",
+                "shift-exponent",
+            ),
+            (
+                "index 256 out of bounds for type uint8_t [256]",
+                "array-bounds",
+            ),
+            ("applying zero offset to null pointer", "pointer-overflow"),
+            (
+                "applying non-zero offset 8 to null pointer",
+                "pointer-overflow",
+            ),
+            (
+                "addition of unsigned offset to 0x7ffd4c2a3f10 overflowed to 0x7ffd4c2a3f0c",
+                "pointer-overflow",
+            ),
+            (
+                "load of misaligned address 0x000001f4 for type 'int', which requires 4 byte alignment",
+                "alignment",
+            ),
+            ("load of null pointer of type double", "null"),
+            (
+                "load of value 161, which is not a valid value for type 'bool'",
+                "bool",
+            ),
+            (
+                "member call on address 0x15577a7fc900 which does not point to an object of type net::QuicSpdySession",
+                "vptr",
+            ),
+            (
+                "unsupported ubsan error that needs a new signature",
+                "undefined-behavior",
+            ),
+        ];
+
+        for (message, expected) in test_cases {
+            assert_eq!(fault_type(message), expected, "{message}");
+        }
+    }
+
+    #[test]
+    fn test_parse_location() {
+        let text = "INFO: Seed: 1\nC:\\src\\hash.c:93:36: runtime error: division by zero\n";
+
+        let summary = parse_summary(text).unwrap();
+        assert_eq!(
+            summary.summary,
+            "UndefinedBehaviorSanitizer: integer-divide-by-zero C:\\src\\hash.c:93:36"
+        );
+
+        let expected = StackEntry {
+            line: "C:\\src\\hash.c:93:36".to_string(),
+            source_file_name: Some("hash.c".to_string()),
+            source_file_path: Some("C:\\src\\hash.c".to_string()),
+            source_file_line: Some(93),
+            function_offset: Some(36),
+            ..Default::default()
+        };
+        assert_eq!(parse_location(text), Some(expected));
+    }
+}
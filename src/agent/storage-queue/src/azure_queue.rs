@@ -1,7 +1,7 @@
 // Copyright (c) Microsoft Corporation.
 // Licensed under the MIT License.
 
-use anyhow::{Context, Result};
+use anyhow::{anyhow, Context, Result};
 use base64::Engine;
 use bytes::Buf;
 use reqwest::{Client, Url};
@@ -36,7 +36,9 @@ pub struct AzureQueueMessage {
     // ExpirationTime
     pub pop_receipt: String,
     // TimeNextVisible
-    // DequeueCount
+    /// Number of times the message was popped, including this one.
+    #[serde(default)]
+    pub dequeue_count: u32,
     pub message_text: String,
 
     #[serde(skip)]
@@ -98,6 +100,48 @@ impl AzureQueueMessage {
         Ok(())
     }
 
+    /// Make the message invisible to other consumers for `timeout` from now
+    /// on, such as to keep processing it past the visibility timeout it was
+    /// popped with.
+    ///
+    /// This renews the pop receipt, so it must complete before the message is
+    /// deleted.
+    pub async fn extend_visibility(&mut self, timeout: Duration) -> Result<()> {
+        if let Some(messages_url) = self.messages_url.clone() {
+            let messages_path = messages_url.path();
+            let item_path = format!("{}/{}", messages_path, self.message_id);
+            let mut url = messages_url.clone();
+            url.set_path(&item_path);
+            url.query_pairs_mut()
+                .append_pair("popreceipt", &self.pop_receipt)
+                .append_pair("visibilitytimeout", &timeout.as_secs().to_string());
+
+            let body = quick_xml::se::to_string(&AzureQueueMessageSend {
+                message_text: self.message_text.clone(),
+            })
+            .context("serializing queue message")?;
+
+            let http = Client::new();
+            let response = http
+                .put(url)
+                .body(body)
+                .send_retry_default()
+                .await
+                .context("storage queue update failed")?
+                .error_for_status()
+                .context("storage queue update failed with error")?;
+
+            let pop_receipt = response
+                .headers()
+                .get("x-ms-popreceipt")
+                .ok_or_else(|| anyhow!("storage queue update missing pop receipt"))?
+                .to_str()?;
+            self.pop_receipt = pop_receipt.to_owned();
+        }
+
+        Ok(())
+    }
+
     pub fn get<T: DeserializeOwned>(&self) -> Result<T> {
         let decoded = BASE64.decode(&self.message_text)?;
         let value = serde_json::from_slice(&decoded)?;
@@ -150,6 +194,25 @@ impl AzureQueueClient {
         Ok(())
     }
 
+    /// Enqueue `data` as is, such as the data of a message popped from
+    /// another queue.
+    pub async fn enqueue_raw(&self, data: &[u8]) -> Result<()> {
+        let body = quick_xml::se::to_string(&AzureQueueMessageSend {
+            message_text: BASE64.encode(data),
+        })
+        .context("serializing queue message")?;
+
+        self.http
+            .post(self.messages_url.clone())
+            .body(body)
+            .send_retry_default()
+            .await
+            .context("storage queue enqueue failed")?
+            .error_for_status()
+            .context("storage queue enqueue failed with error")?;
+        Ok(())
+    }
+
     pub async fn pop(&self) -> Result<Option<AzureQueueMessage>> {
         let response = self
             .http
@@ -180,3 +243,33 @@ impl AzureQueueClient {
         Ok(m)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_message_list() -> Result<()> {
+        let body = r#"<QueueMessagesList>
+    <QueueMessage>
+        <MessageId>7d35e47d-f58e-42da-ba4a-9e6ac7e1214d</MessageId>
+        <InsertionTime>Fri, 05 Feb 2021 06:27:47 GMT</InsertionTime>
+        <ExpirationTime>Fri, 12 Feb 2021 06:27:47 GMT</ExpirationTime>
+        <PopReceipt>AgAAAAMAAAAAAAAAtg40eYj71gE=</PopReceipt>
+        <TimeNextVisible>Fri, 05 Feb 2021 06:31:02 GMT</TimeNextVisible>
+        <DequeueCount>3</DequeueCount>
+        <MessageText>dGVzdA==</MessageText>
+    </QueueMessage>
+</QueueMessagesList>"#;
+
+        let list: AzureQueueMessageList = quick_xml::de::from_str(body)?;
+        let message = list
+            .queue_message
+            .ok_or_else(|| anyhow!("missing message"))?;
+        assert_eq!(message.dequeue_count, 3);
+        assert_eq!(message.pop_receipt, "AgAAAAMAAAAAAAAAtg40eYj71gE=");
+        assert_eq!(message.parse(|data| Ok(data.to_vec()))?, b"test");
+
+        Ok(())
+    }
+}
@@ -65,6 +65,16 @@ impl QueueClient {
         .context("QueueClient.enqueue")
     }
 
+    /// Enqueue `data` as is, without serializing it.
+    pub async fn enqueue_raw(&self, data: &[u8]) -> Result<()> {
+        match self {
+            QueueClient::AzureQueue(queue_client) => queue_client.enqueue_raw(data).await,
+            QueueClient::FileQueueClient(queue_client) => queue_client.enqueue_raw(data).await,
+            QueueClient::Channel(queue_client) => queue_client.enqueue_raw(data).await,
+        }
+        .context("QueueClient.enqueue_raw")
+    }
+
     pub async fn pop(&self) -> Result<Option<Message>> {
         match self {
             QueueClient::AzureQueue(queue_client) => {
@@ -103,17 +113,55 @@ impl Message {
     pub async fn claim<T: DeserializeOwned>(self) -> Result<T> {
         match self {
             Message::QueueMessage(message) => Ok(message.claim().await?),
-            Message::LocalQueueMessage(message) => Ok(serde_json::from_slice(&message.data)?),
+            Message::LocalQueueMessage(message) => {
+                message.delete()?;
+                Ok(serde_json::from_slice(&message.data)?)
+            }
         }
     }
 
     pub async fn delete(&self) -> Result<()> {
         match self {
             Message::QueueMessage(message) => Ok(message.delete().await?),
-            Message::LocalQueueMessage(_) => Ok(()),
+            Message::LocalQueueMessage(message) => message.delete(),
+        }
+    }
+
+    /// Number of times the message was popped, including this one.
+    pub fn dequeue_count(&self) -> u32 {
+        match self {
+            Message::QueueMessage(message) => message.dequeue_count,
+            Message::LocalQueueMessage(message) => message.dequeue_count,
         }
     }
 
+    /// Make the message invisible to other consumers for `timeout` from now
+    /// on, such as to keep processing it past the visibility timeout it was
+    /// popped with.
+    pub async fn extend_visibility(&mut self, timeout: Duration) -> Result<()> {
+        match self {
+            Message::QueueMessage(message) => message.extend_visibility(timeout).await,
+            Message::LocalQueueMessage(message) => message.extend_visibility(timeout),
+        }
+        .context("Message.extend_visibility")
+    }
+
+    /// Move the message to `queue`, such as a dead-letter queue, keeping its
+    /// payload as is, even if `queue` is of another kind.
+    pub async fn move_to(self, queue: &QueueClient) -> Result<()> {
+        let payload = self.parse(|data| Ok(data.to_vec()))?;
+
+        // encoded as the parsers of messages of `queue` expect it
+        let data = match queue {
+            QueueClient::AzureQueue(_) => payload,
+            QueueClient::FileQueueClient(_) | QueueClient::Channel(_) => {
+                bincode::serialize(&payload)?
+            }
+        };
+        queue.enqueue_raw(&data).await?;
+        self.delete().await.context("Message.move_to")
+    }
+
     pub fn parse<T>(&self, parser: impl FnOnce(&[u8]) -> Result<T>) -> Result<T> {
         match self {
             Message::QueueMessage(message) => message.parse(parser),
@@ -138,3 +186,80 @@ impl Message {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_local_visibility_timeout() -> Result<()> {
+        let queue = QueueClient::Channel(ChannelQueueClient::new()?);
+        queue.enqueue("input").await?;
+
+        let mut first = queue
+            .pop()
+            .await?
+            .ok_or_else(|| anyhow!("missing message"))?;
+        assert_eq!(first.dequeue_count(), 1);
+        assert!(queue.pop().await?.is_none());
+
+        // popped again once its visibility timeout expires, as if processing
+        // it failed
+        first.extend_visibility(Duration::from_secs(60)).await?;
+        assert!(queue.pop().await?.is_none());
+
+        first.extend_visibility(Duration::ZERO).await?;
+        let second = queue
+            .pop()
+            .await?
+            .ok_or_else(|| anyhow!("missing message"))?;
+        assert_eq!(second.dequeue_count(), 2);
+        assert_eq!(second.get::<String>()?, "input");
+
+        // the first pop no longer owns the message
+        assert!(first.delete().await.is_err());
+
+        let dead_letter = QueueClient::Channel(ChannelQueueClient::new()?);
+        second.move_to(&dead_letter).await?;
+        assert!(queue.pop().await?.is_none());
+
+        let moved = dead_letter
+            .pop()
+            .await?
+            .ok_or_else(|| anyhow!("missing message"))?;
+        assert_eq!(moved.dequeue_count(), 1);
+        assert_eq!(moved.get::<String>()?, "input");
+
+        // deleted when popped, as its visibility was not extended
+        drop(moved);
+        assert!(dead_letter.pop().await?.is_none());
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_move_to_other_queue_kind() -> Result<()> {
+        let queue = QueueClient::Channel(ChannelQueueClient::new()?);
+        queue.enqueue("https://example.com/inputs/crash").await?;
+
+        let path = std::env::temp_dir().join(format!("onefuzz-test-{}", Uuid::new_v4()));
+        let file_queue =
+            QueueClient::FileQueueClient(Box::new(FileQueueClient::new(path.clone())?));
+
+        let message = queue
+            .pop()
+            .await?
+            .ok_or_else(|| anyhow!("missing message"))?;
+        message.move_to(&file_queue).await?;
+
+        let moved = file_queue
+            .pop()
+            .await?
+            .ok_or_else(|| anyhow!("missing message"))?;
+        let payload = moved.parse(|data| Ok(data.to_vec()));
+        std::fs::remove_file(&path)?;
+        assert_eq!(payload?, b"https://example.com/inputs/crash");
+
+        Ok(())
+    }
+}
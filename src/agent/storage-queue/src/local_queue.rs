@@ -1,14 +1,15 @@
 // Copyright (c) Microsoft Corporation.
 // Licensed under the MIT License.
 
-use anyhow::{anyhow, Result};
+use anyhow::{anyhow, bail, Result};
 use backoff::{future::retry_notify, ExponentialBackoff};
 use queue_file::QueueFile;
 use serde::de::DeserializeOwned;
 use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
 use std::path::PathBuf;
 use std::sync::{Arc, Mutex};
-use std::time::Duration;
+use std::time::{Duration, Instant};
 
 pub const EMPTY_QUEUE_DELAY: Duration = Duration::from_secs(10);
 pub const SEND_RETRY_DELAY: Duration = Duration::from_millis(500);
@@ -16,13 +17,75 @@ pub const RECEIVE_RETRY_DELAY: Duration = Duration::from_millis(500);
 pub const MAX_SEND_ATTEMPTS: i32 = 5;
 pub const MAX_RECEIVE_ATTEMPTS: i32 = 5;
 pub const MAX_ELAPSED_TIME: Duration = Duration::from_secs(2 * 60);
-
+/// Message of a local queue.
+///
+/// Unlike those of Azure Storage queues, messages are deleted when popped,
+/// unless their visibility is extended, after which they are popped again
+/// once it expires, unless deleted.
 #[derive(Serialize, Deserialize, Clone)]
 pub struct LocalQueueMessage {
     pub data: Vec<u8>,
+
+    /// Number of times the message was popped, including this one.
+    #[serde(skip)]
+    pub dequeue_count: u32,
+
+    // Leases of the queue the message was popped from.
+    #[serde(skip)]
+    leases: Option<Leases>,
+
+    #[serde(skip)]
+    lease: Option<u64>,
 }
 
 impl LocalQueueMessage {
+    pub fn new(data: Vec<u8>) -> Self {
+        Self {
+            data,
+            dequeue_count: 0,
+            leases: None,
+            lease: None,
+        }
+    }
+
+    fn popped(data: Vec<u8>, dequeue_count: u32, leases: &Leases) -> Self {
+        Self {
+            data,
+            dequeue_count,
+            leases: Some(leases.clone()),
+            lease: None,
+        }
+    }
+
+    /// Make the message invisible for `timeout` from now on, after which it
+    /// is popped again, unless deleted.
+    ///
+    /// Fails if the message was popped again since, as its visibility timeout
+    /// expired.
+    pub fn extend_visibility(&mut self, timeout: Duration) -> Result<()> {
+        let Some(leases) = &self.leases else {
+            return Ok(());
+        };
+
+        match self.lease {
+            Some(id) => leases.extend(id, timeout)?,
+            None => {
+                let id = leases.lease(self.data.clone(), self.dequeue_count, timeout)?;
+                self.lease = Some(id);
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Delete the message, so that it is not popped again.
+    pub fn delete(&self) -> Result<()> {
+        if let (Some(leases), Some(id)) = (&self.leases, self.lease) {
+            leases.release(id)?;
+        }
+        Ok(())
+    }
+
     pub fn get<T: DeserializeOwned>(&self) -> Result<T> {
         let value = bincode::deserialize(&self.data)?;
         Ok(value)
@@ -40,10 +103,90 @@ impl std::fmt::Debug for LocalQueueMessage {
     }
 }
 
+// Messages whose visibility was extended, but which were not deleted yet,
+// which emulate the visibility timeout and dequeue count of Azure Storage
+// queues.
+#[derive(Clone, Debug, Default)]
+struct Leases {
+    inner: Arc<Mutex<LeasesInner>>,
+}
+
+#[derive(Debug, Default)]
+struct LeasesInner {
+    next_id: u64,
+    leased: HashMap<u64, Leased>,
+}
+
+#[derive(Debug)]
+struct Leased {
+    data: Vec<u8>,
+    dequeue_count: u32,
+    visible_at: Instant,
+}
+
+impl Leases {
+    fn lock(&self) -> Result<std::sync::MutexGuard<LeasesInner>> {
+        self.inner
+            .lock()
+            .map_err(|_| anyhow!("unable to acquire lock"))
+    }
+
+    fn lease(&self, data: Vec<u8>, dequeue_count: u32, timeout: Duration) -> Result<u64> {
+        let mut inner = self.lock()?;
+        let id = inner.next_id;
+        inner.next_id += 1;
+        inner.leased.insert(
+            id,
+            Leased {
+                data,
+                dequeue_count,
+                visible_at: Instant::now() + timeout,
+            },
+        );
+        Ok(id)
+    }
+
+    // Pop a message again, if its visibility timeout expired.
+    fn pop_expired(&self) -> Result<Option<LocalQueueMessage>> {
+        let expired = {
+            let mut inner = self.lock()?;
+            let now = Instant::now();
+            let id = inner
+                .leased
+                .iter()
+                .filter(|(_, leased)| leased.visible_at <= now)
+                .min_by_key(|(_, leased)| leased.visible_at)
+                .map(|(id, _)| *id);
+            id.and_then(|id| inner.leased.remove(&id))
+        };
+
+        Ok(expired
+            .map(|leased| LocalQueueMessage::popped(leased.data, leased.dequeue_count + 1, self)))
+    }
+
+    fn extend(&self, id: u64, timeout: Duration) -> Result<()> {
+        let mut inner = self.lock()?;
+        match inner.leased.get_mut(&id) {
+            Some(leased) => leased.visible_at = Instant::now() + timeout,
+            None => bail!("message was popped again since"),
+        }
+        Ok(())
+    }
+
+    fn release(&self, id: u64) -> Result<()> {
+        let mut inner = self.lock()?;
+        if inner.leased.remove(&id).is_none() {
+            bail!("message was popped again since");
+        }
+        Ok(())
+    }
+}
+
 /// File backed queue
 #[derive(Debug, Clone)]
 pub struct FileQueueClient {
     queue: Arc<Mutex<QueueFile>>,
+    leases: Leases,
     pub path: PathBuf,
 }
 
@@ -56,6 +199,7 @@ impl FileQueueClient {
 
         Ok(FileQueueClient {
             queue,
+            leases: Leases::default(),
             path: queue_url,
         })
     }
@@ -87,7 +231,24 @@ impl FileQueueClient {
         Ok(())
     }
 
+    /// Enqueue `data` as is, such as the data of a message popped from
+    /// another queue.
+    pub async fn enqueue_raw(&self, data: &[u8]) -> Result<()> {
+        let mut locked_q = self
+            .queue
+            .lock()
+            .map_err(|_| anyhow::anyhow!("unable to acquire lock"))?;
+        locked_q
+            .add(data)
+            .map_err(|_| anyhow::anyhow!("unable to queue message"))?;
+        Ok(())
+    }
+
     pub async fn pop(&self) -> Result<Option<LocalQueueMessage>> {
+        if let Some(message) = self.leases.pop_expired()? {
+            return Ok(Some(message));
+        }
+
         let receive_data = || async {
             let mut locked_q = self
                 .queue
@@ -100,8 +261,7 @@ impl FileQueueClient {
                 .remove()
                 .map_err(|_| anyhow::anyhow!("unable to pop message"))?;
 
-            let message = data.map(|d| LocalQueueMessage { data: d.to_vec() });
-            Ok(message)
+            Ok(data.map(|d| d.to_vec()))
         };
 
         let backoff = ExponentialBackoff {
@@ -113,7 +273,7 @@ impl FileQueueClient {
         let notify = |err, _| println!("IO error: {err}");
         let result = retry_notify(backoff, receive_data, notify).await?;
 
-        Ok(result)
+        Ok(result.map(|data| LocalQueueMessage::popped(data, 1, &self.leases)))
     }
 }
 
@@ -124,6 +284,7 @@ use flume::{unbounded, Receiver, Sender, TryRecvError};
 pub struct ChannelQueueClient {
     sender: Arc<Mutex<Sender<Vec<u8>>>>,
     receiver: Arc<Mutex<Receiver<Vec<u8>>>>,
+    leases: Leases,
     pub url: reqwest::Url,
     low_resource: bool,
 }
@@ -136,6 +297,7 @@ impl ChannelQueueClient {
         Ok(ChannelQueueClient {
             sender: Arc::new(Mutex::new(sender)),
             receiver: Arc::new(Mutex::new(receiver)),
+            leases: Leases::default(),
             url: reqwest::Url::parse("mpsc://channel")?,
             low_resource,
         })
@@ -153,9 +315,20 @@ impl ChannelQueueClient {
             .lock()
             .map_err(|_| anyhow::anyhow!("unable to acquire lock"))?;
 
-        let msg = bincode::serialize(&LocalQueueMessage {
-            data: bincode::serialize(&data)?,
-        })?;
+        let msg = bincode::serialize(&LocalQueueMessage::new(bincode::serialize(&data)?))?;
+        sender.send(msg)?;
+        Ok(())
+    }
+
+    /// Enqueue `data` as is, such as the data of a message popped from
+    /// another queue.
+    pub async fn enqueue_raw(&self, data: &[u8]) -> Result<()> {
+        let sender = self
+            .sender
+            .lock()
+            .map_err(|_| anyhow::anyhow!("unable to acquire lock"))?;
+
+        let msg = bincode::serialize(&LocalQueueMessage::new(data.to_vec()))?;
         sender.send(msg)?;
         Ok(())
     }
@@ -166,6 +339,11 @@ impl ChannelQueueClient {
         if self.low_resource {
             tokio::task::yield_now().await;
         }
+
+        if let Some(message) = self.leases.pop_expired()? {
+            return Ok(Some(message));
+        }
+
         let receiver = self
             .receiver
             .lock()
@@ -173,8 +351,12 @@ impl ChannelQueueClient {
 
         match receiver.try_recv() {
             Ok(data) => {
-                let message = bincode::deserialize(&data)?;
-                Ok(Some(message))
+                let message: LocalQueueMessage = bincode::deserialize(&data)?;
+                Ok(Some(LocalQueueMessage::popped(
+                    message.data,
+                    1,
+                    &self.leases,
+                )))
             }
             Err(TryRecvError::Empty) => Ok(None),
             Err(err) => Err(err.into()),
@@ -173,6 +173,9 @@ class TaskType(Enum):
     generic_generator = "generic_generator"
     generic_crash_report = "generic_crash_report"
     generic_regression = "generic_regression"
+    aflpp_fuzz = "aflpp_fuzz"
+    libfuzzer_minimize = "libfuzzer_minimize"
+    generic_minimize = "generic_minimize"
 
 
 class VmState(Enum):
@@ -228,6 +231,7 @@ class ContainerType(Enum):
     unique_inputs = "unique_inputs"
     unique_reports = "unique_reports"
     regression_reports = "regression_reports"
+    minimized_crashes = "minimized_crashes"
     logs = "logs"
     extra_setup = "extra_setup"
     extra_output = "extra_output"
@@ -247,6 +251,7 @@ class ContainerType(Enum):
             cls.unique_inputs,
             cls.unique_reports,
             cls.regression_reports,
+            cls.minimized_crashes,
         ]
 
     @classmethod
@@ -256,6 +261,7 @@ class ContainerType(Enum):
 
 class StatsFormat(Enum):
     AFL = "AFL"
+    Honggfuzz = "Honggfuzz"
 
 
 class ErrorCode(Enum):
@@ -174,6 +174,7 @@ class TaskDetails(BaseModel):
     target_method: Optional[str]
     task_env: Optional[Dict[str, str]]
     min_available_memory_mb: Optional[int] = Field(ge=0)
+    max_dequeue_count: Optional[int] = Field(ge=1)
 
 
 class TaskPool(BaseModel):
@@ -664,12 +665,22 @@ class NodeSettingUpEventData(BaseModel):
     tasks: List[UUID]
 
 
+class NodeWorkUnitData(BaseModel):
+    job_id: UUID
+    task_id: UUID
+    state: str
+
+
+class NodeBusyEventData(BaseModel):
+    work_units: List[NodeWorkUnitData]
+
+
 class NodeDoneEventData(BaseModel):
     error: Optional[str]
     script_output: Optional[ProcessOutput]
 
 
-NodeStateData = Union[NodeSettingUpEventData, NodeDoneEventData]
+NodeStateData = Union[NodeSettingUpEventData, NodeBusyEventData, NodeDoneEventData]
 
 
 class NodeStateUpdate(BaseModel):
@@ -687,6 +698,10 @@ class NodeStateUpdate(BaseModel):
                 if isinstance(data, NodeSettingUpEventData):
                     return values
 
+            if state == NodeState.busy:
+                if isinstance(data, NodeBusyEventData):
+                    return values
+
             if state == NodeState.done:
                 if isinstance(data, NodeDoneEventData):
                     return values